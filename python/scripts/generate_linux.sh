@@ -2,19 +2,21 @@
 set -euo pipefail
 ${PYBIN}/python --version
 ${PYBIN}/pip install -r requirements.txt -r requirements-dev.txt
-LIBNAME=libpayjoin_ffi.so
+LIBNAME=libpayjoin_ffi_v0_22.so
 LINUX_TARGET=x86_64-unknown-linux-gnu
 
-echo "Generating payjoin_ffi.py..."
+echo "Generating payjoin_ffi_v0_22.py..."
 cd ../
 cargo build --profile release --features uniffi
 cargo run --profile release --features uniffi --bin uniffi-bindgen generate --library target/release/$LIBNAME --language python --out-dir python/src/payjoin/
+VERSION=$(grep -m1 '^version' Cargo.toml | cut -d '"' -f2)
+echo "BINDINGS_VERSION = \"$VERSION\"" > python/src/payjoin/_bindings_version.py
 
 echo "Generating native binaries..."
 rustup target add $LINUX_TARGET
 cargo build  --profile release-smaller --target $LINUX_TARGET --features uniffi
 
-echo "Copying linux payjoin_ffi.so"
+echo "Copying linux $LIBNAME"
 cp target/$LINUX_TARGET/release-smaller/$LIBNAME python/src/payjoin/$LIBNAME
 
 echo "All done!"
\ No newline at end of file
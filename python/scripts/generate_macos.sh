@@ -3,12 +3,14 @@
 set -euo pipefail
 python3 --version
 pip install -r requirements.txt -r requirements-dev.txt
-LIBNAME=libpayjoin_ffi.dylib
+LIBNAME=libpayjoin_ffi_v0_22.dylib
 
-echo "Generating payjoin_ffi.py..."
+echo "Generating payjoin_ffi_v0_22.py..."
 cd ../
 cargo build --features uniffi --profile release 
 cargo run --features uniffi --profile release --bin uniffi-bindgen generate --library target/release/$LIBNAME --language python --out-dir python/src/payjoin/
+VERSION=$(grep -m1 '^version' Cargo.toml | cut -d '"' -f2)
+echo "BINDINGS_VERSION = \"$VERSION\"" > python/src/payjoin/_bindings_version.py
 
 echo "Generating native binaries..."
 rustup target add aarch64-apple-darwin x86_64-apple-darwin
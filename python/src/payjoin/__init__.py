@@ -1 +1,9 @@
-from payjoin.payjoin_ffi import *
\ No newline at end of file
+from payjoin._bindings_version import BINDINGS_VERSION
+from payjoin.payjoin_ffi_v0_22 import *
+
+# The FFI symbols carry the release series, so bindings can't load a library from another series
+# at all. Within a series, check the release the bindings were generated for is compatible.
+try:
+    check_bindings_version(BINDINGS_VERSION)
+except BindingsMismatch as e:
+    raise ImportError(str(e)) from e
@@ -1,5 +1,7 @@
+import importlib
 import unittest
 import payjoin as payjoin
+import payjoin._bindings_version as payjoin_bindings_version
 
 
 class TestURIs(unittest.TestCase):
@@ -106,5 +108,19 @@ class TestReceiveModule(unittest.TestCase):
             self.fail(f"test_unchecked_proposal_unlocks_after_checks exception: {e}")
 
 
+
+class TestBindingsVersion(unittest.TestCase):
+    def tearDown(self):
+        importlib.reload(payjoin_bindings_version)
+        importlib.reload(payjoin)
+
+    def test_bindings_for_another_release_fail_to_import(self):
+        payjoin_bindings_version.BINDINGS_VERSION = "999.0.0"
+        with self.assertRaises(ImportError) as raised:
+            importlib.reload(payjoin)
+        self.assertIsInstance(raised.exception.__cause__, payjoin.BindingsMismatch)
+        self.assertIn("cannot call into payjoin-ffi", str(raised.exception))
+
+
 if __name__ == "__main__":
     unittest.main()
@@ -0,0 +1,51 @@
+/// Session lifetime in seconds assumed when a directory doesn't declare its maximum.
+///
+/// Matches the default expiry of a receiver session.
+pub const LEGACY_MAX_TTL_SECS: u64 = 60 * 60 * 24;
+
+/// The protocol features a payjoin directory supports.
+///
+/// Directories don't describe themselves: neither their `/ohttp-keys` response nor any other
+/// endpoint says whether they long-poll or how long they keep messages. So these aren't derived
+/// from the directory but come from its operator or documentation. Use the conservative
+/// [`DirectoryCapabilities::legacy`] profile otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct DirectoryCapabilities {
+    /// Protocol revisions the directory accepts, e.g. `"2"`.
+    pub versions: Vec<String>,
+    /// Whether the directory holds poll requests open until a message arrives.
+    pub long_poll: bool,
+    /// Longest session lifetime in seconds the directory keeps messages for.
+    pub max_ttl_secs: u64,
+}
+
+impl DirectoryCapabilities {
+    /// The profile assumed for directories that don't describe themselves.
+    pub fn legacy() -> Self {
+        Self {
+            versions: vec!["2".to_string()],
+            long_poll: false,
+            max_ttl_secs: LEGACY_MAX_TTL_SECS,
+        }
+    }
+
+    /// The session lifetime to use when `requested` seconds were asked for, capped at what the
+    /// directory keeps messages for.
+    ///
+    /// Pass the result as `expire_after` when creating a receiver session.
+    pub fn effective_expire_after(&self, requested: Option<u64>) -> u64 {
+        requested.map_or(self.max_ttl_secs, |requested| requested.min(self.max_ttl_secs))
+    }
+
+    /// The `timeout_secs` of a poll request when the caller waits up to `wait_secs` seconds and
+    /// `remaining` seconds are left before the session ends.
+    ///
+    /// `None` if the directory doesn't long-poll, so the request is a plain poll.
+    pub(crate) fn poll_timeout_secs(&self, wait_secs: u64, remaining: Option<u64>) -> Option<u64> {
+        if !self.long_poll {
+            return None;
+        }
+        Some(remaining.map_or(wait_secs, |left| wait_secs.min(left)))
+    }
+}
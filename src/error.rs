@@ -2,3 +2,31 @@
 #[error("Error de/serializing JSON object: {0}")]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct SerdeJsonError(#[from] serde_json::Error);
+
+/// Error parsing a PSBT passed across the FFI boundary.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Error parsing the PSBT: {msg}")]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct PsbtParseError {
+    msg: String,
+}
+
+impl From<payjoin::bitcoin::psbt::PsbtParseError> for PsbtParseError {
+    fn from(value: payjoin::bitcoin::psbt::PsbtParseError) -> Self {
+        PsbtParseError { msg: value.to_string() }
+    }
+}
+
+/// Error constructing a [`crate::FeeRate`].
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum FeeRateError {
+    #[error("Fee rate of {sat_per_vb} sat/vB overflows when converted to sat/kwu")]
+    Overflow { sat_per_vb: u64 },
+    #[error("Fee rate is not a number")]
+    NotANumber,
+    #[error("Fee rate is negative")]
+    Negative,
+    #[error("Fee rate exceeds the maximum of {max_sat_per_vb} sat/vB")]
+    TooHigh { max_sat_per_vb: u64 },
+}
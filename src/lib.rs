@@ -1,21 +1,46 @@
-#![crate_name = "payjoin_ffi"]
+#![crate_name = "payjoin_ffi_v0_22"]
 
 pub mod bitcoin_ffi;
+pub mod directory;
 pub mod error;
 pub mod io;
+#[cfg(feature = "_danger-local-testing")]
+pub mod local_testing;
 pub mod ohttp;
 pub mod receive;
+pub mod record;
 pub mod request;
 pub mod send;
+pub mod types;
 pub mod uri;
+pub mod version;
 
 pub use crate::bitcoin_ffi::*;
+pub use crate::directory::DirectoryCapabilities;
+pub use crate::error::FeeRateError;
+#[cfg(feature = "_danger-local-testing")]
+pub use crate::local_testing::{
+    local_testing_ohttp_keys, unwrap_request, LocalTestingError, PlainContext, PlainRequest,
+};
 pub use crate::ohttp::*;
 #[cfg(feature = "uniffi")]
 pub use crate::receive::uni::*;
+pub use crate::record::{PayjoinDirection, PayjoinTxRecord};
 pub use crate::request::Request;
 #[cfg(feature = "uniffi")]
 pub use crate::send::uni::*;
-pub use crate::uri::{PjUri, Uri, Url};
+pub use crate::types::{FeeRate, TransactionView};
+pub use crate::uri::{
+    format_btc_amount, parse_btc_amount, Bip21Builder, InvalidAmount, PjUri, PjUriBuilder, Uri, Url,
+};
+pub use crate::version::{check_bindings_version, library_version, BindingsMismatch};
+// The namespace and the library name, which prefixes the FFI symbols, carry the
+// semver-compatible version series so that bindings for different releases loaded into one
+// process do not resolve each other's components. Update both alongside any breaking version
+// bump.
 #[cfg(feature = "uniffi")]
-uniffi::setup_scaffolding!();
+uniffi::setup_scaffolding!("payjoin_ffi_v0_22");
+// bitcoin-ffi registers `bitcoin::Network` with UniFFI as a remote type, which exported
+// signatures in this crate can only use once it is imported here.
+#[cfg(feature = "uniffi")]
+uniffi::use_remote_type!(bitcoin_ffi::Network);
@@ -0,0 +1,207 @@
+//! Plain HTTP requests to a local payjoin directory, for testing without an OHTTP relay.
+//!
+//! Sessions created with [`local_testing_ohttp_keys`] encapsulate their v2 requests for a key
+//! whose private half is public. [`unwrap_request`] decapsulates such a request into the plain
+//! HTTP request it carries to the directory, and [`PlainContext::wrap_response`] turns the
+//! directory's reply back into the OHTTP response the session's `process_*` method expects.
+//!
+//! Two functions cover every v2 request and response, the sender's and the receiver's alike,
+//! rather than a plain variant of each `extract_*` and `process_*` method. The session types
+//! still process their responses as usual, so a test drives the same code paths as production.
+//!
+//! Anyone can decapsulate these requests, so never use this outside of tests. Like
+//! `_danger-local-https` the feature is off by default and the scripts building release
+//! bindings don't enable it. It isn't a compile error in release builds, so that
+//! `--all-features` release builds still compile.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+
+use crate::ohttp::ENCAPSULATED_MESSAGE_BYTES;
+use crate::uri::Url;
+use crate::{OhttpKeys, Request};
+
+/// The input keying material of [`local_testing_ohttp_keys`]. It is public, so anyone can
+/// decapsulate requests for those keys.
+const LOCAL_TESTING_IKM: [u8; 32] = [0x10; 32];
+
+/// Size of the binary HTTP response inside an encapsulated directory response: the message
+/// less the response nonce and the AEAD tag.
+const BHTTP_RESPONSE_BYTES: usize = ENCAPSULATED_MESSAGE_BYTES - 32 - 16;
+
+fn key_config() -> KeyConfig {
+    KeyConfig::derive(
+        1,
+        Kem::K256Sha256,
+        vec![SymmetricSuite::new(Kdf::HkdfSha256, Aead::ChaCha20Poly1305)],
+        &LOCAL_TESTING_IKM,
+    )
+    .expect("the local testing key configuration is valid")
+}
+
+/// OHTTP keys for sessions whose requests go to a local directory without a relay.
+///
+/// Pass them to `Receiver::new` in place of the directory's keys. Senders pick them up from the
+/// receiver's pj URI.
+#[cfg(not(feature = "uniffi"))]
+pub fn local_testing_ohttp_keys() -> OhttpKeys {
+    OhttpKeys(payjoin::OhttpKeys(key_config()))
+}
+
+/// OHTTP keys for sessions whose requests go to a local directory without a relay.
+///
+/// Pass them to `Receiver::new` in place of the directory's keys. Senders pick them up from the
+/// receiver's pj URI.
+#[cfg(feature = "uniffi")]
+#[uniffi::export]
+pub fn local_testing_ohttp_keys() -> Arc<OhttpKeys> {
+    Arc::new(OhttpKeys(payjoin::OhttpKeys(key_config())))
+}
+
+/// Error converting between an OHTTP encapsulated message and its plain HTTP form.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum LocalTestingError {
+    /// The request isn't encapsulated for [`local_testing_ohttp_keys`], e.g. the session was
+    /// created with a real directory's keys.
+    #[error("The request isn't encapsulated for the local testing OHTTP keys")]
+    ForeignOhttpKeys,
+    /// The decapsulated request isn't a known-length binary HTTP request.
+    #[error("Malformed binary HTTP request: {msg}")]
+    MalformedRequest { msg: String },
+    /// The directory's response body doesn't fit in an encapsulated directory response.
+    #[error("The response body of {len} bytes exceeds the maximum of {max}")]
+    ResponseTooLarge { len: u64, max: u64 },
+    /// A response was already wrapped with this context.
+    #[error("The context was already used to wrap a response")]
+    ContextAlreadyUsed,
+}
+
+/// A v2 request as plain HTTP, and the context to wrap the directory's response with.
+#[derive(Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct PlainRequest {
+    /// The request to send to the directory as is.
+    pub request: Request,
+    pub context: Arc<PlainContext>,
+}
+
+/// Decapsulate `request`, a v2 request of a session using [`local_testing_ohttp_keys`], into the
+/// plain HTTP request it carries to the directory.
+///
+/// Works for every v2 request, e.g. from `Sender::extract_v2`, `Receiver::extract_req` or
+/// `PayjoinProposal::extract_v2_req`. The OHTTP relay those take is not contacted and may be any
+/// URL. Keep the OHTTP context they return to process the wrapped response with.
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+pub fn unwrap_request(request: Request) -> Result<PlainRequest, LocalTestingError> {
+    let server = ohttp::Server::new(key_config()).expect("the local testing keys are valid");
+    let (bhttp, response) =
+        server.decapsulate(&request.body).map_err(|_| LocalTestingError::ForeignOhttpKeys)?;
+    let mut plain = parse_bhttp_request(&bhttp)?;
+    plain.timeout_secs = request.timeout_secs;
+    Ok(PlainRequest { request: plain, context: Arc::new(PlainContext(Mutex::new(Some(response)))) })
+}
+
+/// The gateway half of the OHTTP exchange of a request from [`unwrap_request`].
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct PlainContext(Mutex<Option<ohttp::ServerResponse>>);
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl PlainContext {
+    /// Encapsulate the directory's reply to the plain request, with HTTP `status` and `body`, as
+    /// the response to the original v2 request.
+    ///
+    /// Pass the result to the `process_*` method matching the request, along with the OHTTP
+    /// context the request was extracted with.
+    pub fn wrap_response(&self, status: u16, body: Vec<u8>) -> Result<Vec<u8>, LocalTestingError> {
+        let mut bhttp = vec![1];
+        write_varint(&mut bhttp, status.into());
+        write_varint(&mut bhttp, 0);
+        write_varint(&mut bhttp, body.len() as u64);
+        bhttp.extend(&body);
+        write_varint(&mut bhttp, 0);
+        if bhttp.len() > BHTTP_RESPONSE_BYTES {
+            return Err(LocalTestingError::ResponseTooLarge {
+                len: body.len() as u64,
+                max: (BHTTP_RESPONSE_BYTES - (bhttp.len() - body.len())) as u64,
+            });
+        }
+        bhttp.resize(BHTTP_RESPONSE_BYTES, 0);
+        let response = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+            .ok_or(LocalTestingError::ContextAlreadyUsed)?;
+        Ok(response.encapsulate(&bhttp).expect("a padded response encapsulates"))
+    }
+}
+
+/// Parse a known-length binary HTTP request (RFC 9292).
+fn parse_bhttp_request(mut bytes: &[u8]) -> Result<Request, LocalTestingError> {
+    let malformed = |msg: &str| LocalTestingError::MalformedRequest { msg: msg.to_string() };
+    let truncated = || malformed("truncated");
+    let bytes = &mut bytes;
+    if read_varint(bytes).ok_or_else(truncated)? != 0 {
+        return Err(malformed("not a known-length request"));
+    }
+    let mut control = || read_field(bytes).map(String::from_utf8_lossy).ok_or_else(truncated);
+    let (method, scheme, authority, path) = (control()?, control()?, control()?, control()?);
+    let url = Url::parse(format!("{scheme}://{authority}{path}"))
+        .map_err(|e| malformed(&e.to_string()))?;
+    let mut fields = read_field(bytes).ok_or_else(truncated)?;
+    let mut headers = HashMap::new();
+    while !fields.is_empty() {
+        let name = read_field(&mut fields).ok_or_else(truncated)?;
+        let value = read_field(&mut fields).ok_or_else(truncated)?;
+        headers.insert(
+            String::from_utf8_lossy(name).into_owned(),
+            String::from_utf8_lossy(value).into_owned(),
+        );
+    }
+    let body = read_field(bytes).ok_or_else(truncated)?.to_vec();
+    let content_type = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default();
+    headers.insert("Content-Length".to_string(), body.len().to_string());
+    Ok(Request {
+        url: Arc::new(url),
+        method: method.into_owned(),
+        headers,
+        content_type,
+        body,
+        timeout_secs: None,
+    })
+}
+
+/// Read a QUIC variable-length integer (RFC 9000, section 16).
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let first = *bytes.first()?;
+    let len = 1 << (first >> 6);
+    let (varint, rest) = (bytes.get(..len)?, &bytes[len..]);
+    *bytes = rest;
+    Some(varint[1..].iter().fold(u64::from(first & 0x3f), |value, b| (value << 8) | u64::from(*b)))
+}
+
+/// Read a length-prefixed field.
+fn read_field<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = usize::try_from(read_varint(bytes)?).ok()?;
+    let (field, rest) = (bytes.get(..len)?, &bytes[len..]);
+    *bytes = rest;
+    Some(field)
+}
+
+/// Write `value` as a QUIC variable-length integer.
+fn write_varint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0x3f => out.push(value as u8),
+        0x40..=0x3fff => out.extend((value as u16 | 0x4000).to_be_bytes()),
+        0x4000..=0x3fff_ffff => out.extend((value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => out.extend((value | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
@@ -10,6 +10,42 @@ impl From<ohttp::Error> for OhttpError {
     }
 }
 
+/// Error decoding [`OhttpKeys`] from a directory's key configuration or their cached forms.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum OhttpKeysParseError {
+    /// The encoding ends before the keys do, e.g. a cache entry cut short.
+    #[error("OHTTP keys are truncated")]
+    Truncated,
+    /// The encoding is complete but isn't valid OHTTP keys.
+    #[error("OHTTP keys are corrupt: {msg}")]
+    Corrupt { msg: String },
+    /// No key configuration uses the secp256k1 KEM payjoin requires. `kem` is the RFC 9180
+    /// identifier of the first one.
+    #[error("Unsupported OHTTP KEM 0x{kem:04x}")]
+    UnsupportedKem { kem: u16 },
+    /// No key configuration uses only algorithms the OHTTP implementation supports.
+    #[error("No supported OHTTP key configuration")]
+    Unsupported,
+}
+
+impl From<ohttp::Error> for OhttpKeysParseError {
+    fn from(value: ohttp::Error) -> Self {
+        match value {
+            ohttp::Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Self::Truncated,
+            ohttp::Error::Unsupported => Self::Unsupported,
+            e => Self::Corrupt { msg: format!("{e:?}") },
+        }
+    }
+}
+
+/// RFC 9180 identifier of the only KEM payjoin uses for OHTTP.
+const KEM_K256_SHA256: u16 = 0x0016;
+
+/// Length of the compressed key configuration in the `OH` parameter of a pj URI: a key
+/// identifier and a compressed secp256k1 public key.
+const COMPRESSED_KEYS_BYTES: usize = 34;
+
 impl From<payjoin::OhttpKeys> for OhttpKeys {
     fn from(value: payjoin::OhttpKeys) -> Self {
         Self(value)
@@ -21,27 +57,94 @@ impl From<OhttpKeys> for payjoin::OhttpKeys {
     }
 }
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+#[cfg_attr(feature = "uniffi", uniffi::export(Display))]
 #[derive(Debug, Clone)]
 pub struct OhttpKeys(pub payjoin::OhttpKeys);
 
 #[cfg_attr(feature = "uniffi", uniffi::export)]
 impl OhttpKeys {
-    /// Decode an OHTTP KeyConfig
+    /// Decode the response body of a directory's `/ohttp-keys` endpoint.
+    ///
+    /// Both the length-prefixed list of RFC 9458 and the single key configuration payjoin
+    /// directories serve are accepted. The first configuration with the secp256k1 KEM is used
+    /// as served, with all of its symmetric suites.
     #[cfg_attr(feature = "uniffi", uniffi::constructor)]
-    pub fn decode(bytes: Vec<u8>) -> Result<Self, OhttpError> {
-        payjoin::OhttpKeys::decode(bytes.as_slice()).map(Into::into).map_err(Into::into)
+    pub fn decode(bytes: Vec<u8>) -> Result<Self, OhttpKeysParseError> {
+        let configs = match ohttp::KeyConfig::decode_list(&bytes) {
+            Ok(configs) if !configs.is_empty() => configs,
+            _ => vec![ohttp::KeyConfig::decode(&bytes)?],
+        };
+        let mut unsupported = None;
+        for config in configs {
+            // A configuration starts with a one byte key identifier and the KEM identifier
+            let kem = match config.encode()?.get(1..3) {
+                Some(&[high, low]) => u16::from_be_bytes([high, low]),
+                _ => return Err(OhttpKeysParseError::Truncated),
+            };
+            if kem == KEM_K256_SHA256 {
+                return Ok(payjoin::OhttpKeys(config).into());
+            }
+            unsupported.get_or_insert(OhttpKeysParseError::UnsupportedKem { kem });
+        }
+        Err(unsupported.unwrap_or(OhttpKeysParseError::Unsupported))
+    }
+
+    /// The key configuration encoded as the directory serves it, to cache across launches and
+    /// restore with [`OhttpKeys::decode`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, OhttpError> {
+        Ok(self.0 .0.encode()?)
+    }
+
+    /// Restore keys from the string form of [`OhttpKeys::to_string`], the `OH1...` encoding of
+    /// the `OH` parameter of a pj URI.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn parse(encoded: String) -> Result<Self, OhttpKeysParseError> {
+        use payjoin::bitcoin::bech32::primitives::decode::CheckedHrpstring;
+        use payjoin::bitcoin::bech32::NoChecksum;
+
+        encoded.parse::<payjoin::OhttpKeys>().map(Into::into).map_err(|e| {
+            match CheckedHrpstring::new::<NoChecksum>(&encoded) {
+                Ok(param)
+                    if param.hrp().to_lowercase() == "oh"
+                        && param.byte_iter().count() < COMPRESSED_KEYS_BYTES =>
+                {
+                    OhttpKeysParseError::Truncated
+                }
+                _ => OhttpKeysParseError::Corrupt { msg: e.to_string() },
+            }
+        })
+    }
+}
+
+/// The `OH1...` encoding of the `OH` parameter of a pj URI.
+impl std::fmt::Display for OhttpKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for OhttpKeys {
+    type Err = OhttpKeysParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OhttpKeys::parse(s.to_string())
     }
 }
 
 use std::sync::Mutex;
 
+/// The single-use OHTTP context needed to decapsulate the response to a request.
+///
+/// It can be shared across threads, e.g. to send the request on one thread and process the
+/// response on another. Processing a response consumes the context, so processing a second
+/// response with it fails with an `OhttpContextAlreadyUsed` error.
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct ClientResponse(Mutex<Option<ohttp::ClientResponse>>);
 
-impl From<&ClientResponse> for ohttp::ClientResponse {
-    fn from(value: &ClientResponse) -> Self {
-        let mut data_guard = value.0.lock().unwrap();
-        Option::take(&mut *data_guard).expect("ClientResponse moved out of memory")
+impl ClientResponse {
+    /// Take the OHTTP context out, or `None` if a response was already processed with it.
+    pub(crate) fn take(&self) -> Option<ohttp::ClientResponse> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take()
     }
 }
 
@@ -50,3 +153,22 @@ impl From<ohttp::ClientResponse> for ClientResponse {
         Self(Mutex::new(Some(value)))
     }
 }
+
+/// Size of every OHTTP encapsulated response from a payjoin directory, which pads its messages
+/// to a fixed length.
+pub(crate) const ENCAPSULATED_MESSAGE_BYTES: usize = 8192;
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl ClientResponse {
+    /// Cheaply check whether `body` could be the response this context decapsulates, without
+    /// decrypting it or consuming the context.
+    ///
+    /// Only the length of `body` and whether the context is still unused are checked. OHTTP
+    /// responses carry no key identifier and are bound to their request solely by encryption,
+    /// so a well-formed response meant for another context also matches. Decapsulation remains
+    /// the authoritative check; this only filters out bodies that cannot possibly succeed.
+    pub fn matches(&self, body: &[u8]) -> bool {
+        body.len() == ENCAPSULATED_MESSAGE_BYTES
+            && self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).is_some()
+    }
+}
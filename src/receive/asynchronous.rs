@@ -0,0 +1,174 @@
+//! Async variants of the receiver API for callers whose callbacks await I/O, such as a node RPC.
+//!
+//! The payjoin crate runs its checks with synchronous callbacks. Each variant here works out the
+//! scripts, outpoints or PSBT the check will ask about, awaits the async callback once for each,
+//! and hands the answers to the synchronous check.
+
+use super::{
+    ClientResponse, Error, ImplementationError, MaybeInputsOwned, MaybeInputsSeen, OutputsUnknown,
+    PayjoinProposal, ProvisionalProposal, Receiver, ReceiverResponse, ReplyableError, Request,
+    UncheckedProposal, WantsOutputs,
+};
+use crate::bitcoin_ffi::OutPoint;
+use crate::send::{HttpTransport, PollingPolicy, RunError};
+use crate::uri::Url;
+
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait CanBroadcastAsync: Send + Sync {
+    async fn callback(&self, tx: Vec<u8>) -> Result<bool, ImplementationError>;
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait IsScriptOwnedAsync: Send + Sync {
+    async fn callback(&self, script: Vec<u8>) -> Result<bool, ImplementationError>;
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait IsOutputKnownAsync: Send + Sync {
+    async fn callback(&self, outpoint: OutPoint) -> Result<bool, ImplementationError>;
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait ProcessPsbtAsync: Send + Sync {
+    async fn callback(&self, psbt: String) -> Result<String, ImplementationError>;
+}
+
+impl Receiver {
+    /// Async variant of [`Receiver::extract_req`].
+    pub async fn extract_req_async(
+        &self,
+        ohttp_relay: String,
+    ) -> Result<(Request, ClientResponse), Error> {
+        self.extract_req(ohttp_relay)
+    }
+
+    /// Async variant of [`Receiver::process_res`].
+    pub async fn process_res_async(
+        &self,
+        body: &[u8],
+        ctx: &ClientResponse,
+    ) -> Result<ReceiverResponse, Error> {
+        self.process_res(body, ctx)
+    }
+
+    /// Poll the directory through `ohttp_relay` for the sender's Original PSBT on the schedule
+    /// of `policy`, each poll encapsulated afresh, and return it once it arrives.
+    ///
+    /// Transient errors are retried on the same schedule, and any other error is returned at
+    /// once, including [`Error::SessionExpired`] once the session expires. Returns `None` once
+    /// the schedule ends without a proposal: the session can be polled again later.
+    ///
+    /// Dropping the returned future cancels polling between requests.
+    pub async fn poll_with_policy(
+        &self,
+        http: &dyn HttpTransport,
+        ohttp_relay: Url,
+        policy: &PollingPolicy,
+    ) -> Result<Option<UncheckedProposal>, RunError> {
+        let mut attempt = 0u32;
+        loop {
+            let result = async {
+                let (request, ctx) =
+                    self.extract_req(ohttp_relay.as_string()).map_err(RunError::Receive)?;
+                let response = crate::send::asynchronous::post(http, request).await?;
+                self.process_res(&response, &ctx).map_err(RunError::Receive)
+            };
+            let error = match result.await {
+                Ok(ReceiverResponse::Proposal(proposal)) => return Ok(Some(proposal)),
+                Ok(ReceiverResponse::SessionExpired { expired_at }) => {
+                    return Err(RunError::Receive(Error::SessionExpired { expired_at }))
+                }
+                Ok(ReceiverResponse::NoProposalYet) => None,
+                Err(error) => Some(error),
+            };
+            let delay = policy.next_delay(attempt, None);
+            if let Some(error) = error.filter(|e| delay.is_none() || !e.is_transient()) {
+                return Err(error);
+            }
+            let Some(delay) = delay else {
+                return Ok(None);
+            };
+            attempt = attempt.saturating_add(1);
+            http.sleep(delay).await;
+        }
+    }
+}
+
+impl UncheckedProposal {
+    /// Async variant of [`UncheckedProposal::check_broadcast_suitability`].
+    pub async fn check_broadcast_suitability_async(
+        &self,
+        min_fee_rate: Option<u64>,
+        can_broadcast: &dyn CanBroadcastAsync,
+    ) -> Result<MaybeInputsOwned, ReplyableError> {
+        let can_broadcast = can_broadcast.callback(self.extract_tx_to_schedule_broadcast()).await?;
+        self.check_broadcast_suitability(min_fee_rate, |_| Ok(can_broadcast))
+    }
+}
+
+impl MaybeInputsOwned {
+    /// Async variant of [`MaybeInputsOwned::check_inputs_not_owned`].
+    pub async fn check_inputs_not_owned_async(
+        &self,
+        is_owned: &dyn IsScriptOwnedAsync,
+    ) -> Result<MaybeInputsSeen, ReplyableError> {
+        let scripts = self.input_scripts()?;
+        let mut answers = Vec::with_capacity(scripts.len());
+        for script in &scripts {
+            answers.push(is_owned.callback(script.clone()).await?);
+        }
+        self.check_with_answers(&scripts, &answers)
+    }
+}
+
+impl MaybeInputsSeen {
+    /// Async variant of [`MaybeInputsSeen::check_no_inputs_seen_before`].
+    pub async fn check_no_inputs_seen_before_async(
+        &self,
+        is_known: &dyn IsOutputKnownAsync,
+    ) -> Result<OutputsUnknown, ReplyableError> {
+        let outpoints = self.input_outpoints();
+        let mut answers = Vec::with_capacity(outpoints.len());
+        for outpoint in &outpoints {
+            answers.push(is_known.callback((*outpoint).into()).await?);
+        }
+        self.check_with_answers(&outpoints, &answers)
+    }
+}
+
+impl OutputsUnknown {
+    /// Async variant of [`OutputsUnknown::identify_receiver_outputs`].
+    pub async fn identify_receiver_outputs_async(
+        &self,
+        is_receiver_output: &dyn IsScriptOwnedAsync,
+    ) -> Result<WantsOutputs, ReplyableError> {
+        let scripts = self.output_scripts();
+        let mut answers = Vec::with_capacity(scripts.len());
+        for script in &scripts {
+            answers.push(is_receiver_output.callback(script.to_bytes()).await?);
+        }
+        self.identify_with_answers(&scripts, &answers)
+    }
+}
+
+impl ProvisionalProposal {
+    /// Async variant of [`ProvisionalProposal::finalize_proposal`].
+    pub async fn finalize_proposal_async(
+        &self,
+        process_psbt: &dyn ProcessPsbtAsync,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        let psbt = self.preview_psbt(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)?;
+        let processed = process_psbt.callback(psbt.to_string()).await?;
+        self.finalize_proposal(
+            |_| Ok(processed.clone()),
+            min_feerate_sat_per_vb,
+            max_effective_fee_rate_sat_per_vb,
+        )
+    }
+}
@@ -0,0 +1,62 @@
+use super::{FallbackTransaction, SerdeJsonError};
+
+/// The Original PSBT's transaction paired with the time it must be broadcast if the payjoin
+/// doesn't complete.
+///
+/// Non-interactive receivers rely on broadcasting the fallback to make probing costly for
+/// senders. Persist it with [`ScheduledBroadcast::to_json`] alongside the session and check
+/// [`ScheduledBroadcast::should_broadcast_now`] on a timer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct ScheduledBroadcast {
+    tx: FallbackTransaction,
+    broadcast_at: u64,
+}
+
+impl ScheduledBroadcast {
+    pub(crate) fn new(tx: FallbackTransaction, broadcast_at: u64) -> Self {
+        Self { tx, broadcast_at }
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl ScheduledBroadcast {
+    /// Restore a schedule persisted with [`ScheduledBroadcast::to_json`].
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn from_json(json: &str) -> Result<Self, SerdeJsonError> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        Ok(Self {
+            tx: FallbackTransaction {
+                bytes: serde_json::from_value(value["tx"].clone())?,
+                txid: serde_json::from_value(value["txid"].clone())?,
+                weight: serde_json::from_value(value["weight"].clone())?,
+            },
+            broadcast_at: serde_json::from_value(value["broadcast_at"].clone())?,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, SerdeJsonError> {
+        serde_json::to_string(&serde_json::json!({
+            "tx": self.tx.bytes,
+            "txid": self.tx.txid,
+            "weight": self.tx.weight,
+            "broadcast_at": self.broadcast_at,
+        }))
+        .map_err(Into::into)
+    }
+
+    pub fn fallback_transaction(&self) -> FallbackTransaction {
+        self.tx.clone()
+    }
+
+    /// Unix timestamp in seconds from which the fallback should be broadcast.
+    pub fn broadcast_at(&self) -> u64 {
+        self.broadcast_at
+    }
+
+    /// Whether the fallback should be broadcast at unix time `now`, i.e. once `broadcast_at` is
+    /// reached.
+    pub fn should_broadcast_now(&self, now: u64) -> bool {
+        now >= self.broadcast_at
+    }
+}
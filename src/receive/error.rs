@@ -2,6 +2,8 @@ use std::sync::Arc;
 
 use payjoin::receive;
 
+use crate::error::SerdeJsonError;
+
 /// The top-level error type for the payjoin receiver
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -13,6 +15,16 @@ pub enum Error {
     /// V2-specific errors that are infeasable to reply to the sender
     #[error("Unreplyable error: {0}")]
     V2(Arc<SessionError>),
+    /// The session expired at the given unix timestamp and can no longer be polled
+    #[error("The session expired at unix time {expired_at}")]
+    SessionExpired { expired_at: u64 },
+    /// The sender's parameters could not be read from its proposal
+    #[error("The sender's parameters could not be read: {msg}")]
+    UnreadableSenderParams { msg: String },
+    /// The OHTTP context was already used to process a response. Each context processes
+    /// exactly one response; extract a new request to get another
+    #[error("The OHTTP context was already used to process a response")]
+    OhttpContextAlreadyUsed,
     /// Catch-all for unhandled error variants
     #[error("An unexpected error occurred")]
     Unexpected,
@@ -28,6 +40,177 @@ impl From<receive::Error> for Error {
     }
 }
 
+/// Error restoring a persisted receiver session
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum ReceiverPersistError {
+    /// The persisted session could not be decoded
+    #[error("Malformed session: {0}")]
+    Malformed(Arc<SerdeJsonError>),
+    /// The persisted session expired before it was restored
+    #[error("The session expired at unix time {expired_at}")]
+    Expired { expired_at: u64 },
+}
+
+impl From<serde_json::Error> for ReceiverPersistError {
+    fn from(value: serde_json::Error) -> Self {
+        ReceiverPersistError::Malformed(Arc::new(value.into()))
+    }
+}
+
+/// Error creating a receiver with fixed session keys
+#[cfg(feature = "test-utils")]
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiverKeysError {
+    /// The directory URL is invalid
+    #[error(transparent)]
+    Directory(#[from] crate::uri::error::IntoUrlError),
+    /// The secret key is not a valid secp256k1 secret key
+    #[error("Invalid session secret key")]
+    InvalidSecretKey,
+    /// The serialized session has no context with session keys and an expiry to replace
+    #[error("The session has no context with session keys and an expiry to replace")]
+    MissingContext,
+    /// The session could not be rebuilt with the keys
+    #[error("Malformed session: {0}")]
+    Malformed(#[from] serde_json::Error),
+}
+
+/// Error recording a review decision on a provisional proposal
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum ReviewError {
+    /// The reviewer declined the proposal
+    #[error("The proposal was rejected by its reviewer")]
+    Rejected,
+    /// The reviewed bundle does not describe the proposal as it is now
+    #[error("The reviewed bundle {reviewed} does not match the proposal bundle {actual}")]
+    BundleMismatch { reviewed: String, actual: String },
+    /// The proposal could not be prepared for review
+    #[error(transparent)]
+    Proposal(Arc<ReplyableError>),
+}
+
+impl From<ReplyableError> for ReviewError {
+    fn from(value: ReplyableError) -> Self {
+        ReviewError::Proposal(Arc::new(value))
+    }
+}
+
+/// Error ingesting a BIP78 POST request
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum V1RequestError {
+    /// A required header is absent
+    #[error("Missing header {name}")]
+    MissingHeader { name: String },
+    /// The body isn't declared as `text/plain`
+    #[error("Invalid content type {content_type}, expected text/plain")]
+    InvalidContentType { content_type: String },
+    /// The `content-length` header isn't a number
+    #[error("Invalid content length {value}")]
+    InvalidContentLength { value: String },
+    /// The body is larger than a payjoin PSBT can be
+    #[error("Content length {length} exceeds the maximum of {max}")]
+    ContentTooLarge { length: u64, max: u64 },
+    /// The `content-length` header doesn't match the body
+    #[error("Content length {declared} does not match the body length {actual}")]
+    ContentLengthMismatch { declared: u64, actual: u64 },
+    /// The sender asked for a protocol version other than 1
+    #[error("Unsupported payjoin version {version}")]
+    UnsupportedVersion { version: String },
+    /// A fee or output substitution parameter of the query string is malformed
+    #[error("Invalid value {value} for parameter {name}")]
+    InvalidParameter { name: String, value: String },
+    /// The Original PSBT was rejected
+    #[error(transparent)]
+    Proposal(Arc<ReplyableError>),
+}
+
+impl From<receive::ReplyableError> for V1RequestError {
+    fn from(value: receive::ReplyableError) -> Self {
+        V1RequestError::Proposal(Arc::new(value.into()))
+    }
+}
+
+/// Error checking a proposal's inputs against outpoints the receiver has seen before
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum InputSeenError {
+    /// The proposal spends `outpoint`, which was seen before. Reply to the sender with `reply`
+    #[error("The proposal spends the previously seen outpoint {outpoint:?}")]
+    Seen { outpoint: crate::bitcoin_ffi::OutPoint, reply: Arc<ReplyableError> },
+    /// The proposal could not be checked
+    #[error(transparent)]
+    Proposal(Arc<ReplyableError>),
+}
+
+/// Error checking a proposal with a callback that answers for all its inputs at once
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum BatchCheckError {
+    /// The callback returned a different number of answers than it was asked questions
+    #[error("Expected {expected} answers from the callback, got {actual}")]
+    AnswerCountMismatch { expected: u64, actual: u64 },
+    /// The proposal failed the check, or the callback failed
+    #[error(transparent)]
+    Proposal(Arc<ReplyableError>),
+}
+
+impl From<ReplyableError> for BatchCheckError {
+    fn from(value: ReplyableError) -> Self {
+        BatchCheckError::Proposal(Arc::new(value))
+    }
+}
+
+impl From<ImplementationError> for BatchCheckError {
+    fn from(value: ImplementationError) -> Self {
+        BatchCheckError::Proposal(Arc::new(value.into()))
+    }
+}
+
+/// Error finalizing a proposal whose signatures are collected in several steps
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum FinalizeError {
+    /// Some contributed receiver inputs are still missing signatures
+    #[error("{unsatisfied} receiver inputs are not finalized yet")]
+    Incomplete { unsatisfied: u64 },
+    /// The session expired before the proposal was completed
+    #[error("The session expired at unix time {expired_at}")]
+    SessionExpired { expired_at: u64 },
+    /// The pending signatures were collected for a different proposal
+    #[error("The pending signatures do not belong to this proposal")]
+    ProposalMismatch,
+    /// The persisted pending signatures could not be decoded
+    #[error("Malformed pending signatures: {0}")]
+    Malformed(Arc<SerdeJsonError>),
+    /// The wallet failed to process the PSBT
+    #[error(transparent)]
+    Implementation(Arc<ImplementationError>),
+    /// The proposal could not be finalized
+    #[error(transparent)]
+    Proposal(Arc<ReplyableError>),
+}
+
+impl From<ReplyableError> for FinalizeError {
+    fn from(value: ReplyableError) -> Self {
+        FinalizeError::Proposal(Arc::new(value))
+    }
+}
+
+impl From<ImplementationError> for FinalizeError {
+    fn from(value: ImplementationError) -> Self {
+        FinalizeError::Implementation(Arc::new(value))
+    }
+}
+
+impl From<serde_json::Error> for FinalizeError {
+    fn from(value: serde_json::Error) -> Self {
+        FinalizeError::Malformed(Arc::new(value.into()))
+    }
+}
+
 /// The replyable error type for the payjoin receiver, representing failures need to be
 /// returned to the sender.
 ///
@@ -67,17 +250,249 @@ impl From<ReplyableError> for JsonReply {
     }
 }
 
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl JsonReply {
+    /// The reply to send the sender when a receiver check fails with `error`.
+    ///
+    /// Only BIP78 well-known error codes are disclosed. Implementation errors are reported as
+    /// `unavailable` without their message, so internal details never reach the sender.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn from_replyable_error(error: Arc<ReplyableError>) -> Self {
+        Self((&error.0).into())
+    }
+
+    /// The reply as the JSON document sent to the sender.
+    pub fn to_json(&self) -> String {
+        self.0.to_json().to_string()
+    }
+}
+
+/// The error codes BIP78 defines for replies to the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum WellKnownError {
+    /// The payjoin endpoint is not available for now.
+    Unavailable,
+    /// The receiver added inputs, but the sender's fee contribution can't pay for them.
+    NotEnoughMoney,
+    /// The payjoin version requested by the sender isn't supported.
+    VersionUnsupported,
+    /// The receiver rejected the Original PSBT.
+    OriginalPsbtRejected,
+}
+
+impl From<WellKnownError> for payjoin::error_codes::ErrorCode {
+    fn from(value: WellKnownError) -> Self {
+        use payjoin::error_codes::ErrorCode;
+        match value {
+            WellKnownError::Unavailable => ErrorCode::Unavailable,
+            WellKnownError::NotEnoughMoney => ErrorCode::NotEnoughMoney,
+            WellKnownError::VersionUnsupported => ErrorCode::VersionUnsupported,
+            WellKnownError::OriginalPsbtRejected => ErrorCode::OriginalPsbtRejected,
+        }
+    }
+}
+
+impl From<payjoin::error_codes::ErrorCode> for WellKnownError {
+    fn from(value: payjoin::error_codes::ErrorCode) -> Self {
+        use payjoin::error_codes::ErrorCode;
+        match value {
+            ErrorCode::NotEnoughMoney => WellKnownError::NotEnoughMoney,
+            ErrorCode::VersionUnsupported => WellKnownError::VersionUnsupported,
+            ErrorCode::OriginalPsbtRejected => WellKnownError::OriginalPsbtRejected,
+            ErrorCode::Unavailable => WellKnownError::Unavailable,
+        }
+    }
+}
+
+/// The HTTP response a BIP78 receiver sends when it rejects a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ReceiverErrorResponse {
+    /// 500 for `unavailable`, 400 for the other error codes.
+    pub status_code: u16,
+    /// The [`JsonReply`] as UTF-8 JSON, served as `application/json`.
+    pub body: Vec<u8>,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl JsonReply {
+    /// A reply with one of the BIP78 well-known error codes and a custom `message`.
+    ///
+    /// `message` is sanitized and capped as described in [`MAX_REPLY_MESSAGE_CHARS`].
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn well_known(error_code: WellKnownError, message: String) -> Self {
+        Self(receive::JsonReply::new(error_code.into(), sanitize_message(&message)))
+    }
+
+    /// The well-known error code of this reply.
+    pub fn error_code(&self) -> WellKnownError {
+        self.0.to_json()["errorCode"]
+            .as_str()
+            .and_then(|code| code.parse::<payjoin::error_codes::ErrorCode>().ok())
+            .map(Into::into)
+            .unwrap_or(WellKnownError::Unavailable)
+    }
+
+    /// The HTTP status code and body to answer a BIP78 request with.
+    pub fn to_bip78_response(&self) -> ReceiverErrorResponse {
+        let status_code = match self.error_code() {
+            WellKnownError::Unavailable => 500,
+            _ => 400,
+        };
+        ReceiverErrorResponse { status_code, body: self.to_json().into_bytes() }
+    }
+}
+
+/// Maximum number of characters of a custom message placed in a [`JsonReply`].
+///
+/// Longer messages are truncated rather than rejected.
+pub const MAX_REPLY_MESSAGE_CHARS: usize = 256;
+
+impl JsonReply {
+    /// The reply to send the sender when a v1 request is rejected with `error`.
+    ///
+    /// A version other than 1 is reported as `version-unsupported`, malformed headers and
+    /// parameters as `original-psbt-rejected`.
+    pub fn from_v1_request_error(error: &V1RequestError) -> Self {
+        match error {
+            V1RequestError::Proposal(error) => Self::from_replyable_error(error.clone()),
+            V1RequestError::UnsupportedVersion { .. } => {
+                Self::well_known(
+                    WellKnownError::VersionUnsupported,
+                    "This version of payjoin is not supported.".to_string(),
+                )
+            }
+            error => Self::well_known(WellKnownError::OriginalPsbtRejected, error.to_string()),
+        }
+    }
+
+    /// Replace the human readable message of this reply, keeping its error code.
+    ///
+    /// Control characters in `message` are replaced with spaces and it is truncated to
+    /// [`MAX_REPLY_MESSAGE_CHARS`] characters.
+    pub fn with_message(&self, message: &str) -> Self {
+        Self::well_known(self.error_code(), message.to_string())
+    }
+}
+
+fn sanitize_message(message: &str) -> String {
+    message
+        .chars()
+        .take(MAX_REPLY_MESSAGE_CHARS)
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
 /// Error arising due to the specific receiver implementation
 ///
 /// e.g. database errors, network failures, wallet errors
-#[derive(Debug, thiserror::Error)]
-#[error(transparent)]
+///
+/// An error returned from a callback is carried through the receiver unchanged, and can be
+/// recovered with [`ReplyableError::implementation_error`].
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{inner}")]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
-pub struct ImplementationError(#[from] receive::ImplementationError);
+pub struct ImplementationError {
+    inner: Arc<receive::ImplementationError>,
+    code: Option<String>,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl ImplementationError {
+    /// An error described by `message`, with an optional machine readable `code`, e.g.
+    /// `"rpc-timeout"`, to tell failures apart once the error is returned from the outer call.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn new(message: String, code: Option<String>) -> Self {
+        Self { inner: Arc::new(message.into()), code }
+    }
+
+    pub fn message(&self) -> String {
+        self.inner.to_string()
+    }
+
+    pub fn code(&self) -> Option<String> {
+        self.code.clone()
+    }
+}
+
+/// The code of the [`ImplementationError`] raised when a `process_psbt` callback returns a PSBT
+/// that can't be parsed.
+pub const INVALID_CALLBACK_PSBT: &str = "invalid-callback-psbt";
+
+/// The code of the [`ImplementationError`] raised when a fee rate given in sat/vB overflows
+/// once converted to sat/kwu.
+pub const INVALID_FEE_RATE: &str = "invalid-fee-rate";
+
+impl From<crate::error::FeeRateError> for ImplementationError {
+    fn from(value: crate::error::FeeRateError) -> Self {
+        Self { inner: Arc::new(Box::new(value)), code: Some(INVALID_FEE_RATE.to_string()) }
+    }
+}
+
+/// The PSBT returned from a `process_psbt` callback could not be parsed.
+#[derive(Debug, thiserror::Error)]
+#[error("The PSBT returned by the process_psbt callback is invalid: {0}")]
+pub struct CallbackPsbtError(String);
+
+impl From<CallbackPsbtError> for ImplementationError {
+    fn from(value: CallbackPsbtError) -> Self {
+        Self { inner: Arc::new(Box::new(value)), code: Some(INVALID_CALLBACK_PSBT.to_string()) }
+    }
+}
+
+impl ImplementationError {
+    /// Whether this error is a [`CallbackPsbtError`].
+    pub fn is_invalid_callback_psbt(&self) -> bool {
+        self.inner.downcast_ref::<CallbackPsbtError>().is_some()
+    }
+
+    pub(crate) fn invalid_callback_psbt(error: impl std::fmt::Display) -> Self {
+        CallbackPsbtError(error.to_string()).into()
+    }
+}
+
+impl From<receive::ImplementationError> for ImplementationError {
+    fn from(value: receive::ImplementationError) -> Self {
+        match value.downcast::<ImplementationError>() {
+            Ok(error) => *error,
+            Err(value) => Self { inner: Arc::new(value), code: None },
+        }
+    }
+}
 
 impl From<String> for ImplementationError {
     fn from(value: String) -> Self {
-        Self(value.into())
+        Self::new(value, None)
+    }
+}
+
+impl From<ImplementationError> for ReplyableError {
+    fn from(value: ImplementationError) -> Self {
+        Self(receive::ReplyableError::Implementation(value.into()))
+    }
+}
+
+impl ReplyableError {
+    fn implementation(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        match &self.0 {
+            receive::ReplyableError::Implementation(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl ReplyableError {
+    /// The error a callback failed with, if this error was caused by one.
+    ///
+    /// Errors returned from callbacks come back as they were raised, code included.
+    pub fn implementation_error(&self) -> Option<Arc<ImplementationError>> {
+        let error = self.implementation()?;
+        Some(Arc::new(match error.downcast_ref::<ImplementationError>() {
+            Some(error) => error.clone(),
+            None => error.to_string().into(),
+        }))
     }
 }
 
@@ -89,9 +504,65 @@ pub struct SessionError(#[from] receive::v2::SessionError);
 
 /// Error that may occur when output substitution fails.
 #[derive(Debug, thiserror::Error)]
-#[error(transparent)]
-#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
-pub struct OutputSubstitutionError(#[from] receive::OutputSubstitutionError);
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum OutputSubstitutionError {
+    /// The sender disabled output substitution, so the receiver's output scripts can't be
+    /// changed
+    #[error("Output substitution was disabled by the sender")]
+    Disabled,
+    /// No replacement outputs were given to take the place of the receiver's outputs
+    #[error("No replacement outputs were given for the receiver's outputs")]
+    NoReplacementOutputs,
+    /// Fewer replacement outputs were given than the receiver has outputs, which payjoin can't
+    /// substitute
+    #[error("Fewer replacement outputs were given than the receiver has outputs")]
+    NotEnoughOutputs,
+    /// None of the replacement outputs pays to the drain script
+    #[error("The drain script isn't the script of any replacement output")]
+    InvalidDrainScript,
+    /// The replacement outputs pay the receiver less than the Original PSBT while the sender
+    /// disabled output substitution
+    #[error("Replacement outputs pay {replacement} sats, less than the original {original} sats")]
+    DecreasedValue { original: u64, replacement: u64 },
+    /// payjoin rejected a replacement output paying the receiver less than the output it
+    /// replaces while the sender disabled output substitution
+    #[error("A receiver output's value was decreased while output substitution is disabled")]
+    DecreasedValueWhenDisabled,
+    /// A replacement output is below the dust limit of its script
+    #[error("Replacement output {index} of {value} sats is dust")]
+    DustOutput { index: u32, value: u64 },
+    /// payjoin rejected the substitution for a reason this version doesn't know of
+    #[error("Invalid output substitution: {msg}")]
+    Invalid { msg: String },
+    /// The callback generating the replacement outputs failed
+    #[error(transparent)]
+    Implementation(Arc<ImplementationError>),
+}
+
+impl From<ImplementationError> for OutputSubstitutionError {
+    fn from(value: ImplementationError) -> Self {
+        OutputSubstitutionError::Implementation(Arc::new(value))
+    }
+}
+
+/// payjoin keeps the reasons for rejecting a substitution private, but names them in its
+/// `Debug` output.
+impl From<receive::OutputSubstitutionError> for OutputSubstitutionError {
+    fn from(value: receive::OutputSubstitutionError) -> Self {
+        let reason = format!("{value:?}");
+        if reason.contains("ScriptPubKeyChangedWhenDisabled") {
+            OutputSubstitutionError::Disabled
+        } else if reason.contains("DecreasedValueWhenDisabled") {
+            OutputSubstitutionError::DecreasedValueWhenDisabled
+        } else if reason.contains("NotEnoughOutputs") {
+            OutputSubstitutionError::NotEnoughOutputs
+        } else if reason.contains("InvalidDrainScript") {
+            OutputSubstitutionError::InvalidDrainScript
+        } else {
+            OutputSubstitutionError::Invalid { msg: value.to_string() }
+        }
+    }
+}
 
 /// Error that may occur when coin selection fails.
 #[derive(Debug, thiserror::Error)]
@@ -110,3 +581,33 @@ pub struct InputContributionError(#[from] receive::InputContributionError);
 #[error(transparent)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct PsbtInputError(#[from] receive::PsbtInputError);
+
+/// Error contributing a legacy receiver input from its previous transaction
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum NonWitnessInputError {
+    /// The previous transaction could not be decoded
+    #[error("Invalid previous transaction: {msg}")]
+    InvalidTransaction { msg: String },
+    /// The previous transaction does not create the spent output
+    #[error("The previous transaction {txid} does not create output {vout}")]
+    OutpointMismatch { txid: String, vout: u32 },
+    /// The spent output is not a supported input type
+    #[error(transparent)]
+    PsbtInput(Arc<PsbtInputError>),
+    /// The input could not be added to the proposal
+    #[error(transparent)]
+    Contribution(Arc<InputContributionError>),
+}
+
+impl From<PsbtInputError> for NonWitnessInputError {
+    fn from(value: PsbtInputError) -> Self {
+        NonWitnessInputError::PsbtInput(Arc::new(value))
+    }
+}
+
+impl From<InputContributionError> for NonWitnessInputError {
+    fn from(value: InputContributionError) -> Self {
+        NonWitnessInputError::Contribution(Arc::new(value))
+    }
+}
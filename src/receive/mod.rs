@@ -1,25 +1,50 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+pub use broadcast::ScheduledBroadcast;
+#[cfg(feature = "test-utils")]
+pub use error::ReceiverKeysError;
 pub use error::{
-    Error, ImplementationError, InputContributionError, JsonReply, OutputSubstitutionError,
-    PsbtInputError, ReplyableError, SelectionError, SessionError,
+    BatchCheckError, CallbackPsbtError, Error, FinalizeError, ImplementationError,
+    InputContributionError, InputSeenError, JsonReply, NonWitnessInputError,
+    OutputSubstitutionError, PsbtInputError, ReceiverErrorResponse, ReceiverPersistError,
+    ReplyableError, ReviewError, SelectionError, SessionError, V1RequestError, WellKnownError,
+    INVALID_CALLBACK_PSBT, INVALID_FEE_RATE, MAX_REPLY_MESSAGE_CHARS,
 };
+use payjoin::bitcoin::hashes::{sha256, Hash};
 use payjoin::bitcoin::psbt::Psbt;
-use payjoin::bitcoin::FeeRate;
+use payjoin::bitcoin::{Amount, FeeRate, ScriptBuf};
+use proposal::ProposalContext;
+pub use proposal::{FeePreview, FeeSplit, LockedUtxo, SenderParams};
+pub use seen_inputs::{InMemorySeenInputs, SeenInputsStore};
 
 use crate::bitcoin_ffi::{Address, OutPoint, Script, TxOut};
+use crate::directory::DirectoryCapabilities;
 pub use crate::error::SerdeJsonError;
 use crate::ohttp::OhttpKeys;
+use crate::record::PayjoinTxRecord;
 use crate::uri::error::IntoUrlError;
+use crate::uri::{fragment, InvalidAmount, PjUriBuilder, Url};
 use crate::{ClientResponse, Request};
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+mod broadcast;
 pub mod error;
+mod proposal;
+mod seen_inputs;
+pub mod session;
 #[cfg(feature = "uniffi")]
 pub mod uni;
+pub mod v1;
 
+/// A v2 receiver session, with where its OHTTP keys came from and the signatures collected for
+/// its proposal, if any, in the form [`PendingSignatures::to_json`] writes them.
 #[derive(Clone, Debug)]
-pub struct Receiver(pub payjoin::receive::v2::Receiver);
+pub struct Receiver(pub payjoin::receive::v2::Receiver, KeysSource, Option<serde_json::Value>);
 impl From<Receiver> for payjoin::receive::v2::Receiver {
     fn from(value: Receiver) -> Self {
         value.0
@@ -28,15 +53,54 @@ impl From<Receiver> for payjoin::receive::v2::Receiver {
 
 impl From<payjoin::receive::v2::Receiver> for Receiver {
     fn from(value: payjoin::receive::v2::Receiver) -> Self {
-        Self(value)
+        Self(value, KeysSource::Provided, None)
     }
 }
 
+/// Where a [`Receiver`]'s OHTTP keys came from, so operators can tell how stale they are.
+///
+/// Timestamps are unix times in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum KeysSource {
+    /// Fetched from the directory when the session was created
+    Fetched { at: u64 },
+    /// Supplied by the application when the session was created
+    Provided,
+    /// Replaced with [`Receiver::refresh_ohttp_keys`]
+    Refreshed { at: u64 },
+}
+
+impl KeysSource {
+    fn to_json(self) -> serde_json::Value {
+        match self {
+            KeysSource::Fetched { at } => serde_json::json!({ "fetched": at }),
+            KeysSource::Provided => serde_json::json!("provided"),
+            KeysSource::Refreshed { at } => serde_json::json!({ "refreshed": at }),
+        }
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        if let Some(at) = value["fetched"].as_u64() {
+            return Some(KeysSource::Fetched { at });
+        }
+        if let Some(at) = value["refreshed"].as_u64() {
+            return Some(KeysSource::Refreshed { at });
+        }
+        (value.as_str()? == "provided").then_some(KeysSource::Provided)
+    }
+}
+
+/// Number of characters of a session id kept by [`Receiver::short_id`].
+pub const SHORT_ID_CHARS: usize = 8;
+
 impl Receiver {
     /// Creates a new `SessionInitializer` with the provided parameters.
     ///
     /// # Parameters
-    /// - `address`: The Bitcoin address for the payjoin session.
+    /// - `address`: The Bitcoin address for the payjoin session. `Address::new` checks the
+    ///   address against the network it is given, so an address can't be paired with the wrong
+    ///   network here.
     /// - `directory`: The URL of the store-and-forward payjoin directory.
     /// - `ohttp_keys`: The OHTTP keys used for encrypting and decrypting HTTP requests and responses.
     /// - `ohttp_relay`: The URL of the OHTTP relay, used to keep client IP address confidential.
@@ -63,7 +127,72 @@ impl Receiver {
         .map_err(Into::into)
     }
 
+    /// Like [`Receiver::new`], but with the session key derived from `secret_key` and the
+    /// session expiring at unix time `expiry_at`, so the session id and pj URI are the same on
+    /// every run.
+    ///
+    /// Only for tests: a session key that isn't random lets anyone who knows it read the
+    /// session's messages.
+    ///
+    /// payjoin has no constructor taking the session keys, so they are set in the serialized
+    /// session of a receiver created with [`Receiver::new`], failing with
+    /// [`ReceiverKeysError::MissingContext`] or [`ReceiverKeysError::Malformed`] if it doesn't
+    /// have the expected shape.
+    #[cfg(feature = "test-utils")]
+    pub fn new_with_keys(
+        address: Address,
+        directory: String,
+        ohttp_keys: OhttpKeys,
+        secret_key: [u8; 32],
+        expiry_at: u64,
+    ) -> Result<Self, ReceiverKeysError> {
+        use payjoin::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+        let secret =
+            SecretKey::from_slice(&secret_key).map_err(|_| ReceiverKeysError::InvalidSecretKey)?;
+        let receiver = Self::new(address, directory, ohttp_keys, None)?;
+        let mut value = serde_json::to_value(&receiver.0)?;
+        let public = PublicKey::from_secret_key(&Secp256k1::new(), &secret);
+        // Keep the encoding of the generated key pair, whose public key may be compressed or not
+        let compressed = value["context"]["s"][1].as_array().is_some_and(|pk| pk.len() == 33);
+        let public = match compressed {
+            true => public.serialize().to_vec(),
+            false => public.serialize_uncompressed().to_vec(),
+        };
+        let expiry = serde_json::to_value(UNIX_EPOCH + Duration::from_secs(expiry_at))?;
+        let context = value
+            .get_mut("context")
+            .and_then(serde_json::Value::as_object_mut)
+            .ok_or(ReceiverKeysError::MissingContext)?;
+        // Fields payjoin no longer has would be ignored, leaving the session with random keys
+        let has_keys =
+            context.get("s").and_then(serde_json::Value::as_array).is_some_and(|s| s.len() == 2);
+        if !has_keys || !context.contains_key("expiry") {
+            return Err(ReceiverKeysError::MissingContext);
+        }
+        context
+            .insert("s".to_string(), serde_json::json!([secret.secret_bytes().to_vec(), public]));
+        context.insert("expiry".to_string(), expiry);
+        Ok(Self(serde_json::from_value(value)?, receiver.1, receiver.2))
+    }
+
+    /// Like [`Receiver::new`], but caps `expire_after` at the session lifetime `capabilities`
+    /// allows. The effective expiry is then available from [`Receiver::expiration`].
+    pub fn new_for_directory(
+        address: Address,
+        directory: String,
+        ohttp_keys: OhttpKeys,
+        expire_after: Option<u64>,
+        capabilities: &DirectoryCapabilities,
+    ) -> Result<Self, IntoUrlError> {
+        let expire_after = capabilities.effective_expire_after(expire_after);
+        Self::new(address, directory, ohttp_keys, Some(expire_after))
+    }
+
     pub fn extract_req(&self, ohttp_relay: String) -> Result<(Request, ClientResponse), Error> {
+        if let Some(expired_at) = self.expired_at() {
+            return Err(Error::SessionExpired { expired_at });
+        }
         self.0
             .clone()
             .extract_req(ohttp_relay)
@@ -71,48 +200,296 @@ impl Receiver {
             .map_err(Into::into)
     }
 
-    ///The response can either be an UncheckedProposal or an ACCEPTED message indicating no UncheckedProposal is available yet.
+    /// Like [`Receiver::extract_req`], for a poll request the directory may hold open for up to
+    /// `wait_secs` seconds until the sender's proposal arrives.
+    ///
+    /// The directory decides how long it holds a poll open, as the encapsulated request has no
+    /// field for it. `wait_secs` bounds how long the caller waits and is returned as the
+    /// request's [`Request::timeout_secs`], shortened to the time left before the session
+    /// expires. If `capabilities` says the directory doesn't long-poll, this is a plain poll
+    /// request as from [`Receiver::extract_req`].
+    pub fn extract_req_with_wait(
+        &self,
+        ohttp_relay: String,
+        wait_secs: u64,
+        capabilities: &DirectoryCapabilities,
+    ) -> Result<(Request, ClientResponse), Error> {
+        let (mut request, ctx) = self.extract_req(ohttp_relay)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let remaining = self.expiration().map(|expiry| expiry.saturating_sub(now));
+        request.timeout_secs = capabilities.poll_timeout_secs(wait_secs, remaining);
+        Ok((request, ctx))
+    }
+
+    /// Process the directory's response to a poll request.
+    ///
+    /// The response is either the sender's proposal or an ACCEPTED message indicating no
+    /// proposal is available yet. Once the session has expired the response is not processed and
+    /// [`ReceiverResponse::SessionExpired`] tells the caller to stop polling. Unexpected
+    /// directory responses are reported as [`Error::V2`].
     pub fn process_res(
         &self,
         body: &[u8],
         ctx: &ClientResponse,
-    ) -> Result<Option<UncheckedProposal>, Error> {
-        <Self as Into<payjoin::receive::v2::Receiver>>::into(self.clone())
-            .process_res(body, ctx.into())
-            .map(|e| e.map(|o| o.into()))
-            .map_err(Into::into)
+    ) -> Result<ReceiverResponse, Error> {
+        if let Some(expired_at) = self.expired_at() {
+            return Ok(ReceiverResponse::SessionExpired { expired_at });
+        }
+        let ctx = ctx.take().ok_or(Error::OhttpContextAlreadyUsed)?;
+        let proposal = <Self as Into<payjoin::receive::v2::Receiver>>::into(self.clone())
+            .process_res(body, ctx)?;
+        let Some(proposal) = proposal else {
+            return Ok(ReceiverResponse::NoProposalYet);
+        };
+        let params = SenderParams::from_v2_proposal(&proposal)
+            .map_err(|e| Error::UnreadableSenderParams { msg: e.to_string() })?;
+        Ok(ReceiverResponse::Proposal(UncheckedProposal(proposal, self.expiration(), params)))
     }
 
-    /// Build a V2 Payjoin URI from the receiver's context
-    pub fn pj_uri(&self) -> crate::PjUri {
+    /// Build a V2 Payjoin URI from the receiver's context, ready to render as a QR code.
+    ///
+    /// `amount_sats`, `label` and `message` become the URI's BIP21 parameters, alongside the
+    /// same `pj` parameters the receiver always advertises. Amounts above
+    /// [`crate::uri::MAX_AMOUNT_SATS`] are rejected. See [`Receiver::pj_uri_builder`] to set
+    /// them one by one.
+    pub fn pj_uri(
+        &self,
+        amount_sats: Option<u64>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> Result<crate::PjUri, InvalidAmount> {
+        let mut builder = Arc::new(self.pj_uri_builder());
+        if let Some(sats) = amount_sats {
+            builder = builder.amount(sats)?;
+        }
+        if let Some(label) = label {
+            builder = builder.label(label);
+        }
+        if let Some(message) = message {
+            builder = builder.message(message);
+        }
+        Ok(builder.build())
+    }
+
+    /// The session's pj URI with no amount, label or message: the address and the `pj` endpoint
+    /// with its OHTTP keys and expiry. The same as [`Receiver::pj_uri_builder`] builds when no
+    /// parameter is set.
+    pub fn session_pj_uri(&self) -> crate::PjUri {
+        self.pj_uri_builder().build()
+    }
+
+    /// A builder for the session's pj URI, with the `pj` parameters the receiver always
+    /// advertises and no amount, label or message yet.
+    pub fn pj_uri_builder(&self) -> PjUriBuilder {
         <Self as Into<payjoin::receive::v2::Receiver>>::into(self.clone()).pj_uri().into()
     }
 
+    /// The session's pj URI requesting `amount_sats`, e.g. for the next invoice of a point of
+    /// sale that reuses one session.
+    ///
+    /// The `pj` endpoint and its parameters are the same as in every other URI of the session.
+    /// Zero amounts and amounts above [`crate::uri::MAX_AMOUNT_SATS`] are rejected.
+    pub fn update_pj_uri(&self, amount_sats: u64) -> Result<crate::PjUri, InvalidAmount> {
+        if amount_sats == 0 {
+            return Err(InvalidAmount {
+                input: amount_sats.to_string(),
+                reason: "zero amount".to_string(),
+            });
+        }
+        self.pj_uri(Some(amount_sats), None, None)
+    }
+
     ///The per-session public key to use as an identifier
+    ///
+    /// This is the short id derived from the session public key that names the session's
+    /// mailbox on the directory, and it appears as the last path segment of the pj URL.
     pub fn id(&self) -> String {
         <Self as Into<payjoin::receive::v2::Receiver>>::into(self.clone()).id().to_string()
     }
 
+    /// The session public key [`Receiver::id`] is derived from, in compressed SEC1 encoding.
+    ///
+    /// Read from the `RK` parameter of the session's pj URI, so `None` only if payjoin leaves
+    /// it out.
+    pub fn id_bytes(&self) -> Option<Vec<u8>> {
+        fragment::receiver_key(&self.endpoint()).map(|key| key.to_vec())
+    }
+
+    /// The first [`SHORT_ID_CHARS`] characters of [`Receiver::id`], for logs.
+    ///
+    /// It is a prefix of the pj URL's last path segment, so log lines can be matched to the
+    /// URL a sender was given.
+    pub fn short_id(&self) -> String {
+        self.id().chars().take(SHORT_ID_CHARS).collect()
+    }
+
+    /// The payjoin directory the session's messages are relayed through: the session's pj
+    /// endpoint without the mailbox it names.
+    pub fn directory(&self) -> Url {
+        let endpoint = self.endpoint();
+        endpoint.join(".").unwrap_or(endpoint).into()
+    }
+
+    /// The directory's OHTTP keys the session encapsulates its requests to.
+    ///
+    /// Read from the `OH` parameter of the session's pj URI, so `None` only if payjoin leaves
+    /// it out.
+    pub fn ohttp_keys(&self) -> Option<OhttpKeys> {
+        fragment::ohttp_keys(&self.endpoint()).map(Into::into)
+    }
+
+    /// The session's pj endpoint, whose fragment carries the session parameters.
+    fn endpoint(&self) -> payjoin::Url {
+        <Self as Into<payjoin::receive::v2::Receiver>>::into(self.clone())
+            .pj_uri()
+            .extras
+            .endpoint()
+            .clone()
+    }
+
+    /// Where the session's OHTTP keys came from.
+    pub fn keys_source(&self) -> KeysSource {
+        self.1
+    }
+
+    /// Record that the OHTTP keys this receiver was created with were fetched from the
+    /// directory at unix time `at`.
+    pub fn with_keys_fetched_at(&self, at: u64) -> Self {
+        Self(self.0.clone(), KeysSource::Fetched { at }, self.2.clone())
+    }
+
+    /// Replace the session's OHTTP keys, e.g. after the directory rotated them, without
+    /// starting a new session.
+    ///
+    /// `now` is the unix time recorded as [`KeysSource::Refreshed`].
+    pub fn refresh_ohttp_keys(
+        &self,
+        ohttp_keys: OhttpKeys,
+        now: u64,
+    ) -> Result<Self, SerdeJsonError> {
+        let mut receiver = serde_json::to_value(&self.0)?;
+        // Check payjoin still keeps the keys there, as a field it no longer has would be ignored
+        let old_keys = receiver.pointer_mut("/context/ohttp_keys").map(serde_json::Value::take);
+        serde_json::from_value::<payjoin::OhttpKeys>(old_keys.unwrap_or_default())?;
+        receiver["context"]["ohttp_keys"] = serde_json::to_value(&ohttp_keys.0)?;
+        Ok(Self(
+            serde_json::from_value(receiver)?,
+            KeysSource::Refreshed { at: now },
+            self.2.clone(),
+        ))
+    }
+
     pub fn to_json(&self) -> Result<String, SerdeJsonError> {
-        serde_json::to_string(&self.0).map_err(Into::into)
+        match (self.1, &self.2) {
+            (KeysSource::Provided, None) => serde_json::to_string(&self.0),
+            (source, pending) => {
+                let mut value = serde_json::json!({
+                    "receiver": serde_json::to_value(&self.0)?,
+                    "keys_source": source.to_json(),
+                });
+                if let Some(pending) = pending {
+                    value["pending_signatures"] = pending.clone();
+                }
+                serde_json::to_string(&value)
+            }
+        }
+        .map_err(Into::into)
     }
 
-    pub fn from_json(json: &str) -> Result<Self, SerdeJsonError> {
-        serde_json::from_str::<payjoin::receive::v2::Receiver>(json)
-            .map_err(Into::into)
-            .map(Into::into)
+    /// Restore a receiver persisted with [`Receiver::to_json`].
+    ///
+    /// The restored receiver resumes polling with a fresh [`Receiver::extract_req`]. Sessions
+    /// that have already expired are rejected.
+    ///
+    /// The [`ClientResponse`] of a request extracted before the receiver was persisted can't be
+    /// persisted with it, so a response to that request is lost with the old process. The
+    /// session itself holds no per-request state, and the directory keeps the sender's message
+    /// until the session expires, so the fresh request receives it again.
+    pub fn from_json(json: &str) -> Result<Self, ReceiverPersistError> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let keys_source = KeysSource::from_json(&value["keys_source"]);
+        let mut pending = None;
+        if keys_source.is_some() {
+            pending = value.get_mut("pending_signatures").map(serde_json::Value::take);
+            value = value["receiver"].take();
+        }
+        let receiver = Self(
+            serde_json::from_value(value)?,
+            keys_source.unwrap_or(KeysSource::Provided),
+            pending,
+        );
+        match receiver.expired_at() {
+            Some(expired_at) => Err(ReceiverPersistError::Expired { expired_at }),
+            None => Ok(receiver),
+        }
+    }
+
+    /// Keep the signatures collected so far with the session, so [`Receiver::to_json`] persists
+    /// them and a restored receiver can [`Receiver::resume_finalize`].
+    pub fn with_pending_signatures(&self, pending: &PendingSignatures) -> Self {
+        Self(self.0.clone(), self.1, Some(pending.to_value()))
+    }
+
+    /// Resume finalizing `proposal` from the signatures persisted with the session, or `None` if
+    /// there are none.
+    ///
+    /// After a restore, poll the directory again for the sender's proposal and take it through
+    /// the same checks and contributions to get `proposal`. It must have the same inputs and
+    /// outputs as the proposal the signatures were collected for.
+    pub fn resume_finalize(
+        &self,
+        proposal: &ProvisionalProposal,
+    ) -> Result<Option<PendingSignatures>, FinalizeError> {
+        self.2.as_ref().map(|pending| proposal.resume_from_value(pending)).transpose()
+    }
+
+    /// The unix timestamp in seconds at which the session expires.
+    ///
+    /// Read from the `EX` parameter of the session's pj URI, so `None` only if payjoin leaves it
+    /// out.
+    pub fn expiration(&self) -> Option<u64> {
+        fragment::expiry(&self.endpoint())
+    }
+
+    /// Whether the session has expired, after which the directory will no longer relay it.
+    pub fn is_expired(&self) -> bool {
+        self.expired_at().is_some()
+    }
+
+    /// The expiration timestamp if the session has already expired.
+    fn expired_at(&self) -> Option<u64> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.expiration().filter(|expired_at| *expired_at <= now)
     }
 }
 
+/// The outcome of polling the directory for the sender's proposal.
 #[derive(Clone)]
-pub struct UncheckedProposal(payjoin::receive::v2::UncheckedProposal);
+pub enum ReceiverResponse {
+    /// The sender's proposal arrived.
+    Proposal(UncheckedProposal),
+    /// The directory has no proposal yet. Poll again.
+    NoProposalYet,
+    /// The session expired at the given unix timestamp. Stop polling.
+    SessionExpired { expired_at: u64 },
+}
 
-impl From<payjoin::receive::v2::UncheckedProposal> for UncheckedProposal {
-    fn from(value: payjoin::receive::v2::UncheckedProposal) -> Self {
-        Self(value)
-    }
+/// The Original PSBT's transaction, to broadcast if the payjoin fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct FallbackTransaction {
+    /// The consensus-encoded transaction.
+    pub bytes: Vec<u8>,
+    pub txid: String,
+    /// The transaction weight in weight units.
+    pub weight: u64,
 }
 
+/// The sender's proposal, the unix time at which the receiver's session expires, if known, and
+/// the sender's parameters.
+#[derive(Clone)]
+pub struct UncheckedProposal(payjoin::receive::v2::UncheckedProposal, Option<u64>, SenderParams);
+
 impl From<UncheckedProposal> for payjoin::receive::v2::UncheckedProposal {
     fn from(value: UncheckedProposal) -> Self {
         value.0
@@ -120,6 +497,13 @@ impl From<UncheckedProposal> for payjoin::receive::v2::UncheckedProposal {
 }
 
 impl UncheckedProposal {
+    /// Whether the sender disabled output substitution, known as soon as the proposal arrives.
+    ///
+    /// A receiver that would substitute its output can skip deriving a new address if so.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.2.output_substitution_disabled
+    }
+
     ///The Sender’s Original PSBT
     pub fn extract_tx_to_schedule_broadcast(&self) -> Vec<u8> {
         payjoin::bitcoin::consensus::encode::serialize(
@@ -127,20 +511,67 @@ impl UncheckedProposal {
         )
     }
 
+    /// The Original PSBT's transaction together with the details needed to schedule its
+    /// broadcast, so callers don't have to parse [`Self::extract_tx_to_schedule_broadcast`].
+    pub fn fallback_transaction(&self) -> FallbackTransaction {
+        let tx = self.0.clone().extract_tx_to_schedule_broadcast();
+        FallbackTransaction {
+            bytes: payjoin::bitcoin::consensus::encode::serialize(&tx),
+            txid: tx.compute_txid().to_string(),
+            weight: tx.weight().to_wu(),
+        }
+    }
+
+    /// Schedule the broadcast of [`Self::fallback_transaction`] for unix time `broadcast_at`,
+    /// defaulting to the expiry of the receiver's session.
+    ///
+    /// If neither is known the fallback is due immediately.
+    pub fn schedule_broadcast(&self, broadcast_at: Option<u64>) -> ScheduledBroadcast {
+        ScheduledBroadcast::new(self.fallback_transaction(), broadcast_at.or(self.1).unwrap_or(0))
+    }
+
+    /// `min_fee_rate` is in sat/kwu. See
+    /// [`UncheckedProposal::check_broadcast_suitability_with_fee_rate`] for a typed fee rate.
     pub fn check_broadcast_suitability(
         &self,
         min_fee_rate: Option<u64>,
         can_broadcast: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<MaybeInputsOwned, ReplyableError> {
+        self.check_broadcast_suitability_with_fee_rate(
+            min_fee_rate.map(crate::FeeRate::from_sat_per_kwu),
+            can_broadcast,
+        )
+    }
+
+    /// Like [`UncheckedProposal::check_broadcast_suitability`], with the minimum fee rate of the
+    /// Original PSBT given in an explicit unit.
+    pub fn check_broadcast_suitability_with_fee_rate(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        can_broadcast: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
     ) -> Result<MaybeInputsOwned, ReplyableError> {
         self.0
             .clone()
-            .check_broadcast_suitability(
-                min_fee_rate.map(FeeRate::from_sat_per_kwu),
-                |transaction| {
-                    Ok(can_broadcast(&payjoin::bitcoin::consensus::encode::serialize(transaction))?)
-                },
-            )
-            .map(Into::into)
+            .check_broadcast_suitability(min_fee_rate.map(Into::into), |transaction| {
+                Ok(can_broadcast(&payjoin::bitcoin::consensus::encode::serialize(transaction))?)
+            })
+            .map(|inner| MaybeInputsOwned(inner, self.context()))
+            .map_err(Into::into)
+    }
+
+    /// Like [`UncheckedProposal::check_broadcast_suitability`], handing `can_broadcast` the
+    /// transaction's bytes, hex and txid rather than raw bytes only.
+    pub fn check_broadcast_suitability_with_view(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        can_broadcast: impl Fn(&crate::TransactionView) -> Result<bool, ImplementationError>,
+    ) -> Result<MaybeInputsOwned, ReplyableError> {
+        self.0
+            .clone()
+            .check_broadcast_suitability(min_fee_rate.map(Into::into), |transaction| {
+                Ok(can_broadcast(&transaction.clone().into())?)
+            })
+            .map(|inner| MaybeInputsOwned(inner, self.context()))
             .map_err(Into::into)
     }
 
@@ -150,43 +581,73 @@ impl UncheckedProposal {
     /// So-called "non-interactive" receivers, like payment processors, that allow arbitrary requests are otherwise vulnerable to probing attacks.
     /// Those receivers call `extract_tx_to_check_broadcast()` and `attest_tested_and_scheduled_broadcast()` after making those checks downstream.
     pub fn assume_interactive_receiver(&self) -> MaybeInputsOwned {
-        self.0.clone().assume_interactive_receiver().into()
+        MaybeInputsOwned(self.0.clone().assume_interactive_receiver(), self.context())
+    }
+
+    fn context(&self) -> ProposalContext {
+        ProposalContext::new(self.0.clone().extract_tx_to_schedule_broadcast(), self.1)
+            .with_sender_params(self.2.clone())
     }
 
     /// Extract an OHTTP Encapsulated HTTP POST request to return
     /// a Receiver Error Response
+    ///
+    /// Build `err` with `JsonReply::from_replyable_error` from the error a check step failed
+    /// with, so the sender stops polling without learning internal details.
+    ///
+    /// A custom `message`, e.g. one localized for the store, replaces the reply's message while
+    /// its error code is kept. It is sanitized and capped as described in
+    /// [`MAX_REPLY_MESSAGE_CHARS`].
     pub fn extract_err_req(
         &self,
         err: &JsonReply,
+        message: Option<String>,
         ohttp_relay: String,
     ) -> Result<(Request, ClientResponse), SessionError> {
+        let err = match message {
+            Some(message) => err.with_message(&message),
+            None => err.clone(),
+        };
         self.0
             .clone()
-            .extract_err_req(&err.clone().into(), ohttp_relay)
+            .extract_err_req(&err.into(), ohttp_relay)
             .map(|(req, ctx)| (req.into(), ctx.into()))
             .map_err(Into::into)
     }
 
     /// Process an OHTTP Encapsulated HTTP POST Error response
     /// to ensure it has been posted properly
-    pub fn process_err_res(
-        &self,
-        body: &[u8],
-        context: &ClientResponse,
-    ) -> Result<(), SessionError> {
-        self.0.clone().process_err_res(body, context.into()).map_err(Into::into)
+    pub fn process_err_res(&self, body: &[u8], context: &ClientResponse) -> Result<(), Error> {
+        let context = context.take().ok_or(Error::OhttpContextAlreadyUsed)?;
+        self.0.clone().process_err_res(body, context).map_err(|e| Error::V2(Arc::new(e.into())))
     }
 }
 #[derive(Clone)]
-pub struct MaybeInputsOwned(payjoin::receive::v2::MaybeInputsOwned);
+pub struct MaybeInputsOwned(payjoin::receive::v2::MaybeInputsOwned, ProposalContext);
+
+impl MaybeInputsOwned {
+    /// Whether the sender disabled output substitution.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.1.sender_params.output_substitution_disabled
+    }
 
-impl From<payjoin::receive::v2::MaybeInputsOwned> for MaybeInputsOwned {
-    fn from(value: payjoin::receive::v2::MaybeInputsOwned) -> Self {
-        Self(value)
+    /// The sender's Original PSBT, rebuilt from its finalized transaction.
+    ///
+    /// It carries each input's final script sig and witness but not the UTXOs they spend.
+    pub fn psbt(&self) -> String {
+        self.1.original_psbt().to_string()
     }
-}
 
-impl MaybeInputsOwned {
+    /// The serialized form of [`MaybeInputsOwned::psbt`].
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.1.original_psbt().serialize()
+    }
+
+    /// Check that the Original PSBT has no receiver-owned inputs.
+    ///
+    /// This leads straight to [`MaybeInputsSeen`]: senders whose inputs mix script types, e.g.
+    /// while migrating from p2wpkh to p2tr, are accepted, as the payjoin crate no longer
+    /// rejects them.
     pub fn check_inputs_not_owned(
         &self,
         is_owned: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
@@ -195,19 +656,55 @@ impl MaybeInputsOwned {
             .clone()
             .check_inputs_not_owned(|input| Ok(is_owned(&input.to_bytes())?))
             .map_err(Into::into)
-            .map(Into::into)
+            .map(|inner| MaybeInputsSeen(inner, self.1.clone()))
     }
-}
 
-#[derive(Clone)]
-pub struct MaybeInputsSeen(payjoin::receive::v2::MaybeInputsSeen);
+    /// Like [`MaybeInputsOwned::check_inputs_not_owned`], asking `is_owned` about the scripts
+    /// of all inputs in one call instead of one call per input.
+    ///
+    /// `is_owned` must return one answer per script, in the same order.
+    pub fn check_inputs_not_owned_batch(
+        &self,
+        is_owned: impl Fn(Vec<Vec<u8>>) -> Result<Vec<bool>, ImplementationError>,
+    ) -> Result<MaybeInputsSeen, BatchCheckError> {
+        let scripts = self.input_scripts()?;
+        let answers = is_owned(scripts.clone())?;
+        if answers.len() != scripts.len() {
+            return Err(BatchCheckError::AnswerCountMismatch {
+                expected: scripts.len() as u64,
+                actual: answers.len() as u64,
+            });
+        }
+        self.check_with_answers(&scripts, &answers).map_err(Into::into)
+    }
+
+    /// The scripts of the UTXOs the Original PSBT's inputs spend, in the order the check asks
+    /// about them.
+    ///
+    /// Only payjoin's check knows these scripts, so it runs with every input answered as not
+    /// owned. Any other failure of the check is returned.
+    fn input_scripts(&self) -> Result<Vec<Vec<u8>>, ReplyableError> {
+        let scripts = RefCell::new(vec![]);
+        self.check_inputs_not_owned(|script| {
+            scripts.borrow_mut().push(script.clone());
+            Ok(false)
+        })?;
+        Ok(scripts.into_inner())
+    }
 
-impl From<payjoin::receive::v2::MaybeInputsSeen> for MaybeInputsSeen {
-    fn from(value: payjoin::receive::v2::MaybeInputsSeen) -> Self {
-        Self(value)
+    /// Check the inputs given whether each of `scripts` is owned by the receiver.
+    fn check_with_answers(
+        &self,
+        scripts: &[Vec<u8>],
+        answers: &[bool],
+    ) -> Result<MaybeInputsSeen, ReplyableError> {
+        self.check_inputs_not_owned(|script| answer_for(scripts, answers, script))
     }
 }
 
+#[derive(Clone)]
+pub struct MaybeInputsSeen(payjoin::receive::v2::MaybeInputsSeen, ProposalContext);
+
 impl MaybeInputsSeen {
     pub fn check_no_inputs_seen_before(
         &self,
@@ -217,50 +714,167 @@ impl MaybeInputsSeen {
             .clone()
             .check_no_inputs_seen_before(|outpoint| Ok(is_known(&(*outpoint).into())?))
             .map_err(Into::into)
-            .map(Into::into)
+            .map(|inner| OutputsUnknown(inner, self.1.clone()))
+    }
+
+    /// The outpoints the Original PSBT's inputs spend, in order.
+    #[cfg(feature = "async")]
+    fn input_outpoints(&self) -> Vec<payjoin::bitcoin::OutPoint> {
+        self.1.original_tx.input.iter().map(|txin| txin.previous_output).collect()
+    }
+
+    /// Check the inputs given whether each of `outpoints` was seen before.
+    #[cfg(feature = "async")]
+    fn check_with_answers(
+        &self,
+        outpoints: &[payjoin::bitcoin::OutPoint],
+        answers: &[bool],
+    ) -> Result<OutputsUnknown, ReplyableError> {
+        self.check_no_inputs_seen_before(|outpoint| {
+            answer_for(outpoints, answers, &outpoint.clone().into())
+        })
+    }
+
+    /// Like [`MaybeInputsSeen::check_no_inputs_seen_before`], looking each input up in `store`.
+    ///
+    /// The check doesn't change `store`, so a proposal that fails later can be retried. Record
+    /// the inputs with [`PayjoinProposal::mark_inputs_seen`] once the proposal is committed.
+    pub fn check_no_inputs_seen_before_with_store(
+        &self,
+        store: &dyn SeenInputsStore,
+    ) -> Result<OutputsUnknown, ReplyableError> {
+        self.check_no_inputs_seen_before(|outpoint| store.is_seen(outpoint.clone()))
+    }
+
+    /// Like [`MaybeInputsSeen::check_no_inputs_seen_before`], checking the inputs against the
+    /// outpoints in `seen`.
+    ///
+    /// Rejections report the input that was seen before.
+    pub fn check_no_inputs_seen_before_in(
+        &self,
+        seen: Vec<OutPoint>,
+    ) -> Result<OutputsUnknown, InputSeenError> {
+        let seen: HashSet<payjoin::bitcoin::OutPoint> = seen.into_iter().map(Into::into).collect();
+        let first_seen = RefCell::new(None);
+        self.check_no_inputs_seen_before(|outpoint| {
+            let is_seen = seen.contains(&outpoint.clone().into());
+            if is_seen && first_seen.borrow().is_none() {
+                *first_seen.borrow_mut() = Some(outpoint.clone());
+            }
+            Ok(is_seen)
+        })
+        .map_err(|reply| {
+            match first_seen.into_inner() {
+                Some(outpoint) => InputSeenError::Seen { outpoint, reply: Arc::new(reply) },
+                None => InputSeenError::Proposal(Arc::new(reply)),
+            }
+        })
     }
 }
 
+/// The answer given for `question`, one of `questions` answered in the same order by `answers`.
+fn answer_for<Q: PartialEq>(
+    questions: &[Q],
+    answers: &[bool],
+    question: &Q,
+) -> Result<bool, ImplementationError> {
+    questions
+        .iter()
+        .position(|q| q == question)
+        .map(|i| answers[i])
+        .ok_or_else(|| "check asked a question it was not given an answer for".to_string().into())
+}
+
 /// The receiver has not yet identified which outputs belong to the receiver.
 ///
 /// Only accept PSBTs that send us money.
 /// Identify those outputs with `identify_receiver_outputs()` to proceed
 #[derive(Clone)]
-pub struct OutputsUnknown(payjoin::receive::v2::OutputsUnknown);
+pub struct OutputsUnknown(payjoin::receive::v2::OutputsUnknown, ProposalContext);
 
-impl From<payjoin::receive::v2::OutputsUnknown> for OutputsUnknown {
-    fn from(value: payjoin::receive::v2::OutputsUnknown) -> Self {
-        Self(value)
+impl OutputsUnknown {
+    /// The sender's Original PSBT, rebuilt from its finalized transaction.
+    ///
+    /// It carries each input's final script sig and witness but not the UTXOs they spend.
+    pub fn psbt(&self) -> String {
+        self.1.original_psbt().to_string()
+    }
+
+    /// The serialized form of [`OutputsUnknown::psbt`].
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.1.original_psbt().serialize()
     }
-}
 
-impl OutputsUnknown {
     /// Find which outputs belong to the receiver
     pub fn identify_receiver_outputs(
         &self,
         is_receiver_output: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
     ) -> Result<WantsOutputs, ReplyableError> {
-        self.0
-            .clone()
-            .identify_receiver_outputs(|input| Ok(is_receiver_output(&input.to_bytes())?))
-            .map_err(Into::into)
-            .map(Into::into)
+        let scripts = self.output_scripts();
+        let answers = scripts
+            .iter()
+            .map(|script| is_receiver_output(&script.to_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.identify_with_answers(&scripts, &answers)
+    }
+
+    /// Like [`OutputsUnknown::identify_receiver_outputs`], asking `is_receiver_output` about
+    /// the scripts of all outputs in one call instead of one call per output.
+    ///
+    /// `is_receiver_output` must return one answer per script, in the same order.
+    pub fn identify_receiver_outputs_batch(
+        &self,
+        is_receiver_output: impl Fn(Vec<Vec<u8>>) -> Result<Vec<bool>, ImplementationError>,
+    ) -> Result<WantsOutputs, BatchCheckError> {
+        let scripts = self.output_scripts();
+        let answers = is_receiver_output(scripts.iter().map(|s| s.to_bytes()).collect())?;
+        if answers.len() != scripts.len() {
+            return Err(BatchCheckError::AnswerCountMismatch {
+                expected: scripts.len() as u64,
+                actual: answers.len() as u64,
+            });
+        }
+        self.identify_with_answers(&scripts, &answers).map_err(Into::into)
     }
-}
 
-pub struct WantsOutputs(payjoin::receive::v2::WantsOutputs);
+    /// The scripts of the Original PSBT's outputs, in order.
+    fn output_scripts(&self) -> Vec<ScriptBuf> {
+        self.1.original_tx.output.iter().map(|txout| txout.script_pubkey.clone()).collect()
+    }
 
-impl From<payjoin::receive::v2::WantsOutputs> for WantsOutputs {
-    fn from(value: payjoin::receive::v2::WantsOutputs) -> Self {
-        Self(value)
+    /// Identify the receiver's outputs given whether each of `scripts` is the receiver's.
+    fn identify_with_answers(
+        &self,
+        scripts: &[ScriptBuf],
+        answers: &[bool],
+    ) -> Result<WantsOutputs, ReplyableError> {
+        let receiver_scripts = scripts
+            .iter()
+            .zip(answers)
+            .filter(|(_, is_receiver_output)| **is_receiver_output)
+            .map(|(script, _)| script.clone())
+            .collect();
+        let result = self
+            .0
+            .clone()
+            .identify_receiver_outputs(|script| answer_for(scripts, answers, &script.to_owned()));
+        let context = self.1.with_original_receiver_scripts(receiver_scripts);
+        result.map(|inner| WantsOutputs(inner, context)).map_err(Into::into)
     }
 }
 
+/// The receiver may substitute its outputs or add new ones before committing them.
+///
+/// Call `commit_outputs()` to proceed to input contribution.
+pub struct WantsOutputs(payjoin::receive::v2::WantsOutputs, ProposalContext);
+
 impl WantsOutputs {
     pub fn output_substitution(&self) -> bool {
         self.0.output_substitution() == payjoin::OutputSubstitution::Enabled
     }
 
+    /// Replace the receiver's outputs with `replacement_outputs`, sending any excess to
+    /// `drain_script`.
     pub fn replace_receiver_outputs(
         &self,
         replacement_outputs: Vec<TxOut>,
@@ -268,13 +882,56 @@ impl WantsOutputs {
     ) -> Result<WantsOutputs, OutputSubstitutionError> {
         let replacement_outputs: Vec<payjoin::bitcoin::TxOut> =
             replacement_outputs.iter().map(|o| o.clone().into()).collect();
+        let mut receiver_scripts: Vec<ScriptBuf> =
+            replacement_outputs.iter().map(|o| o.script_pubkey.clone()).collect();
+        receiver_scripts.push(drain_script.0.clone());
+        check_replacement_outputs(&replacement_outputs, &drain_script.0)?;
         self.0
             .clone()
             .replace_receiver_outputs(replacement_outputs, &drain_script.0)
-            .map(Into::into)
+            .map(|inner| {
+                let context =
+                    self.1.with_receiver_scripts(receiver_scripts, drain_script.0.clone());
+                WantsOutputs(inner, context)
+            })
             .map_err(Into::into)
     }
 
+    /// Replace the receiver's outputs with those `generate` returns, e.g. to split the payment
+    /// across several addresses, sending any excess to `drain_script`.
+    ///
+    /// Unlike [`WantsOutputs::replace_receiver_outputs`], the outputs are checked up front:
+    /// none may be dust, and unless the sender allows output substitution they must pay the
+    /// receiver at least what the Original PSBT did. An error from `generate` is returned as
+    /// [`OutputSubstitutionError::Implementation`].
+    pub fn try_substitute_receiver_outputs(
+        &self,
+        generate: impl Fn() -> Result<Vec<TxOut>, ImplementationError>,
+        drain_script: &Script,
+    ) -> Result<WantsOutputs, OutputSubstitutionError> {
+        let outputs = generate()?;
+        let mut replacement = 0;
+        for (index, output) in outputs.iter().enumerate() {
+            let output: payjoin::bitcoin::TxOut = output.clone().into();
+            if output.value < output.script_pubkey.minimal_non_dust() {
+                return Err(OutputSubstitutionError::DustOutput {
+                    index: index as u32,
+                    value: output.value.to_sat(),
+                });
+            }
+            replacement += output.value.to_sat();
+        }
+        let original = self.1.payment();
+        if replacement < original && !self.output_substitution() {
+            return Err(OutputSubstitutionError::DecreasedValue { original, replacement });
+        }
+        self.replace_receiver_outputs(outputs, drain_script)
+    }
+
+    /// Pay the receiver's outputs to `output_script` instead, keeping their amounts.
+    ///
+    /// Fails with [`OutputSubstitutionError::Disabled`] if the sender disabled output
+    /// substitution and `output_script` isn't already the receiver's script.
     pub fn substitute_receiver_script(
         &self,
         output_script: &Script,
@@ -282,25 +939,28 @@ impl WantsOutputs {
         self.0
             .clone()
             .substitute_receiver_script(&output_script.0)
-            .map(Into::into)
+            .map(|inner| {
+                let script = output_script.0.clone();
+                WantsOutputs(inner, self.1.with_receiver_scripts(vec![script.clone()], script))
+            })
             .map_err(Into::into)
     }
 
+    /// Proceed to input contribution with the current outputs.
     pub fn commit_outputs(&self) -> WantsInputs {
-        self.0.clone().commit_outputs().into()
+        WantsInputs(self.0.clone().commit_outputs(), self.1.clone())
     }
 }
 
-pub struct WantsInputs(payjoin::receive::v2::WantsInputs);
-
-impl From<payjoin::receive::v2::WantsInputs> for WantsInputs {
-    fn from(value: payjoin::receive::v2::WantsInputs) -> Self {
-        Self(value)
-    }
-}
+/// The receiver may contribute inputs to the proposal before committing them.
+///
+/// Call `commit_inputs()` to proceed to a `ProvisionalProposal`.
+pub struct WantsInputs(payjoin::receive::v2::WantsInputs, ProposalContext);
 impl WantsInputs {
     /// Select receiver input such that the payjoin avoids surveillance.
-    /// Return the input chosen that has been applied to the Proposal.
+    ///
+    /// Every candidate is considered, including candidates with the same amount, and the chosen
+    /// one is returned unchanged so it can be passed straight to `contribute_inputs()`.
     ///
     /// Proper coin selection allows payjoin to resemble ordinary transactions.
     /// To ensure the resemblance, a number of heuristics must be avoided.
@@ -314,12 +974,31 @@ impl WantsInputs {
         &self,
         candidate_inputs: Vec<InputPair>,
     ) -> Result<InputPair, SelectionError> {
-        match self.0.clone().try_preserving_privacy(candidate_inputs.into_iter().map(Into::into)) {
-            Ok(t) => Ok(t.into()),
-            Err(e) => Err(e.into()),
-        }
+        first_selected(candidate_inputs, |candidates| {
+            self.0.clone().try_preserving_privacy(candidates.into_iter().map(Into::into))
+        })
     }
 
+    /// Like [`WantsInputs::try_preserving_privacy`], returning every candidate it would accept,
+    /// best first, so a candidate that turns out to be unusable can be skipped.
+    ///
+    /// Fails with the error `try_preserving_privacy` gives for the whole list.
+    pub fn try_preserving_privacy_ranked(
+        &self,
+        candidate_inputs: Vec<InputPair>,
+    ) -> Result<Vec<InputPair>, SelectionError> {
+        let values = self.1.drain_script.as_ref().and_then(|drain_script| {
+            let psbt = self.commit_inputs().preview_psbt(None, None).ok()?;
+            UihValues::from_psbt(&psbt, drain_script)
+        });
+        rank_candidates(
+            candidate_inputs,
+            |candidates| self.try_preserving_privacy(candidates),
+            values,
+        )
+    }
+
+    /// Add the given receiver inputs to the proposal, adjusting the drain output accordingly.
     pub fn contribute_inputs(
         &self,
         replacement_inputs: Vec<InputPair>,
@@ -327,27 +1006,168 @@ impl WantsInputs {
         self.0
             .clone()
             .contribute_inputs(replacement_inputs.into_iter().map(Into::into))
-            .map(Into::into)
+            .map(|inner| WantsInputs(inner, self.1.clone()))
             .map_err(Into::into)
     }
 
+    /// Add a legacy receiver input spending `outpoint`, given the full previous transaction
+    /// `tx_bytes` it spends from.
+    ///
+    /// See [`InputPair::new_non_witness`].
+    pub fn contribute_non_witness_input(
+        &self,
+        tx_bytes: Vec<u8>,
+        outpoint: OutPoint,
+    ) -> Result<WantsInputs, NonWitnessInputError> {
+        let input = InputPair::new_non_witness(tx_bytes, outpoint)?;
+        Ok(self.contribute_inputs(vec![input])?)
+    }
+
+    /// The fee parameters of the sender's request, to size the receiver's contribution.
+    pub fn sender_params(&self) -> SenderParams {
+        self.1.sender_params.clone()
+    }
+
+    /// Proceed to finalization with the current inputs.
     pub fn commit_inputs(&self) -> ProvisionalProposal {
-        self.0.clone().commit_inputs().into()
+        ProvisionalProposal(self.0.clone().commit_inputs(), self.1.clone())
+    }
+}
+
+/// The candidate `select` picks from `candidates`, handed back as given rather than as payjoin's
+/// copy, which doesn't know the value the candidate spends.
+///
+/// payjoin picks the first candidate that avoids UIH, which is the first it accepts on its own.
+fn first_selected(
+    candidates: Vec<InputPair>,
+    select: impl Fn(
+        Vec<InputPair>,
+    ) -> Result<payjoin::receive::InputPair, payjoin::receive::SelectionError>,
+) -> Result<InputPair, SelectionError> {
+    let chosen = select(candidates.clone())?;
+    Ok(candidates
+        .into_iter()
+        .find(|candidate| select(vec![candidate.clone()]).is_ok())
+        .unwrap_or_else(|| chosen.into()))
+}
+
+/// Order the candidates `select` accepts by how far they keep the payjoin clear of the
+/// unnecessary input heuristic, best first.
+///
+/// A candidate avoids UIH2 while the smallest input is larger than the smallest output once the
+/// candidate's value is added to the drain output. Candidates are scored by that margin, computed
+/// from `values` rather than a proposal built with each one. Candidates whose value isn't known
+/// are left out, as are all of them without `values`, and equal margins keep their order.
+fn rank_candidates(
+    candidates: Vec<InputPair>,
+    select: impl Fn(Vec<InputPair>) -> Result<InputPair, SelectionError>,
+    values: Option<UihValues>,
+) -> Result<Vec<InputPair>, SelectionError> {
+    select(candidates.clone())?;
+    let mut ranked: Vec<(i64, InputPair)> = candidates
+        .into_iter()
+        .filter(|candidate| select(vec![candidate.clone()]).is_ok())
+        .filter_map(|candidate| Some((values.as_ref()?.margin(candidate.1?), candidate)))
+        .collect();
+    ranked.sort_by_key(|(margin, _)| std::cmp::Reverse(*margin));
+    Ok(ranked.into_iter().map(|(_, candidate)| candidate).collect())
+}
+
+/// The values the unnecessary input heuristic compares, in a proposal before the receiver
+/// contributes another input.
+struct UihValues {
+    min_input: Amount,
+    min_output: Amount,
+    drain: Amount,
+}
+
+impl UihValues {
+    /// Read the values from the PSBT a proposal would be finalized with, whose receiver inputs
+    /// are drained to the first output paying `drain_script`.
+    ///
+    /// Inputs whose spent output `psbt` doesn't carry are skipped.
+    fn from_psbt(psbt: &Psbt, drain_script: &ScriptBuf) -> Option<Self> {
+        let outputs = &psbt.unsigned_tx.output;
+        let drain = outputs.iter().find(|txout| txout.script_pubkey == *drain_script)?.value;
+        let min_input = (0..psbt.inputs.len())
+            .filter_map(|index| crate::record::input_utxo(psbt, index))
+            .map(|txout| txout.value)
+            .min()
+            .unwrap_or(Amount::MAX_MONEY);
+        let min_output = outputs.iter().map(|txout| txout.value).min().unwrap_or(Amount::MAX_MONEY);
+        Some(Self { min_input, min_output, drain })
+    }
+
+    /// By how many sats the smallest input exceeds the smallest output once a receiver input
+    /// worth `candidate` is drained, negative where the payjoin would show UIH2.
+    fn margin(&self, candidate: Amount) -> i64 {
+        let min_input = self.min_input.min(candidate);
+        let min_output = self.min_output.min(self.drain + candidate);
+        min_input.to_sat() as i64 - min_output.to_sat() as i64
     }
 }
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
-pub struct InputPair(payjoin::receive::InputPair);
+pub struct InputPair(
+    payjoin::receive::InputPair,
+    /// The value of the output the input spends, where it was known when the pair was built.
+    Option<Amount>,
+);
 
 #[cfg_attr(feature = "uniffi", uniffi::export)]
 impl InputPair {
+    /// Pair a receiver input with the PSBT data describing the output it spends.
+    ///
+    /// The input's satisfaction weight, used to pay its share of the payjoin fee, is derived
+    /// from `psbtin`. This only succeeds for script types whose weight can be known in advance,
+    /// so inputs of other types are rejected here rather than underpaying fees later.
     #[cfg_attr(feature = "uniffi", uniffi::constructor)]
     pub fn new(
         txin: bitcoin_ffi::TxIn,
         psbtin: crate::bitcoin_ffi::PsbtInput,
     ) -> Result<Self, PsbtInputError> {
-        Ok(Self(payjoin::receive::InputPair::new(txin.into(), psbtin.into())?))
+        let txin: payjoin::bitcoin::TxIn = txin.into();
+        let psbtin: payjoin::bitcoin::psbt::Input = psbtin.into();
+        let value = match (&psbtin.witness_utxo, &psbtin.non_witness_utxo) {
+            (Some(txout), _) => Some(txout.value),
+            (None, Some(tx)) => {
+                tx.output.get(txin.previous_output.vout as usize).map(|txout| txout.value)
+            }
+            (None, None) => None,
+        };
+        Ok(Self(payjoin::receive::InputPair::new(txin, psbtin)?, value))
+    }
+
+    /// Pair a legacy receiver input spending `outpoint` with the full previous transaction
+    /// `tx_bytes`, which the PSBT input carries as its `non_witness_utxo`.
+    ///
+    /// The input pays its share of the fee at the larger weight of a non-witness spend.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn new_non_witness(
+        tx_bytes: Vec<u8>,
+        outpoint: OutPoint,
+    ) -> Result<Self, NonWitnessInputError> {
+        let previous_tx: payjoin::bitcoin::Transaction =
+            payjoin::bitcoin::consensus::deserialize(&tx_bytes)
+                .map_err(|e| NonWitnessInputError::InvalidTransaction { msg: e.to_string() })?;
+        let outpoint: payjoin::bitcoin::OutPoint = outpoint.into();
+        if previous_tx.compute_txid() != outpoint.txid
+            || previous_tx.output.len() <= outpoint.vout as usize
+        {
+            return Err(NonWitnessInputError::OutpointMismatch {
+                txid: outpoint.txid.to_string(),
+                vout: outpoint.vout,
+            });
+        }
+        let value = previous_tx.output[outpoint.vout as usize].value;
+        let txin = payjoin::bitcoin::TxIn { previous_output: outpoint, ..Default::default() };
+        let psbtin = payjoin::bitcoin::psbt::Input {
+            non_witness_utxo: Some(previous_tx),
+            ..Default::default()
+        };
+        let pair = payjoin::receive::InputPair::new(txin, psbtin).map_err(PsbtInputError::from)?;
+        Ok(Self(pair, Some(value)))
     }
 }
 
@@ -359,52 +1179,494 @@ impl From<InputPair> for payjoin::receive::InputPair {
 
 impl From<payjoin::receive::InputPair> for InputPair {
     fn from(value: payjoin::receive::InputPair) -> Self {
-        Self(value)
+        Self(value, None)
     }
 }
 
-pub struct ProvisionalProposal(pub payjoin::receive::v2::ProvisionalProposal);
+#[derive(Clone)]
+pub struct ProvisionalProposal(
+    pub payjoin::receive::v2::ProvisionalProposal,
+    pub(crate) ProposalContext,
+);
+
+impl ProvisionalProposal {
+    /// The fee parameters of the sender's request.
+    pub fn sender_params(&self) -> SenderParams {
+        self.1.sender_params.clone()
+    }
 
-impl From<payjoin::receive::v2::ProvisionalProposal> for ProvisionalProposal {
-    fn from(value: payjoin::receive::v2::ProvisionalProposal) -> Self {
-        Self(value)
+    /// The most the sender will pay, in satoshis, towards the fee of the receiver's additions.
+    pub fn max_additional_fee_contribution(&self) -> Option<u64> {
+        self.1.sender_params.max_additional_fee_contribution
+    }
+
+    /// The minimum fee rate the sender requires of the payjoin, in sat/vB.
+    pub fn min_fee_rate_sat_per_vb(&self) -> Option<u64> {
+        self.1.sender_params.min_fee_rate_sat_per_vb
+    }
+
+    /// The proposal PSBT `finalize_proposal` would hand to `process_psbt` with the default fee
+    /// rates, before the receiver signs it.
+    pub fn psbt(&self) -> Result<String, ReplyableError> {
+        self.preview_psbt(None, None).map(|psbt| psbt.to_string())
+    }
+
+    /// The serialized form of [`ProvisionalProposal::psbt`].
+    pub fn psbt_bytes(&self) -> Result<Vec<u8>, ReplyableError> {
+        self.preview_psbt(None, None).map(|psbt| psbt.serialize())
+    }
+
+    /// Preview the fees of the proposal [`ProvisionalProposal::finalize_proposal`] would
+    /// produce given the same fee rates, without signing it.
+    pub fn preview_fees(
+        &self,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<FeePreview, ReplyableError> {
+        self.preview_psbt(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)
+            .map(|psbt| self.1.fee_preview(&psbt))
+    }
+
+    /// Like [`ProvisionalProposal::preview_fees`], for
+    /// [`ProvisionalProposal::finalize_proposal_with_fee_rates`].
+    pub fn preview_fees_with_fee_rates(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        max_effective_fee_rate: Option<crate::FeeRate>,
+    ) -> Result<FeePreview, ReplyableError> {
+        self.preview_psbt_at(min_fee_rate.map(Into::into), max_effective_fee_rate.map(Into::into))
+            .map(|psbt| self.1.fee_preview(&psbt))
     }
-}
 
-impl ProvisionalProposal {
     pub fn finalize_proposal(
         &self,
         process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
         min_feerate_sat_per_vb: Option<u64>,
         max_effective_fee_rate_sat_per_vb: Option<u64>,
     ) -> Result<PayjoinProposal, ReplyableError> {
-        self.0
-            .clone()
+        self.finalize_with(
+            |psbt| {
+                let psbt = process_psbt(psbt.to_string())?;
+                Psbt::from_str(&psbt).map_err(ImplementationError::invalid_callback_psbt)
+            },
+            fee_rate_sat_per_vb(min_feerate_sat_per_vb)?,
+            fee_rate_sat_per_vb(max_effective_fee_rate_sat_per_vb)?,
+        )
+    }
+
+    /// Like [`ProvisionalProposal::finalize_proposal`], with fee rates given in an explicit unit.
+    pub fn finalize_proposal_with_fee_rates(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+        min_fee_rate: Option<crate::FeeRate>,
+        max_effective_fee_rate: Option<crate::FeeRate>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        self.finalize_with(
+            |psbt| {
+                let psbt = process_psbt(psbt.to_string())?;
+                Psbt::from_str(&psbt).map_err(ImplementationError::invalid_callback_psbt)
+            },
+            min_fee_rate.map(Into::into),
+            max_effective_fee_rate.map(Into::into),
+        )
+    }
+
+    /// Like [`ProvisionalProposal::finalize_proposal`], handing `process_psbt` the PSBT as raw
+    /// bytes and expecting raw bytes back instead of base64.
+    pub fn finalize_proposal_bytes(
+        &self,
+        process_psbt: impl Fn(Vec<u8>) -> Result<Vec<u8>, ImplementationError>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        self.finalize_with(
+            |psbt| {
+                let psbt = process_psbt(psbt.serialize())?;
+                Psbt::deserialize(&psbt).map_err(ImplementationError::invalid_callback_psbt)
+            },
+            fee_rate_sat_per_vb(min_feerate_sat_per_vb)?,
+            fee_rate_sat_per_vb(max_effective_fee_rate_sat_per_vb)?,
+        )
+    }
+
+    fn finalize_with(
+        &self,
+        process_psbt: impl Fn(&Psbt) -> Result<Psbt, ImplementationError>,
+        min_fee_rate: Option<FeeRate>,
+        max_effective_fee_rate: Option<FeeRate>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        let proposal_psbt = RefCell::new(None);
+        let result = self.0.clone().finalize_proposal(
+            |pre_processed| {
+                *proposal_psbt.borrow_mut() = Some(pre_processed.clone());
+                Ok(process_psbt(pre_processed)?)
+            },
+            min_fee_rate,
+            max_effective_fee_rate,
+        );
+        let context = match proposal_psbt.into_inner() {
+            Some(psbt) => self.1.with_proposal_psbt(psbt),
+            None => self.1.clone(),
+        };
+        result.map(|inner| PayjoinProposal(inner, context)).map_err(Into::into)
+    }
+
+    /// Export a self-contained JSON snapshot of the proposal for an external reviewer.
+    ///
+    /// The document describes the proposal as `finalize_proposal` would sign it with the given
+    /// fee rates. Record the reviewer's verdict with `apply_review_decision` using the returned
+    /// hash and the same fee rates.
+    pub fn export_review_bundle(
+        &self,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<ReviewBundle, ReplyableError> {
+        let psbt = self.preview_psbt(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)?;
+        let context = &self.1;
+        let receiver_inputs: Vec<serde_json::Value> = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .enumerate()
+            .filter(|(_, txin)| !context.is_sender_input(&txin.previous_output))
+            .map(|(i, txin)| {
+                serde_json::json!({
+                    "outpoint": txin.previous_output.to_string(),
+                    "value": crate::record::input_value(&psbt, i),
+                })
+            })
+            .collect();
+        let (receiver_outputs, sender_outputs): (Vec<_>, Vec<_>) =
+            psbt.unsigned_tx.output.iter().partition(|txout| context.is_receiver_output(txout));
+        let receiver_outputs: Vec<serde_json::Value> = receiver_outputs
+            .iter()
+            .map(|txout| {
+                serde_json::json!({
+                    "script_pubkey": txout.script_pubkey.to_hex_string(),
+                    "value": txout.value.to_sat(),
+                })
+            })
+            .collect();
+        let fees = context.fee_split(&psbt);
+
+        let document = serde_json::json!({
+            "version": REVIEW_BUNDLE_VERSION,
+            "original_tx": payjoin::bitcoin::consensus::encode::serialize_hex(&*context.original_tx),
+            "proposal_psbt": psbt.to_string(),
+            "contributions": {
+                "inputs": receiver_inputs,
+                "outputs": receiver_outputs,
+            },
+            "fees": {
+                "original_fee": fees.original_fee,
+                "total_fee": fees.total_fee,
+                "sender_fee": fees.sender_fee,
+                "receiver_fee": fees.receiver_fee,
+                "min_feerate_sat_per_vb": min_feerate_sat_per_vb,
+                "max_effective_fee_rate_sat_per_vb": max_effective_fee_rate_sat_per_vb,
+            },
+            "counterparty": {
+                "input_count": context.original_tx.input.len(),
+                "input_value": crate::record::sum_input_values(&psbt, |outpoint| {
+                    context.is_sender_input(outpoint)
+                }),
+                "output_count": sender_outputs.len(),
+                "payment": context.payment(),
+            },
+        })
+        .to_string();
+        let hash = sha256::Hash::hash(document.as_bytes()).to_string();
+        Ok(ReviewBundle { document, hash })
+    }
+
+    /// Record a reviewer's verdict on the bundle identified by `bundle_hash`.
+    ///
+    /// The bundle is exported again and must hash to `bundle_hash`, so an approval only ever
+    /// applies to the proposal and fee rates that were actually reviewed.
+    pub fn apply_review_decision(
+        &self,
+        approved: bool,
+        bundle_hash: String,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<ReviewedProposal, ReviewError> {
+        let bundle =
+            self.export_review_bundle(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)?;
+        if bundle.hash != bundle_hash {
+            return Err(ReviewError::BundleMismatch { reviewed: bundle_hash, actual: bundle.hash });
+        }
+        if !approved {
+            return Err(ReviewError::Rejected);
+        }
+        Ok(ReviewedProposal {
+            proposal: self.clone(),
+            min_feerate_sat_per_vb,
+            max_effective_fee_rate_sat_per_vb,
+        })
+    }
+
+    /// Start finalizing a proposal whose receiver inputs can't all be signed at once, as when a
+    /// multisig co-signer responds later.
+    ///
+    /// `process_psbt` adds the signatures available now. Collect the rest with
+    /// [`PendingSignatures::add_signatures`] and finish with [`PendingSignatures::complete`].
+    pub fn begin_finalize(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<PendingSignatures, FinalizeError> {
+        let psbt = self.preview_psbt(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)?;
+        let pending = PendingSignatures {
+            proposal: self.clone(),
+            psbt,
+            min_feerate_sat_per_vb,
+            max_effective_fee_rate_sat_per_vb,
+        };
+        pending.add_signatures(process_psbt)
+    }
+
+    /// Resume finalizing this proposal from signatures persisted with
+    /// [`PendingSignatures::to_json`].
+    ///
+    /// The proposal must be the one the signatures were collected for, rebuilt with the same
+    /// inputs and outputs.
+    pub fn resume_finalize(&self, json: &str) -> Result<PendingSignatures, FinalizeError> {
+        self.resume_from_value(&serde_json::from_str(json)?)
+    }
+
+    fn resume_from_value(
+        &self,
+        value: &serde_json::Value,
+    ) -> Result<PendingSignatures, FinalizeError> {
+        let psbt = value["psbt"]
+            .as_str()
+            .and_then(|psbt| Psbt::from_str(psbt).ok())
+            .ok_or(FinalizeError::ProposalMismatch)?;
+        let min_feerate_sat_per_vb = value["min_feerate_sat_per_vb"].as_u64();
+        let max_effective_fee_rate_sat_per_vb = value["max_effective_fee_rate_sat_per_vb"].as_u64();
+        let preview =
+            self.preview_psbt(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)?;
+        let psbt = with_signatures_of(preview, &psbt).ok_or(FinalizeError::ProposalMismatch)?;
+        Ok(PendingSignatures {
+            proposal: self.clone(),
+            psbt,
+            min_feerate_sat_per_vb,
+            max_effective_fee_rate_sat_per_vb,
+        })
+    }
+
+    /// The PSBT `finalize_proposal` would hand to `process_psbt` with these fee rates.
+    fn preview_psbt(
+        &self,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Psbt, ReplyableError> {
+        self.preview_psbt_at(
+            fee_rate_sat_per_vb(min_feerate_sat_per_vb)?,
+            fee_rate_sat_per_vb(max_effective_fee_rate_sat_per_vb)?,
+        )
+    }
+
+    /// The PSBT `finalize_with` hands to `process_psbt` at these fee rates.
+    fn preview_psbt_at(
+        &self,
+        min_fee_rate: Option<FeeRate>,
+        max_effective_fee_rate: Option<FeeRate>,
+    ) -> Result<Psbt, ReplyableError> {
+        let preview = RefCell::new(None);
+        let result = self.0.clone().finalize_proposal(
+            |pre_processed| {
+                *preview.borrow_mut() = Some(pre_processed.clone());
+                Err("proposal preview only".into())
+            },
+            min_fee_rate,
+            max_effective_fee_rate,
+        );
+        match (preview.into_inner(), result) {
+            (Some(psbt), _) => Ok(psbt),
+            (None, Err(e)) => Err(e.into()),
+            (None, Ok(_)) => {
+                Err(ImplementationError::from(
+                    "finalize_proposal didn't hand the proposal to process_psbt".to_string(),
+                )
+                .into())
+            }
+        }
+    }
+}
+
+/// A fee rate given in sat/vB to the untyped finalize methods, failing rather than dropping a
+/// rate that overflows in sat/kwu.
+pub(crate) fn fee_rate_sat_per_vb(
+    sat_per_vb: Option<u64>,
+) -> Result<Option<FeeRate>, ReplyableError> {
+    sat_per_vb
+        .map(|rate| crate::FeeRate::from_sat_per_vb(rate).map(Into::into))
+        .transpose()
+        .map_err(|e| ImplementationError::from(e).into())
+}
+
+/// Check `replacement_outputs` can take the place of the receiver's outputs with the excess
+/// paid to `drain_script`, the rejections payjoin can't tell apart from one another.
+pub(crate) fn check_replacement_outputs(
+    replacement_outputs: &[payjoin::bitcoin::TxOut],
+    drain_script: &ScriptBuf,
+) -> Result<(), OutputSubstitutionError> {
+    if replacement_outputs.is_empty() {
+        return Err(OutputSubstitutionError::NoReplacementOutputs);
+    }
+    if !replacement_outputs.iter().any(|txout| txout.script_pubkey == *drain_script) {
+        return Err(OutputSubstitutionError::InvalidDrainScript);
+    }
+    Ok(())
+}
+
+/// `preview` with the inputs of `signed`, a PSBT of the same transaction whose inputs and
+/// outputs may be in another order.
+///
+/// payjoin places contributed inputs at random positions, so a proposal rebuilt after a restart
+/// orders them differently from the one the signatures were collected for. Returns `None` if
+/// the two PSBTs don't spend the same inputs to the same outputs.
+fn with_signatures_of(mut preview: Psbt, signed: &Psbt) -> Option<Psbt> {
+    let sorted = |psbt: &Psbt| {
+        let mut tx = psbt.unsigned_tx.clone();
+        tx.input.sort_by_key(|txin| txin.previous_output);
+        tx.output.sort_by(|a, b| (&a.script_pubkey, a.value).cmp(&(&b.script_pubkey, b.value)));
+        tx
+    };
+    if sorted(&preview) != sorted(signed) {
+        return None;
+    }
+    for (txin, input) in preview.unsigned_tx.input.iter().zip(preview.inputs.iter_mut()) {
+        let index = signed
+            .unsigned_tx
+            .input
+            .iter()
+            .position(|signed| signed.previous_output == txin.previous_output)?;
+        *input = signed.inputs.get(index)?.clone();
+    }
+    Some(preview)
+}
+
+/// A proposal whose receiver inputs are being signed in several steps.
+///
+/// See [`ProvisionalProposal::begin_finalize`].
+#[derive(Clone)]
+pub struct PendingSignatures {
+    proposal: ProvisionalProposal,
+    /// The proposal PSBT with the signatures collected so far.
+    psbt: Psbt,
+    min_feerate_sat_per_vb: Option<u64>,
+    max_effective_fee_rate_sat_per_vb: Option<u64>,
+}
+
+impl PendingSignatures {
+    /// Add the signatures `process_psbt` can provide now.
+    pub fn add_signatures(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+    ) -> Result<PendingSignatures, FinalizeError> {
+        let psbt = Psbt::from_str(&process_psbt(self.psbt.to_string())?)
+            .map_err(ImplementationError::invalid_callback_psbt)?;
+        if psbt.unsigned_tx != self.psbt.unsigned_tx {
+            return Err(FinalizeError::ProposalMismatch);
+        }
+        Ok(PendingSignatures { psbt, ..self.clone() })
+    }
+
+    /// The contributed receiver inputs that are not finalized yet.
+    pub fn unsatisfied_inputs(&self) -> Vec<OutPoint> {
+        self.psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(&self.psbt.inputs)
+            .filter(|(txin, _)| !self.proposal.1.is_sender_input(&txin.previous_output))
+            .filter(|(_, input)| {
+                input.final_script_sig.is_none() && input.final_script_witness.is_none()
+            })
+            .map(|(txin, _)| txin.previous_output.into())
+            .collect()
+    }
+
+    /// Finalize the proposal once every receiver input is signed, at unix time `now`.
+    ///
+    /// Fails if the receiver's session has expired by then.
+    pub fn complete(&self, now: u64) -> Result<PayjoinProposal, FinalizeError> {
+        if let Some(expired_at) = self.proposal.1.session_expiry.filter(|expiry| *expiry <= now) {
+            return Err(FinalizeError::SessionExpired { expired_at });
+        }
+        let unsatisfied = self.unsatisfied_inputs().len() as u64;
+        if unsatisfied > 0 {
+            return Err(FinalizeError::Incomplete { unsatisfied });
+        }
+        let signed = self.psbt.to_string();
+        self.proposal
             .finalize_proposal(
-                |pre_processed| {
-                    let psbt = process_psbt(pre_processed.to_string())?;
-                    Ok(Psbt::from_str(&psbt)?)
-                },
-                min_feerate_sat_per_vb.and_then(FeeRate::from_sat_per_vb),
-                max_effective_fee_rate_sat_per_vb.and_then(FeeRate::from_sat_per_vb),
+                |_| Ok(signed.clone()),
+                self.min_feerate_sat_per_vb,
+                self.max_effective_fee_rate_sat_per_vb,
             )
-            .map(Into::into)
             .map_err(Into::into)
     }
+
+    /// Persist the signatures collected so far, to resume with
+    /// [`ProvisionalProposal::resume_finalize`]. [`Receiver::with_pending_signatures`] keeps them
+    /// with the session instead.
+    pub fn to_json(&self) -> Result<String, SerdeJsonError> {
+        serde_json::to_string(&self.to_value()).map_err(Into::into)
+    }
+
+    fn to_value(&self) -> serde_json::Value {
+        serde_json::json!({
+            "psbt": self.psbt.to_string(),
+            "min_feerate_sat_per_vb": self.min_feerate_sat_per_vb,
+            "max_effective_fee_rate_sat_per_vb": self.max_effective_fee_rate_sat_per_vb,
+        })
+    }
 }
 
+/// Version of the document produced by [`ProvisionalProposal::export_review_bundle`].
+pub const REVIEW_BUNDLE_VERSION: u32 = 1;
+
+/// A JSON snapshot of a provisional proposal and the SHA256 hash identifying it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ReviewBundle {
+    pub document: String,
+    pub hash: String,
+}
+
+/// A provisional proposal approved by an external reviewer.
+///
+/// It can only be finalized with the fee rates that were reviewed.
 #[derive(Clone)]
-pub struct PayjoinProposal(pub payjoin::receive::v2::PayjoinProposal);
+pub struct ReviewedProposal {
+    proposal: ProvisionalProposal,
+    min_feerate_sat_per_vb: Option<u64>,
+    max_effective_fee_rate_sat_per_vb: Option<u64>,
+}
 
-impl From<PayjoinProposal> for payjoin::receive::v2::PayjoinProposal {
-    fn from(value: PayjoinProposal) -> Self {
-        value.0
+impl ReviewedProposal {
+    pub fn finalize_proposal(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        self.proposal.finalize_proposal(
+            process_psbt,
+            self.min_feerate_sat_per_vb,
+            self.max_effective_fee_rate_sat_per_vb,
+        )
     }
 }
 
-impl From<payjoin::receive::v2::PayjoinProposal> for PayjoinProposal {
-    fn from(value: payjoin::receive::v2::PayjoinProposal) -> Self {
-        Self(value)
+#[derive(Clone)]
+pub struct PayjoinProposal(pub payjoin::receive::v2::PayjoinProposal, pub(crate) ProposalContext);
+
+impl From<PayjoinProposal> for payjoin::receive::v2::PayjoinProposal {
+    fn from(value: PayjoinProposal) -> Self {
+        value.0
     }
 }
 
@@ -420,6 +1682,16 @@ impl PayjoinProposal {
         outpoints
     }
 
+    /// The UTXOs spent by this payjoin with the outputs they spend, and who provided them.
+    ///
+    /// Values come from the proposal as handed to the receiver's signer, so they're known for
+    /// the sender's inputs too. Falls back to the bare outpoints of
+    /// [`PayjoinProposal::utxos_to_be_locked`] if that proposal wasn't recorded.
+    pub fn locked_utxos(&self) -> Vec<LockedUtxo> {
+        let psbt = self.1.proposal_psbt.as_deref().unwrap_or(self.0.psbt());
+        self.1.locked_utxos(psbt)
+    }
+
     pub fn psbt(&self) -> String {
         <PayjoinProposal as Into<payjoin::receive::v2::PayjoinProposal>>::into(self.clone())
             .psbt()
@@ -427,6 +1699,68 @@ impl PayjoinProposal {
             .to_string()
     }
 
+    /// The serialized form of [`PayjoinProposal::psbt`].
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.0.psbt().serialize()
+    }
+
+    /// How the fee of this payjoin is shared between sender and receiver.
+    ///
+    /// Computed from the proposal as handed to the receiver's signer, which still carries the
+    /// UTXO of every input, so it accounts for all contributed inputs and substituted outputs.
+    pub fn fee_split(&self) -> FeeSplit {
+        match &self.1.proposal_psbt {
+            Some(psbt) => self.1.fee_split(psbt),
+            None => {
+                FeeSplit {
+                    original_fee: None,
+                    total_fee: None,
+                    sender_fee: None,
+                    receiver_fee: None,
+                }
+            }
+        }
+    }
+
+    /// The fee of the payjoin transaction in satoshis.
+    pub fn total_fee(&self) -> Option<u64> {
+        self.fee_split().total_fee
+    }
+
+    /// How much more fee the payjoin transaction pays than the Original PSBT, in satoshis.
+    pub fn additional_fee_contribution(&self) -> Option<u64> {
+        let split = self.fee_split();
+        split.total_fee?.checked_sub(split.original_fee?)
+    }
+
+    /// What the receiver's outputs receive net of the inputs it contributed, in satoshis.
+    pub fn receiver_amount_received(&self) -> Option<u64> {
+        self.1.proposal_psbt.as_ref().and_then(|psbt| self.1.receiver_amount(psbt))
+    }
+
+    /// The sender's Original PSBT this proposal was built on, to diff against [`Self::psbt`].
+    ///
+    /// It is rebuilt from the sender's finalized transaction, so it carries each input's final
+    /// script sig and witness but not the UTXOs they spend.
+    pub fn original_psbt(&self) -> String {
+        self.1.original_psbt().to_string()
+    }
+
+    /// Summarize this payjoin for the receiver's transaction history.
+    ///
+    /// `session_id` is the receiver's session identifier as returned by [`Receiver::id`], if
+    /// it should be recorded, and `now` the unix timestamp to record. The fees are those of
+    /// [`PayjoinProposal::fee_split`].
+    pub fn to_tx_record(&self, session_id: Option<String>, now: u64) -> PayjoinTxRecord {
+        PayjoinTxRecord::for_receiver(
+            self.0.psbt(),
+            self.0.owned_vouts(),
+            &self.fee_split(),
+            session_id,
+            now,
+        )
+    }
+
     pub fn extract_v2_req(&self, ohttp_relay: String) -> Result<(Request, ClientResponse), Error> {
         self.0
             .clone()
@@ -441,10 +1775,22 @@ impl PayjoinProposal {
     ///
     /// After this function is called, the receiver can either wait for the Payjoin transaction to be broadcast or choose to broadcast the original PSBT.
     pub fn process_res(&self, body: &[u8], ohttp_context: &ClientResponse) -> Result<(), Error> {
+        let ohttp_context = ohttp_context.take().ok_or(Error::OhttpContextAlreadyUsed)?;
         <PayjoinProposal as Into<payjoin::receive::v2::PayjoinProposal>>::into(self.clone())
-            .process_res(body, ohttp_context.into())
+            .process_res(body, ohttp_context)
             .map_err(|e| e.into())
     }
+
+    /// Mark the Original PSBT's inputs as seen in `store`, so later proposals spending them are
+    /// refused by [`MaybeInputsSeen::check_no_inputs_seen_before_with_store`].
+    ///
+    /// Call this once the proposal is committed, i.e. after [`PayjoinProposal::process_res`]
+    /// succeeds.
+    pub fn mark_inputs_seen(&self, store: &dyn SeenInputsStore) -> Result<(), ImplementationError> {
+        store.mark_seen(
+            self.1.original_tx.input.iter().map(|txin| txin.previous_output.into()).collect(),
+        )
+    }
 }
 
 // #[cfg(test)]
@@ -0,0 +1,359 @@
+use std::sync::Arc;
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::transaction::{predict_weight, InputWeightPrediction};
+use payjoin::bitcoin::{FeeRate, OutPoint, ScriptBuf, Transaction, TxOut, Weight, Witness};
+
+use crate::record::{input_utxo, sum_input_values};
+
+/// Details of a proposal the wrappers carry across typestates, since the underlying typestates
+/// stop exposing the Original PSBT and the receiver's outputs once they've been checked.
+#[derive(Clone, Debug)]
+pub(crate) struct ProposalContext {
+    /// The transaction extracted from the sender's Original PSBT.
+    pub(crate) original_tx: Arc<Transaction>,
+    /// Scripts identified as the receiver's in the Original PSBT.
+    original_receiver_scripts: Vec<ScriptBuf>,
+    /// Scripts of the receiver's outputs after any output substitution.
+    receiver_scripts: Vec<ScriptBuf>,
+    /// Script of the receiver output that receives the value of the receiver's inputs.
+    pub(crate) drain_script: Option<ScriptBuf>,
+    /// Unix time in seconds at which the receiver's session expires, if known.
+    pub(crate) session_expiry: Option<u64>,
+    /// The finalized proposal as handed to the receiver's signer, with the UTXO of every input.
+    pub(crate) proposal_psbt: Option<Arc<Psbt>>,
+    /// The fee parameters of the sender's request.
+    pub(crate) sender_params: SenderParams,
+}
+
+impl ProposalContext {
+    pub(crate) fn new(original_tx: Transaction, session_expiry: Option<u64>) -> Self {
+        Self {
+            original_tx: Arc::new(original_tx),
+            original_receiver_scripts: vec![],
+            receiver_scripts: vec![],
+            drain_script: None,
+            session_expiry,
+            proposal_psbt: None,
+            sender_params: SenderParams::default(),
+        }
+    }
+
+    /// Record the fee parameters of the sender's request.
+    pub(crate) fn with_sender_params(&self, sender_params: SenderParams) -> Self {
+        Self { sender_params, ..self.clone() }
+    }
+
+    /// Record the scripts identified as the receiver's in the Original PSBT, the first of
+    /// which drains the receiver's inputs until the outputs are substituted.
+    pub(crate) fn with_original_receiver_scripts(&self, scripts: Vec<ScriptBuf>) -> Self {
+        Self {
+            original_receiver_scripts: scripts.clone(),
+            drain_script: scripts.first().cloned(),
+            receiver_scripts: scripts,
+            ..self.clone()
+        }
+    }
+
+    /// Record the scripts of the receiver's outputs after they were substituted.
+    pub(crate) fn with_receiver_scripts(
+        &self,
+        scripts: Vec<ScriptBuf>,
+        drain_script: ScriptBuf,
+    ) -> Self {
+        Self { receiver_scripts: scripts, drain_script: Some(drain_script), ..self.clone() }
+    }
+
+    /// The sender's Original PSBT, rebuilt from its finalized transaction.
+    ///
+    /// Each input's signature data is kept as its final script sig and witness. The UTXOs the
+    /// inputs spend aren't part of the transaction, so the rebuilt PSBT doesn't carry them.
+    pub(crate) fn original_psbt(&self) -> Psbt {
+        let mut unsigned_tx = (*self.original_tx).clone();
+        for txin in &mut unsigned_tx.input {
+            txin.script_sig = ScriptBuf::new();
+            txin.witness = Witness::new();
+        }
+        let mut psbt =
+            Psbt::from_unsigned_tx(unsigned_tx).expect("script sigs and witnesses were cleared");
+        for (input, txin) in psbt.inputs.iter_mut().zip(&self.original_tx.input) {
+            if !txin.script_sig.is_empty() {
+                input.final_script_sig = Some(txin.script_sig.clone());
+            }
+            if !txin.witness.is_empty() {
+                input.final_script_witness = Some(txin.witness.clone());
+            }
+        }
+        psbt
+    }
+
+    /// Record the finalized proposal before the receiver signed it.
+    pub(crate) fn with_proposal_psbt(&self, psbt: Psbt) -> Self {
+        Self { proposal_psbt: Some(Arc::new(psbt)), ..self.clone() }
+    }
+
+    pub(crate) fn is_sender_input(&self, outpoint: &OutPoint) -> bool {
+        self.original_tx.input.iter().any(|txin| txin.previous_output == *outpoint)
+    }
+
+    pub(crate) fn is_receiver_output(&self, txout: &TxOut) -> bool {
+        self.receiver_scripts.contains(&txout.script_pubkey)
+    }
+
+    /// The amount the Original PSBT pays the receiver.
+    pub(crate) fn payment(&self) -> u64 {
+        self.original_tx
+            .output
+            .iter()
+            .filter(|txout| self.original_receiver_scripts.contains(&txout.script_pubkey))
+            .map(|txout| txout.value.to_sat())
+            .sum()
+    }
+
+    /// What the receiver's outputs in `psbt` receive net of the receiver's own inputs, or `None`
+    /// if the value of a receiver input is unknown.
+    pub(crate) fn receiver_amount(&self, psbt: &Psbt) -> Option<u64> {
+        let receiver_in = sum_input_values(psbt, |outpoint| !self.is_sender_input(outpoint))?;
+        let receiver_out: u64 = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .filter(|txout| self.is_receiver_output(txout))
+            .map(|txout| txout.value.to_sat())
+            .sum();
+        receiver_out.checked_sub(receiver_in)
+    }
+
+    /// The UTXOs spent by `psbt`, a proposal built on this context's Original PSBT, with the
+    /// outputs they spend where `psbt` still carries them.
+    pub(crate) fn locked_utxos(&self, psbt: &Psbt) -> Vec<LockedUtxo> {
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .enumerate()
+            .map(|(i, txin)| {
+                LockedUtxo {
+                    outpoint: txin.previous_output.into(),
+                    txout: input_utxo(psbt, i).map(Into::into),
+                    receiver_contributed: !self.is_sender_input(&txin.previous_output),
+                }
+            })
+            .collect()
+    }
+
+    /// Preview the fees of `psbt`, a proposal built on this context's Original PSBT that still
+    /// carries the UTXO of every input.
+    pub(crate) fn fee_preview(&self, psbt: &Psbt) -> FeePreview {
+        let split = self.fee_split(psbt);
+        let sender_fee_output = psbt.unsigned_tx.output.iter().position(|txout| {
+            !self.is_receiver_output(txout)
+                && self.original_tx.output.iter().any(|original| {
+                    original.script_pubkey == txout.script_pubkey && original.value > txout.value
+                })
+        });
+        let effective_fee_rate =
+            split.total_fee.zip(self.predict_weight(psbt)).map(|(fee, weight)| {
+                Arc::new(FeeRate::from_sat_per_kwu(fee * 1000 / weight.to_wu().max(1)).into())
+            });
+        FeePreview {
+            receiver_fee: split.receiver_fee,
+            sender_fee_contribution: split
+                .sender_fee
+                .zip(split.original_fee)
+                .and_then(|(sender_fee, original_fee)| sender_fee.checked_sub(original_fee)),
+            effective_fee_rate,
+            sender_fee_output: sender_fee_output.map(|index| index as u32),
+        }
+    }
+
+    /// Predict the weight of `psbt` once signed, using the signatures of the sender's inputs in
+    /// the Original PSBT and worst case signatures for the receiver's inputs.
+    ///
+    /// Returns `None` if a receiver input spends a script type whose signature size is unknown.
+    fn predict_weight(&self, psbt: &Psbt) -> Option<Weight> {
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .enumerate()
+            .map(|(i, txin)| {
+                match self
+                    .original_tx
+                    .input
+                    .iter()
+                    .find(|o| o.previous_output == txin.previous_output)
+                {
+                    Some(signed) => {
+                        Some(InputWeightPrediction::new(
+                            signed.script_sig.len(),
+                            signed.witness.iter().map(|element| element.len()),
+                        ))
+                    }
+                    None => predict_receiver_input(psbt, i),
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let output_script_lens = psbt.unsigned_tx.output.iter().map(|o| o.script_pubkey.len());
+        Some(predict_weight(inputs, output_script_lens))
+    }
+
+    /// Split the fee of `psbt`, a proposal built on this context's Original PSBT.
+    ///
+    /// The sender's inputs are valued from the UTXO data in `psbt`, so fields depending on them
+    /// are `None` once that data has been cleared, as it is in a finalized proposal.
+    pub(crate) fn fee_split(&self, psbt: &Psbt) -> FeeSplit {
+        let sender_in = sum_input_values(psbt, |outpoint| self.is_sender_input(outpoint));
+        let receiver_in = sum_input_values(psbt, |outpoint| !self.is_sender_input(outpoint));
+        let (receiver_outputs, sender_outputs): (Vec<_>, Vec<_>) =
+            psbt.unsigned_tx.output.iter().partition(|txout| self.is_receiver_output(txout));
+        let receiver_out: u64 = receiver_outputs.iter().map(|txout| txout.value.to_sat()).sum();
+        let sender_out: u64 = sender_outputs.iter().map(|txout| txout.value.to_sat()).sum();
+        let original_out: u64 = self.original_tx.output.iter().map(|o| o.value.to_sat()).sum();
+        let payment = self.payment();
+
+        FeeSplit {
+            original_fee: sender_in.and_then(|sender_in| sender_in.checked_sub(original_out)),
+            total_fee: sender_in
+                .zip(receiver_in)
+                .and_then(|(s, r)| (s + r).checked_sub(sender_out + receiver_out)),
+            sender_fee: sender_in
+                .and_then(|sender_in| sender_in.checked_sub(sender_out)?.checked_sub(payment)),
+            receiver_fee: receiver_in
+                .and_then(|receiver_in| (receiver_in + payment).checked_sub(receiver_out)),
+        }
+    }
+}
+
+/// Worst case size of the signature data of the receiver's input at `index` in `psbt`.
+fn predict_receiver_input(psbt: &Psbt, index: usize) -> Option<InputWeightPrediction> {
+    let script = input_utxo(psbt, index)?.script_pubkey;
+    if script.is_p2wpkh() {
+        Some(InputWeightPrediction::P2WPKH_MAX)
+    } else if script.is_p2tr() {
+        Some(InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH)
+    } else if script.is_p2pkh() {
+        Some(InputWeightPrediction::P2PKH_COMPRESSED_MAX)
+    } else if script.is_p2sh()
+        && psbt.inputs[index].redeem_script.as_ref().is_some_and(|redeem| redeem.is_p2wpkh())
+    {
+        // The script sig pushes the 22 byte P2WPKH redeem script
+        Some(InputWeightPrediction::new(23, [72, 33]))
+    } else {
+        None
+    }
+}
+
+/// The fee parameters the sender put in its request, as opposed to what the receiver applies.
+///
+/// A proposal whose receiver fee exceeds `max_additional_fee_contribution`, or whose fee rate
+/// falls below `min_fee_rate_sat_per_vb`, is rejected by the sender.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct SenderParams {
+    /// The most the sender will pay, in satoshis, towards the fee of the receiver's additions.
+    pub max_additional_fee_contribution: Option<u64>,
+    /// Index of the sender's output the additional fee contribution is drawn from.
+    pub additional_fee_output_index: Option<u32>,
+    /// The minimum fee rate of the payjoin, rounded up to whole sat/vB.
+    pub min_fee_rate_sat_per_vb: Option<u64>,
+    /// Whether the sender forbids the receiver from changing its outputs' scripts or lowering
+    /// their amounts.
+    pub output_substitution_disabled: bool,
+}
+
+impl SenderParams {
+    /// Read the parameters from the query string of a BIP78 request.
+    ///
+    /// Malformed values are left out, as payjoin ignores them.
+    pub(crate) fn from_query(query: &str) -> Self {
+        let mut params = Self::default();
+        for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match name.as_ref() {
+                "maxadditionalfeecontribution" => {
+                    params.max_additional_fee_contribution = value.parse().ok()
+                }
+                "additionalfeeoutputindex" => {
+                    params.additional_fee_output_index = value.parse().ok()
+                }
+                "minfeerate" => {
+                    params.min_fee_rate_sat_per_vb = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|rate| rate.is_finite() && *rate > 0.0)
+                        .map(|rate| rate.ceil() as u64)
+                }
+                _ => {}
+            }
+        }
+        params
+    }
+
+    /// Read the parameters of a v2 proposal from its serialized form.
+    ///
+    /// The v2 typestates keep the parameters private; this reads the `params` payjoin persists
+    /// with the proposal, failing if they aren't there. A minimum fee rate of zero is reported
+    /// as absent, since senders that omit it are recorded with one.
+    pub(crate) fn from_v2_proposal(
+        proposal: &payjoin::receive::v2::UncheckedProposal,
+    ) -> Result<Self, serde_json::Error> {
+        let proposal = serde_json::to_value(proposal)?;
+        let params = proposal.pointer("/v1/params").cloned().unwrap_or_default();
+        let contribution: Option<(u64, u32)> =
+            serde_json::from_value(params["additional_fee_contribution"].clone())?;
+        let min_fee_rate: u64 = serde_json::from_value(params["min_fee_rate"].clone())?;
+        let output_substitution: String =
+            serde_json::from_value(params["output_substitution"].clone())?;
+        Ok(Self {
+            max_additional_fee_contribution: contribution.map(|(amount, _)| amount),
+            additional_fee_output_index: contribution.map(|(_, index)| index),
+            min_fee_rate_sat_per_vb: Some(min_fee_rate)
+                .filter(|sat_per_kwu| *sat_per_kwu > 0)
+                .map(|sat_per_kwu| FeeRate::from_sat_per_kwu(sat_per_kwu).to_sat_per_vb_ceil()),
+            output_substitution_disabled: output_substitution == "Disabled",
+        })
+    }
+}
+
+/// What a proposal will cost once finalized, previewed before the receiver signs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct FeePreview {
+    /// Additional fee in satoshis the receiver pays for the inputs and outputs it contributes,
+    /// or `None` if the value of a receiver input is unknown.
+    pub receiver_fee: Option<u64>,
+    /// Fee in satoshis the sender contributes on top of the Original PSBT's fee.
+    pub sender_fee_contribution: Option<u64>,
+    /// Fee rate of the finalized payjoin transaction, predicting worst case signatures for the
+    /// receiver's inputs, or `None` if their script type isn't known.
+    pub effective_fee_rate: Option<Arc<crate::FeeRate>>,
+    /// Index of the sender's output the sender's fee contribution is drawn from, if any.
+    pub sender_fee_output: Option<u32>,
+}
+
+/// How the fee of a payjoin is shared between its parties, in satoshis.
+///
+/// Each field is `None` when the UTXO data required to compute it is unavailable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct FeeSplit {
+    /// Fee paid by the Original PSBT.
+    pub original_fee: Option<u64>,
+    /// Fee paid by the payjoin transaction.
+    pub total_fee: Option<u64>,
+    /// Share of `total_fee` paid by the sender.
+    pub sender_fee: Option<u64>,
+    /// Share of `total_fee` paid by the receiver.
+    pub receiver_fee: Option<u64>,
+}
+
+/// A UTXO spent by a payjoin, to be locked so the wallet doesn't spend it elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct LockedUtxo {
+    pub outpoint: crate::bitcoin_ffi::OutPoint,
+    /// The output being spent, or `None` if the proposal doesn't carry it.
+    pub txout: Option<crate::bitcoin_ffi::TxOut>,
+    /// Whether the receiver contributed the UTXO, as opposed to the sender providing it.
+    pub receiver_contributed: bool,
+}
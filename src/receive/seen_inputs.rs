@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use super::ImplementationError;
+use crate::bitcoin_ffi::OutPoint;
+
+/// Remembers which outpoints the receiver has committed to in payjoin proposals.
+///
+/// Used by [`super::MaybeInputsSeen::check_no_inputs_seen_before_with_store`] and
+/// [`super::PayjoinProposal::mark_inputs_seen`] so that each integrator doesn't reimplement the
+/// bookkeeping.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+pub trait SeenInputsStore: Send + Sync {
+    /// Whether `outpoint` has been marked as seen. Must not change the store.
+    fn is_seen(&self, outpoint: OutPoint) -> Result<bool, ImplementationError>;
+
+    /// Mark `outpoints` as seen, so later proposals spending them are refused.
+    ///
+    /// Marking an outpoint that is already seen must succeed. Persist the marks before
+    /// returning so they survive a restart.
+    fn mark_seen(&self, outpoints: Vec<OutPoint>) -> Result<(), ImplementationError>;
+}
+
+/// A [`SeenInputsStore`] that keeps outpoints in memory for the lifetime of the process.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct InMemorySeenInputs(Mutex<HashSet<payjoin::bitcoin::OutPoint>>);
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl InMemorySeenInputs {
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl SeenInputsStore for InMemorySeenInputs {
+    fn is_seen(&self, outpoint: OutPoint) -> Result<bool, ImplementationError> {
+        let seen = self.0.lock().map_err(|_| "seen inputs store poisoned".to_string())?;
+        Ok(seen.contains(&outpoint.into()))
+    }
+
+    fn mark_seen(&self, outpoints: Vec<OutPoint>) -> Result<(), ImplementationError> {
+        let mut seen = self.0.lock().map_err(|_| "seen inputs store poisoned".to_string())?;
+        seen.extend(outpoints.into_iter().map(payjoin::bitcoin::OutPoint::from));
+        Ok(())
+    }
+}
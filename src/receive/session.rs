@@ -0,0 +1,490 @@
+//! Typestates wrapping either protocol version, so a receiver's approval pipeline can be
+//! written once for proposals from both BIP78 senders and the BIP77 directory.
+//!
+//! Each stage exposes the methods the two protocols share. Protocol specific steps, such as
+//! replying through the directory, are reached through the `v1()` and `v2()` accessors.
+
+use super::error::{
+    ImplementationError, InputContributionError, OutputSubstitutionError, ReplyableError,
+    SelectionError,
+};
+use super::{v1, InputPair, SenderParams};
+use crate::bitcoin_ffi::{OutPoint, Script, TxOut};
+use crate::TransactionView;
+
+/// The sender's Original PSBT, received over either protocol version.
+#[derive(Clone)]
+pub enum ReceiveSession {
+    V1(v1::UncheckedProposal),
+    V2(super::UncheckedProposal),
+}
+
+impl From<v1::UncheckedProposal> for ReceiveSession {
+    fn from(value: v1::UncheckedProposal) -> Self {
+        Self::V1(value)
+    }
+}
+
+impl From<super::UncheckedProposal> for ReceiveSession {
+    fn from(value: super::UncheckedProposal) -> Self {
+        Self::V2(value)
+    }
+}
+
+impl ReceiveSession {
+    /// Whether the proposal came through a BIP77 directory.
+    pub fn is_v2(&self) -> bool {
+        matches!(self, Self::V2(_))
+    }
+
+    /// The v1 proposal, if the sender posted it directly.
+    pub fn v1(&self) -> Option<v1::UncheckedProposal> {
+        match self {
+            Self::V1(proposal) => Some(proposal.clone()),
+            Self::V2(_) => None,
+        }
+    }
+
+    /// The v2 proposal, if it came through a directory, e.g. to reply with `extract_err_req`.
+    pub fn v2(&self) -> Option<super::UncheckedProposal> {
+        match self {
+            Self::V1(_) => None,
+            Self::V2(proposal) => Some(proposal.clone()),
+        }
+    }
+
+    /// Whether the sender disabled output substitution.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        match self {
+            Self::V1(proposal) => proposal.is_output_substitution_disabled(),
+            Self::V2(proposal) => proposal.is_output_substitution_disabled(),
+        }
+    }
+
+    /// The Sender's Original PSBT
+    pub fn extract_tx_to_schedule_broadcast(&self) -> Vec<u8> {
+        match self {
+            Self::V1(proposal) => proposal.extract_tx_to_schedule_broadcast(),
+            Self::V2(proposal) => proposal.extract_tx_to_schedule_broadcast(),
+        }
+    }
+
+    /// `min_fee_rate` is in sat/kwu.
+    pub fn check_broadcast_suitability(
+        &self,
+        min_fee_rate: Option<u64>,
+        can_broadcast: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<SessionMaybeInputsOwned, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .check_broadcast_suitability(min_fee_rate, can_broadcast)
+                    .map(SessionMaybeInputsOwned::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .check_broadcast_suitability(min_fee_rate, can_broadcast)
+                    .map(SessionMaybeInputsOwned::V2)
+            }
+        }
+    }
+
+    /// Like [`ReceiveSession::check_broadcast_suitability`], with the minimum fee rate of the
+    /// Original PSBT given in an explicit unit.
+    pub fn check_broadcast_suitability_with_fee_rate(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        can_broadcast: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<SessionMaybeInputsOwned, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .check_broadcast_suitability_with_fee_rate(min_fee_rate, can_broadcast)
+                    .map(SessionMaybeInputsOwned::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .check_broadcast_suitability_with_fee_rate(min_fee_rate, can_broadcast)
+                    .map(SessionMaybeInputsOwned::V2)
+            }
+        }
+    }
+
+    /// Like [`ReceiveSession::check_broadcast_suitability`], handing `can_broadcast` the
+    /// transaction's bytes, hex and txid.
+    pub fn check_broadcast_suitability_with_view(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        can_broadcast: impl Fn(&TransactionView) -> Result<bool, ImplementationError>,
+    ) -> Result<SessionMaybeInputsOwned, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .check_broadcast_suitability_with_view(min_fee_rate, can_broadcast)
+                    .map(SessionMaybeInputsOwned::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .check_broadcast_suitability_with_view(min_fee_rate, can_broadcast)
+                    .map(SessionMaybeInputsOwned::V2)
+            }
+        }
+    }
+
+    /// Skip the broadcast check, for receivers that only payjoin after manual intervention.
+    pub fn assume_interactive_receiver(&self) -> SessionMaybeInputsOwned {
+        match self {
+            Self::V1(proposal) => {
+                SessionMaybeInputsOwned::V1(proposal.assume_interactive_receiver())
+            }
+            Self::V2(proposal) => {
+                SessionMaybeInputsOwned::V2(proposal.assume_interactive_receiver())
+            }
+        }
+    }
+}
+
+/// [`super::MaybeInputsOwned`] of either protocol version.
+#[derive(Clone)]
+pub enum SessionMaybeInputsOwned {
+    V1(v1::MaybeInputsOwned),
+    V2(super::MaybeInputsOwned),
+}
+
+impl SessionMaybeInputsOwned {
+    /// Check that the Original PSBT has no receiver-owned inputs.
+    pub fn check_inputs_not_owned(
+        &self,
+        is_owned: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<SessionMaybeInputsSeen, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal.check_inputs_not_owned(is_owned).map(SessionMaybeInputsSeen::V1)
+            }
+            Self::V2(proposal) => {
+                proposal.check_inputs_not_owned(is_owned).map(SessionMaybeInputsSeen::V2)
+            }
+        }
+    }
+}
+
+/// [`super::MaybeInputsSeen`] of either protocol version.
+#[derive(Clone)]
+pub enum SessionMaybeInputsSeen {
+    V1(v1::MaybeInputsSeen),
+    V2(super::MaybeInputsSeen),
+}
+
+impl SessionMaybeInputsSeen {
+    /// Make sure that the original transaction inputs have never been seen before.
+    pub fn check_no_inputs_seen_before(
+        &self,
+        is_known: impl Fn(&OutPoint) -> Result<bool, ImplementationError>,
+    ) -> Result<SessionOutputsUnknown, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal.check_no_inputs_seen_before(is_known).map(SessionOutputsUnknown::V1)
+            }
+            Self::V2(proposal) => {
+                proposal.check_no_inputs_seen_before(is_known).map(SessionOutputsUnknown::V2)
+            }
+        }
+    }
+}
+
+/// [`super::OutputsUnknown`] of either protocol version.
+#[derive(Clone)]
+pub enum SessionOutputsUnknown {
+    V1(v1::OutputsUnknown),
+    V2(super::OutputsUnknown),
+}
+
+impl SessionOutputsUnknown {
+    /// Find which outputs belong to the receiver
+    pub fn identify_receiver_outputs(
+        &self,
+        is_receiver_output: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<SessionWantsOutputs, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal.identify_receiver_outputs(is_receiver_output).map(SessionWantsOutputs::V1)
+            }
+            Self::V2(proposal) => {
+                proposal.identify_receiver_outputs(is_receiver_output).map(SessionWantsOutputs::V2)
+            }
+        }
+    }
+}
+
+/// [`super::WantsOutputs`] of either protocol version.
+pub enum SessionWantsOutputs {
+    V1(v1::WantsOutputs),
+    V2(super::WantsOutputs),
+}
+
+impl SessionWantsOutputs {
+    pub fn output_substitution(&self) -> bool {
+        match self {
+            Self::V1(proposal) => proposal.output_substitution(),
+            Self::V2(proposal) => proposal.output_substitution(),
+        }
+    }
+
+    /// Replace the receiver's outputs with `replacement_outputs`, sending any excess to
+    /// `drain_script`.
+    pub fn replace_receiver_outputs(
+        &self,
+        replacement_outputs: Vec<TxOut>,
+        drain_script: &Script,
+    ) -> Result<SessionWantsOutputs, OutputSubstitutionError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .replace_receiver_outputs(replacement_outputs, drain_script)
+                    .map(SessionWantsOutputs::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .replace_receiver_outputs(replacement_outputs, drain_script)
+                    .map(SessionWantsOutputs::V2)
+            }
+        }
+    }
+
+    /// Pay the receiver's outputs to `output_script` instead, keeping their amounts.
+    pub fn substitute_receiver_script(
+        &self,
+        output_script: &Script,
+    ) -> Result<SessionWantsOutputs, OutputSubstitutionError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal.substitute_receiver_script(output_script).map(SessionWantsOutputs::V1)
+            }
+            Self::V2(proposal) => {
+                proposal.substitute_receiver_script(output_script).map(SessionWantsOutputs::V2)
+            }
+        }
+    }
+
+    /// Proceed to input contribution with the current outputs.
+    pub fn commit_outputs(&self) -> SessionWantsInputs {
+        match self {
+            Self::V1(proposal) => SessionWantsInputs::V1(proposal.commit_outputs()),
+            Self::V2(proposal) => SessionWantsInputs::V2(proposal.commit_outputs()),
+        }
+    }
+}
+
+/// [`super::WantsInputs`] of either protocol version.
+pub enum SessionWantsInputs {
+    V1(v1::WantsInputs),
+    V2(super::WantsInputs),
+}
+
+impl SessionWantsInputs {
+    /// The fee parameters of the sender's request, to size the receiver's contribution.
+    pub fn sender_params(&self) -> SenderParams {
+        match self {
+            Self::V1(proposal) => proposal.sender_params(),
+            Self::V2(proposal) => proposal.sender_params(),
+        }
+    }
+
+    /// Select the receiver input that best avoids the unnecessary input heuristic.
+    pub fn try_preserving_privacy(
+        &self,
+        candidate_inputs: Vec<InputPair>,
+    ) -> Result<InputPair, SelectionError> {
+        match self {
+            Self::V1(proposal) => proposal.try_preserving_privacy(candidate_inputs),
+            Self::V2(proposal) => proposal.try_preserving_privacy(candidate_inputs),
+        }
+    }
+
+    /// Every candidate `try_preserving_privacy` would accept, best first.
+    pub fn try_preserving_privacy_ranked(
+        &self,
+        candidate_inputs: Vec<InputPair>,
+    ) -> Result<Vec<InputPair>, SelectionError> {
+        match self {
+            Self::V1(proposal) => proposal.try_preserving_privacy_ranked(candidate_inputs),
+            Self::V2(proposal) => proposal.try_preserving_privacy_ranked(candidate_inputs),
+        }
+    }
+
+    /// Add the given receiver inputs to the proposal, adjusting the drain output accordingly.
+    pub fn contribute_inputs(
+        &self,
+        replacement_inputs: Vec<InputPair>,
+    ) -> Result<SessionWantsInputs, InputContributionError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal.contribute_inputs(replacement_inputs).map(SessionWantsInputs::V1)
+            }
+            Self::V2(proposal) => {
+                proposal.contribute_inputs(replacement_inputs).map(SessionWantsInputs::V2)
+            }
+        }
+    }
+
+    /// Proceed to finalization with the current inputs.
+    pub fn commit_inputs(&self) -> SessionProvisionalProposal {
+        match self {
+            Self::V1(proposal) => SessionProvisionalProposal::V1(proposal.commit_inputs()),
+            Self::V2(proposal) => SessionProvisionalProposal::V2(proposal.commit_inputs()),
+        }
+    }
+}
+
+/// [`super::ProvisionalProposal`] of either protocol version.
+#[derive(Clone)]
+pub enum SessionProvisionalProposal {
+    V1(v1::ProvisionalProposal),
+    V2(super::ProvisionalProposal),
+}
+
+impl SessionProvisionalProposal {
+    /// The fee parameters of the sender's request.
+    pub fn sender_params(&self) -> SenderParams {
+        match self {
+            Self::V1(proposal) => proposal.sender_params(),
+            Self::V2(proposal) => proposal.sender_params(),
+        }
+    }
+
+    /// Sign the proposal with `process_psbt`, which receives and returns a base64 PSBT.
+    pub fn finalize_proposal(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<SessionPayjoinProposal, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .finalize_proposal(
+                        process_psbt,
+                        min_feerate_sat_per_vb,
+                        max_effective_fee_rate_sat_per_vb,
+                    )
+                    .map(SessionPayjoinProposal::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .finalize_proposal(
+                        process_psbt,
+                        min_feerate_sat_per_vb,
+                        max_effective_fee_rate_sat_per_vb,
+                    )
+                    .map(SessionPayjoinProposal::V2)
+            }
+        }
+    }
+
+    /// Like [`SessionProvisionalProposal::finalize_proposal`], with fee rates given in an
+    /// explicit unit.
+    pub fn finalize_proposal_with_fee_rates(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+        min_fee_rate: Option<crate::FeeRate>,
+        max_effective_fee_rate: Option<crate::FeeRate>,
+    ) -> Result<SessionPayjoinProposal, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .finalize_proposal_with_fee_rates(
+                        process_psbt,
+                        min_fee_rate,
+                        max_effective_fee_rate,
+                    )
+                    .map(SessionPayjoinProposal::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .finalize_proposal_with_fee_rates(
+                        process_psbt,
+                        min_fee_rate,
+                        max_effective_fee_rate,
+                    )
+                    .map(SessionPayjoinProposal::V2)
+            }
+        }
+    }
+
+    /// Sign the proposal with `process_psbt`, which receives and returns a serialized PSBT.
+    pub fn finalize_proposal_bytes(
+        &self,
+        process_psbt: impl Fn(Vec<u8>) -> Result<Vec<u8>, ImplementationError>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<SessionPayjoinProposal, ReplyableError> {
+        match self {
+            Self::V1(proposal) => {
+                proposal
+                    .finalize_proposal_bytes(
+                        process_psbt,
+                        min_feerate_sat_per_vb,
+                        max_effective_fee_rate_sat_per_vb,
+                    )
+                    .map(SessionPayjoinProposal::V1)
+            }
+            Self::V2(proposal) => {
+                proposal
+                    .finalize_proposal_bytes(
+                        process_psbt,
+                        min_feerate_sat_per_vb,
+                        max_effective_fee_rate_sat_per_vb,
+                    )
+                    .map(SessionPayjoinProposal::V2)
+            }
+        }
+    }
+}
+
+/// [`super::PayjoinProposal`] of either protocol version.
+///
+/// Return a v1 proposal with the `body()` of [`SessionPayjoinProposal::v1`], and post a v2
+/// proposal to the directory with the `extract_v2_req()` of [`SessionPayjoinProposal::v2`].
+#[derive(Clone)]
+pub enum SessionPayjoinProposal {
+    V1(v1::PayjoinProposal),
+    V2(super::PayjoinProposal),
+}
+
+impl SessionPayjoinProposal {
+    /// Whether the proposal is returned through a BIP77 directory.
+    pub fn is_v2(&self) -> bool {
+        matches!(self, Self::V2(_))
+    }
+
+    /// The v1 proposal, to answer the sender's request with.
+    pub fn v1(&self) -> Option<v1::PayjoinProposal> {
+        match self {
+            Self::V1(proposal) => Some(proposal.clone()),
+            Self::V2(_) => None,
+        }
+    }
+
+    /// The v2 proposal, to post to the directory.
+    pub fn v2(&self) -> Option<super::PayjoinProposal> {
+        match self {
+            Self::V1(_) => None,
+            Self::V2(proposal) => Some(proposal.clone()),
+        }
+    }
+
+    /// The signed Payjoin PSBT, base64 encoded.
+    pub fn psbt(&self) -> String {
+        match self {
+            Self::V1(proposal) => proposal.psbt(),
+            Self::V2(proposal) => proposal.psbt(),
+        }
+    }
+
+    pub fn utxos_to_be_locked(&self) -> Vec<OutPoint> {
+        match self {
+            Self::V1(proposal) => proposal.utxos_to_be_locked(),
+            Self::V2(proposal) => proposal.utxos_to_be_locked(),
+        }
+    }
+}
@@ -1,13 +1,21 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use super::v1::Headers;
 use super::InputPair;
 use crate::bitcoin_ffi::{Address, OutPoint, Script, TxOut};
+use crate::directory::DirectoryCapabilities;
 pub use crate::receive::{
-    Error, ImplementationError, InputContributionError, JsonReply, OutputSubstitutionError,
-    ReplyableError, SelectionError, SerdeJsonError, SessionError,
+    BatchCheckError, Error, FallbackTransaction, FeePreview, FeeSplit, FinalizeError,
+    ImplementationError, InMemorySeenInputs, InputContributionError, InputSeenError, JsonReply,
+    KeysSource, LockedUtxo, NonWitnessInputError, OutputSubstitutionError, ReceiverErrorResponse,
+    ReceiverPersistError, ReplyableError, ReviewBundle, ReviewError, ScheduledBroadcast,
+    SeenInputsStore, SelectionError, SenderParams, SerdeJsonError, SessionError, V1RequestError,
+    WellKnownError,
 };
 use crate::uri::error::IntoUrlError;
-use crate::{ClientResponse, OhttpKeys, Request};
+use crate::uri::{InvalidAmount, Url};
+use crate::{ClientResponse, FeeRate, OhttpKeys, PayjoinTxRecord, Request, TransactionView};
 
 #[derive(Clone, Debug, uniffi::Object)]
 pub struct Receiver(pub super::Receiver);
@@ -29,7 +37,9 @@ impl Receiver {
     /// Creates a new `SessionInitializer` with the provided parameters.
     ///
     /// # Parameters
-    /// - `address`: The Bitcoin address for the payjoin session.
+    /// - `address`: The Bitcoin address for the payjoin session. `Address::new` checks the
+    ///   address against the network it is given, so an address can't be paired with the wrong
+    ///   network here.
     /// - `directory`: The URL of the store-and-forward payjoin directory.
     /// - `ohttp_keys`: The OHTTP keys used for encrypting and decrypting HTTP requests and responses.
     /// - `ohttp_relay`: The URL of the OHTTP relay, used to keep client IP address confidential.
@@ -51,10 +61,59 @@ impl Receiver {
             .map(Into::into)
     }
 
-    /// The contents of the `&pj=` query parameter including the base64url-encoded public key receiver subdirectory.
-    /// This identifies a session at the payjoin directory server.
-    pub fn pj_uri(&self) -> crate::PjUri {
-        self.0.pj_uri()
+    /// Like `new()`, but caps `expire_after` at the session lifetime `capabilities` allows.
+    /// The effective expiry is then available from `expiration()`.
+    #[uniffi::constructor]
+    pub fn new_for_directory(
+        address: Arc<Address>,
+        directory: String,
+        ohttp_keys: Arc<OhttpKeys>,
+        expire_after: Option<u64>,
+        capabilities: DirectoryCapabilities,
+    ) -> Result<Self, IntoUrlError> {
+        super::Receiver::new_for_directory(
+            (*address).clone(),
+            directory,
+            (*ohttp_keys).clone(),
+            expire_after,
+            &capabilities,
+        )
+        .map(Into::into)
+    }
+
+    /// Build a BIP21 URI for this session, ready to render as a QR code.
+    ///
+    /// `amount_sats`, `label` and `message` become the URI's BIP21 parameters. The `pj`
+    /// parameter includes the base64url-encoded public key receiver subdirectory, which
+    /// identifies a session at the payjoin directory server. Amounts above 21,000,000 BTC are
+    /// rejected.
+    pub fn pj_uri(
+        &self,
+        amount_sats: Option<u64>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> Result<crate::PjUri, InvalidAmount> {
+        self.0.pj_uri(amount_sats, label, message)
+    }
+
+    /// The session's BIP21 URI with no amount, label or message, the same as `pj_uri_builder()`
+    /// builds when no parameter is set.
+    pub fn session_pj_uri(&self) -> crate::PjUri {
+        self.0.session_pj_uri()
+    }
+
+    /// A builder for the session's BIP21 URI, to set its amount, label and message one by one.
+    pub fn pj_uri_builder(&self) -> crate::uri::PjUriBuilder {
+        self.0.pj_uri_builder()
+    }
+
+    /// The session's pj URI requesting `amount_sats`, e.g. for the next invoice of a point of
+    /// sale that reuses one session.
+    ///
+    /// The `pj` endpoint and its parameters are the same as in every other URI of the session.
+    /// Zero amounts and amounts above 21,000,000 BTC are rejected.
+    pub fn update_pj_uri(&self, amount_sats: u64) -> Result<crate::PjUri, InvalidAmount> {
+        self.0.update_pj_uri(amount_sats)
     }
 
     pub fn extract_req(&self, ohttp_relay: String) -> Result<RequestResponse, Error> {
@@ -63,32 +122,166 @@ impl Receiver {
             .map(|(request, ctx)| RequestResponse { request, client_response: Arc::new(ctx) })
     }
 
-    ///The response can either be an UncheckedProposal or an ACCEPTED message indicating no UncheckedProposal is available yet.
+    /// Like `extract_req()`, for a poll request the directory may hold open for up to
+    /// `wait_secs` seconds until the sender's proposal arrives.
+    ///
+    /// The directory decides how long it holds a poll open. `wait_secs` bounds how long the
+    /// caller waits and is returned as the request's `timeout_secs`, shortened to the time left
+    /// before the session expires. If `capabilities` says the directory doesn't long-poll, this
+    /// is a plain poll request as from `extract_req()`.
+    pub fn extract_req_with_wait(
+        &self,
+        ohttp_relay: String,
+        wait_secs: u64,
+        capabilities: DirectoryCapabilities,
+    ) -> Result<RequestResponse, Error> {
+        self.0
+            .extract_req_with_wait(ohttp_relay, wait_secs, &capabilities)
+            .map(|(request, ctx)| RequestResponse { request, client_response: Arc::new(ctx) })
+    }
+
+    /// Process the directory's response to a poll request.
+    ///
+    /// The response is either the sender's proposal or an ACCEPTED message indicating no
+    /// proposal is available yet. Once the session has expired the response is not processed and
+    /// `ReceiverResponse::SessionExpired` tells the caller to stop polling.
     pub fn process_res(
         &self,
         body: &[u8],
         context: Arc<ClientResponse>,
-    ) -> Result<Option<Arc<UncheckedProposal>>, Error> {
+    ) -> Result<ReceiverResponse, Error> {
         <Self as Into<super::Receiver>>::into(self.clone())
             .process_res(body, context.as_ref())
-            .map(|e| e.map(|x| Arc::new(x.into())))
+            .map(Into::into)
     }
 
     ///The per-session public key to use as an identifier
+    ///
+    /// This is the short id derived from the session public key that names the session's
+    /// mailbox on the directory, and it appears as the last path segment of the pj URL.
     pub fn id(&self) -> String {
         self.0.id()
     }
 
+    /// The session public key `id()` is derived from, in compressed SEC1 encoding.
+    pub fn id_bytes(&self) -> Option<Vec<u8>> {
+        self.0.id_bytes()
+    }
+
+    /// The first few characters of `id()`, for logs.
+    ///
+    /// It is a prefix of the pj URL's last path segment, so log lines can be matched to the
+    /// URL a sender was given.
+    pub fn short_id(&self) -> String {
+        self.0.short_id()
+    }
+
+    /// The payjoin directory the session's messages are relayed through.
+    pub fn directory(&self) -> Arc<Url> {
+        Arc::new(self.0.directory())
+    }
+
+    /// The directory's OHTTP keys the session encapsulates its requests to.
+    pub fn ohttp_keys(&self) -> Option<Arc<OhttpKeys>> {
+        self.0.ohttp_keys().map(Arc::new)
+    }
+
+    /// Where the session's OHTTP keys came from.
+    pub fn keys_source(&self) -> KeysSource {
+        self.0.keys_source()
+    }
+
+    /// Record that the OHTTP keys this receiver was created with were fetched from the
+    /// directory at unix time `at`.
+    pub fn with_keys_fetched_at(&self, at: u64) -> Self {
+        self.0.with_keys_fetched_at(at).into()
+    }
+
+    /// Replace the session's OHTTP keys, e.g. after the directory rotated them, without
+    /// starting a new session.
+    ///
+    /// `now` is the unix time recorded as `KeysSource::Refreshed`.
+    pub fn refresh_ohttp_keys(
+        &self,
+        ohttp_keys: Arc<OhttpKeys>,
+        now: u64,
+    ) -> Result<Self, SerdeJsonError> {
+        self.0.refresh_ohttp_keys((*ohttp_keys).clone(), now).map(Into::into)
+    }
+
+    /// The unix timestamp in seconds at which the session expires.
+    ///
+    /// Returns `None` if the expiry cannot be read from the session context.
+    pub fn expiration(&self) -> Option<u64> {
+        self.0.expiration()
+    }
+
+    /// Whether the session has expired, after which the directory will no longer relay it.
+    pub fn is_expired(&self) -> bool {
+        self.0.is_expired()
+    }
+
+    /// Keep the signatures collected so far with the session, so `to_json()` persists them and
+    /// a restored receiver can `resume_finalize()`.
+    pub fn with_pending_signatures(&self, pending: Arc<PendingSignatures>) -> Self {
+        self.0.with_pending_signatures(&pending.0).into()
+    }
+
+    /// Resume finalizing `proposal` from the signatures persisted with the session, or `None` if
+    /// there are none.
+    ///
+    /// After a restore, poll the directory again for the sender's proposal and take it through
+    /// the same checks and contributions to get `proposal`.
+    pub fn resume_finalize(
+        &self,
+        proposal: Arc<ProvisionalProposal>,
+    ) -> Result<Option<Arc<PendingSignatures>>, FinalizeError> {
+        self.0.resume_finalize(&proposal.0).map(|p| p.map(|p| Arc::new(p.into())))
+    }
+
     pub fn to_json(&self) -> Result<String, SerdeJsonError> {
         self.0.to_json()
     }
 
+    /// Restore a receiver persisted with `to_json()`.
+    ///
+    /// The restored receiver resumes polling with a fresh `extract_req()`. Sessions that have
+    /// already expired are rejected.
+    ///
+    /// A `ClientResponse` can't be persisted, so a response to a request extracted before the
+    /// restart is lost. The directory keeps the sender's message until the session expires, so
+    /// the fresh request receives it again.
     #[uniffi::constructor]
-    pub fn from_json(json: &str) -> Result<Self, SerdeJsonError> {
+    pub fn from_json(json: &str) -> Result<Self, ReceiverPersistError> {
         super::Receiver::from_json(json).map(Into::into)
     }
 }
 
+/// The outcome of polling the directory for the sender's proposal.
+#[derive(uniffi::Enum)]
+pub enum ReceiverResponse {
+    /// The sender's proposal arrived.
+    Proposal(Arc<UncheckedProposal>),
+    /// The directory has no proposal yet. Poll again.
+    NoProposalYet,
+    /// The session expired at the given unix timestamp. Stop polling.
+    SessionExpired { expired_at: u64 },
+}
+
+impl From<super::ReceiverResponse> for ReceiverResponse {
+    fn from(value: super::ReceiverResponse) -> Self {
+        match value {
+            super::ReceiverResponse::Proposal(proposal) => {
+                ReceiverResponse::Proposal(Arc::new(proposal.into()))
+            }
+            super::ReceiverResponse::NoProposalYet => ReceiverResponse::NoProposalYet,
+            super::ReceiverResponse::SessionExpired { expired_at } => {
+                ReceiverResponse::SessionExpired { expired_at }
+            }
+        }
+    }
+}
+
 #[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
 pub struct RequestResponse {
     pub request: Request,
@@ -100,6 +293,12 @@ pub trait CanBroadcast: Send + Sync {
     fn callback(&self, tx: Vec<u8>) -> Result<bool, ImplementationError>;
 }
 
+/// Like `CanBroadcast`, receiving the transaction's bytes, hex and txid.
+#[uniffi::export]
+pub trait CanBroadcastTransaction: Send + Sync {
+    fn callback(&self, tx: Arc<TransactionView>) -> Result<bool, ImplementationError>;
+}
+
 /// The sender’s original PSBT and optional parameters
 ///
 /// This type is used to proces the request. It is returned by UncheckedProposal::from_request().
@@ -116,11 +315,32 @@ impl From<super::UncheckedProposal> for UncheckedProposal {
 
 #[uniffi::export]
 impl UncheckedProposal {
+    /// Whether the sender disabled output substitution, known as soon as the proposal arrives.
+    ///
+    /// A receiver that would substitute its output can skip deriving a new address if so.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.0.is_output_substitution_disabled()
+    }
+
     /// The Sender’s Original PSBT
     pub fn extract_tx_to_schedule_broadcast(&self) -> Vec<u8> {
         self.0.extract_tx_to_schedule_broadcast()
     }
 
+    /// The Original PSBT's transaction together with its txid and weight, to schedule its
+    /// broadcast without parsing it.
+    pub fn fallback_transaction(&self) -> FallbackTransaction {
+        self.0.fallback_transaction()
+    }
+
+    /// Schedule the broadcast of `fallback_transaction()` for unix time `broadcast_at`,
+    /// defaulting to the expiry of the receiver's session.
+    ///
+    /// If neither is known the fallback is due immediately.
+    pub fn schedule_broadcast(&self, broadcast_at: Option<u64>) -> Arc<ScheduledBroadcast> {
+        Arc::new(self.0.schedule_broadcast(broadcast_at))
+    }
+
     /// Call after checking that the Original PSBT can be broadcast.
     ///
     /// Receiver MUST check that the Original PSBT from the sender can be broadcast, i.e. testmempoolaccept bitcoind rpc returns { “allowed”: true,.. } for get_transaction_to_check_broadcast() before calling this method.
@@ -141,35 +361,831 @@ impl UncheckedProposal {
             .map(|e| Arc::new(e.into()))
     }
 
-    /// Call this method if the only way to initiate a Payjoin with this receiver
-    /// requires manual intervention, as in most consumer wallets.
-    ///
-    /// So-called "non-interactive" receivers, like payment processors, that allow arbitrary requests are otherwise vulnerable to probing attacks.
-    /// Those receivers call `extract_tx_to_check_broadcast()` and `attest_tested_and_scheduled_broadcast()` after making those checks downstream.
-    pub fn assume_interactive_receiver(&self) -> Arc<MaybeInputsOwned> {
-        Arc::new(self.0.assume_interactive_receiver().into())
+    /// Like `check_broadcast_suitability()`, handing `can_broadcast` the transaction's bytes,
+    /// hex and txid.
+    pub fn check_broadcast_suitability_with_view(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        can_broadcast: Arc<dyn CanBroadcastTransaction>,
+    ) -> Result<Arc<MaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability_with_view(min_fee_rate.map(|r| *r), |transaction| {
+                can_broadcast.callback(Arc::new(transaction.clone()))
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `check_broadcast_suitability()`, with the minimum fee rate of the Original PSBT
+    /// given in an explicit unit.
+    pub fn check_broadcast_suitability_with_fee_rate(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        can_broadcast: Arc<dyn CanBroadcast>,
+    ) -> Result<Arc<MaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability_with_fee_rate(min_fee_rate.map(|r| *r), |transaction| {
+                can_broadcast.callback(transaction.to_vec())
+            })
+            .map(|e| Arc::new(e.into()))
+    }
+
+    /// Call this method if the only way to initiate a Payjoin with this receiver
+    /// requires manual intervention, as in most consumer wallets.
+    ///
+    /// So-called "non-interactive" receivers, like payment processors, that allow arbitrary requests are otherwise vulnerable to probing attacks.
+    /// Those receivers call `extract_tx_to_check_broadcast()` and `attest_tested_and_scheduled_broadcast()` after making those checks downstream.
+    pub fn assume_interactive_receiver(&self) -> Arc<MaybeInputsOwned> {
+        Arc::new(self.0.assume_interactive_receiver().into())
+    }
+
+    /// Extract an OHTTP Encapsulated HTTP POST request to return
+    /// a Receiver Error Response
+    ///
+    /// Build `err` with `JsonReply::from_replyable_error` from the error a check step failed
+    /// with, so the sender stops polling without learning internal details.
+    ///
+    /// A custom `message`, e.g. one localized for the store, replaces the reply's message while
+    /// its error code is kept. Control characters are replaced with spaces and it is truncated
+    /// to `MAX_REPLY_MESSAGE_CHARS` characters.
+    pub fn extract_err_req(
+        &self,
+        err: Arc<JsonReply>,
+        message: Option<String>,
+        ohttp_relay: String,
+    ) -> Result<RequestResponse, SessionError> {
+        self.0
+            .extract_err_req(&err, message, ohttp_relay)
+            .map(|(req, ctx)| RequestResponse { request: req, client_response: Arc::new(ctx) })
+    }
+
+    /// Process an OHTTP Encapsulated HTTP POST Error response
+    /// to ensure it has been posted properly
+    pub fn process_err_res(&self, body: &[u8], context: Arc<ClientResponse>) -> Result<(), Error> {
+        self.0.clone().process_err_res(body, &context)
+    }
+}
+
+/// The sender's Original PSBT, received in a BIP78 POST request.
+#[derive(Clone, uniffi::Object)]
+pub struct V1UncheckedProposal(super::v1::UncheckedProposal);
+
+impl From<super::v1::UncheckedProposal> for V1UncheckedProposal {
+    fn from(value: super::v1::UncheckedProposal) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1UncheckedProposal {
+    /// Whether the BIP78 request carried `disableoutputsubstitution=true`.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.0.is_output_substitution_disabled()
+    }
+
+    /// Parse the Original PSBT from the body, query string and headers of a BIP78 POST request.
+    #[uniffi::constructor]
+    pub fn from_request(
+        body: Vec<u8>,
+        query: String,
+        headers: HashMap<String, String>,
+    ) -> Result<Self, V1RequestError> {
+        super::v1::UncheckedProposal::from_request(body, query, headers).map(Into::into)
+    }
+
+    /// Like `from_request()`, reading the request headers through `headers`.
+    ///
+    /// For headers already collected in a map, use `from_request()`.
+    #[uniffi::constructor]
+    pub fn from_request_with_headers(
+        body: Vec<u8>,
+        query: String,
+        headers: Arc<dyn Headers>,
+    ) -> Result<Self, V1RequestError> {
+        super::v1::UncheckedProposal::from_request_with_headers(body, query, headers.as_ref())
+            .map(Into::into)
+    }
+
+    /// The Sender’s Original PSBT
+    pub fn extract_tx_to_schedule_broadcast(&self) -> Vec<u8> {
+        self.0.extract_tx_to_schedule_broadcast()
+    }
+
+    /// The Original PSBT's transaction together with its txid and weight, to schedule its
+    /// broadcast without parsing it.
+    pub fn fallback_transaction(&self) -> FallbackTransaction {
+        self.0.fallback_transaction()
+    }
+
+    /// The txid of `extract_tx_to_schedule_broadcast()`.
+    pub fn fallback_txid(&self) -> String {
+        self.0.fallback_txid()
+    }
+
+    /// The weight of `extract_tx_to_schedule_broadcast()` in weight units.
+    pub fn fallback_weight(&self) -> u64 {
+        self.0.fallback_weight()
+    }
+
+    /// Check that the Original PSBT can be broadcast, with `min_fee_rate` in sat/kwu.
+    pub fn check_broadcast_suitability(
+        &self,
+        min_fee_rate: Option<u64>,
+        can_broadcast: Arc<dyn CanBroadcast>,
+    ) -> Result<Arc<V1MaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability(min_fee_rate, |transaction| {
+                can_broadcast.callback(transaction.to_vec())
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `check_broadcast_suitability()`, with the minimum fee rate given in an explicit
+    /// unit, e.g. `FeeRate.from_sat_per_vb_f64()`.
+    pub fn check_broadcast_suitability_with_fee_rate(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        can_broadcast: Arc<dyn CanBroadcast>,
+    ) -> Result<Arc<V1MaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability_with_fee_rate(min_fee_rate.map(|r| *r), |transaction| {
+                can_broadcast.callback(transaction.to_vec())
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `check_broadcast_suitability()`, handing `can_broadcast` the transaction's bytes,
+    /// hex and txid.
+    pub fn check_broadcast_suitability_with_view(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        can_broadcast: Arc<dyn CanBroadcastTransaction>,
+    ) -> Result<Arc<V1MaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability_with_view(min_fee_rate.map(|r| *r), |transaction| {
+                can_broadcast.callback(Arc::new(transaction.clone()))
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Skip the broadcast check, for receivers that only payjoin after manual intervention.
+    pub fn assume_interactive_receiver(&self) -> Arc<V1MaybeInputsOwned> {
+        Arc::new(self.0.assume_interactive_receiver().into())
+    }
+}
+
+/// The v1 counterpart of `MaybeInputsOwned`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1MaybeInputsOwned(super::v1::MaybeInputsOwned);
+
+impl From<super::v1::MaybeInputsOwned> for V1MaybeInputsOwned {
+    fn from(value: super::v1::MaybeInputsOwned) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1MaybeInputsOwned {
+    /// Whether the sender disabled output substitution, carried over from the request.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.0.is_output_substitution_disabled()
+    }
+
+    /// Check that the Original PSBT has no receiver-owned inputs.
+    pub fn check_inputs_not_owned(
+        &self,
+        is_owned: Arc<dyn IsScriptOwned>,
+    ) -> Result<Arc<V1MaybeInputsSeen>, ReplyableError> {
+        self.0
+            .check_inputs_not_owned(|input| is_owned.callback(input.to_vec()))
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// The v1 counterpart of `MaybeInputsSeen`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1MaybeInputsSeen(super::v1::MaybeInputsSeen);
+
+impl From<super::v1::MaybeInputsSeen> for V1MaybeInputsSeen {
+    fn from(value: super::v1::MaybeInputsSeen) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1MaybeInputsSeen {
+    /// Make sure that the original transaction inputs have never been seen before.
+    pub fn check_no_inputs_seen_before(
+        &self,
+        is_known: Arc<dyn IsOutputKnown>,
+    ) -> Result<Arc<V1OutputsUnknown>, ReplyableError> {
+        self.0
+            .check_no_inputs_seen_before(|outpoint| is_known.callback(outpoint.clone()))
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// The v1 counterpart of `OutputsUnknown`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1OutputsUnknown(super::v1::OutputsUnknown);
+
+impl From<super::v1::OutputsUnknown> for V1OutputsUnknown {
+    fn from(value: super::v1::OutputsUnknown) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1OutputsUnknown {
+    /// Find which outputs belong to the receiver
+    pub fn identify_receiver_outputs(
+        &self,
+        is_receiver_output: Arc<dyn IsScriptOwned>,
+    ) -> Result<Arc<V1WantsOutputs>, ReplyableError> {
+        self.0
+            .identify_receiver_outputs(|output_script| {
+                is_receiver_output.callback(output_script.to_vec())
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// The v1 counterpart of `WantsOutputs`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1WantsOutputs(super::v1::WantsOutputs);
+
+impl From<super::v1::WantsOutputs> for V1WantsOutputs {
+    fn from(value: super::v1::WantsOutputs) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1WantsOutputs {
+    pub fn output_substitution(&self) -> bool {
+        self.0.output_substitution()
+    }
+
+    /// Replace the receiver's outputs with `replacement_outputs`, sending any excess to
+    /// `drain_script`.
+    pub fn replace_receiver_outputs(
+        &self,
+        replacement_outputs: Vec<TxOut>,
+        drain_script: Arc<Script>,
+    ) -> Result<Arc<V1WantsOutputs>, OutputSubstitutionError> {
+        self.0
+            .replace_receiver_outputs(replacement_outputs, &drain_script)
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Pay the receiver's outputs to `output_script` instead, keeping their amounts.
+    pub fn substitute_receiver_script(
+        &self,
+        output_script: Arc<Script>,
+    ) -> Result<Arc<V1WantsOutputs>, OutputSubstitutionError> {
+        self.0.substitute_receiver_script(&output_script).map(|t| Arc::new(t.into()))
+    }
+
+    /// Proceed to input contribution with the current outputs.
+    pub fn commit_outputs(&self) -> Arc<V1WantsInputs> {
+        Arc::new(self.0.commit_outputs().into())
+    }
+}
+
+/// The v1 counterpart of `WantsInputs`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1WantsInputs(super::v1::WantsInputs);
+
+impl From<super::v1::WantsInputs> for V1WantsInputs {
+    fn from(value: super::v1::WantsInputs) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1WantsInputs {
+    /// The fee parameters of the sender's request, to size the receiver's contribution.
+    pub fn sender_params(&self) -> SenderParams {
+        self.0.sender_params()
+    }
+
+    /// Select the receiver input that best avoids the unnecessary input heuristic.
+    pub fn try_preserving_privacy(
+        &self,
+        candidate_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Arc<InputPair>, SelectionError> {
+        let candidate_inputs: Vec<InputPair> =
+            candidate_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0.try_preserving_privacy(candidate_inputs).map(Arc::new)
+    }
+
+    /// Like `try_preserving_privacy()`, returning every candidate it would accept, best first,
+    /// so a candidate that turns out to be unusable can be skipped.
+    pub fn try_preserving_privacy_ranked(
+        &self,
+        candidate_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Vec<Arc<InputPair>>, SelectionError> {
+        let candidate_inputs: Vec<InputPair> =
+            candidate_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0
+            .try_preserving_privacy_ranked(candidate_inputs)
+            .map(|ranked| ranked.into_iter().map(Arc::new).collect())
+    }
+
+    /// Add the given receiver inputs to the proposal, adjusting the drain output accordingly.
+    pub fn contribute_inputs(
+        &self,
+        replacement_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Arc<V1WantsInputs>, InputContributionError> {
+        let replacement_inputs: Vec<InputPair> =
+            replacement_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0.contribute_inputs(replacement_inputs).map(|t| Arc::new(t.into()))
+    }
+
+    /// Proceed to finalization with the current inputs.
+    pub fn commit_inputs(&self) -> Arc<V1ProvisionalProposal> {
+        Arc::new(self.0.commit_inputs().into())
+    }
+}
+
+/// The v1 counterpart of `ProvisionalProposal`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1ProvisionalProposal(super::v1::ProvisionalProposal);
+
+impl From<super::v1::ProvisionalProposal> for V1ProvisionalProposal {
+    fn from(value: super::v1::ProvisionalProposal) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1ProvisionalProposal {
+    /// The fee parameters of the sender's request.
+    pub fn sender_params(&self) -> SenderParams {
+        self.0.sender_params()
+    }
+
+    /// The most the sender will pay, in satoshis, towards the fee of the receiver's additions.
+    pub fn max_additional_fee_contribution(&self) -> Option<u64> {
+        self.0.max_additional_fee_contribution()
+    }
+
+    /// The minimum fee rate the sender requires of the payjoin, in sat/vB.
+    pub fn min_fee_rate_sat_per_vb(&self) -> Option<u64> {
+        self.0.min_fee_rate_sat_per_vb()
+    }
+
+    pub fn finalize_proposal(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<V1PayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal(
+                |psbt| process_psbt.callback(psbt),
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `finalize_proposal()`, with fee rates given in an explicit unit.
+    pub fn finalize_proposal_with_fee_rates(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        max_effective_fee_rate: Option<Arc<FeeRate>>,
+    ) -> Result<Arc<V1PayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal_with_fee_rates(
+                |psbt| process_psbt.callback(psbt),
+                min_fee_rate.map(|r| *r),
+                max_effective_fee_rate.map(|r| *r),
+            )
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `finalize_proposal()`, handing `process_psbt` the PSBT as raw bytes and expecting
+    /// raw bytes back instead of base64.
+    pub fn finalize_proposal_bytes(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbtBytes>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<V1PayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal_bytes(
+                |psbt| process_psbt.callback(psbt),
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// The v1 counterpart of `PayjoinProposal`.
+#[derive(Clone, uniffi::Object)]
+pub struct V1PayjoinProposal(super::v1::PayjoinProposal);
+
+impl From<super::v1::PayjoinProposal> for V1PayjoinProposal {
+    fn from(value: super::v1::PayjoinProposal) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl V1PayjoinProposal {
+    /// The signed Payjoin PSBT, base64 encoded.
+    pub fn psbt(&self) -> String {
+        self.0.psbt()
+    }
+
+    /// The serialized form of `psbt()`.
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.0.psbt_bytes()
+    }
+
+    /// The indexes of the receiver's outputs in the Payjoin PSBT.
+    pub fn owned_vouts(&self) -> Vec<u64> {
+        self.0.owned_vouts()
+    }
+
+    pub fn utxos_to_be_locked(&self) -> Vec<OutPoint> {
+        self.0.utxos_to_be_locked()
+    }
+
+    /// The body of the HTTP 200 response to the sender, to be served as `text/plain`.
+    pub fn body(&self) -> Vec<u8> {
+        self.0.body()
+    }
+}
+
+/// The sender's Original PSBT, received over either protocol version.
+///
+/// Wrap a `V1UncheckedProposal` or an `UncheckedProposal` to write the approval pipeline once
+/// for both.
+#[derive(Clone, uniffi::Object)]
+pub struct ReceiveSession(super::session::ReceiveSession);
+
+impl From<super::session::ReceiveSession> for ReceiveSession {
+    fn from(value: super::session::ReceiveSession) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl ReceiveSession {
+    /// Whether the sender disabled output substitution, for proposals of either version.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.0.is_output_substitution_disabled()
+    }
+
+    #[uniffi::constructor]
+    pub fn from_v1(proposal: Arc<V1UncheckedProposal>) -> Self {
+        Self(proposal.0.clone().into())
+    }
+
+    #[uniffi::constructor]
+    pub fn from_v2(proposal: Arc<UncheckedProposal>) -> Self {
+        Self(proposal.0.clone().into())
+    }
+
+    /// Whether the proposal came through a BIP77 directory.
+    pub fn is_v2(&self) -> bool {
+        self.0.is_v2()
+    }
+
+    /// The v1 proposal, if the sender posted it directly.
+    pub fn v1(&self) -> Option<Arc<V1UncheckedProposal>> {
+        self.0.v1().map(|proposal| Arc::new(proposal.into()))
+    }
+
+    /// The v2 proposal, if it came through a directory, e.g. to reply with `extract_err_req()`.
+    pub fn v2(&self) -> Option<Arc<UncheckedProposal>> {
+        self.0.v2().map(|proposal| Arc::new(proposal.into()))
+    }
+
+    /// The Sender’s Original PSBT
+    pub fn extract_tx_to_schedule_broadcast(&self) -> Vec<u8> {
+        self.0.extract_tx_to_schedule_broadcast()
+    }
+
+    /// `min_fee_rate` is in sat/kwu.
+    pub fn check_broadcast_suitability(
+        &self,
+        min_fee_rate: Option<u64>,
+        can_broadcast: Arc<dyn CanBroadcast>,
+    ) -> Result<Arc<SessionMaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability(min_fee_rate, |transaction| {
+                can_broadcast.callback(transaction.to_vec())
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `check_broadcast_suitability()`, with the minimum fee rate of the Original PSBT given
+    /// in an explicit unit.
+    pub fn check_broadcast_suitability_with_fee_rate(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        can_broadcast: Arc<dyn CanBroadcast>,
+    ) -> Result<Arc<SessionMaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability_with_fee_rate(min_fee_rate.map(|r| *r), |transaction| {
+                can_broadcast.callback(transaction.to_vec())
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `check_broadcast_suitability()`, handing `can_broadcast` the transaction's bytes,
+    /// hex and txid.
+    pub fn check_broadcast_suitability_with_view(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        can_broadcast: Arc<dyn CanBroadcastTransaction>,
+    ) -> Result<Arc<SessionMaybeInputsOwned>, ReplyableError> {
+        self.0
+            .check_broadcast_suitability_with_view(min_fee_rate.map(|r| *r), |transaction| {
+                can_broadcast.callback(Arc::new(transaction.clone()))
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Skip the broadcast check, for receivers that only payjoin after manual intervention.
+    pub fn assume_interactive_receiver(&self) -> Arc<SessionMaybeInputsOwned> {
+        Arc::new(self.0.assume_interactive_receiver().into())
+    }
+}
+
+/// `MaybeInputsOwned` of either protocol version.
+#[derive(Clone, uniffi::Object)]
+pub struct SessionMaybeInputsOwned(super::session::SessionMaybeInputsOwned);
+
+impl From<super::session::SessionMaybeInputsOwned> for SessionMaybeInputsOwned {
+    fn from(value: super::session::SessionMaybeInputsOwned) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionMaybeInputsOwned {
+    /// Check that the Original PSBT has no receiver-owned inputs.
+    pub fn check_inputs_not_owned(
+        &self,
+        is_owned: Arc<dyn IsScriptOwned>,
+    ) -> Result<Arc<SessionMaybeInputsSeen>, ReplyableError> {
+        self.0
+            .check_inputs_not_owned(|input| is_owned.callback(input.to_vec()))
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// `MaybeInputsSeen` of either protocol version.
+#[derive(Clone, uniffi::Object)]
+pub struct SessionMaybeInputsSeen(super::session::SessionMaybeInputsSeen);
+
+impl From<super::session::SessionMaybeInputsSeen> for SessionMaybeInputsSeen {
+    fn from(value: super::session::SessionMaybeInputsSeen) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionMaybeInputsSeen {
+    /// Make sure that the original transaction inputs have never been seen before.
+    pub fn check_no_inputs_seen_before(
+        &self,
+        is_known: Arc<dyn IsOutputKnown>,
+    ) -> Result<Arc<SessionOutputsUnknown>, ReplyableError> {
+        self.0
+            .check_no_inputs_seen_before(|outpoint| is_known.callback(outpoint.clone()))
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// `OutputsUnknown` of either protocol version.
+#[derive(Clone, uniffi::Object)]
+pub struct SessionOutputsUnknown(super::session::SessionOutputsUnknown);
+
+impl From<super::session::SessionOutputsUnknown> for SessionOutputsUnknown {
+    fn from(value: super::session::SessionOutputsUnknown) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionOutputsUnknown {
+    /// Find which outputs belong to the receiver
+    pub fn identify_receiver_outputs(
+        &self,
+        is_receiver_output: Arc<dyn IsScriptOwned>,
+    ) -> Result<Arc<SessionWantsOutputs>, ReplyableError> {
+        self.0
+            .identify_receiver_outputs(|output_script| {
+                is_receiver_output.callback(output_script.to_vec())
+            })
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// `WantsOutputs` of either protocol version.
+#[derive(uniffi::Object)]
+pub struct SessionWantsOutputs(super::session::SessionWantsOutputs);
+
+impl From<super::session::SessionWantsOutputs> for SessionWantsOutputs {
+    fn from(value: super::session::SessionWantsOutputs) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionWantsOutputs {
+    pub fn output_substitution(&self) -> bool {
+        self.0.output_substitution()
+    }
+
+    /// Replace the receiver's outputs with `replacement_outputs`, sending any excess to
+    /// `drain_script`.
+    pub fn replace_receiver_outputs(
+        &self,
+        replacement_outputs: Vec<TxOut>,
+        drain_script: Arc<Script>,
+    ) -> Result<Arc<SessionWantsOutputs>, OutputSubstitutionError> {
+        self.0
+            .replace_receiver_outputs(replacement_outputs, &drain_script)
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Pay the receiver's outputs to `output_script` instead, keeping their amounts.
+    pub fn substitute_receiver_script(
+        &self,
+        output_script: Arc<Script>,
+    ) -> Result<Arc<SessionWantsOutputs>, OutputSubstitutionError> {
+        self.0.substitute_receiver_script(&output_script).map(|t| Arc::new(t.into()))
+    }
+
+    /// Proceed to input contribution with the current outputs.
+    pub fn commit_outputs(&self) -> Arc<SessionWantsInputs> {
+        Arc::new(self.0.commit_outputs().into())
+    }
+}
+
+/// `WantsInputs` of either protocol version.
+#[derive(uniffi::Object)]
+pub struct SessionWantsInputs(super::session::SessionWantsInputs);
+
+impl From<super::session::SessionWantsInputs> for SessionWantsInputs {
+    fn from(value: super::session::SessionWantsInputs) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionWantsInputs {
+    /// The fee parameters of the sender's request, to size the receiver's contribution.
+    pub fn sender_params(&self) -> SenderParams {
+        self.0.sender_params()
+    }
+
+    /// Select the receiver input that best avoids the unnecessary input heuristic.
+    pub fn try_preserving_privacy(
+        &self,
+        candidate_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Arc<InputPair>, SelectionError> {
+        let candidate_inputs: Vec<InputPair> =
+            candidate_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0.try_preserving_privacy(candidate_inputs).map(Arc::new)
+    }
+
+    /// Every candidate `try_preserving_privacy()` would accept, best first.
+    pub fn try_preserving_privacy_ranked(
+        &self,
+        candidate_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Vec<Arc<InputPair>>, SelectionError> {
+        let candidate_inputs: Vec<InputPair> =
+            candidate_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0
+            .try_preserving_privacy_ranked(candidate_inputs)
+            .map(|ranked| ranked.into_iter().map(Arc::new).collect())
+    }
+
+    /// Add the given receiver inputs to the proposal, adjusting the drain output accordingly.
+    pub fn contribute_inputs(
+        &self,
+        replacement_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Arc<SessionWantsInputs>, InputContributionError> {
+        let replacement_inputs: Vec<InputPair> =
+            replacement_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0.contribute_inputs(replacement_inputs).map(|t| Arc::new(t.into()))
+    }
+
+    /// Proceed to finalization with the current inputs.
+    pub fn commit_inputs(&self) -> Arc<SessionProvisionalProposal> {
+        Arc::new(self.0.commit_inputs().into())
+    }
+}
+
+/// `ProvisionalProposal` of either protocol version.
+#[derive(Clone, uniffi::Object)]
+pub struct SessionProvisionalProposal(super::session::SessionProvisionalProposal);
+
+impl From<super::session::SessionProvisionalProposal> for SessionProvisionalProposal {
+    fn from(value: super::session::SessionProvisionalProposal) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionProvisionalProposal {
+    /// The fee parameters of the sender's request.
+    pub fn sender_params(&self) -> SenderParams {
+        self.0.sender_params()
+    }
+
+    pub fn finalize_proposal(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<SessionPayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal(
+                |psbt| process_psbt.callback(psbt),
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `finalize_proposal()`, with fee rates given in an explicit unit.
+    pub fn finalize_proposal_with_fee_rates(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        max_effective_fee_rate: Option<Arc<FeeRate>>,
+    ) -> Result<Arc<SessionPayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal_with_fee_rates(
+                |psbt| process_psbt.callback(psbt),
+                min_fee_rate.map(|r| *r),
+                max_effective_fee_rate.map(|r| *r),
+            )
+            .map(|t| Arc::new(t.into()))
+    }
+
+    pub fn finalize_proposal_bytes(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbtBytes>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<SessionPayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal_bytes(
+                |psbt| process_psbt.callback(psbt),
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|t| Arc::new(t.into()))
+    }
+}
+
+/// `PayjoinProposal` of either protocol version.
+///
+/// Answer a v1 sender with the `body()` of `v1()`, and post a v2 proposal to the directory
+/// with the `extract_v2_req()` of `v2()`.
+#[derive(Clone, uniffi::Object)]
+pub struct SessionPayjoinProposal(super::session::SessionPayjoinProposal);
+
+impl From<super::session::SessionPayjoinProposal> for SessionPayjoinProposal {
+    fn from(value: super::session::SessionPayjoinProposal) -> Self {
+        Self(value)
+    }
+}
+
+#[uniffi::export]
+impl SessionPayjoinProposal {
+    /// Whether the proposal is returned through a BIP77 directory.
+    pub fn is_v2(&self) -> bool {
+        self.0.is_v2()
+    }
+
+    /// The v1 proposal, to answer the sender's request with.
+    pub fn v1(&self) -> Option<Arc<V1PayjoinProposal>> {
+        self.0.v1().map(|proposal| Arc::new(proposal.into()))
     }
 
-    /// Extract an OHTTP Encapsulated HTTP POST request to return
-    /// a Receiver Error Response
-    pub fn extract_err_req(
-        &self,
-        err: Arc<JsonReply>,
-        ohttp_relay: String,
-    ) -> Result<RequestResponse, SessionError> {
-        self.0
-            .extract_err_req(&err, ohttp_relay)
-            .map(|(req, ctx)| RequestResponse { request: req, client_response: Arc::new(ctx) })
+    /// The v2 proposal, to post to the directory.
+    pub fn v2(&self) -> Option<Arc<PayjoinProposal>> {
+        self.0.v2().map(|proposal| Arc::new(proposal.into()))
     }
 
-    /// Process an OHTTP Encapsulated HTTP POST Error response
-    /// to ensure it has been posted properly
-    pub fn process_err_res(
-        &self,
-        body: &[u8],
-        context: Arc<ClientResponse>,
-    ) -> Result<(), SessionError> {
-        self.0.clone().process_err_res(body, &context)
+    /// The signed Payjoin PSBT, base64 encoded.
+    pub fn psbt(&self) -> String {
+        self.0.psbt()
+    }
+
+    pub fn utxos_to_be_locked(&self) -> Vec<OutPoint> {
+        self.0.utxos_to_be_locked()
     }
 }
 
@@ -184,15 +1200,45 @@ impl From<super::MaybeInputsOwned> for MaybeInputsOwned {
     }
 }
 
+#[uniffi::export]
+pub trait GenerateOutputs: Send + Sync {
+    fn callback(&self) -> Result<Vec<TxOut>, ImplementationError>;
+}
+
 #[uniffi::export]
 pub trait IsScriptOwned: Send + Sync {
     fn callback(&self, script: Vec<u8>) -> Result<bool, ImplementationError>;
 }
 
+#[uniffi::export]
+pub trait AreScriptsOwned: Send + Sync {
+    fn callback(&self, scripts: Vec<Vec<u8>>) -> Result<Vec<bool>, ImplementationError>;
+}
+
 #[uniffi::export]
 impl MaybeInputsOwned {
+    /// Whether the sender disabled output substitution, carried over from the proposal.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.0.is_output_substitution_disabled()
+    }
+
+    /// The sender's Original PSBT, rebuilt from its finalized transaction.
+    ///
+    /// It carries each input's final script sig and witness but not the UTXOs they spend.
+    pub fn psbt(&self) -> String {
+        self.0.psbt()
+    }
+
+    /// The serialized form of `psbt()`.
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.0.psbt_bytes()
+    }
+
     ///Check that the Original PSBT has no receiver-owned inputs. Return original-psbt-rejected error or otherwise refuse to sign undesirable inputs.
     /// An attacker could try to spend receiver's own inputs. This check prevents that.
+    ///
+    /// This leads straight to `MaybeInputsSeen`: senders whose inputs mix script types are
+    /// accepted, as the payjoin crate no longer rejects them.
     pub fn check_inputs_not_owned(
         &self,
         is_owned: Arc<dyn IsScriptOwned>,
@@ -201,6 +1247,19 @@ impl MaybeInputsOwned {
             .check_inputs_not_owned(|input| is_owned.callback(input.to_vec()))
             .map(|t| Arc::new(t.into()))
     }
+
+    /// Like `check_inputs_not_owned()`, asking `is_owned` about the scripts of all inputs in one
+    /// call instead of one call per input.
+    ///
+    /// `is_owned` must return one answer per script, in the same order.
+    pub fn check_inputs_not_owned_batch(
+        &self,
+        is_owned: Arc<dyn AreScriptsOwned>,
+    ) -> Result<Arc<MaybeInputsSeen>, BatchCheckError> {
+        self.0
+            .check_inputs_not_owned_batch(|scripts| is_owned.callback(scripts))
+            .map(|t| Arc::new(t.into()))
+    }
 }
 
 #[uniffi::export]
@@ -232,6 +1291,35 @@ impl MaybeInputsSeen {
             .check_no_inputs_seen_before(|outpoint| is_known.callback(outpoint.clone()))
             .map(|t| Arc::new(t.into()))
     }
+
+    /// Like `check_no_inputs_seen_before()`, looking each input up in `store`.
+    ///
+    /// The check doesn't change `store`. Record the inputs with `mark_inputs_seen()` on the
+    /// `PayjoinProposal` once it is committed. Use `in_memory_seen_inputs_store()` or implement
+    /// `SeenInputsStore` over persistent storage.
+    pub fn check_no_inputs_seen_before_with_store(
+        &self,
+        store: Arc<dyn SeenInputsStore>,
+    ) -> Result<Arc<OutputsUnknown>, ReplyableError> {
+        self.0.check_no_inputs_seen_before_with_store(store.as_ref()).map(|t| Arc::new(t.into()))
+    }
+
+    /// Like `check_no_inputs_seen_before()`, checking the inputs against the outpoints in
+    /// `seen`.
+    ///
+    /// Rejections report the input that was seen before.
+    pub fn check_no_inputs_seen_before_in(
+        &self,
+        seen: Vec<OutPoint>,
+    ) -> Result<Arc<OutputsUnknown>, InputSeenError> {
+        self.0.check_no_inputs_seen_before_in(seen).map(|t| Arc::new(t.into()))
+    }
+}
+
+/// A `SeenInputsStore` that keeps outpoints in memory for the lifetime of the process.
+#[uniffi::export]
+pub fn in_memory_seen_inputs_store() -> Arc<dyn SeenInputsStore> {
+    Arc::new(InMemorySeenInputs::new())
 }
 
 /// The receiver has not yet identified which outputs belong to the receiver.
@@ -248,6 +1336,18 @@ impl From<super::OutputsUnknown> for OutputsUnknown {
 
 #[uniffi::export]
 impl OutputsUnknown {
+    /// The sender's Original PSBT, rebuilt from its finalized transaction.
+    ///
+    /// It carries each input's final script sig and witness but not the UTXOs they spend.
+    pub fn psbt(&self) -> String {
+        self.0.psbt()
+    }
+
+    /// The serialized form of `psbt()`.
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.0.psbt_bytes()
+    }
+
     /// Find which outputs belong to the receiver
     pub fn identify_receiver_outputs(
         &self,
@@ -260,8 +1360,24 @@ impl OutputsUnknown {
             })
             .map(|t| Arc::new(t.into()))
     }
+
+    /// Like `identify_receiver_outputs()`, asking `is_receiver_output` about the scripts of all
+    /// outputs in one call instead of one call per output.
+    ///
+    /// `is_receiver_output` must return one answer per script, in the same order.
+    pub fn identify_receiver_outputs_batch(
+        &self,
+        is_receiver_output: Arc<dyn AreScriptsOwned>,
+    ) -> Result<Arc<WantsOutputs>, BatchCheckError> {
+        self.0
+            .identify_receiver_outputs_batch(|scripts| is_receiver_output.callback(scripts))
+            .map(|t| Arc::new(t.into()))
+    }
 }
 
+/// The receiver may substitute its outputs or add new ones before committing them.
+///
+/// Call `commit_outputs()` to proceed to input contribution.
 #[derive(uniffi::Object)]
 pub struct WantsOutputs(super::WantsOutputs);
 
@@ -276,6 +1392,8 @@ impl WantsOutputs {
         self.0.output_substitution()
     }
 
+    /// Replace the receiver's outputs with `replacement_outputs`, sending any excess to
+    /// `drain_script`.
     pub fn replace_receiver_outputs(
         &self,
         replacement_outputs: Vec<TxOut>,
@@ -286,10 +1404,30 @@ impl WantsOutputs {
             .map(|t| Arc::new(t.into()))
     }
 
+    /// Replace the receiver's outputs with those `generate` returns, e.g. to split the payment
+    /// across several addresses, sending any excess to `drain_script`.
+    ///
+    /// Fails if `generate` fails, if an output is dust, or if the outputs pay the receiver
+    /// less than the Original PSBT while the sender disabled output substitution.
+    pub fn try_substitute_receiver_outputs(
+        &self,
+        generate: Arc<dyn GenerateOutputs>,
+        drain_script: Arc<Script>,
+    ) -> Result<Arc<WantsOutputs>, OutputSubstitutionError> {
+        self.0
+            .try_substitute_receiver_outputs(|| generate.callback(), &drain_script)
+            .map(|t| Arc::new(t.into()))
+    }
+
+    /// Proceed to input contribution with the current outputs.
     pub fn commit_outputs(&self) -> Arc<WantsInputs> {
         Arc::new(self.0.commit_outputs().into())
     }
 
+    /// Pay the receiver's outputs to `output_script` instead, keeping their amounts.
+    ///
+    /// Fails with `OutputSubstitutionError::Disabled` if the sender disabled output substitution
+    /// and `output_script` isn't already the receiver's script.
     pub fn substitute_receiver_script(
         &self,
         output_script: Arc<Script>,
@@ -301,6 +1439,9 @@ impl WantsOutputs {
     }
 }
 
+/// The receiver may contribute inputs to the proposal before committing them.
+///
+/// Call `commit_inputs()` to proceed to a `ProvisionalProposal`.
 #[derive(uniffi::Object)]
 pub struct WantsInputs(super::WantsInputs);
 
@@ -312,8 +1453,15 @@ impl From<super::WantsInputs> for WantsInputs {
 
 #[uniffi::export]
 impl WantsInputs {
+    /// The fee parameters of the sender's request, to size the receiver's contribution.
+    pub fn sender_params(&self) -> SenderParams {
+        self.0.sender_params()
+    }
+
     /// Select receiver input such that the payjoin avoids surveillance.
-    /// Return the input chosen that has been applied to the Proposal.
+    ///
+    /// Every candidate is considered, including candidates with the same amount, and the chosen
+    /// one is returned unchanged so it can be passed straight to `contribute_inputs()`.
     ///
     /// Proper coin selection allows payjoin to resemble ordinary transactions.
     /// To ensure the resemblance, a number of heuristics must be avoided.
@@ -327,25 +1475,48 @@ impl WantsInputs {
         &self,
         candidate_inputs: Vec<Arc<InputPair>>,
     ) -> Result<Arc<InputPair>, SelectionError> {
-        let candidate_inputs: Vec<InputPair> = candidate_inputs
-            .into_iter()
-            .map(|pair| Arc::try_unwrap(pair).unwrap_or_else(|arc| (*arc).clone()))
-            .collect();
+        let candidate_inputs: Vec<InputPair> =
+            candidate_inputs.into_iter().map(unwrap_or_clone).collect();
 
         self.0.try_preserving_privacy(candidate_inputs).map(Arc::new)
     }
 
+    /// Like `try_preserving_privacy()`, returning every candidate it would accept, best first,
+    /// so a candidate that turns out to be unusable can be skipped.
+    pub fn try_preserving_privacy_ranked(
+        &self,
+        candidate_inputs: Vec<Arc<InputPair>>,
+    ) -> Result<Vec<Arc<InputPair>>, SelectionError> {
+        let candidate_inputs: Vec<InputPair> =
+            candidate_inputs.into_iter().map(unwrap_or_clone).collect();
+        self.0
+            .try_preserving_privacy_ranked(candidate_inputs)
+            .map(|ranked| ranked.into_iter().map(Arc::new).collect())
+    }
+
+    /// Add the given receiver inputs to the proposal, adjusting the drain output accordingly.
     pub fn contribute_inputs(
         &self,
         replacement_inputs: Vec<Arc<InputPair>>,
     ) -> Result<Arc<WantsInputs>, InputContributionError> {
-        let replacement_inputs: Vec<InputPair> = replacement_inputs
-            .into_iter()
-            .map(|pair| Arc::try_unwrap(pair).unwrap_or_else(|arc| (*arc).clone()))
-            .collect();
+        let replacement_inputs: Vec<InputPair> =
+            replacement_inputs.into_iter().map(unwrap_or_clone).collect();
         self.0.contribute_inputs(replacement_inputs).map(|t| Arc::new(t.into()))
     }
 
+    /// Add a legacy receiver input spending `outpoint`, given the full previous transaction
+    /// `tx_bytes` it spends from.
+    ///
+    /// The input pays its share of the fee at the larger weight of a non-witness spend.
+    pub fn contribute_non_witness_input(
+        &self,
+        tx_bytes: Vec<u8>,
+        outpoint: OutPoint,
+    ) -> Result<Arc<WantsInputs>, NonWitnessInputError> {
+        self.0.contribute_non_witness_input(tx_bytes, outpoint).map(|t| Arc::new(t.into()))
+    }
+
+    /// Proceed to finalization with the current inputs.
     pub fn commit_inputs(&self) -> Arc<ProvisionalProposal> {
         Arc::new(self.0.commit_inputs().into())
     }
@@ -363,6 +1534,54 @@ impl From<super::ProvisionalProposal> for ProvisionalProposal {
 /// A mutable checked proposal that the receiver may contribute inputs to to make a payjoin.
 #[uniffi::export]
 impl ProvisionalProposal {
+    /// The fee parameters of the sender's request.
+    pub fn sender_params(&self) -> SenderParams {
+        self.0.sender_params()
+    }
+
+    /// The most the sender will pay, in satoshis, towards the fee of the receiver's additions.
+    pub fn max_additional_fee_contribution(&self) -> Option<u64> {
+        self.0.max_additional_fee_contribution()
+    }
+
+    /// The minimum fee rate the sender requires of the payjoin, in sat/vB.
+    pub fn min_fee_rate_sat_per_vb(&self) -> Option<u64> {
+        self.0.min_fee_rate_sat_per_vb()
+    }
+
+    /// The proposal PSBT `finalize_proposal()` would hand to `process_psbt` with the default
+    /// fee rates, before the receiver signs it.
+    pub fn psbt(&self) -> Result<String, ReplyableError> {
+        self.0.psbt()
+    }
+
+    /// The serialized form of `psbt()`.
+    pub fn psbt_bytes(&self) -> Result<Vec<u8>, ReplyableError> {
+        self.0.psbt_bytes()
+    }
+
+    /// Preview the fees of the proposal `finalize_proposal()` would produce with the same fee
+    /// rates, without signing it.
+    pub fn preview_fees(
+        &self,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<FeePreview, ReplyableError> {
+        self.0.preview_fees(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)
+    }
+
+    /// Like `preview_fees()`, for `finalize_proposal_with_fee_rates()`.
+    pub fn preview_fees_with_fee_rates(
+        &self,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        max_effective_fee_rate: Option<Arc<FeeRate>>,
+    ) -> Result<FeePreview, ReplyableError> {
+        self.0.preview_fees_with_fee_rates(
+            min_fee_rate.map(|r| *r),
+            max_effective_fee_rate.map(|r| *r),
+        )
+    }
+
     pub fn finalize_proposal(
         &self,
         process_psbt: Arc<dyn ProcessPsbt>,
@@ -377,6 +1596,163 @@ impl ProvisionalProposal {
             )
             .map(|e| Arc::new(e.into()))
     }
+
+    /// Like `finalize_proposal()`, with fee rates given in an explicit unit.
+    pub fn finalize_proposal_with_fee_rates(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+        min_fee_rate: Option<Arc<FeeRate>>,
+        max_effective_fee_rate: Option<Arc<FeeRate>>,
+    ) -> Result<Arc<PayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal_with_fee_rates(
+                |psbt| process_psbt.callback(psbt.to_string()),
+                min_fee_rate.map(|r| *r),
+                max_effective_fee_rate.map(|r| *r),
+            )
+            .map(|e| Arc::new(e.into()))
+    }
+
+    /// Like `finalize_proposal()`, handing `process_psbt` the PSBT as raw bytes and expecting
+    /// raw bytes back instead of base64.
+    pub fn finalize_proposal_bytes(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbtBytes>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<PayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal_bytes(
+                |psbt| process_psbt.callback(psbt),
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|e| Arc::new(e.into()))
+    }
+
+    /// Export a self-contained JSON snapshot of the proposal for an external reviewer.
+    ///
+    /// The document describes the proposal as `finalize_proposal` would sign it with the given
+    /// fee rates. Record the reviewer's verdict with `apply_review_decision` using the returned
+    /// hash and the same fee rates.
+    pub fn export_review_bundle(
+        &self,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<ReviewBundle, ReplyableError> {
+        self.0.export_review_bundle(min_feerate_sat_per_vb, max_effective_fee_rate_sat_per_vb)
+    }
+
+    /// Record a reviewer's verdict on the bundle identified by `bundle_hash`.
+    ///
+    /// Fails with `ReviewError::BundleMismatch` unless the proposal, exported with the given fee
+    /// rates, still hashes to `bundle_hash`, and with `ReviewError::Rejected` unless `approved`.
+    pub fn apply_review_decision(
+        &self,
+        approved: bool,
+        bundle_hash: String,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<ReviewedProposal>, ReviewError> {
+        self.0
+            .apply_review_decision(
+                approved,
+                bundle_hash,
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|p| Arc::new(p.into()))
+    }
+
+    /// Start finalizing a proposal whose receiver inputs can't all be signed at once, as when a
+    /// multisig co-signer responds later.
+    ///
+    /// `process_psbt` adds the signatures available now. Collect the rest with
+    /// `PendingSignatures::add_signatures` and finish with `PendingSignatures::complete`.
+    pub fn begin_finalize(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<Arc<PendingSignatures>, FinalizeError> {
+        self.0
+            .begin_finalize(
+                |psbt| process_psbt.callback(psbt),
+                min_feerate_sat_per_vb,
+                max_effective_fee_rate_sat_per_vb,
+            )
+            .map(|p| Arc::new(p.into()))
+    }
+
+    /// Resume finalizing this proposal from signatures persisted with
+    /// `PendingSignatures::to_json`.
+    ///
+    /// The proposal must be the one the signatures were collected for, rebuilt with the same
+    /// inputs and outputs.
+    pub fn resume_finalize(&self, json: String) -> Result<Arc<PendingSignatures>, FinalizeError> {
+        self.0.resume_finalize(&json).map(|p| Arc::new(p.into()))
+    }
+}
+
+#[derive(uniffi::Object)]
+pub struct PendingSignatures(super::PendingSignatures);
+
+impl From<super::PendingSignatures> for PendingSignatures {
+    fn from(value: super::PendingSignatures) -> Self {
+        Self(value)
+    }
+}
+
+/// A proposal whose receiver inputs are being signed in several steps.
+#[uniffi::export]
+impl PendingSignatures {
+    /// Add the signatures `process_psbt` can provide now.
+    pub fn add_signatures(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+    ) -> Result<Arc<PendingSignatures>, FinalizeError> {
+        self.0.add_signatures(|psbt| process_psbt.callback(psbt)).map(|p| Arc::new(p.into()))
+    }
+
+    /// The contributed receiver inputs that are not finalized yet.
+    pub fn unsatisfied_inputs(&self) -> Vec<OutPoint> {
+        self.0.unsatisfied_inputs()
+    }
+
+    /// Finalize the proposal once every receiver input is signed, at unix time `now`.
+    ///
+    /// Fails if the receiver's session has expired by then.
+    pub fn complete(&self, now: u64) -> Result<Arc<PayjoinProposal>, FinalizeError> {
+        self.0.complete(now).map(|p| Arc::new(p.into()))
+    }
+
+    /// Persist the signatures collected so far, to resume with
+    /// `ProvisionalProposal::resume_finalize`.
+    pub fn to_json(&self) -> Result<String, SerdeJsonError> {
+        self.0.to_json()
+    }
+}
+
+#[derive(uniffi::Object)]
+pub struct ReviewedProposal(super::ReviewedProposal);
+
+impl From<super::ReviewedProposal> for ReviewedProposal {
+    fn from(value: super::ReviewedProposal) -> Self {
+        Self(value)
+    }
+}
+
+/// A provisional proposal approved by an external reviewer.
+#[uniffi::export]
+impl ReviewedProposal {
+    pub fn finalize_proposal(
+        &self,
+        process_psbt: Arc<dyn ProcessPsbt>,
+    ) -> Result<Arc<PayjoinProposal>, ReplyableError> {
+        self.0
+            .finalize_proposal(|psbt| process_psbt.callback(psbt.to_string()))
+            .map(|e| Arc::new(e.into()))
+    }
 }
 
 #[uniffi::export]
@@ -384,6 +1760,12 @@ pub trait ProcessPsbt: Send + Sync {
     fn callback(&self, psbt: String) -> Result<String, ImplementationError>;
 }
 
+/// Like `ProcessPsbt`, trading the PSBT as raw bytes instead of base64.
+#[uniffi::export]
+pub trait ProcessPsbtBytes: Send + Sync {
+    fn callback(&self, psbt: Vec<u8>) -> Result<Vec<u8>, ImplementationError>;
+}
+
 #[derive(Clone, uniffi::Object)]
 pub struct PayjoinProposal(super::PayjoinProposal);
 
@@ -411,10 +1793,59 @@ impl PayjoinProposal {
         outpoints
     }
 
+    /// The UTXOs spent by this payjoin with the outputs they spend, and who provided them.
+    pub fn locked_utxos(&self) -> Vec<LockedUtxo> {
+        self.0.locked_utxos()
+    }
+
     pub fn psbt(&self) -> String {
         self.0.psbt()
     }
 
+    /// The serialized form of `psbt()`.
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.0.psbt_bytes()
+    }
+
+    /// How the fee of this payjoin is shared between sender and receiver.
+    ///
+    /// Accounts for all contributed inputs and substituted outputs.
+    pub fn fee_split(&self) -> FeeSplit {
+        self.0.fee_split()
+    }
+
+    /// The fee of the payjoin transaction in satoshis.
+    pub fn total_fee(&self) -> Option<u64> {
+        self.0.total_fee()
+    }
+
+    /// How much more fee the payjoin transaction pays than the Original PSBT, in satoshis.
+    pub fn additional_fee_contribution(&self) -> Option<u64> {
+        self.0.additional_fee_contribution()
+    }
+
+    /// What the receiver's outputs receive net of the inputs it contributed, in satoshis.
+    pub fn receiver_amount_received(&self) -> Option<u64> {
+        self.0.receiver_amount_received()
+    }
+
+    /// The sender's Original PSBT this proposal was built on, to diff against `psbt()`.
+    ///
+    /// It is rebuilt from the sender's finalized transaction, so it carries each input's final
+    /// script sig and witness but not the UTXOs they spend.
+    pub fn original_psbt(&self) -> String {
+        self.0.original_psbt()
+    }
+
+    /// Summarize this payjoin for the receiver's transaction history.
+    ///
+    /// `session_id` is the receiver's session identifier as returned by `Receiver::id()`, if
+    /// it should be recorded, and `now` the unix timestamp to record. The fees are those of
+    /// `fee_split()`.
+    pub fn to_tx_record(&self, session_id: Option<String>, now: u64) -> PayjoinTxRecord {
+        self.0.to_tx_record(session_id, now)
+    }
+
     pub fn extract_v2_req(&self, ohttp_relay: String) -> Result<RequestResponse, Error> {
         let (req, res) = self.0.extract_v2_req(ohttp_relay)?;
         Ok(RequestResponse { request: req, client_response: Arc::new(res) })
@@ -428,4 +1859,150 @@ impl PayjoinProposal {
     pub fn process_res(&self, body: &[u8], ctx: Arc<ClientResponse>) -> Result<(), Error> {
         self.0.process_res(body, ctx.as_ref())
     }
+
+    /// Mark the Original PSBT's inputs as seen in `store`. Call this once `process_res()`
+    /// succeeds.
+    pub fn mark_inputs_seen(
+        &self,
+        store: Arc<dyn SeenInputsStore>,
+    ) -> Result<(), ImplementationError> {
+        self.0.mark_inputs_seen(store.as_ref())
+    }
+}
+
+#[cfg(feature = "async")]
+mod asynchronous {
+    use std::sync::Arc;
+
+    use super::{
+        ClientResponse, Error, MaybeInputsOwned, MaybeInputsSeen, OutputsUnknown, PayjoinProposal,
+        ProvisionalProposal, Receiver, ReceiverResponse, ReplyableError, RequestResponse,
+        UncheckedProposal, WantsOutputs,
+    };
+    pub use crate::receive::asynchronous::{
+        CanBroadcastAsync, IsOutputKnownAsync, IsScriptOwnedAsync, ProcessPsbtAsync,
+    };
+    use crate::send::{HttpTransport, PollingPolicy, RunError};
+    use crate::Url;
+
+    #[uniffi::export]
+    impl Receiver {
+        /// Async variant of `extract_req()`.
+        pub async fn extract_req_async(
+            &self,
+            ohttp_relay: String,
+        ) -> Result<RequestResponse, Error> {
+            self.0
+                .extract_req_async(ohttp_relay)
+                .await
+                .map(|(request, ctx)| RequestResponse { request, client_response: Arc::new(ctx) })
+        }
+
+        /// Async variant of `process_res()`.
+        pub async fn process_res_async(
+            &self,
+            body: Vec<u8>,
+            context: Arc<ClientResponse>,
+        ) -> Result<ReceiverResponse, Error> {
+            self.0.process_res_async(&body, context.as_ref()).await.map(Into::into)
+        }
+
+        /// Poll the directory for the sender's Original PSBT on the schedule of `policy`.
+        ///
+        /// Transient errors are retried on the same schedule, and any other error is returned at
+        /// once, including an expired session. Returns `null` once the schedule ends without a
+        /// proposal. Cancelling the call stops polling.
+        pub async fn poll_with_policy(
+            &self,
+            http: Arc<dyn HttpTransport>,
+            ohttp_relay: Arc<Url>,
+            policy: Arc<PollingPolicy>,
+        ) -> Result<Option<Arc<UncheckedProposal>>, RunError> {
+            self.0
+                .poll_with_policy(http.as_ref(), (*ohttp_relay).clone(), &policy)
+                .await
+                .map(|proposal| proposal.map(|proposal| Arc::new(proposal.into())))
+        }
+    }
+
+    #[uniffi::export]
+    impl UncheckedProposal {
+        /// Async variant of `check_broadcast_suitability()`.
+        pub async fn check_broadcast_suitability_async(
+            &self,
+            min_fee_rate: Option<u64>,
+            can_broadcast: Arc<dyn CanBroadcastAsync>,
+        ) -> Result<Arc<MaybeInputsOwned>, ReplyableError> {
+            self.0
+                .check_broadcast_suitability_async(min_fee_rate, can_broadcast.as_ref())
+                .await
+                .map(|t| Arc::new(t.into()))
+        }
+    }
+
+    #[uniffi::export]
+    impl MaybeInputsOwned {
+        /// Async variant of `check_inputs_not_owned()`.
+        pub async fn check_inputs_not_owned_async(
+            &self,
+            is_owned: Arc<dyn IsScriptOwnedAsync>,
+        ) -> Result<Arc<MaybeInputsSeen>, ReplyableError> {
+            self.0.check_inputs_not_owned_async(is_owned.as_ref()).await.map(|t| Arc::new(t.into()))
+        }
+    }
+
+    #[uniffi::export]
+    impl MaybeInputsSeen {
+        /// Async variant of `check_no_inputs_seen_before()`.
+        pub async fn check_no_inputs_seen_before_async(
+            &self,
+            is_known: Arc<dyn IsOutputKnownAsync>,
+        ) -> Result<Arc<OutputsUnknown>, ReplyableError> {
+            self.0
+                .check_no_inputs_seen_before_async(is_known.as_ref())
+                .await
+                .map(|t| Arc::new(t.into()))
+        }
+    }
+
+    #[uniffi::export]
+    impl OutputsUnknown {
+        /// Async variant of `identify_receiver_outputs()`.
+        pub async fn identify_receiver_outputs_async(
+            &self,
+            is_receiver_output: Arc<dyn IsScriptOwnedAsync>,
+        ) -> Result<Arc<WantsOutputs>, ReplyableError> {
+            self.0
+                .identify_receiver_outputs_async(is_receiver_output.as_ref())
+                .await
+                .map(|t| Arc::new(t.into()))
+        }
+    }
+
+    #[uniffi::export]
+    impl ProvisionalProposal {
+        /// Async variant of `finalize_proposal()`.
+        pub async fn finalize_proposal_async(
+            &self,
+            process_psbt: Arc<dyn ProcessPsbtAsync>,
+            min_feerate_sat_per_vb: Option<u64>,
+            max_effective_fee_rate_sat_per_vb: Option<u64>,
+        ) -> Result<Arc<PayjoinProposal>, ReplyableError> {
+            self.0
+                .finalize_proposal_async(
+                    process_psbt.as_ref(),
+                    min_feerate_sat_per_vb,
+                    max_effective_fee_rate_sat_per_vb,
+                )
+                .await
+                .map(|t| Arc::new(t.into()))
+        }
+    }
+}
+#[cfg(feature = "async")]
+pub use asynchronous::*;
+
+/// The value behind `arc`, cloned only if another reference to it is still alive.
+fn unwrap_or_clone<T: Clone>(arc: Arc<T>) -> T {
+    Arc::try_unwrap(arc).unwrap_or_else(|arc| (*arc).clone())
 }
@@ -0,0 +1,595 @@
+//! The BIP78 receiver, for senders that POST the Original PSBT directly to the receiver's own
+//! HTTP endpoint.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::ScriptBuf;
+
+use super::error::{
+    ImplementationError, InputContributionError, OutputSubstitutionError, ReplyableError,
+    SelectionError, V1RequestError,
+};
+use super::{
+    check_replacement_outputs, fee_rate_sat_per_vb, FallbackTransaction, InputPair, SenderParams,
+    UihValues,
+};
+use crate::bitcoin_ffi::{OutPoint, Script, TxOut};
+use crate::TransactionView;
+
+/// Largest Original PSBT body accepted, in bytes: a 4MB transaction, base64 encoded.
+pub const MAX_CONTENT_LENGTH: u64 = 4_000_000 * 4 / 3;
+
+/// The content type of the response carrying [`PayjoinProposal::body`].
+pub const RESPONSE_CONTENT_TYPE: &str = "text/plain";
+
+/// The headers of a BIP78 POST request.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+pub trait Headers: Send + Sync {
+    /// The value of the header named `key`, if present.
+    ///
+    /// `key` is lowercase; implementations backed by a case-sensitive map may also be asked
+    /// for the canonical and uppercase spellings of the name.
+    fn get_header(&self, key: String) -> Option<String>;
+}
+
+/// The headers read from a BIP78 POST request.
+const REQUEST_HEADERS: [&str; 2] = ["content-type", "content-length"];
+
+/// [`Headers`] backed by a map, with case-insensitive names.
+///
+/// Names are lowercased once when the map is built, and looked up by their lowercase spelling.
+#[derive(Debug, Default, Clone)]
+pub struct MapHeaders(HashMap<String, String>);
+
+impl MapHeaders {
+    pub fn new(headers: HashMap<String, String>) -> Self {
+        Self(headers.into_iter().map(|(name, value)| (name.to_ascii_lowercase(), value)).collect())
+    }
+
+    /// Copy the headers of a BIP78 request out of `headers`.
+    fn from_headers(headers: &dyn Headers) -> Self {
+        Self(
+            REQUEST_HEADERS
+                .iter()
+                .filter_map(|name| {
+                    let value = [name.to_string(), canonical_name(name), name.to_ascii_uppercase()]
+                        .into_iter()
+                        .find_map(|key| headers.get_header(key))?;
+                    Some((name.to_string(), value))
+                })
+                .collect(),
+        )
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(&key.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+impl Headers for MapHeaders {
+    fn get_header(&self, key: String) -> Option<String> {
+        self.get(&key).map(str::to_string)
+    }
+}
+
+impl payjoin::receive::v1::Headers for MapHeaders {
+    fn get_header(&self, key: &str) -> Option<&str> {
+        self.get(key)
+    }
+}
+
+/// `name` with each dash-separated word capitalized, e.g. `Content-Type`.
+fn canonical_name(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The sender's Original PSBT, received in a BIP78 POST request.
+#[derive(Clone)]
+pub struct UncheckedProposal(pub payjoin::receive::v1::UncheckedProposal, SenderParams);
+
+impl From<UncheckedProposal> for payjoin::receive::v1::UncheckedProposal {
+    fn from(value: UncheckedProposal) -> Self {
+        value.0
+    }
+}
+
+impl UncheckedProposal {
+    /// Parse the Original PSBT from the parts of a BIP78 POST request.
+    ///
+    /// `body` is the request body, `query` the query string without the leading `?`, and
+    /// `headers` the request headers, whose names are matched case-insensitively. The
+    /// `content-type` and `content-length` headers and the version and fee parameters of
+    /// `query` are checked before the PSBT is parsed.
+    pub fn from_request(
+        body: Vec<u8>,
+        query: String,
+        headers: HashMap<String, String>,
+    ) -> Result<Self, V1RequestError> {
+        Self::from_map_headers(body, query, MapHeaders::new(headers))
+    }
+
+    /// Like [`UncheckedProposal::from_request`], reading the request headers from `headers`.
+    pub fn from_request_with_headers(
+        body: Vec<u8>,
+        query: String,
+        headers: &dyn Headers,
+    ) -> Result<Self, V1RequestError> {
+        Self::from_map_headers(body, query, MapHeaders::from_headers(headers))
+    }
+
+    fn from_map_headers(
+        body: Vec<u8>,
+        query: String,
+        headers: MapHeaders,
+    ) -> Result<Self, V1RequestError> {
+        check_headers(&headers, body.len() as u64)?;
+        check_query(&query)?;
+        let sender_params = SenderParams::from_query(&query);
+        payjoin::receive::v1::UncheckedProposal::from_request(body.as_slice(), &query, headers)
+            .map(|inner| Self(inner, sender_params))
+            .map_err(Into::into)
+    }
+
+    /// Whether the sender disabled output substitution, known as soon as the request arrives.
+    ///
+    /// A receiver that would substitute its output can skip deriving a new address if so.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.1.output_substitution_disabled
+    }
+
+    /// The Sender's Original PSBT
+    pub fn extract_tx_to_schedule_broadcast(&self) -> Vec<u8> {
+        payjoin::bitcoin::consensus::encode::serialize(&self.0.extract_tx_to_schedule_broadcast())
+    }
+
+    /// The Original PSBT's transaction together with the details needed to schedule its
+    /// broadcast, so callers don't have to parse [`Self::extract_tx_to_schedule_broadcast`].
+    pub fn fallback_transaction(&self) -> FallbackTransaction {
+        let tx = self.0.extract_tx_to_schedule_broadcast();
+        FallbackTransaction {
+            bytes: payjoin::bitcoin::consensus::encode::serialize(&tx),
+            txid: tx.compute_txid().to_string(),
+            weight: tx.weight().to_wu(),
+        }
+    }
+
+    /// The txid of [`Self::extract_tx_to_schedule_broadcast`].
+    pub fn fallback_txid(&self) -> String {
+        self.0.extract_tx_to_schedule_broadcast().compute_txid().to_string()
+    }
+
+    /// The weight of [`Self::extract_tx_to_schedule_broadcast`] in weight units.
+    pub fn fallback_weight(&self) -> u64 {
+        self.0.extract_tx_to_schedule_broadcast().weight().to_wu()
+    }
+
+    /// Check that the Original PSBT can be broadcast, with `min_fee_rate` in sat/kwu.
+    ///
+    /// See [`super::UncheckedProposal::check_broadcast_suitability`].
+    pub fn check_broadcast_suitability(
+        &self,
+        min_fee_rate: Option<u64>,
+        can_broadcast: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<MaybeInputsOwned, ReplyableError> {
+        self.check_broadcast_suitability_with_fee_rate(
+            min_fee_rate.map(crate::FeeRate::from_sat_per_kwu),
+            can_broadcast,
+        )
+    }
+
+    /// Like [`UncheckedProposal::check_broadcast_suitability`], with the minimum fee rate of the
+    /// Original PSBT given in an explicit unit, e.g. [`crate::FeeRate::from_sat_per_vb_f64`].
+    pub fn check_broadcast_suitability_with_fee_rate(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        can_broadcast: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<MaybeInputsOwned, ReplyableError> {
+        self.0
+            .clone()
+            .check_broadcast_suitability(min_fee_rate.map(Into::into), |transaction| {
+                Ok(can_broadcast(&payjoin::bitcoin::consensus::encode::serialize(transaction))?)
+            })
+            .map(|inner| MaybeInputsOwned(inner, self.1.clone()))
+            .map_err(Into::into)
+    }
+
+    /// Like [`UncheckedProposal::check_broadcast_suitability`], handing `can_broadcast` the
+    /// transaction's bytes, hex and txid rather than raw bytes only.
+    pub fn check_broadcast_suitability_with_view(
+        &self,
+        min_fee_rate: Option<crate::FeeRate>,
+        can_broadcast: impl Fn(&TransactionView) -> Result<bool, ImplementationError>,
+    ) -> Result<MaybeInputsOwned, ReplyableError> {
+        self.0
+            .clone()
+            .check_broadcast_suitability(min_fee_rate.map(Into::into), |transaction| {
+                Ok(can_broadcast(&transaction.clone().into())?)
+            })
+            .map(|inner| MaybeInputsOwned(inner, self.1.clone()))
+            .map_err(Into::into)
+    }
+
+    /// Skip the broadcast check, for receivers that only payjoin after manual intervention.
+    ///
+    /// See [`super::UncheckedProposal::assume_interactive_receiver`].
+    pub fn assume_interactive_receiver(&self) -> MaybeInputsOwned {
+        MaybeInputsOwned(self.0.clone().assume_interactive_receiver(), self.1.clone())
+    }
+}
+
+/// Typestate to validate that the Original PSBT has no receiver-owned inputs.
+#[derive(Clone)]
+pub struct MaybeInputsOwned(payjoin::receive::v1::MaybeInputsOwned, SenderParams);
+
+impl MaybeInputsOwned {
+    /// Whether the sender disabled output substitution.
+    pub fn is_output_substitution_disabled(&self) -> bool {
+        self.1.output_substitution_disabled
+    }
+
+    /// Check that the Original PSBT has no receiver-owned inputs.
+    pub fn check_inputs_not_owned(
+        &self,
+        is_owned: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<MaybeInputsSeen, ReplyableError> {
+        self.0
+            .clone()
+            .check_inputs_not_owned(|input| Ok(is_owned(&input.to_bytes())?))
+            .map(|inner| MaybeInputsSeen(inner, self.1.clone()))
+            .map_err(Into::into)
+    }
+}
+
+/// Typestate to validate that the Original PSBT has no inputs that have been seen before.
+#[derive(Clone)]
+pub struct MaybeInputsSeen(payjoin::receive::v1::MaybeInputsSeen, SenderParams);
+
+impl MaybeInputsSeen {
+    /// Make sure that the original transaction inputs have never been seen before.
+    pub fn check_no_inputs_seen_before(
+        &self,
+        is_known: impl Fn(&OutPoint) -> Result<bool, ImplementationError>,
+    ) -> Result<OutputsUnknown, ReplyableError> {
+        self.0
+            .clone()
+            .check_no_inputs_seen_before(|outpoint| Ok(is_known(&(*outpoint).into())?))
+            .map(|inner| OutputsUnknown(inner, self.1.clone()))
+            .map_err(Into::into)
+    }
+}
+
+/// The receiver has not yet identified which outputs belong to the receiver.
+#[derive(Clone)]
+pub struct OutputsUnknown(payjoin::receive::v1::OutputsUnknown, SenderParams);
+
+impl OutputsUnknown {
+    /// Find which outputs belong to the receiver
+    pub fn identify_receiver_outputs(
+        &self,
+        is_receiver_output: impl Fn(&Vec<u8>) -> Result<bool, ImplementationError>,
+    ) -> Result<WantsOutputs, ReplyableError> {
+        let drain_script = RefCell::new(None);
+        self.0
+            .clone()
+            .identify_receiver_outputs(|script| {
+                let is_receiver = is_receiver_output(&script.to_bytes())?;
+                if is_receiver {
+                    drain_script.borrow_mut().get_or_insert_with(|| script.to_owned());
+                }
+                Ok(is_receiver)
+            })
+            .map(|inner| WantsOutputs(inner, self.1.clone(), drain_script.into_inner()))
+            .map_err(Into::into)
+    }
+}
+
+/// The receiver may substitute its outputs or add new ones before committing them.
+///
+/// Call `commit_outputs()` to proceed to input contribution.
+///
+/// Alongside the sender's fee parameters, it keeps the script of the output the receiver's
+/// inputs are drained to, which payjoin doesn't expose.
+#[derive(Clone)]
+pub struct WantsOutputs(payjoin::receive::v1::WantsOutputs, SenderParams, Option<ScriptBuf>);
+
+impl WantsOutputs {
+    pub fn output_substitution(&self) -> bool {
+        self.0.output_substitution() == payjoin::OutputSubstitution::Enabled
+    }
+
+    /// Replace the receiver's outputs with `replacement_outputs`, sending any excess to
+    /// `drain_script`.
+    pub fn replace_receiver_outputs(
+        &self,
+        replacement_outputs: Vec<TxOut>,
+        drain_script: &Script,
+    ) -> Result<WantsOutputs, OutputSubstitutionError> {
+        let replacement_outputs: Vec<payjoin::bitcoin::TxOut> =
+            replacement_outputs.into_iter().map(Into::into).collect();
+        check_replacement_outputs(&replacement_outputs, &drain_script.0)?;
+        self.0
+            .clone()
+            .replace_receiver_outputs(replacement_outputs, &drain_script.0)
+            .map(|inner| WantsOutputs(inner, self.1.clone(), Some(drain_script.0.clone())))
+            .map_err(Into::into)
+    }
+
+    /// Pay the receiver's outputs to `output_script` instead, keeping their amounts.
+    ///
+    /// Fails with [`OutputSubstitutionError::Disabled`] if the sender disabled output
+    /// substitution and `output_script` isn't already the receiver's script.
+    pub fn substitute_receiver_script(
+        &self,
+        output_script: &Script,
+    ) -> Result<WantsOutputs, OutputSubstitutionError> {
+        self.0
+            .clone()
+            .substitute_receiver_script(&output_script.0)
+            .map(|inner| WantsOutputs(inner, self.1.clone(), Some(output_script.0.clone())))
+            .map_err(Into::into)
+    }
+
+    /// Proceed to input contribution with the current outputs.
+    pub fn commit_outputs(&self) -> WantsInputs {
+        WantsInputs(self.0.clone().commit_outputs(), self.1.clone(), self.2.clone())
+    }
+}
+
+/// The receiver may contribute inputs to the proposal before committing them.
+///
+/// Call `commit_inputs()` to proceed to a `ProvisionalProposal`.
+#[derive(Clone)]
+pub struct WantsInputs(payjoin::receive::v1::WantsInputs, SenderParams, Option<ScriptBuf>);
+
+impl WantsInputs {
+    /// Select the receiver input that best avoids the unnecessary input heuristic.
+    ///
+    /// See [`super::WantsInputs::try_preserving_privacy`].
+    pub fn try_preserving_privacy(
+        &self,
+        candidate_inputs: Vec<InputPair>,
+    ) -> Result<InputPair, SelectionError> {
+        super::first_selected(candidate_inputs, |candidates| {
+            self.0.clone().try_preserving_privacy(candidates.into_iter().map(Into::into))
+        })
+    }
+
+    /// Like [`WantsInputs::try_preserving_privacy`], returning every candidate it would accept,
+    /// best first.
+    ///
+    /// See [`super::WantsInputs::try_preserving_privacy_ranked`].
+    pub fn try_preserving_privacy_ranked(
+        &self,
+        candidate_inputs: Vec<InputPair>,
+    ) -> Result<Vec<InputPair>, SelectionError> {
+        let values = self.2.as_ref().and_then(|drain_script| {
+            UihValues::from_psbt(&self.commit_inputs().preview_psbt()?, drain_script)
+        });
+        super::rank_candidates(
+            candidate_inputs,
+            |candidates| self.try_preserving_privacy(candidates),
+            values,
+        )
+    }
+
+    /// Add the given receiver inputs to the proposal, adjusting the drain output accordingly.
+    pub fn contribute_inputs(
+        &self,
+        replacement_inputs: Vec<InputPair>,
+    ) -> Result<WantsInputs, InputContributionError> {
+        self.0
+            .clone()
+            .contribute_inputs(replacement_inputs.into_iter().map(Into::into))
+            .map(|inner| WantsInputs(inner, self.1.clone(), self.2.clone()))
+            .map_err(Into::into)
+    }
+
+    /// The fee parameters of the sender's request, to size the receiver's contribution.
+    pub fn sender_params(&self) -> SenderParams {
+        self.1.clone()
+    }
+
+    /// Proceed to finalization with the current inputs.
+    pub fn commit_inputs(&self) -> ProvisionalProposal {
+        ProvisionalProposal(self.0.clone().commit_inputs(), self.1.clone())
+    }
+}
+
+/// The payjoin proposal, ready for the receiver to sign.
+#[derive(Clone)]
+pub struct ProvisionalProposal(payjoin::receive::v1::ProvisionalProposal, SenderParams);
+
+impl ProvisionalProposal {
+    /// The fee parameters of the sender's request.
+    pub fn sender_params(&self) -> SenderParams {
+        self.1.clone()
+    }
+
+    /// The most the sender will pay, in satoshis, towards the fee of the receiver's additions.
+    pub fn max_additional_fee_contribution(&self) -> Option<u64> {
+        self.1.max_additional_fee_contribution
+    }
+
+    /// The minimum fee rate the sender requires of the payjoin, in sat/vB.
+    pub fn min_fee_rate_sat_per_vb(&self) -> Option<u64> {
+        self.1.min_fee_rate_sat_per_vb
+    }
+
+    /// Sign the proposal with `process_psbt`, which receives and returns a base64 PSBT.
+    pub fn finalize_proposal(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        self.0
+            .clone()
+            .finalize_proposal(
+                |psbt| {
+                    let psbt = process_psbt(psbt.to_string())?;
+                    Ok(Psbt::from_str(&psbt).map_err(ImplementationError::invalid_callback_psbt)?)
+                },
+                fee_rate_sat_per_vb(min_feerate_sat_per_vb)?,
+                fee_rate_sat_per_vb(max_effective_fee_rate_sat_per_vb)?,
+            )
+            .map(PayjoinProposal)
+            .map_err(Into::into)
+    }
+
+    /// Like [`ProvisionalProposal::finalize_proposal`], with fee rates given in an explicit unit.
+    pub fn finalize_proposal_with_fee_rates(
+        &self,
+        process_psbt: impl Fn(String) -> Result<String, ImplementationError>,
+        min_fee_rate: Option<crate::FeeRate>,
+        max_effective_fee_rate: Option<crate::FeeRate>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        self.0
+            .clone()
+            .finalize_proposal(
+                |psbt| {
+                    let psbt = process_psbt(psbt.to_string())?;
+                    Ok(Psbt::from_str(&psbt).map_err(ImplementationError::invalid_callback_psbt)?)
+                },
+                min_fee_rate.map(Into::into),
+                max_effective_fee_rate.map(Into::into),
+            )
+            .map(PayjoinProposal)
+            .map_err(Into::into)
+    }
+
+    /// Like [`ProvisionalProposal::finalize_proposal`], handing `process_psbt` the PSBT as raw
+    /// bytes and expecting raw bytes back instead of base64.
+    pub fn finalize_proposal_bytes(
+        &self,
+        process_psbt: impl Fn(Vec<u8>) -> Result<Vec<u8>, ImplementationError>,
+        min_feerate_sat_per_vb: Option<u64>,
+        max_effective_fee_rate_sat_per_vb: Option<u64>,
+    ) -> Result<PayjoinProposal, ReplyableError> {
+        self.0
+            .clone()
+            .finalize_proposal(
+                |psbt| {
+                    let psbt = process_psbt(psbt.serialize())?;
+                    Ok(Psbt::deserialize(&psbt)
+                        .map_err(ImplementationError::invalid_callback_psbt)?)
+                },
+                fee_rate_sat_per_vb(min_feerate_sat_per_vb)?,
+                fee_rate_sat_per_vb(max_effective_fee_rate_sat_per_vb)?,
+            )
+            .map(PayjoinProposal)
+            .map_err(Into::into)
+    }
+
+    /// The PSBT `finalize_proposal` hands to `process_psbt` at the default fee rates.
+    fn preview_psbt(&self) -> Option<Psbt> {
+        let preview = RefCell::new(None);
+        let _ = self.0.clone().finalize_proposal(
+            |pre_processed| {
+                *preview.borrow_mut() = Some(pre_processed.clone());
+                Err("proposal preview only".into())
+            },
+            None,
+            None,
+        );
+        preview.into_inner()
+    }
+}
+
+/// The signed payjoin proposal, to be returned to the sender as the response body.
+#[derive(Clone)]
+pub struct PayjoinProposal(pub payjoin::receive::v1::PayjoinProposal);
+
+impl From<PayjoinProposal> for payjoin::receive::v1::PayjoinProposal {
+    fn from(value: PayjoinProposal) -> Self {
+        value.0
+    }
+}
+
+impl PayjoinProposal {
+    /// The signed Payjoin PSBT, base64 encoded.
+    pub fn psbt(&self) -> String {
+        self.0.psbt().to_string()
+    }
+
+    /// The serialized form of [`PayjoinProposal::psbt`].
+    pub fn psbt_bytes(&self) -> Vec<u8> {
+        self.0.psbt().serialize()
+    }
+
+    /// The indexes of the receiver's outputs in the Payjoin PSBT.
+    pub fn owned_vouts(&self) -> Vec<u64> {
+        self.0.owned_vouts().iter().map(|&vout| vout as u64).collect()
+    }
+
+    pub fn utxos_to_be_locked(&self) -> Vec<OutPoint> {
+        self.0.utxos_to_be_locked().map(|o| (*o).into()).collect()
+    }
+
+    /// The body of the HTTP 200 response to the sender: the base64 Payjoin PSBT, without a
+    /// trailing newline.
+    ///
+    /// Serve it with the [`RESPONSE_CONTENT_TYPE`] content type.
+    pub fn body(&self) -> Vec<u8> {
+        self.psbt().into_bytes()
+    }
+}
+
+fn check_headers(headers: &MapHeaders, body_len: u64) -> Result<(), V1RequestError> {
+    let content_type = headers
+        .get("content-type")
+        .ok_or(V1RequestError::MissingHeader { name: "content-type".to_string() })?;
+    if !content_type.starts_with("text/plain") {
+        return Err(V1RequestError::InvalidContentType { content_type: content_type.to_string() });
+    }
+    let content_length = headers
+        .get("content-length")
+        .ok_or(V1RequestError::MissingHeader { name: "content-length".to_string() })?;
+    let declared: u64 = content_length
+        .trim()
+        .parse()
+        .map_err(|_| V1RequestError::InvalidContentLength { value: content_length.to_string() })?;
+    if declared > MAX_CONTENT_LENGTH {
+        return Err(V1RequestError::ContentTooLarge { length: declared, max: MAX_CONTENT_LENGTH });
+    }
+    if declared != body_len {
+        return Err(V1RequestError::ContentLengthMismatch { declared, actual: body_len });
+    }
+    Ok(())
+}
+
+fn check_query(query: &str) -> Result<(), V1RequestError> {
+    for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        let valid = match name.as_ref() {
+            "v" => {
+                if value != "1" {
+                    return Err(V1RequestError::UnsupportedVersion { version: value.into_owned() });
+                }
+                true
+            }
+            "additionalfeeoutputindex" => value.parse::<u32>().is_ok(),
+            "maxadditionalfeecontribution" => value.parse::<u64>().is_ok(),
+            "minfeerate" => value.parse::<f32>().is_ok_and(|rate| rate.is_finite() && rate >= 0.0),
+            "disableoutputsubstitution" => value == "true" || value == "false",
+            _ => true,
+        };
+        if !valid {
+            return Err(V1RequestError::InvalidParameter {
+                name: name.into_owned(),
+                value: value.into_owned(),
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::{OutPoint, ScriptBuf, TxOut};
+
+use crate::receive::FeeSplit;
+
+/// Which side of the payjoin the local wallet was on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum PayjoinDirection {
+    Sent,
+    Received,
+}
+
+/// A wallet-agnostic summary of a completed payjoin, suitable for a transaction history.
+///
+/// Amounts are denominated in satoshis. Fee fields are `None` when the PSBT the record was
+/// built from lacks the UTXO data required to compute them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct PayjoinTxRecord {
+    /// Txid of the payjoin transaction.
+    pub txid: String,
+    pub direction: PayjoinDirection,
+    /// Value of the receiver's outputs minus the value of the inputs it contributed.
+    pub net_amount_to_receiver: i64,
+    pub sender_fee: Option<u64>,
+    pub receiver_fee: Option<u64>,
+    /// Number of outputs belonging to the other party.
+    pub counterparty_output_count: u64,
+    /// Unix timestamp supplied by the caller when the record was made.
+    pub timestamp: u64,
+    /// The receiver's session identifier, if known.
+    pub session_id: Option<String>,
+}
+
+/// The previous output spent by a PSBT input, if it is known.
+pub(crate) fn input_utxo(psbt: &Psbt, index: usize) -> Option<TxOut> {
+    let input = psbt.inputs.get(index)?;
+    if let Some(txout) = &input.witness_utxo {
+        return Some(txout.clone());
+    }
+    let prevout = psbt.unsigned_tx.input.get(index)?.previous_output;
+    input.non_witness_utxo.as_ref()?.output.get(prevout.vout as usize).cloned()
+}
+
+/// The value spent by a PSBT input, if its previous output is known.
+pub(crate) fn input_value(psbt: &Psbt, index: usize) -> Option<u64> {
+    input_utxo(psbt, index).map(|txout| txout.value.to_sat())
+}
+
+/// Sum the values spent by the inputs of `psbt` selected by `filter`.
+pub(crate) fn sum_input_values(psbt: &Psbt, filter: impl Fn(&OutPoint) -> bool) -> Option<u64> {
+    psbt.unsigned_tx
+        .input
+        .iter()
+        .enumerate()
+        .filter(|(_, txin)| filter(&txin.previous_output))
+        .map(|(i, _)| input_value(psbt, i))
+        .sum()
+}
+
+impl PayjoinTxRecord {
+    /// Build the record for a sender from its Original PSBT and the receiver's payjoin proposal.
+    ///
+    /// Outputs paying `payee` or any script absent from the Original PSBT are attributed to the
+    /// receiver, inputs absent from the Original PSBT are attributed to the receiver, and everything
+    /// else to the sender.
+    pub(crate) fn for_sender(
+        original: &Psbt,
+        payjoin_psbt: &Psbt,
+        payee: &ScriptBuf,
+        session_id: Option<String>,
+        now: u64,
+    ) -> PayjoinTxRecord {
+        let original_inputs: HashSet<OutPoint> =
+            original.unsigned_tx.input.iter().map(|txin| txin.previous_output).collect();
+        let sender_scripts: HashSet<&ScriptBuf> = original
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| &txout.script_pubkey)
+            .filter(|script| *script != payee)
+            .collect();
+        let payment: u64 = original
+            .unsigned_tx
+            .output
+            .iter()
+            .filter(|txout| &txout.script_pubkey == payee)
+            .map(|txout| txout.value.to_sat())
+            .sum();
+
+        let (sender_outputs, receiver_outputs): (Vec<_>, Vec<_>) = payjoin_psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .partition(|txout| sender_scripts.contains(&txout.script_pubkey));
+        let sender_out: u64 = sender_outputs.iter().map(|txout| txout.value.to_sat()).sum();
+        let receiver_out: u64 = receiver_outputs.iter().map(|txout| txout.value.to_sat()).sum();
+
+        let sender_in = sum_input_values(original, |_| true);
+        let receiver_in =
+            sum_input_values(payjoin_psbt, |outpoint| !original_inputs.contains(outpoint));
+
+        PayjoinTxRecord {
+            txid: payjoin_psbt.unsigned_tx.compute_txid().to_string(),
+            direction: PayjoinDirection::Sent,
+            net_amount_to_receiver: receiver_out as i64 - receiver_in.unwrap_or(0) as i64,
+            sender_fee: sender_in
+                .and_then(|sender_in| sender_in.checked_sub(sender_out)?.checked_sub(payment)),
+            receiver_fee: receiver_in
+                .and_then(|receiver_in| (receiver_in + payment).checked_sub(receiver_out)),
+            counterparty_output_count: receiver_outputs.len() as u64,
+            timestamp: now,
+            session_id,
+        }
+    }
+
+    /// Build the record for a receiver from its finalized payjoin PSBT.
+    ///
+    /// The payjoin proposal is stripped of the sender's UTXO data, so the receiver's contributed
+    /// inputs are those that still carry it. The fee split depends on the amount the sender
+    /// originally paid, which the proposal does not record, so it is given as `fee_split`.
+    pub(crate) fn for_receiver(
+        payjoin_psbt: &Psbt,
+        owned_vouts: &[usize],
+        fee_split: &FeeSplit,
+        session_id: Option<String>,
+        now: u64,
+    ) -> PayjoinTxRecord {
+        let receiver_in: u64 =
+            (0..payjoin_psbt.inputs.len()).filter_map(|i| input_value(payjoin_psbt, i)).sum();
+        let receiver_out: u64 = owned_vouts
+            .iter()
+            .filter_map(|vout| payjoin_psbt.unsigned_tx.output.get(*vout))
+            .map(|txout| txout.value.to_sat())
+            .sum();
+
+        PayjoinTxRecord {
+            txid: payjoin_psbt.unsigned_tx.compute_txid().to_string(),
+            direction: PayjoinDirection::Received,
+            net_amount_to_receiver: receiver_out as i64 - receiver_in as i64,
+            sender_fee: fee_split.sender_fee,
+            receiver_fee: fee_split.receiver_fee,
+            counterparty_output_count: payjoin_psbt
+                .unsigned_tx
+                .output
+                .len()
+                .saturating_sub(owned_vouts.len()) as u64,
+            timestamp: now,
+            session_id,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::uri::Url;
@@ -12,6 +13,12 @@ pub struct Request {
     /// This is full URL with scheme etc - you can pass it right to `reqwest` or a similar library.
     pub url: Arc<Url>,
 
+    /// The HTTP method to send the request with, always `POST`.
+    pub method: String,
+
+    /// The HTTP headers to send the request with: `Content-Type` and `Content-Length`.
+    pub headers: HashMap<String, String>,
+
     /// The `Content-Type` header to use for the request.
     ///
     /// `text/plain` for v1 requests and `message/ohttp-req` for v2 requests.
@@ -21,14 +28,38 @@ pub struct Request {
     ///
     /// This is properly encoded PSBT payload either in base64 in v1 or an OHTTP encapsulated payload in v2.
     pub body: Vec<u8>,
+
+    /// Seconds the server may hold the request open before it responds, if it long-polls.
+    ///
+    /// Set the HTTP client's deadline slightly above this. `None` for requests that are answered
+    /// right away.
+    pub timeout_secs: Option<u64>,
 }
 
 impl From<payjoin::Request> for Request {
     fn from(value: payjoin::Request) -> Self {
+        let headers = HashMap::from([
+            ("Content-Type".to_string(), value.content_type.to_string()),
+            ("Content-Length".to_string(), value.body.len().to_string()),
+        ]);
         Self {
             url: Arc::new(value.url.into()),
+            method: "POST".to_string(),
+            headers,
             content_type: value.content_type.to_string(),
             body: value.body,
+            timeout_secs: None,
         }
     }
 }
+
+/// Decode a response body that an HTTP stack handed over as base64 text.
+///
+/// Whitespace, e.g. line breaks inserted by MIME encoders or a trailing newline, is ignored.
+pub(crate) fn decode_base64_body(body: &str) -> Result<Vec<u8>, String> {
+    use payjoin::bitcoin::base64::engine::general_purpose::STANDARD;
+    use payjoin::bitcoin::base64::Engine;
+
+    let body: String = body.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    STANDARD.decode(body).map_err(|e| e.to_string())
+}
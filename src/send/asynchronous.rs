@@ -0,0 +1,229 @@
+//! Async driver for a v2 send: post the Original PSBT through the OHTTP relay, then poll the
+//! directory for the receiver's proposal until it arrives or the sender gives up.
+
+use std::collections::HashMap;
+
+use super::error::RunError;
+use super::{now, Sender};
+use crate::receive::ImplementationError;
+use crate::request::Request;
+use crate::uri::Url;
+
+/// The HTTP client [`Sender::run`] sends its requests with, and the timer it waits with between
+/// polls, so the loop runs on any async runtime.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait HttpTransport: Send + Sync {
+    /// POST `body` to `url` with `headers`, `Content-Type` and `Content-Length`, and return the
+    /// response body.
+    async fn post(
+        &self,
+        url: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, ImplementationError>;
+
+    /// Return after `secs` seconds.
+    async fn sleep(&self, secs: u64);
+}
+
+/// When [`Sender::run_with_policy`] polls the directory, and when it gives up.
+///
+/// The delay after the first attempt is `initial_interval_secs`, growing by `multiplier` after
+/// each further attempt up to `max_interval_secs`. Once the delays add up to `deadline_secs`
+/// the schedule ends.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct PollingPolicy {
+    initial_interval_secs: u64,
+    multiplier: f64,
+    max_interval_secs: u64,
+    deadline_secs: u64,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl PollingPolicy {
+    /// A multiplier below 1, or NaN, is treated as 1: a constant interval.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn new(
+        initial_interval_secs: u64,
+        multiplier: f64,
+        max_interval_secs: u64,
+        deadline_secs: u64,
+    ) -> Self {
+        Self {
+            initial_interval_secs,
+            multiplier: multiplier.max(1.0),
+            max_interval_secs,
+            deadline_secs,
+        }
+    }
+
+    /// Poll after 5 seconds, doubling up to once a minute, for an hour.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn recommended() -> Self {
+        Self::new(5, 2.0, 60, 60 * 60)
+    }
+
+    /// Seconds to wait after attempt number `attempt`, counted from 0, before the next one.
+    ///
+    /// `None` if the schedule has ended or `last_error`, the error the attempt failed with, is
+    /// not transient. See [`RunError::is_transient`].
+    pub fn next_delay(&self, attempt: u32, last_error: Option<RunError>) -> Option<u64> {
+        self.delay(attempt, last_error.is_some_and(|e| !e.is_transient()))
+    }
+}
+
+impl PollingPolicy {
+    fn delay(&self, attempt: u32, fatal: bool) -> Option<u64> {
+        let remaining = self.deadline_secs.saturating_sub(self.elapsed(attempt));
+        if fatal || remaining == 0 {
+            return None;
+        }
+        Some(self.interval(attempt).min(remaining))
+    }
+
+    /// The delay after attempt `attempt` before it is cut short by the deadline.
+    fn interval(&self, attempt: u32) -> u64 {
+        let interval = self.initial_interval_secs as f64
+            * self.multiplier.powi(attempt.min(i32::MAX as u32) as i32);
+        interval.min(self.max_interval_secs as f64) as u64
+    }
+
+    /// The sum of the delays after the attempts before `attempt`.
+    fn elapsed(&self, attempt: u32) -> u64 {
+        let mut elapsed = 0u64;
+        for earlier in 0..attempt {
+            let interval = self.interval(earlier);
+            // From here on every interval is the same
+            if interval == self.max_interval_secs
+                || self.multiplier == 1.0
+                || self.initial_interval_secs == 0
+            {
+                return elapsed.saturating_add(interval.saturating_mul((attempt - earlier) as u64));
+            }
+            elapsed = elapsed.saturating_add(interval);
+            if elapsed >= self.deadline_secs {
+                break;
+            }
+        }
+        elapsed
+    }
+}
+
+impl Sender {
+    /// Drive a v2 send to completion and return the validated Payjoin PSBT to sign and
+    /// broadcast.
+    ///
+    /// Polls every `poll_interval_secs` until `deadline_secs` after the call. See
+    /// [`Sender::run_with_policy`].
+    ///
+    /// Transient errors, such as a failed HTTP request, are retried at the next poll rather
+    /// than returned. See [`RunError::is_transient`].
+    pub async fn run(
+        &self,
+        http: &dyn HttpTransport,
+        ohttp_relay: Url,
+        poll_interval_secs: u64,
+        deadline_secs: u64,
+    ) -> Result<String, RunError> {
+        let policy = PollingPolicy::new(poll_interval_secs, 1.0, poll_interval_secs, deadline_secs);
+        self.run_with_policy(http, ohttp_relay, &policy).await
+    }
+
+    /// Drive a v2 send to completion and return the validated Payjoin PSBT to sign and
+    /// broadcast.
+    ///
+    /// Posts the Original PSBT to the receiver's mailbox through `ohttp_relay`, then polls the
+    /// directory on the schedule of `policy`, each poll encapsulated afresh. Transient errors
+    /// are retried on the same schedule, and any other error is returned at once. Gives up with
+    /// [`RunError::FallbackDue`] once the schedule ends or at the sender's fallback deadline,
+    /// which includes the receiver's session expiry, whichever comes first.
+    ///
+    /// Dropping the returned future cancels the send between requests. Nothing is left to clean
+    /// up: persist the sender beforehand to resume polling later.
+    pub async fn run_with_policy(
+        &self,
+        http: &dyn HttpTransport,
+        ohttp_relay: Url,
+        policy: &PollingPolicy,
+    ) -> Result<String, RunError> {
+        let deadline = now().saturating_add(policy.deadline_secs);
+        let deadline = self.fallback_deadline().map_or(deadline, |due| due.min(deadline));
+        let mut attempt = 0;
+
+        let context = loop {
+            self.check_run_deadline(Some(deadline))?;
+            let result = async {
+                let (request, context) = self.extract_v2(ohttp_relay.clone())?;
+                let response = post(http, request).await?;
+                Ok::<_, RunError>(context.process_response(&response)?)
+            };
+            match result.await {
+                Ok(context) => break context,
+                Err(error) => self.back_off(http, policy, &mut attempt, deadline, error).await?,
+            }
+        };
+        loop {
+            self.check_run_deadline(Some(deadline))?;
+            let result = async {
+                let (request, ohttp_ctx) = context.extract_req(ohttp_relay.as_string())?;
+                let response = post(http, request).await?;
+                Ok::<_, RunError>(context.process_response(&response, &ohttp_ctx)?)
+            };
+            let error = match result.await {
+                Ok(Some(psbt)) => return Ok(psbt),
+                Ok(None) => None,
+                Err(error) => Some(error),
+            };
+            match error {
+                Some(error) => self.back_off(http, policy, &mut attempt, deadline, error).await?,
+                None => self.wait(http, policy, &mut attempt, deadline).await?,
+            }
+        }
+    }
+
+    /// Wait out the delay after a failed attempt, or return its error if it isn't retried.
+    async fn back_off(
+        &self,
+        http: &dyn HttpTransport,
+        policy: &PollingPolicy,
+        attempt: &mut u32,
+        deadline: u64,
+        error: RunError,
+    ) -> Result<(), RunError> {
+        if !error.is_transient() || policy.delay(*attempt, false).is_none() {
+            return Err(error);
+        }
+        self.wait(http, policy, attempt, deadline).await
+    }
+
+    /// Wait out the delay after an attempt, or fall back once the schedule ends.
+    async fn wait(
+        &self,
+        http: &dyn HttpTransport,
+        policy: &PollingPolicy,
+        attempt: &mut u32,
+        deadline: u64,
+    ) -> Result<(), RunError> {
+        let Some(delay) = policy.delay(*attempt, false) else {
+            return self.check_run_deadline(None);
+        };
+        *attempt = attempt.saturating_add(1);
+        http.sleep(delay.min(deadline.saturating_sub(now()))).await;
+        Ok(())
+    }
+
+    /// Fall back once the unix time `deadline` has passed, or at once without a deadline, when
+    /// the polling schedule has ended.
+    fn check_run_deadline(&self, deadline: Option<u64>) -> Result<(), RunError> {
+        if deadline.is_some_and(|deadline| now() < deadline) {
+            return Ok(());
+        }
+        Err(RunError::FallbackDue { original_tx: self.fallback_tx() })
+    }
+}
+
+pub(crate) async fn post(http: &dyn HttpTransport, request: Request) -> Result<Vec<u8>, RunError> {
+    http.post(request.url.as_string(), request.headers, request.body).await.map_err(Into::into)
+}
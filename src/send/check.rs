@@ -0,0 +1,110 @@
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::{Amount, Script};
+
+use super::error::BuildSenderErrorKind;
+
+/// Check the Original PSBT pays `payee` with finalized inputs that carry their UTXOs.
+///
+/// payjoin rejects such PSBTs too, but without saying which input or output is at fault.
+pub(crate) fn original_psbt(psbt: &Psbt, payee: &Script) -> Result<(), BuildSenderErrorKind> {
+    if psbt.inputs.is_empty() {
+        return Err(BuildSenderErrorKind::NoInputs);
+    }
+    for (index, input) in psbt.inputs.iter().enumerate() {
+        let input_index = index as u64;
+        if input.witness_utxo.is_none() && input.non_witness_utxo.is_none() {
+            return Err(BuildSenderErrorKind::MissingUtxoInfo { input_index });
+        }
+        if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+            return Err(BuildSenderErrorKind::InputNotFinalized { input_index });
+        }
+    }
+    if !psbt.unsigned_tx.output.iter().any(|output| output.script_pubkey == *payee) {
+        return Err(BuildSenderErrorKind::MissingPayeeOutput);
+    }
+    Ok(())
+}
+
+/// Check the output at `change_index` can pay `max_fee_contribution`.
+pub(crate) fn fee_output(
+    psbt: &Psbt,
+    payee: &Script,
+    change_index: u32,
+    max_fee_contribution: u64,
+    clamp_fee_contribution: bool,
+) -> Result<(), BuildSenderErrorKind> {
+    let index = change_index as u64;
+    let outputs = &psbt.unsigned_tx.output;
+    let output =
+        outputs.get(change_index as usize).ok_or(BuildSenderErrorKind::ChangeIndexOutOfBounds {
+            index,
+            output_count: outputs.len() as u64,
+        })?;
+    if output.script_pubkey == *payee {
+        return Err(BuildSenderErrorKind::ChangeIndexPointsAtPayee { index });
+    }
+    let available = output.value.to_sat();
+    if !clamp_fee_contribution && available < max_fee_contribution {
+        return Err(BuildSenderErrorKind::FeeOutputTooSmall {
+            output_index: index,
+            required: max_fee_contribution,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Find the output paying the payjoin URI among all outputs of the Original PSBT.
+///
+/// With an `amount` the output must pay exactly that amount, and exactly one output may match.
+pub(crate) fn payee_output(
+    psbt: &Psbt,
+    payee: &Script,
+    amount: Option<Amount>,
+) -> Result<u32, BuildSenderErrorKind> {
+    let paying: Vec<(usize, Amount)> = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.script_pubkey == *payee)
+        .map(|(index, output)| (index, output.value))
+        .collect();
+    if paying.is_empty() {
+        return Err(BuildSenderErrorKind::MissingPayeeOutput);
+    }
+    let matching: Vec<usize> = paying
+        .iter()
+        .filter(|(_, value)| amount.is_none() || amount == Some(*value))
+        .map(|(index, _)| *index)
+        .collect();
+    match matching.as_slice() {
+        [index] => Ok(*index as u32),
+        [] => {
+            Err(BuildSenderErrorKind::PayeeAmountMismatch {
+                expected: amount.unwrap_or_default().to_sat(),
+                actual: paying.iter().map(|(_, value)| value.to_sat()).sum(),
+            })
+        }
+        _ => {
+            Err(BuildSenderErrorKind::AmbiguousPayeeOutput {
+                matching_outputs: matching.len() as u64,
+            })
+        }
+    }
+}
+
+/// The change output BIP78 auto-detection picks: the output not paying `payee` when there are
+/// exactly two.
+pub(crate) fn auto_change_output(psbt: &Psbt, payee: &Script) -> Option<u32> {
+    let outputs = &psbt.unsigned_tx.output;
+    if outputs.len() != 2 {
+        return None;
+    }
+    let mut change =
+        outputs.iter().enumerate().filter(|(_, output)| output.script_pubkey != *payee);
+    match (change.next(), change.next()) {
+        (Some((index, _)), None) => Some(index as u32),
+        _ => None,
+    }
+}
@@ -11,20 +11,94 @@ use payjoin::send;
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct BuildSenderError {
     msg: String,
+    kind: Option<BuildSenderErrorKind>,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl BuildSenderError {
+    pub fn message(&self) -> String {
+        self.msg.clone()
+    }
+
+    /// What was wrong with the Original PSBT or the build parameters, when known.
+    pub fn kind(&self) -> Option<BuildSenderErrorKind> {
+        self.kind.clone()
+    }
+}
+
+impl From<BuildSenderErrorKind> for BuildSenderError {
+    fn from(value: BuildSenderErrorKind) -> Self {
+        BuildSenderError { msg: value.to_string(), kind: Some(value) }
+    }
+}
+
+impl From<crate::uri::PjParseError> for BuildSenderError {
+    fn from(value: crate::uri::PjParseError) -> Self {
+        BuildSenderError { msg: value.to_string(), kind: None }
+    }
 }
 
 impl From<PsbtParseError> for BuildSenderError {
     fn from(value: PsbtParseError) -> Self {
-        BuildSenderError { msg: value.to_string() }
+        BuildSenderError { msg: value.to_string(), kind: None }
+    }
+}
+
+impl From<super::psbt_v2::PsbtV2Error> for BuildSenderError {
+    fn from(value: super::psbt_v2::PsbtV2Error) -> Self {
+        BuildSenderError { msg: value.to_string(), kind: None }
     }
 }
 
 impl From<send::BuildSenderError> for BuildSenderError {
     fn from(value: send::BuildSenderError) -> Self {
-        BuildSenderError { msg: value.to_string() }
+        BuildSenderError { msg: value.to_string(), kind: None }
     }
 }
 
+/// A problem with the Original PSBT or the build parameters that a wallet can correct.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum BuildSenderErrorKind {
+    /// The Original PSBT spends no inputs.
+    #[error("The Original PSBT has no inputs")]
+    NoInputs,
+    /// The input at `input_index` has neither a witness nor a non-witness UTXO.
+    #[error("Input {input_index} is missing its UTXO")]
+    MissingUtxoInfo { input_index: u64 },
+    /// The input at `input_index` is not finalized.
+    #[error("Input {input_index} is not finalized")]
+    InputNotFinalized { input_index: u64 },
+    /// No output of the Original PSBT pays the address of the payjoin URI.
+    #[error("The Original PSBT doesn't pay the payee")]
+    MissingPayeeOutput,
+    /// The change index given for the fee contribution is past the last output.
+    #[error("Change index {index} is out of bounds for {output_count} outputs")]
+    ChangeIndexOutOfBounds { index: u64, output_count: u64 },
+    /// The change index given for the fee contribution points at the payee's output.
+    #[error("Change index {index} points at the payee's output")]
+    ChangeIndexPointsAtPayee { index: u64 },
+    /// The change output can't pay the fee contribution. Raise its value, lower the
+    /// contribution or allow it to be clamped.
+    #[error("Output {output_index} holds {available} sats, less than the {required} sat fee contribution")]
+    FeeOutputTooSmall { output_index: u64, required: u64, available: u64 },
+    /// The outputs paying the payjoin URI's address don't pay its amount. `actual` is their
+    /// total.
+    #[error("The payee outputs pay {actual} sats instead of the requested {expected} sats")]
+    PayeeAmountMismatch { expected: u64, actual: u64 },
+    /// Several outputs pay the payjoin URI's address and amount, so the payee's can't be told
+    /// apart.
+    #[error("{matching_outputs} outputs pay the payee")]
+    AmbiguousPayeeOutput { matching_outputs: u64 },
+    /// The BIP21 URI has no `pj` parameter, so the payee doesn't support payjoin.
+    #[error("The URI has no payjoin endpoint")]
+    MissingPayjoinEndpoint,
+    /// The pj endpoint is neither HTTPS nor a Tor onion service, so the Original PSBT would be
+    /// sent in cleartext.
+    #[error("The pj endpoint {endpoint} is not HTTPS or an onion service")]
+    UnsecuredEndpoint { endpoint: String },
+}
+
 /// Error returned when request could not be created.
 ///
 /// This error can currently only happen due to programmer mistake.
@@ -35,11 +109,88 @@ impl From<send::BuildSenderError> for BuildSenderError {
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct CreateRequestError(#[from] send::v2::CreateRequestError);
 
-/// Error returned for v2-specific payload encapsulation errors.
+/// Error extracting a request to poll the receiver for its proposal.
 #[derive(Debug, thiserror::Error)]
-#[error(transparent)]
-#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
-pub struct EncapsulationError(#[from] send::v2::EncapsulationError);
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum PollError {
+    /// The payjoin did not complete before the sender's deadline.
+    ///
+    /// Stop polling and broadcast `original_tx`, the consensus encoded Original PSBT transaction.
+    #[error("The payjoin deadline passed, broadcast the original transaction instead")]
+    FallbackDue { original_tx: Vec<u8> },
+    #[error(transparent)]
+    CreateRequest(Arc<CreateRequestError>),
+}
+
+impl From<CreateRequestError> for PollError {
+    fn from(value: CreateRequestError) -> Self {
+        PollError::CreateRequest(Arc::new(value))
+    }
+}
+
+/// Error driving a v2 session with `Sender::run` or `Receiver::poll_with_policy`.
+#[cfg(feature = "async")]
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum RunError {
+    /// The payjoin did not complete before the deadline.
+    ///
+    /// Broadcast `original_tx`, the consensus encoded Original PSBT transaction, instead. It is
+    /// `None` only for senders persisted by versions that kept no Original PSBT, as for
+    /// `Sender::fallback_tx`.
+    #[error("The payjoin deadline passed, broadcast the original transaction instead")]
+    FallbackDue { original_tx: Option<Vec<u8>> },
+    #[error(transparent)]
+    CreateRequest(Arc<CreateRequestError>),
+    /// The HTTP callback failed.
+    #[error("The HTTP request failed: {0}")]
+    Transport(Arc<crate::receive::ImplementationError>),
+    /// A poll response couldn't be processed, or the receiver replied with an error.
+    #[error(transparent)]
+    Response(ResponseError),
+    /// The receiver couldn't poll the directory or process its response, or its session
+    /// expired.
+    #[error(transparent)]
+    Receive(crate::receive::Error),
+}
+
+#[cfg(feature = "async")]
+impl RunError {
+    /// Whether retrying may succeed: the HTTP request failed or the directory's response
+    /// couldn't be decapsulated. Rejections, invalid proposals and a passed deadline are final.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            RunError::Transport(_) | RunError::Response(ResponseError::Decapsulation { .. })
+        )
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<CreateRequestError> for RunError {
+    fn from(value: CreateRequestError) -> Self {
+        RunError::CreateRequest(Arc::new(value))
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<crate::receive::ImplementationError> for RunError {
+    fn from(value: crate::receive::ImplementationError) -> Self {
+        RunError::Transport(Arc::new(value))
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<ResponseError> for RunError {
+    fn from(value: ResponseError) -> Self {
+        match value {
+            ResponseError::FallbackDue { original_tx } => {
+                RunError::FallbackDue { original_tx: Some(original_tx) }
+            }
+            value => RunError::Response(value),
+        }
+    }
+}
 
 /// Error that may occur when the response from receiver is malformed.
 #[derive(Debug, thiserror::Error)]
@@ -55,6 +206,8 @@ pub enum ResponseError {
     ///
     /// It is safe to display `WellKnown` errors to end users.
     ///
+    /// v1 responses report these as `ResponseError::ReceiverRejected`.
+    ///
     /// [`BIP78::ReceiverWellKnownError`]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki#user-content-Receivers_well_known_errors
     #[error("A receiver error occurred: {0}")]
     WellKnown(Arc<WellKnownError>),
@@ -71,13 +224,157 @@ pub enum ResponseError {
     /// [`BIP78::ReceiverWellKnownError`]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki#user-content-Receivers_well_known_errors
     #[error("An unrecognized error occurred")]
     Unrecognized { error_code: String, msg: String },
+
+    /// The OHTTP context was already used to process a response. Each context processes
+    /// exactly one response; extract a new request to get another.
+    #[error("The OHTTP context was already used to process a response")]
+    OhttpContextAlreadyUsed,
+
+    /// The receiver doesn't support the protocol version of the request.
+    ///
+    /// Retry with a version in `supported`, e.g. a v1 request from `Sender::extract_v1`. It is
+    /// safe to display `msg` to end users.
+    #[error("The receiver doesn't support this version of the protocol: {msg}")]
+    VersionUnsupported { supported: Vec<u64>, msg: String },
+
+    /// The OHTTP context is not the one of the latest request from the poller, so it can't
+    /// decapsulate the response.
+    #[error("The OHTTP context is not from the latest poll request")]
+    StaleOhttpContext,
+
+    /// The receiver rejected the Original PSBT with a BIP78 error body, e.g.
+    /// `{"errorCode": "not-enough-money", "message": "..."}`.
+    ///
+    /// Show end users the description of a well-known `code` rather than `message`, which the
+    /// receiver chose freely. `version-unsupported` is reported as
+    /// `ResponseError::VersionUnsupported` instead, as it is for v2.
+    #[error("The receiver rejected the payjoin: {code}")]
+    ReceiverRejected { code: RejectionCode, message: String },
+
+    /// The response body is neither a JSON error nor a base64 PSBT, or the bytes handed to
+    /// `process_response_bytes` aren't a binary PSBT.
+    #[error("The response body is not a PSBT in a supported encoding: {msg}")]
+    InvalidEncoding { msg: String },
+
+    /// The sender's fallback deadline passed before the proposal was processed.
+    ///
+    /// Stop polling and broadcast `original_tx`, the consensus encoded Original PSBT transaction.
+    #[error("The payjoin deadline passed, broadcast the original transaction instead")]
+    FallbackDue { original_tx: Vec<u8> },
+
+    /// The directory's response to the Original PSBT or to a poll couldn't be decapsulated, e.g.
+    /// it was corrupted in transit or isn't the response to the request of the OHTTP context it
+    /// was processed with.
+    ///
+    /// Unlike `ResponseError::Validation` this says nothing about the receiver's proposal, so
+    /// polling again may succeed.
+    #[error("The response couldn't be decapsulated: {msg}")]
+    Decapsulation { msg: String },
+
+    /// The validated proposal can't be returned in the PSBT version of the Original PSBT.
+    #[error("The proposal can't be converted to the Original PSBT's version: {msg}")]
+    PsbtVersionConversion { msg: String },
+}
+
+impl ResponseError {
+    /// Parse a BIP78 error body into `ResponseError::ReceiverRejected`.
+    ///
+    /// Bodies that aren't a JSON object with a string `errorCode` are left to the PSBT decoder,
+    /// and `version-unsupported` bodies to payjoin, whose error converts to
+    /// `ResponseError::VersionUnsupported` as for v2.
+    pub(crate) fn receiver_rejected(body: &[u8]) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+        let code = RejectionCode::from(value["errorCode"].as_str()?);
+        if code == RejectionCode::VersionUnsupported {
+            return None;
+        }
+        let message = value["message"].as_str().unwrap_or_default().to_string();
+        Some(ResponseError::ReceiverRejected { code, message })
+    }
+}
+
+/// The error codes a receiver may reject the Original PSBT with.
+///
+/// See [`BIP78::ReceiverWellKnownError`].
+///
+/// [`BIP78::ReceiverWellKnownError`]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki#user-content-Receivers_well_known_errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum RejectionCode {
+    /// The payjoin endpoint is not available for now. Retry later.
+    #[error("The payjoin endpoint is not available for now.")]
+    Unavailable,
+    /// The receiver added inputs, but the sender's fee contribution can't pay for them.
+    #[error("The receiver added some inputs but could not bump the fee of the payjoin proposal.")]
+    NotEnoughMoney,
+    /// The payjoin version of the request isn't supported by the receiver.
+    #[error("This version of payjoin is not supported.")]
+    VersionUnsupported,
+    /// The receiver rejected the Original PSBT.
+    #[error("The receiver rejected the original PSBT.")]
+    OriginalPsbtRejected,
+    /// A code BIP78 doesn't define. Only show it in debug logs.
+    #[error("Unrecognized error code {0}")]
+    Unrecognized(String),
+}
+
+impl From<&str> for RejectionCode {
+    fn from(value: &str) -> Self {
+        match value {
+            "unavailable" => RejectionCode::Unavailable,
+            "not-enough-money" => RejectionCode::NotEnoughMoney,
+            "version-unsupported" => RejectionCode::VersionUnsupported,
+            "original-psbt-rejected" => RejectionCode::OriginalPsbtRejected,
+            other => RejectionCode::Unrecognized(other.to_string()),
+        }
+    }
+}
+
+impl From<payjoin::error_codes::ErrorCode> for RejectionCode {
+    fn from(value: payjoin::error_codes::ErrorCode) -> Self {
+        use payjoin::error_codes::ErrorCode;
+
+        match value {
+            ErrorCode::Unavailable => RejectionCode::Unavailable,
+            ErrorCode::NotEnoughMoney => RejectionCode::NotEnoughMoney,
+            ErrorCode::VersionUnsupported => RejectionCode::VersionUnsupported,
+            ErrorCode::OriginalPsbtRejected => RejectionCode::OriginalPsbtRejected,
+        }
+    }
+}
+
+impl From<super::psbt_v2::PsbtV2Error> for ResponseError {
+    fn from(value: super::psbt_v2::PsbtV2Error) -> Self {
+        ResponseError::PsbtVersionConversion { msg: value.to_string() }
+    }
+}
+
+impl From<send::v2::EncapsulationError> for ResponseError {
+    fn from(value: send::v2::EncapsulationError) -> Self {
+        ResponseError::Decapsulation { msg: value.to_string() }
+    }
 }
 
 impl From<send::ResponseError> for ResponseError {
     fn from(value: send::ResponseError) -> Self {
         match value {
-            send::ResponseError::WellKnown(e) => ResponseError::WellKnown(Arc::new(e.into())),
-            send::ResponseError::Validation(e) => ResponseError::Validation(Arc::new(e.into())),
+            send::ResponseError::WellKnown(e) => {
+                match RejectionCode::from(e.error_code()) {
+                    RejectionCode::VersionUnsupported => {
+                        ResponseError::VersionUnsupported {
+                            supported: e.supported_versions().unwrap_or_default(),
+                            msg: e.message().to_string(),
+                        }
+                    }
+                    _ => ResponseError::WellKnown(Arc::new(e.into())),
+                }
+            }
+            send::ResponseError::Validation(e) => {
+                match decapsulation_failure(&e) {
+                    Some(msg) => ResponseError::Decapsulation { msg },
+                    None => ResponseError::Validation(Arc::new(e.into())),
+                }
+            }
             send::ResponseError::Unrecognized { error_code, message } => {
                 ResponseError::Unrecognized { error_code, msg: message }
             }
@@ -85,8 +382,32 @@ impl From<send::ResponseError> for ResponseError {
     }
 }
 
+/// The reason decapsulating a v2 response failed, if that is what `error` reports.
+///
+/// payjoin reports decapsulation failures and invalid proposals alike as a `ValidationError`,
+/// keeping the encapsulation error only as its source.
+fn decapsulation_failure(error: &send::ValidationError) -> Option<String> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if let Some(error) = error.downcast_ref::<send::v2::EncapsulationError>() {
+            return Some(error.to_string());
+        }
+        source = error.source();
+    }
+    None
+}
+
 /// A well-known error that can be safely displayed to end users.
 #[derive(Debug, thiserror::Error)]
 #[error(transparent)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct WellKnownError(#[from] send::WellKnownError);
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl WellKnownError {
+    /// The BIP78 error code the receiver replied with, as v1 reports it in
+    /// `ResponseError::ReceiverRejected`.
+    pub fn code(&self) -> RejectionCode {
+        self.0.error_code().into()
+    }
+}
@@ -0,0 +1,73 @@
+use payjoin::bitcoin::psbt::Psbt;
+
+/// Strip wallet-identifying metadata BIP78 doesn't require from the Original PSBT.
+///
+/// Global xpubs, key origins and proprietary or unknown fields reveal the sender's wallet
+/// structure to the receiver. UTXOs, scripts and final signatures are kept.
+pub(crate) fn sanitize(psbt: &Psbt) -> Psbt {
+    let mut psbt = psbt.clone();
+    psbt.xpub.clear();
+    psbt.proprietary.clear();
+    psbt.unknown.clear();
+    for input in &mut psbt.inputs {
+        input.bip32_derivation.clear();
+        input.tap_key_origins.clear();
+        input.tap_internal_key = None;
+        input.tap_merkle_root = None;
+        input.proprietary.clear();
+        input.unknown.clear();
+    }
+    for output in &mut psbt.outputs {
+        output.bip32_derivation.clear();
+        output.tap_key_origins.clear();
+        output.tap_internal_key = None;
+        output.tap_tree = None;
+        output.proprietary.clear();
+        output.unknown.clear();
+    }
+    psbt
+}
+
+/// Restore the metadata [`sanitize`] stripped from `original` onto the sender's inputs and outputs
+/// of the receiver's `proposal`, so the sender's own signer can process it.
+pub(crate) fn restore(proposal: &mut Psbt, original: &Psbt) {
+    proposal.xpub.extend(original.xpub.clone());
+    proposal.proprietary.extend(original.proprietary.clone());
+    proposal.unknown.extend(original.unknown.clone());
+    for (txin, input) in proposal.unsigned_tx.input.iter().zip(&mut proposal.inputs) {
+        let Some(index) = original
+            .unsigned_tx
+            .input
+            .iter()
+            .position(|original_txin| original_txin.previous_output == txin.previous_output)
+        else {
+            continue;
+        };
+        let original_input = &original.inputs[index];
+        input.bip32_derivation.extend(original_input.bip32_derivation.clone());
+        input.tap_key_origins.extend(original_input.tap_key_origins.clone());
+        input.tap_internal_key = input.tap_internal_key.or(original_input.tap_internal_key);
+        input.tap_merkle_root = input.tap_merkle_root.or(original_input.tap_merkle_root);
+        input.proprietary.extend(original_input.proprietary.clone());
+        input.unknown.extend(original_input.unknown.clone());
+    }
+    for (txout, output) in proposal.unsigned_tx.output.iter().zip(&mut proposal.outputs) {
+        let Some(index) = original
+            .unsigned_tx
+            .output
+            .iter()
+            .position(|original_txout| original_txout.script_pubkey == txout.script_pubkey)
+        else {
+            continue;
+        };
+        let original_output = &original.outputs[index];
+        output.bip32_derivation.extend(original_output.bip32_derivation.clone());
+        output.tap_key_origins.extend(original_output.tap_key_origins.clone());
+        output.tap_internal_key = output.tap_internal_key.or(original_output.tap_internal_key);
+        if output.tap_tree.is_none() {
+            output.tap_tree = original_output.tap_tree.clone();
+        }
+        output.proprietary.extend(original_output.proprietary.clone());
+        output.unknown.extend(original_output.unknown.clone());
+    }
+}
@@ -1,15 +1,37 @@
 use std::io::Cursor;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub use error::{BuildSenderError, CreateRequestError, EncapsulationError, ResponseError};
+#[cfg(feature = "async")]
+pub use asynchronous::{HttpTransport, PollingPolicy};
+#[cfg(feature = "async")]
+pub use error::RunError;
+pub use error::{
+    BuildSenderError, BuildSenderErrorKind, CreateRequestError, PollError, RejectionCode,
+    ResponseError,
+};
+use payjoin::bitcoin::hex::{DisplayHex, FromHex};
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::ScriptBuf;
+use proposal::Changes;
+pub use proposal::CheckedProposal;
 
-pub use crate::error::SerdeJsonError;
-use crate::ohttp::ClientResponse;
+use crate::directory::DirectoryCapabilities;
+pub use crate::error::{PsbtParseError, SerdeJsonError};
+use crate::ohttp::{ClientResponse, OhttpKeys};
+use crate::record::PayjoinTxRecord;
 use crate::request::Request;
-use crate::uri::{PjUri, Url};
+use crate::types::FeeRate;
+use crate::uri::{fragment, PjUri, Url};
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+mod check;
 pub mod error;
+mod metadata;
+mod proposal;
+mod psbt_v2;
 #[cfg(feature = "uniffi")]
 pub mod uni;
 
@@ -17,12 +39,15 @@ pub mod uni;
 ///
 ///These parameters define how client wants to handle Payjoin.
 #[derive(Clone)]
-pub struct SenderBuilder(payjoin::send::v2::SenderBuilder<'static>);
-
-impl From<payjoin::send::v2::SenderBuilder<'static>> for SenderBuilder {
-    fn from(value: payjoin::send::v2::SenderBuilder<'static>) -> Self {
-        Self(value)
-    }
+pub struct SenderBuilder {
+    /// The Original PSBT as supplied, before any metadata is stripped.
+    psbt: Psbt,
+    /// The PSBT version the Original PSBT was supplied in, 0 or 2.
+    psbt_version: u32,
+    uri: PjUri,
+    disable_output_substitution: bool,
+    keep_psbt_metadata: bool,
+    fallback: FallbackPolicy,
 }
 
 impl SenderBuilder {
@@ -30,9 +55,88 @@ impl SenderBuilder {
     ///
     /// Call [`SenderBuilder::build_recommended()`] or other `build` methods
     /// to create a [`Sender`]
+    ///
+    /// `psbt` may be a version 0 or a version 2 (BIP370) PSBT. A v2 PSBT is converted to version
+    /// 0 to be sent to the receiver, and the proposal is returned as version 2 again.
+    ///
+    /// Fails with [`BuildSenderErrorKind::UnsecuredEndpoint`] unless the pj endpoint is HTTPS or
+    /// a Tor onion service.
     pub fn new(psbt: String, uri: PjUri) -> Result<Self, BuildSenderError> {
-        let psbt = payjoin::bitcoin::psbt::Psbt::from_str(psbt.as_str())?;
-        Ok(payjoin::send::v2::SenderBuilder::new(psbt, uri.into()).into())
+        // TODO: discuss whether a `_danger-allow-http` feature should skip this check for local
+        // testing. payjoin's URI parser rejects cleartext endpoints too, so only URIs built
+        // without parsing, e.g. by a receiver, would be affected.
+        let endpoint = uri.0.extras.endpoint();
+        if !crate::uri::is_secure_endpoint(endpoint) {
+            let endpoint = endpoint.to_string();
+            return Err(BuildSenderErrorKind::UnsecuredEndpoint { endpoint }.into());
+        }
+        let (psbt, psbt_version) = match psbt_v2::parse(&psbt) {
+            Some(psbt) => (psbt?, 2),
+            None => (Psbt::from_str(psbt.as_str())?, 0),
+        };
+        let fallback = FallbackPolicy {
+            original_tx: payjoin::bitcoin::consensus::encode::serialize(
+                &psbt.clone().extract_tx_unchecked_fee_rate(),
+            ),
+            receiver_expiry: fragment::expiry(uri.0.extras.endpoint()),
+            deadline: None,
+        };
+        Ok(Self {
+            psbt,
+            psbt_version,
+            uri,
+            disable_output_substitution: false,
+            keep_psbt_metadata: false,
+            fallback,
+        })
+    }
+
+    /// Parse `bip21` and prepare to pay it with `psbt` in one step.
+    ///
+    /// Unlike [`SenderBuilder::new`] this checks the URI against the Original PSBT up front: it
+    /// fails with [`BuildSenderErrorKind::MissingPayjoinEndpoint`] if the URI has no `pj`
+    /// parameter and [`BuildSenderErrorKind::PayeeAmountMismatch`] if no output pays its amount
+    /// to its address.
+    ///
+    /// Output scripts are the same on every network, so the PSBT can't tell which network the
+    /// address should be for. Parse the URI with [`crate::uri::Uri::parse_for_network`] first to
+    /// check that.
+    pub fn from_psbt_and_bip21(psbt: String, bip21: String) -> Result<Self, BuildSenderError> {
+        use payjoin::UriExt;
+
+        let uri: payjoin::Uri<'static, _> = crate::uri::Uri::parse(bip21)?.into();
+        let uri =
+            uri.check_pj_supported().map_err(|_| BuildSenderErrorKind::MissingPayjoinEndpoint)?;
+        let builder = Self::new(psbt, uri.into())?;
+        check::payee_output(&builder.psbt, &builder.payee(), builder.uri.0.amount)?;
+        Ok(builder)
+    }
+
+    /// Send the Original PSBT to the receiver with its wallet metadata intact.
+    ///
+    /// By default global xpubs, key origins and proprietary fields are stripped from the PSBT
+    /// sent to the receiver, since they reveal the sender's wallet structure, and restored on
+    /// the proposal returned by `process_response`.
+    pub fn keep_psbt_metadata(self) -> Self {
+        Self { keep_psbt_metadata: true, ..self }
+    }
+
+    /// Give up on the payjoin `secs` seconds after the [`Sender`] is built.
+    ///
+    /// Past the deadline, or the expiry declared by the receiver if that comes first, the sender
+    /// should broadcast the Original PSBT instead. See [`Sender::fallback_due`].
+    pub fn fallback_after_secs(mut self, secs: u64) -> Self {
+        self.fallback.deadline = Some(FallbackDeadline::AfterSecs(secs));
+        self
+    }
+
+    /// Give up on the payjoin at unix time `deadline`, in seconds.
+    ///
+    /// Like [`SenderBuilder::fallback_after_secs`], for callers keeping their own clock. The
+    /// later of the two calls wins.
+    pub fn fallback_at(mut self, deadline: u64) -> Self {
+        self.fallback.deadline = Some(FallbackDeadline::At(deadline));
+        self
     }
 
     /// Disable output substitution even if the receiver didn't.
@@ -41,8 +145,8 @@ impl SenderBuilder {
     /// It is generally **not** recommended to set this as it may prevent the receiver from
     /// doing advanced operations such as opening LN channels and it also guarantees the
     /// receiver will **not** reward the sender with a discount.
-    pub fn always_disable_output_substitution(&self) -> Self {
-        self.0.clone().always_disable_output_substitution().into()
+    pub fn always_disable_output_substitution(self) -> Self {
+        Self { disable_output_substitution: true, ..self }
     }
     // Calculate the recommended fee contribution for an Original PSBT.
     //
@@ -50,11 +154,27 @@ impl SenderBuilder {
     // The minfeerate parameter is set if the contribution is available in change.
     //
     // This method fails if no recommendation can be made or if the PSBT is malformed.
+    //
+    // `min_fee_rate` is in sat/kwu. See `build_recommended_with_fee_rate` for a typed fee rate.
+    #[deprecated(note = "use `build_recommended_with_fee_rate` with a typed `FeeRate`")]
     pub fn build_recommended(&self, min_fee_rate: u64) -> Result<Sender, BuildSenderError> {
-        self.0
-            .clone()
-            .build_recommended(payjoin::bitcoin::FeeRate::from_sat_per_kwu(min_fee_rate))
-            .map(|e| e.into())
+        self.build_recommended_with_fee_rate(FeeRate::from_sat_per_kwu(min_fee_rate))
+    }
+
+    /// Like [`SenderBuilder::build_recommended`], with the minimum fee rate given in an explicit
+    /// unit.
+    ///
+    /// The change output is auto-detected, so no contribution is offered for an Original PSBT
+    /// with more than two outputs. Use [`SenderBuilder::build_batched_with_fee_rate`] for those.
+    pub fn build_recommended_with_fee_rate(
+        &self,
+        min_fee_rate: FeeRate,
+    ) -> Result<Sender, BuildSenderError> {
+        self.check_original_psbt()?;
+        let change_index = check::auto_change_output(&self.psbt, &self.payee());
+        self.builder()
+            .build_recommended(min_fee_rate.into())
+            .map(|sender| self.sender(sender, self.payee_index(), change_index))
             .map_err(|e| e.into())
     }
     /// Offer the receiver contribution to pay for his input.
@@ -63,59 +183,338 @@ impl SenderBuilder {
     /// output to pay for additional inputs. The recommended fee is `size_of_one_input * fee_rate`.
     ///
     /// `change_index` specifies which output can be used to pay fee. If `None` is provided, then
-    /// the output is auto-detected unless the supplied transaction has more than two outputs.
+    /// the output is auto-detected. Auto-detection fails if the supplied transaction has more
+    /// than two outputs; use [`SenderBuilder::build_batched_with_fee_rate`] for those.
+    /// An index past the last output or at the payee's output fails with
+    /// [`BuildSenderErrorKind::ChangeIndexOutOfBounds`] or
+    /// [`BuildSenderErrorKind::ChangeIndexPointsAtPayee`] before anything is built.
     ///
     /// `clamp_fee_contribution` decreases fee contribution instead of erroring.
     ///
     /// If this option is true and a transaction with change amount lower than fee
     /// contribution is provided then instead of returning error the fee contribution will
     /// be just lowered in the request to match the change amount.
+    ///
+    /// `min_fee_rate` is in sat/kwu.
+    #[deprecated(note = "use `build_with_additional_fee_with_fee_rate` with a typed `FeeRate`")]
     pub fn build_with_additional_fee(
         &self,
         max_fee_contribution: u64,
-        change_index: Option<u8>,
+        change_index: Option<u32>,
         min_fee_rate: u64,
         clamp_fee_contribution: bool,
     ) -> Result<Sender, BuildSenderError> {
-        self.0
-            .clone()
+        self.build_with_additional_fee_with_fee_rate(
+            max_fee_contribution,
+            change_index,
+            FeeRate::from_sat_per_kwu(min_fee_rate),
+            clamp_fee_contribution,
+        )
+    }
+
+    /// Like [`SenderBuilder::build_with_additional_fee`], with the minimum fee rate given in an
+    /// explicit unit.
+    pub fn build_with_additional_fee_with_fee_rate(
+        &self,
+        max_fee_contribution: u64,
+        change_index: Option<u32>,
+        min_fee_rate: FeeRate,
+        clamp_fee_contribution: bool,
+    ) -> Result<Sender, BuildSenderError> {
+        self.check_original_psbt()?;
+        let change_index = match change_index {
+            Some(change_index) => {
+                check::fee_output(
+                    &self.psbt,
+                    &self.payee(),
+                    change_index,
+                    max_fee_contribution,
+                    clamp_fee_contribution,
+                )?;
+                Some(change_index)
+            }
+            None => check::auto_change_output(&self.psbt, &self.payee()),
+        };
+        self.builder()
             .build_with_additional_fee(
                 payjoin::bitcoin::Amount::from_sat(max_fee_contribution),
                 change_index.map(|x| x as usize),
-                payjoin::bitcoin::FeeRate::from_sat_per_kwu(min_fee_rate),
+                min_fee_rate.into(),
                 clamp_fee_contribution,
             )
-            .map(|e| e.into())
+            .map(|sender| self.sender(sender, self.payee_index(), change_index))
+            .map_err(|e| e.into())
+    }
+
+    /// Offer a fee contribution from an Original PSBT with any number of outputs, such as a
+    /// batched payment with the payjoin recipient among many payees.
+    ///
+    /// BIP78 can't auto-detect the change output of more than two outputs, so `change_index`
+    /// must name it. The payjoin recipient's output must be the only one paying the payjoin
+    /// URI's address, and its amount if the URI requests one. Otherwise this fails with
+    /// [`BuildSenderErrorKind::PayeeAmountMismatch`] or
+    /// [`BuildSenderErrorKind::AmbiguousPayeeOutput`]. The other parameters are those of
+    /// [`SenderBuilder::build_with_additional_fee_with_fee_rate`].
+    pub fn build_batched_with_fee_rate(
+        &self,
+        change_index: u32,
+        max_fee_contribution: u64,
+        min_fee_rate: FeeRate,
+        clamp_fee_contribution: bool,
+    ) -> Result<Sender, BuildSenderError> {
+        self.check_original_psbt()?;
+        let payee_index = check::payee_output(&self.psbt, &self.payee(), self.uri.0.amount)?;
+        check::fee_output(
+            &self.psbt,
+            &self.payee(),
+            change_index,
+            max_fee_contribution,
+            clamp_fee_contribution,
+        )?;
+        self.builder()
+            .build_with_additional_fee(
+                payjoin::bitcoin::Amount::from_sat(max_fee_contribution),
+                Some(change_index as usize),
+                min_fee_rate.into(),
+                clamp_fee_contribution,
+            )
+            .map(|sender| self.sender(sender, Some(payee_index), Some(change_index)))
             .map_err(|e| e.into())
     }
     /// Perform Payjoin without incentivizing the payee to cooperate.
     ///
     /// While it's generally better to offer some contribution some users may wish not to.
     /// This function disables contribution.
+    ///
+    /// `min_fee_rate` is in sat/kwu.
+    #[deprecated(note = "use `build_non_incentivizing_with_fee_rate` with a typed `FeeRate`")]
     pub fn build_non_incentivizing(&self, min_fee_rate: u64) -> Result<Sender, BuildSenderError> {
-        match self
-            .0
-            .clone()
-            .build_non_incentivizing(payjoin::bitcoin::FeeRate::from_sat_per_kwu(min_fee_rate))
-        {
-            Ok(e) => Ok(e.into()),
+        self.build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(min_fee_rate))
+    }
+
+    /// Like [`SenderBuilder::build_non_incentivizing`], with the minimum fee rate given in an
+    /// explicit unit.
+    pub fn build_non_incentivizing_with_fee_rate(
+        &self,
+        min_fee_rate: FeeRate,
+    ) -> Result<Sender, BuildSenderError> {
+        self.check_original_psbt()?;
+        match self.builder().build_non_incentivizing(min_fee_rate.into()) {
+            Ok(sender) => Ok(self.sender(sender, self.payee_index(), None)),
             Err(e) => Err(e.into()),
         }
     }
+
+    fn payee(&self) -> payjoin::bitcoin::ScriptBuf {
+        self.uri.0.address.script_pubkey()
+    }
+
+    /// The first output paying the payee.
+    fn payee_index(&self) -> Option<u32> {
+        let payee = self.payee();
+        let outputs = &self.psbt.unsigned_tx.output;
+        outputs.iter().position(|output| output.script_pubkey == payee).map(|index| index as u32)
+    }
+
+    fn check_original_psbt(&self) -> Result<(), BuildSenderError> {
+        check::original_psbt(&self.psbt, &self.payee()).map_err(Into::into)
+    }
+
+    fn builder(&self) -> payjoin::send::v2::SenderBuilder<'static> {
+        let psbt = if self.keep_psbt_metadata {
+            self.psbt.clone()
+        } else {
+            metadata::sanitize(&self.psbt)
+        };
+        let builder = payjoin::send::v2::SenderBuilder::new(psbt, self.uri.clone().into());
+        if self.disable_output_substitution {
+            builder.always_disable_output_substitution()
+        } else {
+            builder
+        }
+    }
+
+    fn sender(
+        &self,
+        sender: payjoin::send::v2::Sender,
+        payee_index: Option<u32>,
+        change_index: Option<u32>,
+    ) -> Sender {
+        let state = SenderState {
+            fallback: self.fallback.start(),
+            original_psbt: Some(self.psbt.clone()),
+            keep_psbt_metadata: self.keep_psbt_metadata,
+            psbt_version: self.psbt_version,
+            payee: Some(self.payee()),
+            payee_index,
+            change_index,
+            endpoint: self.uri.0.extras.endpoint().clone(),
+            request: RequestParams::of(&sender),
+        };
+        Sender(sender, state)
+    }
+}
+
+/// How a [`SenderBuilder`] decides when to fall back to broadcasting the Original PSBT.
+#[derive(Clone, Debug)]
+struct FallbackPolicy {
+    /// The consensus encoded transaction extracted from the Original PSBT.
+    original_tx: Vec<u8>,
+    /// Unix timestamp in seconds at which the receiver's session expires, if it declared one.
+    receiver_expiry: Option<u64>,
+    deadline: Option<FallbackDeadline>,
+}
+
+/// When the sender gives up on the payjoin, if the receiver's expiry doesn't come first.
+#[derive(Clone, Copy, Debug)]
+enum FallbackDeadline {
+    /// This many seconds after the [`Sender`] is built.
+    AfterSecs(u64),
+    /// At this unix timestamp in seconds.
+    At(u64),
+}
+
+impl FallbackPolicy {
+    fn start(&self) -> Option<Fallback> {
+        let deadline = self.deadline.map(|deadline| {
+            match deadline {
+                FallbackDeadline::AfterSecs(secs) => now().saturating_add(secs),
+                FallbackDeadline::At(at) => at,
+            }
+        });
+        let deadline = match (deadline, self.receiver_expiry) {
+            (Some(deadline), Some(expiry)) => deadline.min(expiry),
+            (deadline, expiry) => deadline.or(expiry)?,
+        };
+        Some(Fallback { deadline, original_tx: self.original_tx.clone() })
+    }
+}
+
+/// The current unix timestamp in seconds.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// The value of the query parameter `name` in `params`.
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// The point at which a sender gives up on the payjoin and broadcasts the Original PSBT.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Fallback {
+    /// Unix timestamp in seconds after which the payjoin is abandoned.
+    deadline: u64,
+    /// The consensus encoded transaction extracted from the Original PSBT.
+    original_tx: Vec<u8>,
+}
+
+impl Fallback {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "deadline": self.deadline,
+            "original_tx": self.original_tx.to_lower_hex_string(),
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Some(Fallback {
+            deadline: value["deadline"].as_u64()?,
+            original_tx: Vec::from_hex(value["original_tx"].as_str()?).ok()?,
+        })
+    }
+
+    fn check(&self, now: u64) -> Result<(), PollError> {
+        if now >= self.deadline {
+            return Err(PollError::FallbackDue { original_tx: self.original_tx.clone() });
+        }
+        Ok(())
+    }
 }
+
+/// What the sender keeps locally alongside the payjoin sender and its contexts.
+#[derive(Clone, Debug)]
+struct SenderState {
+    fallback: Option<Fallback>,
+    /// The Original PSBT as supplied, if known. Senders persisted before it was always kept
+    /// only have it when its metadata was stripped.
+    original_psbt: Option<Psbt>,
+    /// Whether the Original PSBT was sent with its metadata, so there is none to restore.
+    keep_psbt_metadata: bool,
+    /// The PSBT version the Original PSBT was supplied in, and the proposal is returned in.
+    psbt_version: u32,
+    /// The script the payjoin URI pays, if known.
+    payee: Option<ScriptBuf>,
+    /// The index of the Original PSBT output treated as the payee's, if known.
+    payee_index: Option<u32>,
+    /// The index of the Original PSBT output the fee contribution is taken from, if any.
+    change_index: Option<u32>,
+    /// The receiver's endpoint from the payjoin URI, also persisted by the payjoin sender.
+    endpoint: payjoin::Url,
+    /// What the request offers the receiver, read once from the payjoin sender.
+    request: RequestParams,
+}
+
+/// The parameters and body of the request a payjoin sender makes.
 #[derive(Clone)]
-pub struct Sender(payjoin::send::v2::Sender);
+struct RequestParams {
+    /// The `maxadditionalfeecontribution` and `additionalfeeoutputindex` parameters.
+    fee_contribution: Option<(u64, u32)>,
+    /// The `minfeerate` parameter, which payjoin leaves out when it is zero.
+    min_fee_rate: Option<FeeRate>,
+    /// The Original PSBT as sent, base64 encoded.
+    original_psbt: String,
+}
+
+impl RequestParams {
+    /// The parameters of `sender`'s request. A v2 request carries the same as the v1 request.
+    fn of(sender: &payjoin::send::v2::Sender) -> Self {
+        let (request, _) = sender.clone().extract_v1();
+        let params: Vec<(String, String)> = request
+            .url
+            .query_pairs()
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        let fee_contribution = param(&params, "maxadditionalfeecontribution")
+            .and_then(|amount| amount.parse().ok())
+            .zip(param(&params, "additionalfeeoutputindex").and_then(|vout| vout.parse().ok()));
+        let min_fee_rate = param(&params, "minfeerate")
+            .and_then(|rate| rate.parse::<f64>().ok())
+            .and_then(|rate| FeeRate::from_sat_per_vb_f64(rate).ok());
+        let original_psbt = String::from_utf8_lossy(&request.body).into_owned();
+        Self { fee_contribution, min_fee_rate, original_psbt }
+    }
+}
+
+impl SenderState {
+    /// Restore the metadata stripped from the Original PSBT onto the receiver's proposal.
+    fn restore_metadata(&self, mut proposal: Psbt) -> Psbt {
+        if let (Some(original), false) = (&self.original_psbt, self.keep_psbt_metadata) {
+            metadata::restore(&mut proposal, original);
+        }
+        proposal
+    }
 
-impl From<payjoin::send::v2::Sender> for Sender {
-    fn from(value: payjoin::send::v2::Sender) -> Self {
-        Self(value)
+    /// Compare the receiver's validated proposal with the Original PSBT.
+    fn check(&self, proposal: Psbt) -> Result<CheckedProposal, ResponseError> {
+        let proposal = self.restore_metadata(proposal);
+        let changes = match (&self.original_psbt, &self.payee) {
+            (Some(original), Some(payee)) => {
+                Some(Changes::between(&original.unsigned_tx, payee, &proposal.unsigned_tx))
+            }
+            _ => None,
+        };
+        Ok(CheckedProposal::new(&proposal, changes, self.psbt_version)?)
     }
 }
 
+#[derive(Clone)]
+pub struct Sender(payjoin::send::v2::Sender, SenderState);
+
 impl Sender {
     pub fn extract_v1(&self) -> (Request, V1Context) {
         let (req, ctx) = self.0.clone().extract_v1();
-        (req.into(), ctx.into())
+        (req.into(), V1Context(Arc::new(ctx), self.1.clone()))
     }
 
     /// Extract serialized Request and Context from a Payjoin Proposal.
@@ -124,78 +523,317 @@ impl Sender {
         ohttp_relay: Url,
     ) -> Result<(Request, V2PostContext), CreateRequestError> {
         match self.0.extract_v2(ohttp_relay.into()) {
-            Ok((req, ctx)) => Ok((req.into(), ctx.into())),
+            Ok((req, ctx)) => {
+                Ok((
+                    req.into(),
+                    V2PostContext(Mutex::new(Some(ctx)), self.1.clone(), self.0.clone()),
+                ))
+            }
             Err(e) => Err(e.into()),
         }
     }
 
+    /// Extract a v2 request if the receiver supports it, falling back to a v1 request otherwise.
+    ///
+    /// Match on the returned [`ExtractedRequest`] to learn which was chosen: post a v1 request
+    /// directly to the receiver, and a v2 request to the OHTTP relay before polling.
+    pub fn extract_highest_version(
+        &self,
+        ohttp_relay: Url,
+    ) -> Result<ExtractedRequest, CreateRequestError> {
+        if !self.can_use_v2() {
+            let (request, context) = self.extract_v1();
+            return Ok(ExtractedRequest::V1 { request, context });
+        }
+        let (request, context) = self.extract_v2(ohttp_relay)?;
+        Ok(ExtractedRequest::V2 { request, context })
+    }
+
+    /// The receiver's endpoint from the payjoin URI.
+    ///
+    /// v1 requests are posted there directly. For v2 it is the receiver's mailbox on the
+    /// directory, reached through the OHTTP relay.
+    pub fn endpoint(&self) -> Url {
+        self.1.endpoint.clone().into()
+    }
+
+    /// Whether the receiver's endpoint accepts v2 requests through a directory, i.e. whether it
+    /// carries the receiver's `RK` public key that a v2 request is encrypted to.
+    pub fn can_use_v2(&self) -> bool {
+        fragment::param(&self.1.endpoint, "rk").is_some()
+    }
+
+    /// The index of the Original PSBT output treated as paying the payjoin recipient.
+    ///
+    /// `None` for senders persisted by earlier versions.
+    pub fn payee_output_index(&self) -> Option<u32> {
+        self.1.payee_index
+    }
+
+    /// The index of the Original PSBT output the receiver may take the fee contribution from,
+    /// given or auto-detected. `None` if no contribution is offered.
+    pub fn change_output_index(&self) -> Option<u32> {
+        self.1.change_index
+    }
+
+    /// The PSBT version the Original PSBT was supplied in, 0 or 2. Proposals are returned in the
+    /// same version.
+    pub fn psbt_version(&self) -> u32 {
+        self.1.psbt_version
+    }
+
+    /// The most the receiver may take from the sender's change for its fee, in sats, and the
+    /// index of the output it is taken from. `None` if no contribution is offered.
+    ///
+    /// These are the `maxadditionalfeecontribution` and `additionalfeeoutputindex` parameters
+    /// of the request.
+    pub fn fee_contribution(&self) -> Option<(u64, u32)> {
+        self.1.request.fee_contribution
+    }
+
+    /// The minimum fee rate the Payjoin transaction must pay, the `minfeerate` parameter of the
+    /// request. `None` if the request sets none, i.e. the minimum is zero.
+    pub fn min_fee_rate(&self) -> Option<FeeRate> {
+        self.1.request.min_fee_rate
+    }
+
+    /// [`Sender::min_fee_rate`] in sat/vB, rounded up.
+    pub fn min_fee_rate_sat_per_vb(&self) -> Option<u64> {
+        self.min_fee_rate().map(|rate| rate.to_sat_per_vb_ceil())
+    }
+
+    /// The Original PSBT as sent to the receiver, base64 encoded.
+    ///
+    /// Its wallet metadata is stripped unless [`SenderBuilder::keep_psbt_metadata`] was set.
+    pub fn original_psbt(&self) -> String {
+        self.1.request.original_psbt.clone()
+    }
+
+    /// The receiver's HPKE public key a v2 request is encrypted to, from the `RK` parameter of a
+    /// v2 endpoint, base64url encoded without padding.
+    ///
+    /// Meant for logs correlating sender and receiver sessions. `None` for v1 endpoints or a key
+    /// that can't be parsed.
+    pub fn receiver_pubkey(&self) -> Option<String> {
+        fragment::receiver_pubkey(&self.1.endpoint)
+    }
+
+    /// The OHTTP keys of the directory, from the `OH` parameter of a v2 endpoint.
+    pub fn ohttp_keys(&self) -> Option<OhttpKeys> {
+        fragment::ohttp_keys(&self.1.endpoint).map(Into::into)
+    }
+
+    /// Unix timestamp in seconds after which the payjoin should be abandoned, if any.
+    ///
+    /// This is the earlier of the deadline set with [`SenderBuilder::fallback_after_secs`] or
+    /// [`SenderBuilder::fallback_at`] and the expiry declared by the receiver.
+    pub fn fallback_deadline(&self) -> Option<u64> {
+        self.1.fallback.as_ref().map(|fallback| fallback.deadline)
+    }
+
+    /// Whether the sender should stop waiting for the payjoin at unix time `now` and broadcast
+    /// the Original PSBT instead.
+    pub fn fallback_due(&self, now: u64) -> bool {
+        self.1.fallback.as_ref().is_some_and(|fallback| fallback.check(now).is_err())
+    }
+
+    /// The consensus encoded Original PSBT transaction to broadcast if the payjoin is abandoned.
+    ///
+    /// `None` only for senders persisted by versions that kept neither.
+    pub fn fallback_tx(&self) -> Option<Vec<u8>> {
+        match (&self.1.fallback, &self.1.original_psbt) {
+            (Some(fallback), _) => Some(fallback.original_tx.clone()),
+            (None, Some(psbt)) => {
+                Some(payjoin::bitcoin::consensus::encode::serialize(
+                    &psbt.clone().extract_tx_unchecked_fee_rate(),
+                ))
+            }
+            (None, None) => None,
+        }
+    }
+
     pub fn to_json(&self) -> Result<String, SerdeJsonError> {
-        serde_json::to_string(&self.0).map_err(Into::into)
+        serde_json::to_string(&self.to_value()?).map_err(Into::into)
     }
 
     pub fn from_json(json: &str) -> Result<Self, SerdeJsonError> {
-        serde_json::from_str::<payjoin::send::v2::Sender>(json).map_err(Into::into).map(Into::into)
+        Self::from_value(serde_json::from_str(json)?)
+    }
+
+    fn to_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+        if self.1.fallback.is_none() && self.1.original_psbt.is_none() {
+            return serde_json::to_value(&self.0);
+        }
+        Ok(serde_json::json!({
+            "sender": serde_json::to_value(&self.0)?,
+            "fallback": self.1.fallback.as_ref().map(Fallback::to_json),
+            "original_psbt": self.1.original_psbt.as_ref().map(Psbt::to_string),
+            "keep_psbt_metadata": self.1.keep_psbt_metadata,
+            "psbt_version": self.1.psbt_version,
+            "payee": self.1.payee.as_ref().map(|payee| payee.to_hex_string()),
+            "payee_index": self.1.payee_index,
+            "change_index": self.1.change_index,
+        }))
     }
+
+    fn from_value(mut value: serde_json::Value) -> Result<Self, SerdeJsonError> {
+        if value.get("sender").is_none() {
+            let sender: payjoin::send::v2::Sender = serde_json::from_value(value)?;
+            let state = SenderState {
+                fallback: None,
+                original_psbt: None,
+                keep_psbt_metadata: false,
+                psbt_version: 0,
+                payee: None,
+                payee_index: None,
+                change_index: None,
+                endpoint: sender_endpoint(&sender)?,
+                request: RequestParams::of(&sender),
+            };
+            return Ok(Self(sender, state));
+        }
+        let sender: payjoin::send::v2::Sender = serde_json::from_value(value["sender"].take())?;
+        let state = SenderState {
+            fallback: Fallback::from_json(&value["fallback"]),
+            original_psbt: value["original_psbt"].as_str().and_then(|psbt| psbt.parse().ok()),
+            keep_psbt_metadata: value["keep_psbt_metadata"].as_bool().unwrap_or(false),
+            psbt_version: value["psbt_version"].as_u64().unwrap_or(0) as u32,
+            payee: value["payee"].as_str().and_then(|payee| ScriptBuf::from_hex(payee).ok()),
+            payee_index: value["payee_index"].as_u64().map(|index| index as u32),
+            change_index: value["change_index"].as_u64().map(|index| index as u32),
+            endpoint: sender_endpoint(&sender)?,
+            request: RequestParams::of(&sender),
+        };
+        Ok(Self(sender, state))
+    }
+}
+
+/// The receiver's endpoint of a restored payjoin `sender`, read back from payjoin's own
+/// serialization rather than the JSON it was restored from, so any JSON payjoin accepts works.
+fn sender_endpoint(sender: &payjoin::send::v2::Sender) -> Result<payjoin::Url, serde_json::Error> {
+    serde_json::from_value(serde_json::to_value(sender)?["v1"]["endpoint"].take())
+}
+
+/// The request chosen by [`Sender::extract_highest_version`], with the context to process the
+/// response.
+pub enum ExtractedRequest {
+    /// A BIP78 request, to post directly to the receiver's endpoint.
+    V1 { request: Request, context: V1Context },
+    /// A BIP77 request, to post to the OHTTP relay before polling for the proposal.
+    V2 { request: Request, context: V2PostContext },
 }
 
 /// Data required for validation of response.
 /// This type is used to process the response. Get it from SenderBuilder's build methods. Then you only need to call .process_response() on it to continue BIP78 flow.
 #[derive(Clone)]
-pub struct V1Context(Arc<payjoin::send::v1::V1Context>);
-impl From<payjoin::send::v1::V1Context> for V1Context {
-    fn from(value: payjoin::send::v1::V1Context) -> Self {
-        Self(Arc::new(value))
-    }
-}
+pub struct V1Context(Arc<payjoin::send::v1::V1Context>, SenderState);
 
 impl V1Context {
     ///Decodes and validates the response.
     /// Call this method with response from receiver to continue BIP78 flow. If the response is valid you will get appropriate PSBT that you should sign and broadcast.
+    ///
+    /// `response` is the raw HTTP body: a JSON error, or the Payjoin PSBT as base64 text, with
+    /// surrounding whitespace and line breaks ignored. Use
+    /// [`V1Context::process_response_bytes`] for a PSBT already decoded to binary. A body that
+    /// isn't a base64 PSBT fails with [`ResponseError::InvalidEncoding`], while a PSBT that fails
+    /// the BIP78 sender checks fails with [`ResponseError::Validation`]. A JSON error fails with
+    /// [`ResponseError::ReceiverRejected`], or [`ResponseError::VersionUnsupported`].
     pub fn process_response(&self, response: Vec<u8>) -> Result<String, ResponseError> {
-        let mut decoder = Cursor::new(response);
+        self.process_response_checked(response).map(|proposal| proposal.psbt())
+    }
+
+    /// Like [`V1Context::process_response`], also reporting what the receiver changed compared
+    /// to the Original PSBT.
+    pub fn process_response_checked(
+        &self,
+        response: Vec<u8>,
+    ) -> Result<CheckedProposal, ResponseError> {
+        if let Some(error) = ResponseError::receiver_rejected(&response) {
+            return Err(error);
+        }
+        let mut decoder = Cursor::new(normalize_v1_response(response)?);
         <payjoin::send::v1::V1Context as Clone>::clone(&self.0.clone())
             .process_response(&mut decoder)
-            .map(|e| e.to_string())
-            .map_err(Into::into)
+            .map_err(ResponseError::from)
+            .and_then(|psbt| self.1.check(psbt))
     }
-}
-
-pub struct V2PostContext(Mutex<Option<payjoin::send::v2::V2PostContext>>);
 
-impl V2PostContext {
-    /// Decodes and validates the response.
-    /// Call this method with response from receiver to continue BIP-??? flow. A successful response can either be None if the relay has not response yet or Some(Psbt).
-    /// If the response is some valid PSBT you should sign and broadcast.
-    pub fn process_response(&self, response: &[u8]) -> Result<V2GetContext, EncapsulationError> {
-        <&V2PostContext as Into<payjoin::send::v2::V2PostContext>>::into(self)
-            .process_response(response)
-            .map(Into::into)
-            .map_err(Into::into)
+    /// Like [`V1Context::process_response`], for a body an HTTP stack handed over as text.
+    pub fn process_response_str(&self, response: String) -> Result<String, ResponseError> {
+        self.process_response(response.into_bytes())
     }
-}
 
-impl From<&V2PostContext> for payjoin::send::v2::V2PostContext {
-    fn from(value: &V2PostContext) -> Self {
-        let mut data_guard = value.0.lock().unwrap();
-        Option::take(&mut *data_guard).expect("ContextV2 moved out of memory")
+    /// Like [`V1Context::process_response`], for a Payjoin PSBT an HTTP stack handed over
+    /// already decoded from base64 to binary.
+    ///
+    /// Fails with [`ResponseError::InvalidEncoding`] if `psbt` isn't a binary PSBT.
+    pub fn process_response_bytes(&self, psbt: Vec<u8>) -> Result<String, ResponseError> {
+        let psbt = Psbt::deserialize(&psbt)
+            .map_err(|e| ResponseError::InvalidEncoding { msg: e.to_string() })?;
+        self.process_response(psbt.to_string().into_bytes())
     }
 }
 
-impl From<payjoin::send::v2::V2PostContext> for V2PostContext {
-    fn from(value: payjoin::send::v2::V2PostContext) -> Self {
-        Self(Mutex::new(Some(value)))
+/// Bring a BIP78 response body into the form payjoin parses: a JSON error as it is, and a
+/// base64 PSBT without the whitespace around and within it.
+fn normalize_v1_response(body: Vec<u8>) -> Result<Vec<u8>, ResponseError> {
+    let invalid = |msg: String| ResponseError::InvalidEncoding { msg };
+    let text = std::str::from_utf8(&body).map_err(|e| invalid(e.to_string()))?.trim();
+    if text.starts_with('{') {
+        return Ok(text.as_bytes().to_vec());
     }
+    let bytes = crate::request::decode_base64_body(text).map_err(invalid)?;
+    let psbt = Psbt::deserialize(&bytes).map_err(|e| invalid(e.to_string()))?;
+    Ok(psbt.to_string().into_bytes())
 }
 
-pub struct V2GetContext(payjoin::send::v2::V2GetContext);
+pub struct V2PostContext(
+    Mutex<Option<payjoin::send::v2::V2PostContext>>,
+    SenderState,
+    payjoin::send::v2::Sender,
+);
 
-impl From<payjoin::send::v2::V2GetContext> for V2GetContext {
-    fn from(value: payjoin::send::v2::V2GetContext) -> Self {
-        Self(value)
+impl V2PostContext {
+    /// Process the directory's response to the request from [`Sender::extract_v2`].
+    ///
+    /// The returned [`V2GetContext`] polls the directory for the receiver's proposal. Fails with
+    /// [`ResponseError::Decapsulation`] if the response can't be decapsulated, so post the
+    /// Original PSBT again, and with [`ResponseError::OhttpContextAlreadyUsed`] if this context
+    /// already processed a response.
+    pub fn process_response(&self, response: &[u8]) -> Result<V2GetContext, ResponseError> {
+        let ctx = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+            .ok_or(ResponseError::OhttpContextAlreadyUsed)?;
+        ctx.process_response(response)
+            .map(|ctx| V2GetContext(ctx, self.1.clone(), self.2.clone()))
+            .map_err(Into::into)
     }
 }
 
+pub struct V2GetContext(payjoin::send::v2::V2GetContext, SenderState, payjoin::send::v2::Sender);
+
 impl V2GetContext {
+    /// Persist this context, to keep polling across restarts.
+    ///
+    /// The directory holds the receiver's proposal until the session expires. Restore with
+    /// [`V2GetContext::from_json`] and keep polling without posting the Original PSBT again.
+    pub fn to_json(&self) -> Result<String, SerdeJsonError> {
+        serde_json::to_string(&serde_json::json!({
+            "sender": Sender(self.2.clone(), self.1.clone()).to_value()?,
+            "context": serde_json::to_value(&self.0)?,
+        }))
+        .map_err(Into::into)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SerdeJsonError> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let sender = Sender::from_value(value["sender"].take())?;
+        Ok(Self(serde_json::from_value(value["context"].take())?, sender.1, sender.0))
+    }
+
     pub fn extract_req(
         &self,
         ohttp_relay: String,
@@ -206,18 +844,160 @@ impl V2GetContext {
             .map_err(|e| e.into())
     }
 
-    /// Decodes and validates the response.
-    /// Call this method with response from receiver to continue BIP-??? flow. A successful response can either be None if the relay has not response yet or Some(Psbt).
-    /// If the response is some valid PSBT you should sign and broadcast.
+    /// Like [`V2GetContext::extract_req`], but stops polling once the sender's fallback deadline
+    /// has passed at unix time `now`.
+    ///
+    /// Fails with [`PollError::FallbackDue`] carrying the transaction to broadcast instead.
+    pub fn extract_poll_req(
+        &self,
+        ohttp_relay: String,
+        now: u64,
+    ) -> Result<(Request, ClientResponse), PollError> {
+        if let Some(fallback) = &self.1.fallback {
+            fallback.check(now)?;
+        }
+        self.extract_req(ohttp_relay).map_err(Into::into)
+    }
+
+    /// Like [`V2GetContext::extract_poll_req`], for a poll request the directory may hold open
+    /// for up to `wait_secs` seconds until the receiver's proposal arrives.
+    ///
+    /// `wait_secs` is returned as the request's [`Request::timeout_secs`], shortened to the time
+    /// left before the sender's fallback deadline. If `capabilities` says the directory doesn't
+    /// long-poll, this is a plain poll request as from [`V2GetContext::extract_poll_req`].
+    pub fn extract_poll_req_with_wait(
+        &self,
+        ohttp_relay: String,
+        now: u64,
+        wait_secs: u64,
+        capabilities: &DirectoryCapabilities,
+    ) -> Result<(Request, ClientResponse), PollError> {
+        let (mut request, ctx) = self.extract_poll_req(ohttp_relay, now)?;
+        let remaining = self.1.fallback.as_ref().map(|f| f.deadline.saturating_sub(now));
+        request.timeout_secs = capabilities.poll_timeout_secs(wait_secs, remaining);
+        Ok((request, ctx))
+    }
+
+    /// Process the directory's response to a request from [`V2GetContext::extract_req`].
+    ///
+    /// Returns `None` if the receiver hasn't posted a proposal yet, so poll again, or the
+    /// validated Payjoin PSBT to sign and broadcast. A response that can't be decapsulated fails
+    /// with [`ResponseError::Decapsulation`], so poll again, and an invalid proposal with
+    /// [`ResponseError::Validation`]. An error the receiver replied with fails with
+    /// [`ResponseError::WellKnown`], [`ResponseError::VersionUnsupported`] or
+    /// [`ResponseError::Unrecognized`]. Once the sender's fallback deadline or the receiver's
+    /// session expiry has passed it fails with [`ResponseError::FallbackDue`] instead, and
+    /// [`V2GetContext::extract_poll_req`] stops polling.
+    ///
+    /// A response of the wrong length is rejected without using up `ohttp_ctx`.
     pub fn process_response(
         &self,
         response: &[u8],
         ohttp_ctx: &ClientResponse,
     ) -> Result<Option<String>, ResponseError> {
-        match self.0.process_response(response, ohttp_ctx.into()) {
-            Ok(Some(psbt)) => Ok(Some(psbt.to_string())),
+        self.process_response_checked(response, ohttp_ctx)
+            .map(|proposal| proposal.map(|proposal| proposal.psbt()))
+    }
+
+    /// Like [`V2GetContext::process_response`], also reporting what the receiver changed
+    /// compared to the Original PSBT.
+    pub fn process_response_checked(
+        &self,
+        response: &[u8],
+        ohttp_ctx: &ClientResponse,
+    ) -> Result<Option<CheckedProposal>, ResponseError> {
+        if let Some(fallback) = self.1.fallback.as_ref().filter(|f| f.check(now()).is_err()) {
+            return Err(ResponseError::FallbackDue { original_tx: fallback.original_tx.clone() });
+        }
+        if response.len() != crate::ohttp::ENCAPSULATED_MESSAGE_BYTES {
+            return Err(ResponseError::Decapsulation {
+                msg: format!(
+                    "expected {} bytes, got {}",
+                    crate::ohttp::ENCAPSULATED_MESSAGE_BYTES,
+                    response.len()
+                ),
+            });
+        }
+        let ohttp_ctx = ohttp_ctx.take().ok_or(ResponseError::OhttpContextAlreadyUsed)?;
+        match self.0.process_response(response, ohttp_ctx) {
+            Ok(Some(psbt)) => self.1.check(psbt).map(Some),
             Ok(None) => Ok(None),
             Err(e) => Err(e.into()),
         }
     }
 }
+
+/// Polls the directory for the receiver's proposal with a fresh OHTTP encapsulation each time.
+///
+/// Each [`SenderPoller::next_request`] returns a new request and the [`ClientResponse`] to
+/// process its response with. Only the context of the latest request is accepted by
+/// [`SenderPoller::handle_response`].
+pub struct SenderPoller {
+    context: Arc<V2GetContext>,
+    ohttp_relay: String,
+    latest: Mutex<Option<Arc<ClientResponse>>>,
+}
+
+impl SenderPoller {
+    pub fn new(context: Arc<V2GetContext>, ohttp_relay: String) -> Self {
+        Self { context, ohttp_relay, latest: Mutex::new(None) }
+    }
+
+    /// Extract a request to poll the directory with, encapsulated afresh.
+    ///
+    /// The context of any earlier request is no longer accepted.
+    pub fn next_request(&self) -> Result<(Request, Arc<ClientResponse>), CreateRequestError> {
+        let (request, ohttp_ctx) = self.context.extract_req(self.ohttp_relay.clone())?;
+        let ohttp_ctx = Arc::new(ohttp_ctx);
+        *self.latest.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+            Some(ohttp_ctx.clone());
+        Ok((request, ohttp_ctx))
+    }
+
+    /// Process the response to the latest request from [`SenderPoller::next_request`].
+    ///
+    /// Returns `None` if the receiver hasn't posted a proposal yet, so poll again. Fails with
+    /// [`ResponseError::StaleOhttpContext`] if `ohttp_ctx` isn't the context of the latest
+    /// request.
+    pub fn handle_response(
+        &self,
+        response: &[u8],
+        ohttp_ctx: &ClientResponse,
+    ) -> Result<Option<String>, ResponseError> {
+        let latest = self.latest.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        if !latest.is_some_and(|latest| std::ptr::eq(latest.as_ref(), ohttp_ctx)) {
+            return Err(ResponseError::StaleOhttpContext);
+        }
+        self.context.process_response(response, ohttp_ctx)
+    }
+}
+
+/// Summarize a payjoin for the sender's transaction history.
+///
+/// `original_psbt` is the Original PSBT the [`Sender`] was built from, `payjoin_psbt` the proposal
+/// returned by `process_response`, `uri` the payjoin URI that was paid and `now` the unix
+/// timestamp to record.
+///
+/// The session is the receiver's mailbox on the directory, so only v2 endpoints record one.
+pub fn sender_tx_record(
+    original_psbt: String,
+    payjoin_psbt: String,
+    uri: &PjUri,
+    now: u64,
+) -> Result<PayjoinTxRecord, PsbtParseError> {
+    let original = Psbt::from_str(&original_psbt)?;
+    let payjoin_psbt = Psbt::from_str(&payjoin_psbt)?;
+    let endpoint = uri.0.extras.endpoint();
+    let session_id = fragment::receiver_pubkey(endpoint)
+        .and_then(|_| endpoint.path_segments())
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string);
+    Ok(PayjoinTxRecord::for_sender(
+        &original,
+        &payjoin_psbt,
+        &uri.0.address.script_pubkey(),
+        session_id,
+        now,
+    ))
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::{OutPoint, ScriptBuf, Transaction};
+
+use super::psbt_v2::PsbtV2Error;
+
+/// The receiver's payjoin proposal after validation, with what the receiver changed compared to
+/// the Original PSBT.
+///
+/// The comparison needs the Original PSBT and payee, which senders persisted by earlier versions
+/// don't keep. Their accessors return `None`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct CheckedProposal {
+    /// The proposal, base64 encoded in the PSBT version of the Original PSBT.
+    psbt: String,
+    changes: Option<Changes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Changes {
+    additional_fee: u64,
+    receiver_inputs: u64,
+    outputs_substituted: bool,
+}
+
+impl Changes {
+    /// What the receiver changed, output by output.
+    ///
+    /// Validation already guarantees the sender's inputs are kept and its outputs only shrink to
+    /// pay fees, so each of the sender's outputs is matched by script and the amount it lost is
+    /// the fee it paid for the receiver. A sender output missing from the proposal paid its whole
+    /// value.
+    pub(crate) fn between(
+        original: &Transaction,
+        payee: &ScriptBuf,
+        proposal: &Transaction,
+    ) -> Self {
+        let original_inputs: HashSet<OutPoint> =
+            original.input.iter().map(|txin| txin.previous_output).collect();
+        let value_to = |tx: &Transaction, script: &ScriptBuf| -> u64 {
+            tx.output
+                .iter()
+                .filter(|txout| &txout.script_pubkey == script)
+                .map(|txout| txout.value.to_sat())
+                .sum()
+        };
+        let sender_scripts: HashSet<&ScriptBuf> = original
+            .output
+            .iter()
+            .map(|txout| &txout.script_pubkey)
+            .filter(|script| *script != payee)
+            .collect();
+        let additional_fee = sender_scripts
+            .iter()
+            .map(|script| value_to(original, script).saturating_sub(value_to(proposal, script)))
+            .sum();
+        let receiver_inputs = proposal
+            .input
+            .iter()
+            .filter(|txin| !original_inputs.contains(&txin.previous_output))
+            .count() as u64;
+        let outputs_substituted =
+            !proposal.output.iter().any(|txout| &txout.script_pubkey == payee)
+                || value_to(proposal, payee) < value_to(original, payee);
+        Self { additional_fee, receiver_inputs, outputs_substituted }
+    }
+}
+
+impl CheckedProposal {
+    /// Encode the validated `proposal` in `psbt_version`, 0 or 2.
+    ///
+    /// `changes` is `None` when the Original PSBT it answers isn't known.
+    pub(crate) fn new(
+        proposal: &Psbt,
+        changes: Option<Changes>,
+        psbt_version: u32,
+    ) -> Result<Self, PsbtV2Error> {
+        let psbt = match psbt_version {
+            2 => super::psbt_v2::to_v2_string(proposal)?,
+            _ => proposal.to_string(),
+        };
+        Ok(Self { psbt, changes })
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl CheckedProposal {
+    /// The Payjoin PSBT to sign and broadcast, base64 encoded in the PSBT version of the Original
+    /// PSBT.
+    pub fn psbt(&self) -> String {
+        self.psbt.clone()
+    }
+
+    /// How much the receiver took from the sender's change outputs to pay for its inputs, in
+    /// sats.
+    pub fn additional_fee_paid_by_sender(&self) -> Option<u64> {
+        self.changes.as_ref().map(|changes| changes.additional_fee)
+    }
+
+    /// The number of inputs the receiver contributed.
+    pub fn receiver_contributed_inputs(&self) -> Option<u64> {
+        self.changes.as_ref().map(|changes| changes.receiver_inputs)
+    }
+
+    /// Whether the receiver replaced the output paying the payjoin URI's address, or lowered its
+    /// amount.
+    pub fn outputs_substituted(&self) -> Option<bool> {
+        self.changes.as_ref().map(|changes| changes.outputs_substituted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use payjoin::bitcoin::{Amount, Sequence, TxIn, Txid, Witness};
+
+    use super::*;
+
+    // From the BIP78 test vectors: pays 0.02 BTC to 3CZZ... and 0.95983068 BTC back to the sender.
+    const ORIGINAL_PSBT: &str = "cHNidP8BAHMCAAAAAY8nutGgJdyYGXWiBEb45Hoe9lWGbkxh/6bNiOJdCDuDAAAAAAD+////AtyVuAUAAAAAF6kUHehJ8GnSdBUOOv6ujXLrWmsJRDCHgIQeAAAAAAAXqRR3QJbbz0hnQ8IvQ0fptGn+votneofTAAAAAAEBIKgb1wUAAAAAF6kU3k4ekGHKWRNbA1rV5tR5kEVDVNCHAQcXFgAUx4pFclNVgo1WWAdN1SYNX8tphTABCGsCRzBEAiB8Q+A6dep+Rz92vhy26lT0AjZn4PRLi8Bf9qoB/CMk0wIgP/Rj2PWZ3gEjUkTlhDRNAQ0gXwTO7t9n+V14pZ6oljUBIQMVmsAaoNWHVMS02LfTSe0e388LNitPa1UQZyOihY+FFgABABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAA=";
+
+    fn original() -> Transaction {
+        Psbt::from_str(ORIGINAL_PSBT).unwrap().unsigned_tx
+    }
+
+    fn payee() -> ScriptBuf {
+        original().output[1].script_pubkey.clone()
+    }
+
+    fn receiver_input() -> TxIn {
+        TxIn {
+            previous_output: OutPoint { txid: Txid::from_str(&"11".repeat(32)).unwrap(), vout: 0 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            witness: Witness::new(),
+        }
+    }
+
+    #[test]
+    fn unchanged_proposal() {
+        let changes = Changes::between(&original(), &payee(), &original());
+        assert_eq!(
+            changes,
+            Changes { additional_fee: 0, receiver_inputs: 0, outputs_substituted: false }
+        );
+    }
+
+    #[test]
+    fn receiver_input_paid_from_sender_change() {
+        let mut proposal = original();
+        proposal.input.push(receiver_input());
+        proposal.output[0].value -= Amount::from_sat(182);
+        proposal.output[1].value += Amount::from_sat(1_000_000);
+        let changes = Changes::between(&original(), &payee(), &proposal);
+        assert_eq!(
+            changes,
+            Changes { additional_fee: 182, receiver_inputs: 1, outputs_substituted: false }
+        );
+    }
+
+    #[test]
+    fn reordered_outputs_are_matched_by_script() {
+        let mut proposal = original();
+        proposal.output.swap(0, 1);
+        proposal.output[1].value -= Amount::from_sat(100);
+        let changes = Changes::between(&original(), &payee(), &proposal);
+        assert_eq!(
+            changes,
+            Changes { additional_fee: 100, receiver_inputs: 0, outputs_substituted: false }
+        );
+    }
+
+    #[test]
+    fn substituted_payee_output() {
+        let mut proposal = original();
+        proposal.output[1].script_pubkey = ScriptBuf::from_bytes(vec![0x51]);
+        let changes = Changes::between(&original(), &payee(), &proposal);
+        assert!(changes.outputs_substituted);
+
+        let mut proposal = original();
+        proposal.output[1].value -= Amount::from_sat(1);
+        let changes = Changes::between(&original(), &payee(), &proposal);
+        assert!(changes.outputs_substituted);
+    }
+}
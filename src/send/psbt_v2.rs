@@ -0,0 +1,368 @@
+//! Conversion between version 2 PSBTs (BIP370) and the version 0 PSBTs payjoin works with.
+//!
+//! A v2 PSBT keeps the fields of the unsigned transaction in the global, input and output maps
+//! instead of a global unsigned transaction. Converting moves them between the two places and
+//! keeps every other field as it is.
+
+use payjoin::bitcoin::base64::engine::general_purpose::STANDARD;
+use payjoin::bitcoin::base64::Engine;
+use payjoin::bitcoin::consensus::encode::serialize;
+use payjoin::bitcoin::psbt::Psbt;
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const GLOBAL_TX_VERSION: u8 = 0x02;
+const GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const GLOBAL_INPUT_COUNT: u8 = 0x04;
+const GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const GLOBAL_VERSION: u8 = 0xfb;
+const IN_PREVIOUS_TXID: u8 = 0x0e;
+const IN_OUTPUT_INDEX: u8 = 0x0f;
+const IN_SEQUENCE: u8 = 0x10;
+const IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+const OUT_AMOUNT: u8 = 0x03;
+const OUT_SCRIPT: u8 = 0x04;
+
+/// Locktimes below this are block heights, at or above it unix timestamps.
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// A v2 PSBT that can't be converted to version 0.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid version 2 PSBT: {0}")]
+pub(crate) struct PsbtV2Error(&'static str);
+
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Convert a base64 v2 PSBT to version 0.
+///
+/// Returns `None` for anything that isn't a v2 PSBT, to be parsed as version 0.
+pub(crate) fn parse(psbt: &str) -> Option<Result<Psbt, PsbtV2Error>> {
+    let bytes = STANDARD.decode(psbt.trim()).ok()?;
+    let (global, _) = read_map(bytes.strip_prefix(MAGIC)?).ok()?;
+    if value(&global, GLOBAL_VERSION)? != 2u32.to_le_bytes().as_slice() {
+        return None;
+    }
+    Some(to_v0(&bytes))
+}
+
+/// Encode a version 0 PSBT as a base64 version 2 PSBT.
+pub(crate) fn to_v2_string(psbt: &Psbt) -> Result<String, PsbtV2Error> {
+    to_v2(psbt).map(|bytes| STANDARD.encode(bytes))
+}
+
+fn to_v0(bytes: &[u8]) -> Result<Psbt, PsbtV2Error> {
+    let rest = bytes.strip_prefix(MAGIC).ok_or(PsbtV2Error("missing magic bytes"))?;
+    let (global, mut rest) = read_map(rest)?;
+    let input_count = count(&global, GLOBAL_INPUT_COUNT)?;
+    let output_count = count(&global, GLOBAL_OUTPUT_COUNT)?;
+    let mut inputs = Vec::with_capacity(input_count.min(rest.len()));
+    for _ in 0..input_count {
+        let (map, remaining) = read_map(rest)?;
+        inputs.push(map);
+        rest = remaining;
+    }
+    let mut outputs = Vec::with_capacity(output_count.min(rest.len()));
+    for _ in 0..output_count {
+        let (map, remaining) = read_map(rest)?;
+        outputs.push(map);
+        rest = remaining;
+    }
+
+    let mut tx = fixed::<4>(&global, GLOBAL_TX_VERSION, "missing transaction version")?.to_vec();
+    write_compact_size(&mut tx, input_count as u64);
+    for input in &inputs {
+        tx.extend(fixed::<32>(input, IN_PREVIOUS_TXID, "missing previous txid")?);
+        tx.extend(fixed::<4>(input, IN_OUTPUT_INDEX, "missing output index")?);
+        tx.push(0);
+        match value(input, IN_SEQUENCE) {
+            Some(sequence) => tx.extend(as_fixed::<4>(sequence, "invalid sequence")?),
+            None => tx.extend(u32::MAX.to_le_bytes()),
+        }
+    }
+    write_compact_size(&mut tx, output_count as u64);
+    for output in &outputs {
+        tx.extend(fixed::<8>(output, OUT_AMOUNT, "missing output amount")?);
+        let script = value(output, OUT_SCRIPT).ok_or(PsbtV2Error("missing output script"))?;
+        write_compact_size(&mut tx, script.len() as u64);
+        tx.extend(script);
+    }
+    tx.extend(locktime(&global, &inputs)?.to_le_bytes());
+
+    let mut v0 = MAGIC.to_vec();
+    write_map(
+        &mut v0,
+        std::iter::once((vec![GLOBAL_UNSIGNED_TX], tx)).chain(without(
+            global,
+            &[
+                GLOBAL_TX_VERSION,
+                GLOBAL_FALLBACK_LOCKTIME,
+                GLOBAL_INPUT_COUNT,
+                GLOBAL_OUTPUT_COUNT,
+                GLOBAL_TX_MODIFIABLE,
+                GLOBAL_VERSION,
+            ],
+        )),
+    );
+    for input in inputs {
+        let v2_only = [
+            IN_PREVIOUS_TXID,
+            IN_OUTPUT_INDEX,
+            IN_SEQUENCE,
+            IN_REQUIRED_TIME_LOCKTIME,
+            IN_REQUIRED_HEIGHT_LOCKTIME,
+        ];
+        write_map(&mut v0, without(input, &v2_only));
+    }
+    for output in outputs {
+        write_map(&mut v0, without(output, &[OUT_AMOUNT, OUT_SCRIPT]));
+    }
+    Psbt::deserialize(&v0).map_err(|_| PsbtV2Error("invalid fields"))
+}
+
+fn to_v2(psbt: &Psbt) -> Result<Vec<u8>, PsbtV2Error> {
+    let tx = &psbt.unsigned_tx;
+    let v0 = psbt.serialize();
+    let mut rest = v0.strip_prefix(MAGIC).ok_or(PsbtV2Error("missing magic bytes"))?;
+    let map_count = 1 + tx.input.len() + tx.output.len();
+    let mut maps = Vec::with_capacity(map_count);
+    for _ in 0..map_count {
+        let (map, remaining) = read_map(rest)?;
+        maps.push(map);
+        rest = remaining;
+    }
+    let mut maps = maps.into_iter();
+
+    let mut v2 = MAGIC.to_vec();
+    let global = maps.next().unwrap_or_default();
+    let v2_fields = [
+        (GLOBAL_TX_VERSION, tx.version.0.to_le_bytes().to_vec()),
+        (GLOBAL_FALLBACK_LOCKTIME, tx.lock_time.to_consensus_u32().to_le_bytes().to_vec()),
+        (GLOBAL_INPUT_COUNT, compact_size(tx.input.len() as u64)),
+        (GLOBAL_OUTPUT_COUNT, compact_size(tx.output.len() as u64)),
+        (GLOBAL_VERSION, 2u32.to_le_bytes().to_vec()),
+    ];
+    write_map(
+        &mut v2,
+        v2_fields
+            .into_iter()
+            .map(|(key, value)| (vec![key], value))
+            .chain(without(global, &[GLOBAL_UNSIGNED_TX, GLOBAL_VERSION])),
+    );
+    for (txin, map) in tx.input.iter().zip(maps.by_ref()) {
+        let v2_fields = [
+            (IN_PREVIOUS_TXID, serialize(&txin.previous_output.txid)),
+            (IN_OUTPUT_INDEX, txin.previous_output.vout.to_le_bytes().to_vec()),
+            (IN_SEQUENCE, txin.sequence.0.to_le_bytes().to_vec()),
+        ];
+        write_map(&mut v2, v2_fields.into_iter().map(|(key, value)| (vec![key], value)).chain(map));
+    }
+    for (txout, map) in tx.output.iter().zip(maps) {
+        let v2_fields = [
+            (OUT_AMOUNT, txout.value.to_sat().to_le_bytes().to_vec()),
+            (OUT_SCRIPT, txout.script_pubkey.to_bytes()),
+        ];
+        write_map(&mut v2, v2_fields.into_iter().map(|(key, value)| (vec![key], value)).chain(map));
+    }
+    Ok(v2)
+}
+
+/// The locktime of the transaction, following the BIP370 rules: the largest required locktime
+/// of the inputs, preferring heights if the inputs allow both, or else the fallback locktime.
+fn locktime(global: &Map, inputs: &[Map]) -> Result<u32, PsbtV2Error> {
+    let mut heights = Vec::new();
+    let mut times = Vec::new();
+    let mut height_possible = true;
+    let mut time_possible = true;
+    for input in inputs {
+        let height = value(input, IN_REQUIRED_HEIGHT_LOCKTIME)
+            .map(|height| as_fixed::<4>(height, "invalid required height locktime"))
+            .transpose()?
+            .map(u32::from_le_bytes);
+        let time = value(input, IN_REQUIRED_TIME_LOCKTIME)
+            .map(|time| as_fixed::<4>(time, "invalid required time locktime"))
+            .transpose()?
+            .map(u32::from_le_bytes);
+        if height.is_some() || time.is_some() {
+            height_possible &= height.is_some();
+            time_possible &= time.is_some();
+        }
+        heights.extend(height);
+        times.extend(time);
+    }
+    if heights.is_empty() && times.is_empty() {
+        return match value(global, GLOBAL_FALLBACK_LOCKTIME) {
+            Some(locktime) => Ok(u32::from_le_bytes(as_fixed(locktime, "invalid locktime")?)),
+            None => Ok(0),
+        };
+    }
+    match (height_possible, time_possible) {
+        (true, _) => heights.into_iter().max().filter(|height| *height < LOCKTIME_THRESHOLD),
+        (false, true) => times.into_iter().max().filter(|time| *time >= LOCKTIME_THRESHOLD),
+        (false, false) => None,
+    }
+    .ok_or(PsbtV2Error("conflicting required locktimes"))
+}
+
+fn value(map: &Map, key_type: u8) -> Option<&[u8]> {
+    map.iter().find(|(key, _)| key.as_slice() == [key_type]).map(|(_, value)| value.as_slice())
+}
+
+fn fixed<const N: usize>(
+    map: &Map,
+    key_type: u8,
+    missing: &'static str,
+) -> Result<[u8; N], PsbtV2Error> {
+    as_fixed(value(map, key_type).ok_or(PsbtV2Error(missing))?, missing)
+}
+
+fn as_fixed<const N: usize>(value: &[u8], invalid: &'static str) -> Result<[u8; N], PsbtV2Error> {
+    value.try_into().map_err(|_| PsbtV2Error(invalid))
+}
+
+fn count(global: &Map, key_type: u8) -> Result<usize, PsbtV2Error> {
+    let value = value(global, key_type).ok_or(PsbtV2Error("missing input or output count"))?;
+    match read_compact_size(value) {
+        Some((count, [])) => usize::try_from(count).map_err(|_| PsbtV2Error("count too large")),
+        _ => Err(PsbtV2Error("invalid input or output count")),
+    }
+}
+
+/// The entries of `map` whose key type isn't in `key_types`.
+fn without(map: Map, key_types: &[u8]) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+    map.into_iter().filter(move |(key, _)| !(key.len() == 1 && key_types.contains(&key[0])))
+}
+
+/// Read a key-value map up to its separator, returning the map and the bytes after it.
+fn read_map(mut bytes: &[u8]) -> Result<(Map, &[u8]), PsbtV2Error> {
+    let mut map = Map::new();
+    loop {
+        let (key, rest) = read_bytes(bytes)?;
+        if key.is_empty() {
+            return Ok((map, rest));
+        }
+        let (value, rest) = read_bytes(rest)?;
+        map.push((key.to_vec(), value.to_vec()));
+        bytes = rest;
+    }
+}
+
+fn read_bytes(bytes: &[u8]) -> Result<(&[u8], &[u8]), PsbtV2Error> {
+    let (len, rest) = read_compact_size(bytes).ok_or(PsbtV2Error("truncated"))?;
+    let len = usize::try_from(len).map_err(|_| PsbtV2Error("truncated"))?;
+    if rest.len() < len {
+        return Err(PsbtV2Error("truncated"));
+    }
+    Ok(rest.split_at(len))
+}
+
+fn read_compact_size(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (first, rest) = bytes.split_first()?;
+    let width = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => return Some((*n as u64, rest)),
+    };
+    if rest.len() < width {
+        return None;
+    }
+    let (value, rest) = rest.split_at(width);
+    let mut buf = [0u8; 8];
+    buf[..width].copy_from_slice(value);
+    Some((u64::from_le_bytes(buf), rest))
+}
+
+fn compact_size(n: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_compact_size(&mut bytes, n);
+    bytes
+}
+
+fn write_compact_size(bytes: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => bytes.push(n as u8),
+        0xfd..=0xffff => {
+            bytes.push(0xfd);
+            bytes.extend((n as u16).to_le_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            bytes.push(0xfe);
+            bytes.extend((n as u32).to_le_bytes());
+        }
+        _ => {
+            bytes.push(0xff);
+            bytes.extend(n.to_le_bytes());
+        }
+    }
+}
+
+fn write_map(bytes: &mut Vec<u8>, map: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) {
+    for (key, value) in map {
+        write_compact_size(bytes, key.len() as u64);
+        bytes.extend(key);
+        write_compact_size(bytes, value.len() as u64);
+        bytes.extend(value);
+    }
+    bytes.push(0);
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use payjoin::bitcoin::psbt::Input;
+    use payjoin::bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Txid, Witness};
+
+    use super::*;
+
+    // OriginalPSBT test vector from BIP78, and the same PSBT as a BIP370 v2 PSBT
+    const ORIGINAL_PSBT: &str = "cHNidP8BAHMCAAAAAY8nutGgJdyYGXWiBEb45Hoe9lWGbkxh/6bNiOJdCDuDAAAAAAD+////AtyVuAUAAAAAF6kUHehJ8GnSdBUOOv6ujXLrWmsJRDCHgIQeAAAAAAAXqRR3QJbbz0hnQ8IvQ0fptGn+votneofTAAAAAAEBIKgb1wUAAAAAF6kU3k4ekGHKWRNbA1rV5tR5kEVDVNCHAQcXFgAUx4pFclNVgo1WWAdN1SYNX8tphTABCGsCRzBEAiB8Q+A6dep+Rz92vhy26lT0AjZn4PRLi8Bf9qoB/CMk0wIgP/Rj2PWZ3gEjUkTlhDRNAQ0gXwTO7t9n+V14pZ6oljUBIQMVmsAaoNWHVMS02LfTSe0e388LNitPa1UQZyOihY+FFgABABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAA=";
+    const ORIGINAL_PSBT_V2: &str = "cHNidP8BAgQCAAAAAQME0wAAAAEEAQEBBQECAfsEAgAAAAABDiCPJ7rRoCXcmBl1ogRG+OR6HvZVhm5MYf+mzYjiXQg7gwEPBAAAAAABEAT+////AQEgqBvXBQAAAAAXqRTeTh6QYcpZE1sDWtXm1HmQRUNU0IcBBxcWABTHikVyU1WCjVZYB03VJg1fy2mFMAEIawJHMEQCIHxD4Dp16n5HP3a+HLbqVPQCNmfg9EuLwF/2qgH8IyTTAiA/9GPY9ZneASNSROWENE0BDSBfBM7u32f5XXilnqiWNQEhAxWawBqg1YdUxLTYt9NJ7R7fzws2K09rVRBnI6KFj4UWAAEDCNyVuAUAAAAAAQQXqRQd6EnwadJ0FQ46/q6NcutaawlEMIcBABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAEDCICEHgAAAAAAAQQXqRR3QJbbz0hnQ8IvQ0fptGn+votneocA";
+
+    /// The Original PSBT with a finalized receiver input added, paid for from the sender's change.
+    fn proposal() -> Psbt {
+        let mut psbt = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+        let receiver_script =
+            ScriptBuf::from_bytes([[0x00, 0x14].as_slice(), &[0x22; 20]].concat());
+        psbt.unsigned_tx.input.push(TxIn {
+            previous_output: OutPoint { txid: Txid::from_str(&"11".repeat(32)).unwrap(), vout: 3 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            witness: Witness::new(),
+        });
+        psbt.inputs.push(Input {
+            witness_utxo: Some(TxOut {
+                value: Amount::from_sat(1_000_000),
+                script_pubkey: receiver_script,
+            }),
+            final_script_witness: Some(Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]])),
+            ..Default::default()
+        });
+        psbt.unsigned_tx.output[0].value -= Amount::from_sat(182);
+        psbt.unsigned_tx.output[1].value += Amount::from_sat(1_000_000);
+        psbt
+    }
+
+    #[test]
+    fn original_psbt_converts_to_the_bip370_encoding() {
+        let original = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+        assert_eq!(to_v2_string(&original).unwrap(), ORIGINAL_PSBT_V2);
+        assert_eq!(parse(ORIGINAL_PSBT_V2), Some(Ok(original)));
+    }
+
+    #[test]
+    fn proposal_with_receiver_inputs_round_trips() {
+        let proposal = proposal();
+        let v2 = to_v2(&proposal).unwrap();
+        assert_eq!(to_v0(&v2), Ok(proposal.clone()));
+        assert_eq!(parse(&to_v2_string(&proposal).unwrap()), Some(Ok(proposal)));
+    }
+
+    #[test]
+    fn v0_psbts_are_left_to_the_v0_parser() {
+        assert_eq!(parse(ORIGINAL_PSBT), None);
+    }
+}
@@ -1,9 +1,12 @@
 use std::sync::Arc;
 
 pub use crate::send::{
-    BuildSenderError, CreateRequestError, EncapsulationError, ResponseError, SerdeJsonError,
+    BuildSenderError, CheckedProposal, CreateRequestError, PollError, PsbtParseError,
+    RejectionCode, ResponseError, SerdeJsonError,
+};
+use crate::{
+    ClientResponse, DirectoryCapabilities, FeeRate, OhttpKeys, PayjoinTxRecord, PjUri, Request, Url,
 };
-use crate::{ClientResponse, PjUri, Request, Url};
 
 #[derive(uniffi::Object)]
 struct SenderBuilder(super::SenderBuilder);
@@ -31,6 +34,40 @@ impl SenderBuilder {
         super::SenderBuilder::new(psbt, (*uri).clone()).map(Into::into).map_err(Into::into)
     }
 
+    /// Parse `bip21` and prepare to pay it with `psbt` in one step.
+    ///
+    /// Fails with a `BuildSenderErrorKind` if the URI has no `pj` parameter or no output pays
+    /// its amount to its address.
+    #[uniffi::constructor]
+    pub fn from_psbt_and_bip21(psbt: String, bip21: String) -> Result<Self, BuildSenderError> {
+        super::SenderBuilder::from_psbt_and_bip21(psbt, bip21).map(Into::into).map_err(Into::into)
+    }
+
+    /// Send the Original PSBT to the receiver with its wallet metadata intact.
+    ///
+    /// By default global xpubs, key origins and proprietary fields are stripped from the PSBT
+    /// sent to the receiver, since they reveal the sender's wallet structure, and restored on
+    /// the proposal returned by `process_response`.
+    pub fn keep_psbt_metadata(&self) -> Self {
+        self.0.clone().keep_psbt_metadata().into()
+    }
+
+    /// Give up on the payjoin `secs` seconds after the `Sender` is built.
+    ///
+    /// Past the deadline, or the expiry declared by the receiver if that comes first, the sender
+    /// should broadcast the Original PSBT instead. See `Sender::fallback_due`.
+    pub fn fallback_after_secs(&self, secs: u64) -> Self {
+        self.0.clone().fallback_after_secs(secs).into()
+    }
+
+    /// Give up on the payjoin at unix time `deadline`, in seconds.
+    ///
+    /// Like `fallback_after_secs`, for callers keeping their own clock. The later of the two
+    /// calls wins.
+    pub fn fallback_at(&self, deadline: u64) -> Self {
+        self.0.clone().fallback_at(deadline).into()
+    }
+
     /// Disable output substitution even if the receiver didn't.
     ///
     /// This forbids receiver switching output or decreasing amount.
@@ -38,7 +75,7 @@ impl SenderBuilder {
     /// doing advanced operations such as opening LN channels and it also guarantees the
     /// receiver will **not** reward the sender with a discount.
     pub fn always_disable_output_substitution(&self) -> Self {
-        self.0.always_disable_output_substitution().into()
+        self.0.clone().always_disable_output_substitution().into()
     }
     // Calculate the recommended fee contribution for an Original PSBT.
     //
@@ -46,8 +83,12 @@ impl SenderBuilder {
     // The minfeerate parameter is set if the contribution is available in change.
     //
     // This method fails if no recommendation can be made or if the PSBT is malformed.
+    //
+    // Deprecated: `min_fee_rate` is in sat/kwu, use `build_recommended_with_fee_rate()`.
     pub fn build_recommended(&self, min_fee_rate: u64) -> Result<Arc<Sender>, BuildSenderError> {
-        self.0.build_recommended(min_fee_rate).map(|e| Arc::new(e.into()))
+        self.0
+            .build_recommended_with_fee_rate(FeeRate::from_sat_per_kwu(min_fee_rate))
+            .map(|e| Arc::new(e.into()))
     }
 
     /// Offer the receiver contribution to pay for his input.
@@ -63,18 +104,20 @@ impl SenderBuilder {
     /// If this option is true and a transaction with change amount lower than fee
     /// contribution is provided then instead of returning error the fee contribution will
     /// be just lowered in the request to match the change amount.
+    ///
+    /// Deprecated: `min_fee_rate` is in sat/kwu, use `build_with_additional_fee_with_fee_rate()`.
     pub fn build_with_additional_fee(
         &self,
         max_fee_contribution: u64,
-        change_index: Option<u8>,
+        change_index: Option<u32>,
         min_fee_rate: u64,
         clamp_fee_contribution: bool,
     ) -> Result<Arc<Sender>, BuildSenderError> {
         self.0
-            .build_with_additional_fee(
+            .build_with_additional_fee_with_fee_rate(
                 max_fee_contribution,
                 change_index,
-                min_fee_rate,
+                FeeRate::from_sat_per_kwu(min_fee_rate),
                 clamp_fee_contribution,
             )
             .map(|e| Arc::new(e.into()))
@@ -83,11 +126,71 @@ impl SenderBuilder {
     ///
     /// While it's generally better to offer some contribution some users may wish not to.
     /// This function disables contribution.
+    ///
+    /// Deprecated: `min_fee_rate` is in sat/kwu, use `build_non_incentivizing_with_fee_rate()`.
     pub fn build_non_incentivizing(
         &self,
         min_fee_rate: u64,
     ) -> Result<Arc<Sender>, BuildSenderError> {
-        self.0.build_non_incentivizing(min_fee_rate).map(|e| Arc::new(e.into()))
+        self.0
+            .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(min_fee_rate))
+            .map(|e| Arc::new(e.into()))
+    }
+
+    /// Like `build_recommended()`, with the minimum fee rate given in an explicit unit.
+    pub fn build_recommended_with_fee_rate(
+        &self,
+        min_fee_rate: Arc<FeeRate>,
+    ) -> Result<Arc<Sender>, BuildSenderError> {
+        self.0.build_recommended_with_fee_rate(*min_fee_rate).map(|e| Arc::new(e.into()))
+    }
+
+    /// Like `build_with_additional_fee()`, with the minimum fee rate given in an explicit unit.
+    pub fn build_with_additional_fee_with_fee_rate(
+        &self,
+        max_fee_contribution: u64,
+        change_index: Option<u32>,
+        min_fee_rate: Arc<FeeRate>,
+        clamp_fee_contribution: bool,
+    ) -> Result<Arc<Sender>, BuildSenderError> {
+        self.0
+            .build_with_additional_fee_with_fee_rate(
+                max_fee_contribution,
+                change_index,
+                *min_fee_rate,
+                clamp_fee_contribution,
+            )
+            .map(|e| Arc::new(e.into()))
+    }
+
+    /// Offer a fee contribution from an Original PSBT with any number of outputs, such as a
+    /// batched payment.
+    ///
+    /// `change_index` must name the change output. The payjoin recipient's output must be the
+    /// only one paying the payjoin URI's address and amount.
+    pub fn build_batched_with_fee_rate(
+        &self,
+        change_index: u32,
+        max_fee_contribution: u64,
+        min_fee_rate: Arc<FeeRate>,
+        clamp_fee_contribution: bool,
+    ) -> Result<Arc<Sender>, BuildSenderError> {
+        self.0
+            .build_batched_with_fee_rate(
+                change_index,
+                max_fee_contribution,
+                *min_fee_rate,
+                clamp_fee_contribution,
+            )
+            .map(|e| Arc::new(e.into()))
+    }
+
+    /// Like `build_non_incentivizing()`, with the minimum fee rate given in an explicit unit.
+    pub fn build_non_incentivizing_with_fee_rate(
+        &self,
+        min_fee_rate: Arc<FeeRate>,
+    ) -> Result<Arc<Sender>, BuildSenderError> {
+        self.0.build_non_incentivizing_with_fee_rate(*min_fee_rate).map(|e| Arc::new(e.into()))
     }
 }
 
@@ -129,6 +232,101 @@ impl Sender {
         }
     }
 
+    /// Extract a v2 request if the receiver supports it, falling back to a v1 request otherwise.
+    ///
+    /// Post a `V1` request directly to the receiver, and a `V2` request to the OHTTP relay
+    /// before polling.
+    pub fn extract_highest_version(
+        &self,
+        ohttp_relay: Arc<Url>,
+    ) -> Result<ExtractedRequest, CreateRequestError> {
+        self.0.extract_highest_version((*ohttp_relay).clone()).map(Into::into)
+    }
+
+    /// The receiver's endpoint from the payjoin URI.
+    ///
+    /// v1 requests are posted there directly. For v2 it is the receiver's mailbox on the
+    /// directory, reached through the OHTTP relay.
+    pub fn endpoint(&self) -> Arc<Url> {
+        Arc::new(self.0.endpoint())
+    }
+
+    /// Whether the receiver's endpoint accepts v2 requests through a directory.
+    pub fn can_use_v2(&self) -> bool {
+        self.0.can_use_v2()
+    }
+
+    /// The index of the Original PSBT output treated as paying the payjoin recipient.
+    pub fn payee_output_index(&self) -> Option<u32> {
+        self.0.payee_output_index()
+    }
+
+    /// The index of the Original PSBT output the receiver may take the fee contribution from.
+    /// `None` if no contribution is offered.
+    pub fn change_output_index(&self) -> Option<u32> {
+        self.0.change_output_index()
+    }
+
+    /// The PSBT version the Original PSBT was supplied in, 0 or 2. Proposals are returned in the
+    /// same version.
+    pub fn psbt_version(&self) -> u32 {
+        self.0.psbt_version()
+    }
+
+    /// The fee contribution offered to the receiver, as sent in the request. `None` if no
+    /// contribution is offered.
+    pub fn fee_contribution(&self) -> Option<FeeContribution> {
+        self.0
+            .fee_contribution()
+            .map(|(max_amount_sats, vout)| FeeContribution { max_amount_sats, vout })
+    }
+
+    /// The minimum fee rate the Payjoin transaction must pay, as sent in the request. `None`
+    /// if the request sets none, i.e. the minimum is zero.
+    pub fn min_fee_rate(&self) -> Option<Arc<FeeRate>> {
+        self.0.min_fee_rate().map(Arc::new)
+    }
+
+    /// `min_fee_rate()` in sat/vB, rounded up.
+    pub fn min_fee_rate_sat_per_vb(&self) -> Option<u64> {
+        self.0.min_fee_rate_sat_per_vb()
+    }
+
+    /// The Original PSBT as sent to the receiver, base64 encoded.
+    pub fn original_psbt(&self) -> String {
+        self.0.original_psbt()
+    }
+
+    /// The receiver's HPKE public key a v2 request is encrypted to, base64url encoded without
+    /// padding. `None` for v1 endpoints or a key that can't be parsed.
+    pub fn receiver_pubkey(&self) -> Option<String> {
+        self.0.receiver_pubkey()
+    }
+
+    /// The OHTTP keys of the directory, from the `OH` parameter of a v2 endpoint.
+    pub fn ohttp_keys(&self) -> Option<Arc<OhttpKeys>> {
+        self.0.ohttp_keys().map(Arc::new)
+    }
+
+    /// Unix timestamp in seconds after which the payjoin should be abandoned, if any.
+    ///
+    /// This is the earlier of the deadline set with `SenderBuilder::fallback_after_secs` or
+    /// `fallback_at` and the expiry declared by the receiver.
+    pub fn fallback_deadline(&self) -> Option<u64> {
+        self.0.fallback_deadline()
+    }
+
+    /// Whether the sender should stop waiting for the payjoin at unix time `now` and broadcast
+    /// the Original PSBT instead.
+    pub fn fallback_due(&self, now: u64) -> bool {
+        self.0.fallback_due(now)
+    }
+
+    /// The consensus encoded Original PSBT transaction to broadcast if the payjoin is abandoned.
+    pub fn fallback_tx(&self) -> Option<Vec<u8>> {
+        self.0.fallback_tx()
+    }
+
     pub fn to_json(&self) -> Result<String, SerdeJsonError> {
         self.0.to_json()
     }
@@ -139,12 +337,44 @@ impl Sender {
     }
 }
 
+/// The request chosen by `Sender::extract_highest_version`, with the context to process the
+/// response.
+#[derive(uniffi::Enum)]
+pub enum ExtractedRequest {
+    /// A BIP78 request, to post directly to the receiver's endpoint.
+    V1 { request: Request, context: Arc<V1Context> },
+    /// A BIP77 request, to post to the OHTTP relay before polling for the proposal.
+    V2 { request: Request, context: Arc<V2PostContext> },
+}
+
+impl From<super::ExtractedRequest> for ExtractedRequest {
+    fn from(value: super::ExtractedRequest) -> Self {
+        match value {
+            super::ExtractedRequest::V1 { request, context } => {
+                Self::V1 { request, context: Arc::new(context.into()) }
+            }
+            super::ExtractedRequest::V2 { request, context } => {
+                Self::V2 { request, context: Arc::new(context.into()) }
+            }
+        }
+    }
+}
+
 #[derive(uniffi::Record)]
 pub struct RequestV2PostContext {
     pub request: Request,
     pub context: Arc<V2PostContext>,
 }
 
+/// The fee contribution a sender offers the receiver.
+#[derive(uniffi::Record)]
+pub struct FeeContribution {
+    /// The most the receiver may take from the sender's change, in sats.
+    pub max_amount_sats: u64,
+    /// The index of the output it is taken from.
+    pub vout: u32,
+}
+
 #[derive(uniffi::Record)]
 pub struct RequestV1Context {
     pub request: Request,
@@ -166,9 +396,36 @@ impl From<super::V1Context> for V1Context {
 impl V1Context {
     /// Decodes and validates the response.
     /// Call this method with response from receiver to continue BIP78 flow. If the response is valid you will get appropriate PSBT that you should sign and broadcast.
+    ///
+    /// `response` is the raw HTTP body: a JSON error, or the Payjoin PSBT as base64 text. Use
+    /// `process_response_bytes()` for a PSBT already decoded to binary. A body that isn't a
+    /// base64 PSBT fails with `ResponseError::InvalidEncoding`, a PSBT that fails the BIP78
+    /// sender checks with `ResponseError::Validation`. A JSON error fails with
+    /// `ResponseError::ReceiverRejected`, or `ResponseError::VersionUnsupported`.
     pub fn process_response(&self, response: Vec<u8>) -> Result<String, ResponseError> {
         self.0.process_response(response)
     }
+
+    /// Like `process_response()`, also reporting what the receiver changed compared to the
+    /// Original PSBT.
+    pub fn process_response_checked(
+        &self,
+        response: Vec<u8>,
+    ) -> Result<Arc<CheckedProposal>, ResponseError> {
+        self.0.process_response_checked(response).map(Arc::new)
+    }
+
+    /// Like `process_response()`, for a body an HTTP stack handed over as text.
+    pub fn process_response_str(&self, response: String) -> Result<String, ResponseError> {
+        self.0.process_response_str(response)
+    }
+
+    /// Like `process_response()`, for a Payjoin PSBT already decoded from base64 to binary.
+    ///
+    /// Fails with `ResponseError::InvalidEncoding` if `psbt` isn't a binary PSBT.
+    pub fn process_response_bytes(&self, psbt: Vec<u8>) -> Result<String, ResponseError> {
+        self.0.process_response_bytes(psbt)
+    }
 }
 
 #[derive(uniffi::Object)]
@@ -176,13 +433,13 @@ pub struct V2PostContext(super::V2PostContext);
 
 #[uniffi::export]
 impl V2PostContext {
-    /// Decodes and validates the response.
-    /// Call this method with response from receiver to continue BIP-??? flow. A successful response can either be None if the relay has not response yet or Some(Psbt).
-    /// If the response is some valid PSBT you should sign and broadcast.
-    pub fn process_response(
-        &self,
-        response: &[u8],
-    ) -> Result<Arc<V2GetContext>, EncapsulationError> {
+    /// Process the directory's response to the request from `Sender::extract_v2()`.
+    ///
+    /// The returned `V2GetContext` polls the directory for the receiver's proposal. Fails with
+    /// `ResponseError::Decapsulation` if the response can't be decapsulated, so post the Original
+    /// PSBT again, and with `ResponseError::OhttpContextAlreadyUsed` if this context already
+    /// processed a response.
+    pub fn process_response(&self, response: &[u8]) -> Result<Arc<V2GetContext>, ResponseError> {
         self.0.process_response(response).map(|t| Arc::new(t.into()))
     }
 }
@@ -200,16 +457,29 @@ pub struct RequestOhttpContext {
 }
 
 #[derive(uniffi::Object)]
-pub struct V2GetContext(super::V2GetContext);
+pub struct V2GetContext(Arc<super::V2GetContext>);
 
 impl From<super::V2GetContext> for V2GetContext {
     fn from(value: super::V2GetContext) -> Self {
-        Self(value)
+        Self(Arc::new(value))
     }
 }
 
 #[uniffi::export]
 impl V2GetContext {
+    /// Persist this context, to keep polling across restarts.
+    ///
+    /// Restore with `V2GetContext.from_json()` and keep polling without posting the Original
+    /// PSBT again.
+    pub fn to_json(&self) -> Result<String, SerdeJsonError> {
+        self.0.to_json()
+    }
+
+    #[uniffi::constructor]
+    pub fn from_json(json: &str) -> Result<Self, SerdeJsonError> {
+        super::V2GetContext::from_json(json).map(Into::into)
+    }
+
     pub fn extract_req(
         &self,
         ohttp_relay: String,
@@ -219,9 +489,50 @@ impl V2GetContext {
             .map(|(request, ctx)| RequestOhttpContext { request, ohttp_ctx: Arc::new(ctx) })
     }
 
-    /// Decodes and validates the response.
-    /// Call this method with response from receiver to continue BIP-??? flow. A successful response can either be None if the relay has not response yet or Some(Psbt).
-    /// If the response is some valid PSBT you should sign and broadcast.
+    /// Like `extract_req`, but stops polling once the sender's fallback deadline has passed at
+    /// unix time `now`.
+    ///
+    /// Fails with `PollError::FallbackDue` carrying the transaction to broadcast instead.
+    pub fn extract_poll_req(
+        &self,
+        ohttp_relay: String,
+        now: u64,
+    ) -> Result<RequestOhttpContext, PollError> {
+        self.0
+            .extract_poll_req(ohttp_relay, now)
+            .map(|(request, ctx)| RequestOhttpContext { request, ohttp_ctx: Arc::new(ctx) })
+    }
+
+    /// Like `extract_poll_req()`, for a poll request the directory may hold open for up to
+    /// `wait_secs` seconds until the receiver's proposal arrives.
+    ///
+    /// `wait_secs` is returned as the request's `timeout_secs`, shortened to the time left before
+    /// the sender's fallback deadline. If `capabilities` says the directory doesn't long-poll,
+    /// this is a plain poll request as from `extract_poll_req()`.
+    pub fn extract_poll_req_with_wait(
+        &self,
+        ohttp_relay: String,
+        now: u64,
+        wait_secs: u64,
+        capabilities: DirectoryCapabilities,
+    ) -> Result<RequestOhttpContext, PollError> {
+        self.0
+            .extract_poll_req_with_wait(ohttp_relay, now, wait_secs, &capabilities)
+            .map(|(request, ctx)| RequestOhttpContext { request, ohttp_ctx: Arc::new(ctx) })
+    }
+
+    /// Process the directory's response to a request from `extract_req()`.
+    ///
+    /// Returns `null` if the receiver hasn't posted a proposal yet, so poll again, or the
+    /// validated Payjoin PSBT to sign and broadcast. A response that can't be decapsulated fails
+    /// with `ResponseError::Decapsulation`, so poll again, and an invalid proposal with
+    /// `ResponseError::Validation`. An error the receiver replied with fails with
+    /// `ResponseError::WellKnown`, `ResponseError::VersionUnsupported` or
+    /// `ResponseError::Unrecognized`. Once the sender's fallback deadline or the receiver's
+    /// session expiry has passed it fails with `ResponseError::FallbackDue` instead, and
+    /// `extract_poll_req()` stops polling.
+    ///
+    /// A response of the wrong length is rejected without using up `ohttp_ctx`.
     pub fn process_response(
         &self,
         response: &[u8],
@@ -229,4 +540,110 @@ impl V2GetContext {
     ) -> Result<Option<String>, ResponseError> {
         self.0.process_response(response, ohttp_ctx.as_ref())
     }
+
+    /// Like `process_response()`, also reporting what the receiver changed compared to the
+    /// Original PSBT.
+    pub fn process_response_checked(
+        &self,
+        response: &[u8],
+        ohttp_ctx: Arc<ClientResponse>,
+    ) -> Result<Option<Arc<CheckedProposal>>, ResponseError> {
+        self.0
+            .process_response_checked(response, ohttp_ctx.as_ref())
+            .map(|proposal| proposal.map(Arc::new))
+    }
+}
+
+/// Polls the directory for the receiver's proposal with a fresh OHTTP encapsulation each time.
+///
+/// Only the `ohttp_ctx` of the latest `next_request()` is accepted by `handle_response()`.
+#[derive(uniffi::Object)]
+pub struct SenderPoller(super::SenderPoller);
+
+#[uniffi::export]
+impl SenderPoller {
+    #[uniffi::constructor]
+    pub fn new(context: Arc<V2GetContext>, ohttp_relay: String) -> Self {
+        Self(super::SenderPoller::new(context.0.clone(), ohttp_relay))
+    }
+
+    /// Extract a request to poll the directory with, encapsulated afresh.
+    pub fn next_request(&self) -> Result<RequestOhttpContext, CreateRequestError> {
+        self.0.next_request().map(|(request, ohttp_ctx)| RequestOhttpContext { request, ohttp_ctx })
+    }
+
+    /// Process the response to the latest request.
+    ///
+    /// Returns `null` if the receiver hasn't posted a proposal yet, so poll again. Fails with
+    /// `ResponseError::StaleOhttpContext` if `ohttp_ctx` isn't from the latest request.
+    pub fn handle_response(
+        &self,
+        response: &[u8],
+        ohttp_ctx: Arc<ClientResponse>,
+    ) -> Result<Option<String>, ResponseError> {
+        self.0.handle_response(response, ohttp_ctx.as_ref())
+    }
+}
+
+/// Summarize a payjoin for the sender's transaction history.
+///
+/// `original_psbt` is the Original PSBT the `Sender` was built from, `payjoin_psbt` the proposal
+/// returned by `process_response`, `uri` the payjoin URI that was paid and `now` the unix
+/// timestamp to record.
+#[uniffi::export]
+pub fn sender_tx_record(
+    original_psbt: String,
+    payjoin_psbt: String,
+    uri: Arc<PjUri>,
+    now: u64,
+) -> Result<PayjoinTxRecord, PsbtParseError> {
+    super::sender_tx_record(original_psbt, payjoin_psbt, &uri, now)
+}
+
+#[cfg(feature = "async")]
+mod asynchronous {
+    use std::sync::Arc;
+
+    use super::Sender;
+    pub use crate::send::{HttpTransport, PollingPolicy, RunError};
+    use crate::Url;
+
+    #[uniffi::export]
+    impl Sender {
+        /// Drive a v2 send to completion and return the validated Payjoin PSBT to sign and
+        /// broadcast.
+        ///
+        /// Posts the Original PSBT through `ohttp_relay`, then polls the directory every
+        /// `poll_interval_secs`, each poll encapsulated afresh. Gives up with
+        /// `RunError::FallbackDue` `deadline_secs` after the call or at the sender's fallback
+        /// deadline, whichever comes first. Transient errors, such as a failed HTTP request, are
+        /// retried at the next poll rather than returned. Cancelling the call stops the send.
+        pub async fn run(
+            &self,
+            http: Arc<dyn HttpTransport>,
+            ohttp_relay: Arc<Url>,
+            poll_interval_secs: u64,
+            deadline_secs: u64,
+        ) -> Result<String, RunError> {
+            self.0
+                .run(http.as_ref(), (*ohttp_relay).clone(), poll_interval_secs, deadline_secs)
+                .await
+        }
+
+        /// Drive a v2 send to completion, polling on the schedule of `policy`.
+        ///
+        /// Transient errors are retried on the same schedule, and any other error is returned at
+        /// once. Gives up with `RunError::FallbackDue` once the schedule ends or at the sender's
+        /// fallback deadline, whichever comes first. Cancelling the call stops the send.
+        pub async fn run_with_policy(
+            &self,
+            http: Arc<dyn HttpTransport>,
+            ohttp_relay: Arc<Url>,
+            policy: Arc<PollingPolicy>,
+        ) -> Result<String, RunError> {
+            self.0.run_with_policy(http.as_ref(), (*ohttp_relay).clone(), &policy).await
+        }
+    }
 }
+#[cfg(feature = "async")]
+pub use asynchronous::*;
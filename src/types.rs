@@ -0,0 +1,101 @@
+use crate::error::FeeRateError;
+
+/// The highest fee rate [`FeeRate::from_sat_per_vb_f64`] accepts, far above any rate a
+/// transaction has paid, to catch unit mix-ups.
+pub const MAX_SAT_PER_VB: u64 = 1_000_000;
+
+/// A fee rate with an explicit unit, so sat/vB and sat/kwu values can't be mixed up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct FeeRate(pub(crate) payjoin::bitcoin::FeeRate);
+
+impl From<payjoin::bitcoin::FeeRate> for FeeRate {
+    fn from(value: payjoin::bitcoin::FeeRate) -> Self {
+        Self(value)
+    }
+}
+
+impl From<FeeRate> for payjoin::bitcoin::FeeRate {
+    fn from(value: FeeRate) -> Self {
+        value.0
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl FeeRate {
+    /// A fee rate in satoshis per virtual byte.
+    ///
+    /// Errors if the rate doesn't fit in sat/kwu, the unit fee rates are stored in.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn from_sat_per_vb(sat_per_vb: u64) -> Result<Self, FeeRateError> {
+        payjoin::bitcoin::FeeRate::from_sat_per_vb(sat_per_vb)
+            .map(Self)
+            .ok_or(FeeRateError::Overflow { sat_per_vb })
+    }
+
+    /// A fee rate in satoshis per virtual byte with a fractional part, e.g. 12.4 as reported by
+    /// mempool fee estimators.
+    ///
+    /// The rate is stored in sat/kwu, rounded up so it's never below `sat_per_vb`. NaN,
+    /// negative rates and rates above [`MAX_SAT_PER_VB`] are rejected.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn from_sat_per_vb_f64(sat_per_vb: f64) -> Result<Self, FeeRateError> {
+        if sat_per_vb.is_nan() {
+            return Err(FeeRateError::NotANumber);
+        }
+        if sat_per_vb < 0.0 {
+            return Err(FeeRateError::Negative);
+        }
+        if sat_per_vb > MAX_SAT_PER_VB as f64 {
+            return Err(FeeRateError::TooHigh { max_sat_per_vb: MAX_SAT_PER_VB });
+        }
+        // 1 vB is 4 WU, so 1 sat/vB is 250 sat/kwu.
+        let sat_per_kwu = (sat_per_vb * 250.0).ceil() as u64;
+        Ok(Self(payjoin::bitcoin::FeeRate::from_sat_per_kwu(sat_per_kwu)))
+    }
+
+    /// A fee rate in satoshis per 1000 weight units.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn from_sat_per_kwu(sat_per_kwu: u64) -> Self {
+        Self(payjoin::bitcoin::FeeRate::from_sat_per_kwu(sat_per_kwu))
+    }
+
+    /// The fee rate in satoshis per virtual byte, rounded up.
+    pub fn to_sat_per_vb_ceil(&self) -> u64 {
+        self.0.to_sat_per_vb_ceil()
+    }
+
+    pub fn to_sat_per_kwu(&self) -> u64 {
+        self.0.to_sat_per_kwu()
+    }
+}
+
+/// A transaction handed to a callback, with the encodings backends expect computed up front.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct TransactionView(pub(crate) payjoin::bitcoin::Transaction);
+
+impl From<payjoin::bitcoin::Transaction> for TransactionView {
+    fn from(value: payjoin::bitcoin::Transaction) -> Self {
+        Self(value)
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl TransactionView {
+    /// The consensus-encoded transaction.
+    pub fn bytes(&self) -> Vec<u8> {
+        payjoin::bitcoin::consensus::encode::serialize(&self.0)
+    }
+
+    /// The consensus-encoded transaction as lowercase hex, as taken by bitcoind's
+    /// `testmempoolaccept` and `sendrawtransaction`, Esplora and Electrum.
+    pub fn hex(&self) -> String {
+        payjoin::bitcoin::consensus::encode::serialize_hex(&self.0)
+    }
+
+    /// The transaction id, in the byte order block explorers display.
+    pub fn txid(&self) -> String {
+        self.0.compute_txid().to_string()
+    }
+}
@@ -0,0 +1,55 @@
+//! Conversion between satoshi amounts and the decimal BTC amounts used in BIP21 URIs.
+
+pub use super::error::InvalidAmount;
+
+/// Satoshis per bitcoin.
+const SATS_PER_BTC: u64 = 100_000_000;
+
+/// The largest amount a BIP21 URI may request, 21,000,000 BTC in satoshis.
+pub const MAX_AMOUNT_SATS: u64 = 21_000_000 * SATS_PER_BTC;
+
+/// Parse a BIP21 `amount` denominated in BTC into satoshis.
+///
+/// Only digits with an optional decimal point and at most 8 decimal places are accepted, up to
+/// 21,000,000 BTC.
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+pub fn parse_btc_amount(input: &str) -> Result<u64, InvalidAmount> {
+    let invalid = |reason: &str| InvalidAmount { input: input.to_string(), reason: reason.into() };
+    let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid("no digits"));
+    }
+    if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(invalid("non-digit character"));
+    }
+    if fraction.len() > 8 {
+        return Err(invalid("more than 8 decimal places"));
+    }
+    let digits = |digits: &str| {
+        digits.bytes().try_fold(0u64, |acc, b| acc.checked_mul(10)?.checked_add((b - b'0') as u64))
+    };
+    let fraction_sats = digits(fraction).map(|f| f * 10u64.pow(8 - fraction.len() as u32));
+    digits(whole)
+        .and_then(|btc| btc.checked_mul(SATS_PER_BTC))
+        .zip(fraction_sats)
+        .and_then(|(whole, fraction)| whole.checked_add(fraction))
+        .filter(|sats| *sats <= MAX_AMOUNT_SATS)
+        .ok_or_else(|| invalid("more than 21,000,000 BTC"))
+}
+
+/// Format a satoshi amount as a BIP21 `amount` denominated in BTC, without trailing zeros.
+///
+/// Formatting is exact for every `u64`, including amounts no URI may request.
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+pub fn format_btc_amount(sats: u64) -> String {
+    let (whole, fraction) = (sats / SATS_PER_BTC, sats % SATS_PER_BTC);
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    format!("{whole}.{fraction:08}").trim_end_matches('0').to_string()
+}
+
+/// The raw `amount` parameter of a BIP21 URI, if present.
+pub(crate) fn amount_param(uri: &str) -> Option<&str> {
+    super::query_param(uri, "amount")
+}
@@ -3,14 +3,54 @@
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct PjParseError {
     msg: String,
+    kind: Option<PjParseErrorKind>,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl PjParseError {
+    /// What was wrong with the URI, when a wallet can act on it.
+    pub fn kind(&self) -> Option<PjParseErrorKind> {
+        self.kind.clone()
+    }
 }
 
 impl From<String> for PjParseError {
     fn from(msg: String) -> Self {
-        PjParseError { msg }
+        PjParseError { msg, kind: None }
+    }
+}
+
+impl From<PjParseErrorKind> for PjParseError {
+    fn from(value: PjParseErrorKind) -> Self {
+        PjParseError { msg: value.to_string(), kind: Some(value) }
     }
 }
 
+/// A problem with a BIP21 URI that a wallet can report specifically, with the offending part
+/// of the URI.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum PjParseErrorKind {
+    /// The URI isn't a `bitcoin:` URI, e.g. a QR code of a website or another coin.
+    #[error("Not a bitcoin URI: {scheme:?}")]
+    InvalidScheme { scheme: String },
+    /// The URI's address can't be parsed.
+    #[error("Invalid address {address:?}")]
+    InvalidAddress { address: String },
+    /// The URI's address is for another network than the wallet's.
+    #[error("The address {address} is for another network")]
+    NetworkMismatch { address: String },
+    /// The URI has no `pj` parameter, so the receiver doesn't support payjoin.
+    #[error("The URI has no pj parameter")]
+    MissingPjParam,
+    /// The `amount` parameter isn't a valid BTC amount.
+    #[error("Invalid amount {amount:?}: {reason}")]
+    InvalidAmount { amount: String, reason: String },
+    /// The URI has a `req-` parameter that isn't understood, so BIP21 forbids paying it.
+    #[error("The URI requires the unsupported parameter {name}")]
+    UnsupportedRequiredParameter { name: String },
+}
+
 #[derive(Debug, PartialEq, Eq, thiserror::Error)]
 #[error("URI doesn't support payjoin: {msg}")]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
@@ -24,6 +64,20 @@ impl From<String> for PjNotSupported {
     }
 }
 
+/// Error replacing the OHTTP keys advertised by a payjoin URI.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Cannot replace the OHTTP keys of the pj endpoint: {msg}")]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct OhttpKeysOverrideError {
+    msg: String,
+}
+
+impl From<String> for OhttpKeysOverrideError {
+    fn from(msg: String) -> Self {
+        OhttpKeysOverrideError { msg }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error(transparent)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
@@ -33,3 +87,12 @@ pub struct UrlParseError(#[from] payjoin::ParseError);
 #[error(transparent)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
 pub struct IntoUrlError(#[from] payjoin::IntoUrlError);
+
+/// Error parsing a BIP21 amount denominated in BTC.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid amount {input:?}: {reason}")]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct InvalidAmount {
+    pub(crate) input: String,
+    pub(crate) reason: String,
+}
@@ -0,0 +1,124 @@
+//! BIP21 extras that keep the query parameters payjoin doesn't know alongside its own, so a URI
+//! is parsed and rendered by `bitcoin_uri` alone, e.g. with a `lightning` fallback.
+
+use std::borrow::Cow;
+
+use bitcoin_uri::de::{DeserializationError, DeserializationState, DeserializeParams, ParamKind};
+use bitcoin_uri::ser::SerializeParams;
+use bitcoin_uri::Param;
+use payjoin::bitcoin::address::{NetworkChecked, NetworkValidation};
+
+use super::error::PjParseErrorKind;
+
+/// The extras of a URI type, to name payjoin's without depending on where it exports them.
+pub(crate) trait UriExtras {
+    type Extras;
+}
+
+impl<N: NetworkValidation, E> UriExtras for bitcoin_uri::Uri<'_, N, E> {
+    type Extras = E;
+}
+
+/// payjoin's extras of a URI that supports payjoin.
+pub(crate) type PayjoinExtras = <payjoin::PjUri<'static> as UriExtras>::Extras;
+
+/// payjoin's extras of a URI that may or may not support payjoin.
+pub(crate) type MaybePayjoinExtras = <payjoin::Uri<'static, NetworkChecked> as UriExtras>::Extras;
+
+/// payjoin's extras `payjoin`, then the parameters it doesn't know, percent-decoded and in the
+/// order they appeared.
+#[derive(Clone)]
+pub(crate) struct Extras<E> {
+    pub(crate) payjoin: E,
+    pub(crate) unknown: Vec<(String, String)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ExtrasError {
+    #[error(transparent)]
+    Payjoin(<MaybePayjoinExtras as DeserializationError>::Error),
+    #[error(transparent)]
+    Kind(#[from] PjParseErrorKind),
+}
+
+impl DeserializationError for Extras<MaybePayjoinExtras> {
+    type Error = ExtrasError;
+}
+
+impl<'de> DeserializeParams<'de> for Extras<MaybePayjoinExtras> {
+    type DeserializationState = ExtrasState<'de>;
+}
+
+#[derive(Default)]
+pub(crate) struct ExtrasState<'de> {
+    payjoin: <MaybePayjoinExtras as DeserializeParams<'de>>::DeserializationState,
+    unknown: Vec<(String, String)>,
+}
+
+impl<'de> DeserializationState<'de> for ExtrasState<'de> {
+    type Value = Extras<MaybePayjoinExtras>;
+
+    fn is_param_known(&self, key: &str) -> bool {
+        self.payjoin.is_param_known(key)
+    }
+
+    /// Per BIP21 a URI with a `req-` parameter that isn't understood must not be paid, so it
+    /// fails with [`PjParseErrorKind::UnsupportedRequiredParameter`].
+    fn deserialize_temp(&mut self, key: &str, value: Param<'de>) -> Result<ParamKind, ExtrasError> {
+        if self.payjoin.is_param_known(key) {
+            return self.payjoin.deserialize_temp(key, value).map_err(ExtrasError::Payjoin);
+        }
+        if key.get(..4).is_some_and(|prefix| prefix.eq_ignore_ascii_case("req-")) {
+            let name = key.to_string();
+            return Err(PjParseErrorKind::UnsupportedRequiredParameter { name }.into());
+        }
+        self.unknown.push((key.to_string(), decode(value)));
+        Ok(ParamKind::Unknown)
+    }
+
+    fn finalize(self) -> Result<Self::Value, ExtrasError> {
+        let payjoin = self.payjoin.finalize().map_err(ExtrasError::Payjoin)?;
+        Ok(Extras { payjoin, unknown: self.unknown })
+    }
+}
+
+/// payjoin's parameters, then the unknown ones, which `bitcoin_uri` percent-encodes alike.
+impl<E> SerializeParams for &Extras<E>
+where
+    for<'a> &'a E: SerializeParams,
+{
+    type Key = String;
+    type Value = String;
+    type Iterator = std::vec::IntoIter<(String, String)>;
+
+    fn serialize_params(self) -> Self::Iterator {
+        let payjoin = self
+            .payjoin
+            .serialize_params()
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+        payjoin.chain(self.unknown.iter().cloned()).collect::<Vec<_>>().into_iter()
+    }
+}
+
+/// `uri` rendered with the `unknown` parameters after payjoin's.
+pub(crate) fn to_string<E>(
+    uri: &bitcoin_uri::Uri<'_, NetworkChecked, E>,
+    unknown: &[(String, String)],
+) -> String
+where
+    E: Clone,
+    for<'a> &'a E: SerializeParams,
+{
+    let extras = Extras { payjoin: uri.extras.clone(), unknown: unknown.to_vec() };
+    let mut with_unknown = bitcoin_uri::Uri::with_extras(uri.address.clone(), extras);
+    with_unknown.amount = uri.amount;
+    with_unknown.label = uri.label.clone();
+    with_unknown.message = uri.message.clone();
+    with_unknown.to_string()
+}
+
+/// The percent-decoded `value`, with invalid UTF-8 replaced.
+fn decode(value: Param<'_>) -> String {
+    let bytes: Cow<'_, [u8]> = value.into();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
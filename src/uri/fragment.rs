@@ -0,0 +1,71 @@
+//! The BIP77 parameters carried in the fragment of a v2 `pj` endpoint, e.g.
+//! `#RK1...+OH1...+EX1...`.
+//!
+//! Each parameter is a bech32 string without checksum, named by its human readable part.
+
+use payjoin::bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use payjoin::bitcoin::bech32::NoChecksum;
+
+/// The characters separating fragment parameters. Older receivers used `-`.
+const SEPARATORS: [char; 2] = ['+', '-'];
+
+/// The fragment parameter of `endpoint` with human readable part `hrp`, e.g. `RK1...` for
+/// `"rk"`.
+pub(crate) fn param<'a>(endpoint: &'a payjoin::Url, hrp: &str) -> Option<&'a str> {
+    endpoint.fragment()?.split(SEPARATORS).find(|param| has_hrp(param, hrp))
+}
+
+/// Replace the fragment parameter of `endpoint` with human readable part `hrp` by `value`,
+/// keeping the other parameters and their order. Returns `false`, leaving `endpoint` as it is,
+/// if there is no such parameter.
+pub(crate) fn set_param(endpoint: &mut payjoin::Url, hrp: &str, value: &str) -> bool {
+    let Some(fragment) = endpoint.fragment().filter(|_| param(endpoint, hrp).is_some()) else {
+        return false;
+    };
+    let separator = fragment.chars().find(|c| SEPARATORS.contains(c)).unwrap_or(SEPARATORS[0]);
+    let fragment = fragment
+        .split(SEPARATORS)
+        .map(|param| if has_hrp(param, hrp) { value } else { param })
+        .collect::<Vec<_>>()
+        .join(&separator.to_string());
+    endpoint.set_fragment(Some(&fragment));
+    true
+}
+
+/// The data of the fragment parameter of `endpoint` with human readable part `hrp`.
+fn param_bytes(endpoint: &payjoin::Url, hrp: &str) -> Option<Vec<u8>> {
+    let param = CheckedHrpstring::new::<NoChecksum>(param(endpoint, hrp)?).ok()?;
+    Some(param.byte_iter().collect())
+}
+
+fn has_hrp(param: &str, hrp: &str) -> bool {
+    CheckedHrpstring::new::<NoChecksum>(param).is_ok_and(|param| param.hrp().to_lowercase() == hrp)
+}
+
+/// The unix timestamp encoded in the `EX` parameter.
+pub(crate) fn expiry(endpoint: &payjoin::Url) -> Option<u64> {
+    let bytes: [u8; 4] = param_bytes(endpoint, "ex")?.try_into().ok()?;
+    Some(u32::from_be_bytes(bytes) as u64)
+}
+
+/// The receiver's HPKE public key from the `RK` parameter, in compressed SEC1 encoding.
+/// `None` if there is none or it isn't a valid public key.
+pub(crate) fn receiver_key(endpoint: &payjoin::Url) -> Option<[u8; 33]> {
+    let key = payjoin::bitcoin::secp256k1::PublicKey::from_slice(&param_bytes(endpoint, "rk")?);
+    key.ok().map(|key| key.serialize())
+}
+
+/// The receiver's HPKE public key from the `RK` parameter, base64url encoded without padding.
+/// `None` if there is none or it isn't a valid public key.
+pub(crate) fn receiver_pubkey(endpoint: &payjoin::Url) -> Option<String> {
+    use payjoin::bitcoin::base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use payjoin::bitcoin::base64::Engine;
+
+    Some(URL_SAFE_NO_PAD.encode(receiver_key(endpoint)?))
+}
+
+/// The directory's OHTTP keys from the `OH` parameter. `None` if there are none or they can't
+/// be parsed.
+pub(crate) fn ohttp_keys(endpoint: &payjoin::Url) -> Option<payjoin::OhttpKeys> {
+    param(endpoint, "oh")?.parse().ok()
+}
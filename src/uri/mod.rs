@@ -1,14 +1,30 @@
+use std::collections::HashMap;
 use std::str::FromStr;
-#[cfg(feature = "uniffi")]
 use std::sync::Arc;
 
-pub use error::{PjNotSupported, PjParseError, UrlParseError};
+pub use amount::{format_btc_amount, parse_btc_amount, MAX_AMOUNT_SATS};
+pub use error::{
+    InvalidAmount, OhttpKeysOverrideError, PjNotSupported, PjParseError, PjParseErrorKind,
+    UrlParseError,
+};
 use payjoin::bitcoin::address::NetworkChecked;
+use payjoin::bitcoin::Address;
 use payjoin::UriExt;
 
+use self::extras::{Extras, ExtrasError, MaybePayjoinExtras, PayjoinExtras};
+use crate::bitcoin_ffi::{Amount, Network};
+use crate::ohttp::OhttpKeys;
+
+pub mod amount;
 pub mod error;
+pub(crate) mod extras;
+pub(crate) mod fragment;
+
+/// A BIP21 URI, and the percent-decoded query parameters it carries that payjoin doesn't know,
+/// e.g. a `lightning` fallback.
 #[derive(Clone)]
-pub struct Uri(payjoin::Uri<'static, NetworkChecked>);
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct Uri(payjoin::Uri<'static, NetworkChecked>, Vec<(String, String)>);
 impl From<Uri> for payjoin::Uri<'static, NetworkChecked> {
     fn from(value: Uri) -> Self {
         value.0
@@ -17,16 +33,49 @@ impl From<Uri> for payjoin::Uri<'static, NetworkChecked> {
 
 impl From<payjoin::Uri<'static, NetworkChecked>> for Uri {
     fn from(value: payjoin::Uri<'static, NetworkChecked>) -> Self {
-        Uri(value)
+        Uri(value, vec![])
     }
 }
 
+#[cfg_attr(feature = "uniffi", uniffi::export)]
 impl Uri {
+    /// Parse a BIP21 URI, keeping the parameters payjoin doesn't know.
+    ///
+    /// Per BIP21 a URI with a `req-` parameter that isn't understood must not be paid, so it
+    /// fails with [`PjParseErrorKind::UnsupportedRequiredParameter`].
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
     pub fn parse(uri: String) -> Result<Self, PjParseError> {
-        match payjoin::Uri::from_str(uri.as_str()) {
-            Ok(e) => Ok(e.assume_checked().into()),
-            Err(e) => Err(e.to_string().into()),
+        if let Some(amount) = amount::amount_param(&uri) {
+            parse_btc_amount(amount).map_err(|e| {
+                PjParseErrorKind::InvalidAmount { amount: amount.to_string(), reason: e.reason }
+            })?;
         }
+        let uri = lowercase_keys(&uri);
+        let parsed =
+            match bitcoin_uri::Uri::<'static, _, Extras<MaybePayjoinExtras>>::from_str(&uri) {
+                Ok(parsed) => parsed.assume_checked(),
+                Err(bitcoin_uri::de::Error::Extras(ExtrasError::Kind(kind))) => {
+                    return Err(kind.into())
+                }
+                Err(e) => return Err(parse_error(&uri, &e)),
+            };
+        let Extras { payjoin, unknown } = parsed.extras;
+        let mut inner = payjoin::Uri::with_extras(parsed.address, payjoin);
+        inner.amount = parsed.amount;
+        inner.label = parsed.label;
+        inner.message = parsed.message;
+        Ok(Uri(inner, unknown))
+    }
+    /// Like [`Uri::parse`], also failing with [`PjParseErrorKind::NetworkMismatch`] if the
+    /// address isn't for `network`.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn parse_for_network(uri: String, network: Network) -> Result<Self, PjParseError> {
+        let parsed = Self::parse(uri)?;
+        if !parsed.0.address.as_unchecked().is_valid_for_network(network) {
+            let address = parsed.address();
+            return Err(PjParseErrorKind::NetworkMismatch { address }.into());
+        }
+        Ok(parsed)
     }
     pub fn address(&self) -> String {
         self.clone().0.address.to_string()
@@ -35,34 +84,138 @@ impl Uri {
     pub fn amount_sats(&self) -> Option<u64> {
         self.0.amount.map(|x| x.to_sat())
     }
+    /// Gets the amount, to convert to any unit.
+    #[cfg(not(feature = "uniffi"))]
+    pub fn amount(&self) -> Option<Amount> {
+        self.0.amount.map(Into::into)
+    }
+    /// Gets the amount, to convert to any unit.
+    #[cfg(feature = "uniffi")]
+    pub fn amount(&self) -> Option<Arc<Amount>> {
+        self.0.amount.map(|amount| Arc::new(amount.into()))
+    }
+    /// Gets the label for the payee, percent-decoded. `None` if absent or not valid UTF-8.
     pub fn label(&self) -> Option<String> {
         self.0.label.clone().and_then(|x| String::try_from(x).ok())
     }
+    /// Gets the message describing the payment, percent-decoded. `None` if absent or not valid
+    /// UTF-8.
     pub fn message(&self) -> Option<String> {
         self.0.message.clone().and_then(|x| String::try_from(x).ok())
     }
+    /// The query parameters payjoin doesn't know, percent-decoded.
+    pub fn extra_params(&self) -> HashMap<String, String> {
+        self.1.iter().cloned().collect()
+    }
+    /// Whether the URI has a `pj` parameter, i.e. [`Uri::check_pj_supported`] succeeds.
+    pub fn has_pj(&self) -> bool {
+        self.0.clone().check_pj_supported().is_ok()
+    }
     #[cfg(not(feature = "uniffi"))]
     pub fn check_pj_supported(&self) -> Result<PjUri, PjNotSupported> {
         match self.0.clone().check_pj_supported() {
-            Ok(e) => Ok(e.into()),
+            Ok(e) => Ok(PjUri(e, self.1.clone())),
             Err(uri) => Err(uri.to_string().into()),
         }
     }
     #[cfg(feature = "uniffi")]
     pub fn check_pj_supported(&self) -> Result<Arc<PjUri>, PjNotSupported> {
         match self.0.clone().check_pj_supported() {
-            Ok(e) => Ok(Arc::new(e.into())),
+            Ok(e) => Ok(Arc::new(PjUri(e, self.1.clone()))),
             Err(uri) => Err(uri.to_string().into()),
         }
     }
     pub fn as_string(&self) -> String {
-        self.0.clone().to_string()
+        extras::to_string(&self.0, &self.1)
+    }
+}
+
+/// `error`, payjoin's error parsing `uri`, with the kind of problem it reports.
+///
+/// An invalid address is recognized by its cause. A URI with another scheme fails before
+/// anything else is parsed, with no cause.
+fn parse_error(uri: &str, error: &(dyn std::error::Error + 'static)) -> PjParseError {
+    let (scheme, rest) = uri.split_once(':').unwrap_or(("", uri));
+    let mut cause = Some(error);
+    while let Some(e) = cause {
+        if e.is::<payjoin::bitcoin::address::ParseError>() {
+            let address = rest.split_once('?').map_or(rest, |(address, _)| address);
+            return PjParseErrorKind::InvalidAddress { address: address.to_string() }.into();
+        }
+        cause = e.source();
     }
+    if !scheme.eq_ignore_ascii_case("bitcoin") {
+        return PjParseErrorKind::InvalidScheme { scheme: scheme.to_string() }.into();
+    }
+    error.to_string().into()
+}
+
+/// `uri` with the names of its query parameters lowercased, since they are case-insensitive, e.g.
+/// `AMOUNT` in a URI from a QR code.
+fn lowercase_keys(uri: &str) -> String {
+    let Some((path, query)) = uri.split_once('?') else {
+        return uri.to_string();
+    };
+    let params: Vec<String> = query
+        .split('&')
+        .map(|param| {
+            match param.split_once('=') {
+                Some((key, value)) => format!("{}={value}", key.to_ascii_lowercase()),
+                None => param.to_ascii_lowercase(),
+            }
+        })
+        .collect();
+    format!("{path}?{}", params.join("&"))
+}
+
+/// `url`, a raw pj parameter, with the scheme and host uppercased.
+fn uppercase_origin(url: &str) -> String {
+    let lower = url.to_ascii_lowercase();
+    let Some(host_start) =
+        lower.find("://").map(|i| i + 3).or_else(|| lower.find("%3a%2f%2f").map(|i| i + 9))
+    else {
+        return url.to_string();
+    };
+    let host_end =
+        url[host_start..].find(['/', '?', '#', '%']).map_or(url.len(), |i| host_start + i);
+    format!("{}{}", url[..host_end].to_ascii_uppercase(), &url[host_end..])
+}
+
+/// `uri` with the hex digits of its percent escapes uppercased. A `%` not followed by two hex
+/// digits isn't an escape, so what follows it is kept as is.
+fn uppercase_escapes(uri: &str) -> String {
+    let mut bytes = uri.as_bytes().to_vec();
+    for i in 0..bytes.len().saturating_sub(2) {
+        if bytes[i] == b'%' && bytes[i + 1..i + 3].iter().all(u8::is_ascii_hexdigit) {
+            bytes[i + 1..i + 3].make_ascii_uppercase();
+        }
+    }
+    String::from_utf8(bytes).expect("only ASCII hex digits are changed")
+}
+
+/// Whether payment data sent to `endpoint` is encrypted in transit, as BIP78 requires: over
+/// HTTPS, or over HTTP to a Tor onion service.
+pub(crate) fn is_secure_endpoint(endpoint: &payjoin::Url) -> bool {
+    match endpoint.scheme() {
+        "https" => true,
+        "http" => endpoint.host_str().is_some_and(|host| host.ends_with(".onion")),
+        _ => false,
+    }
+}
+
+/// The raw value of the query parameter `name` of a BIP21 URI, if present.
+fn query_param<'a>(uri: &'a str, name: &str) -> Option<&'a str> {
+    let query = uri.split_once('?')?.1;
+    let query = query.split_once('#').map_or(query, |(query, _)| query);
+    query.split('&').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.eq_ignore_ascii_case(name).then_some(value)
+    })
 }
 
 impl From<payjoin::PjUri<'static>> for PjUri {
     fn from(value: payjoin::PjUri<'static>) -> Self {
-        Self(value)
+        Self(value, vec![])
     }
 }
 
@@ -74,10 +227,21 @@ impl<'a> From<PjUri> for payjoin::PjUri<'a> {
 
 #[derive(Clone)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
-pub struct PjUri(pub payjoin::PjUri<'static>);
+#[cfg_attr(feature = "uniffi", uniffi::export(Display, Eq, Hash))]
+pub struct PjUri(pub payjoin::PjUri<'static>, Vec<(String, String)>);
 
 #[cfg_attr(feature = "uniffi", uniffi::export)]
 impl PjUri {
+    /// Parse a BIP21 URI that supports payjoin, failing with a [`PjParseError`] whose `kind`
+    /// says what is wrong with it, e.g. [`PjParseErrorKind::MissingPjParam`].
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn parse(uri: String) -> Result<Self, PjParseError> {
+        let uri = Uri::parse(uri)?;
+        match uri.0.clone().check_pj_supported() {
+            Ok(pj_uri) => Ok(PjUri(pj_uri, uri.1)),
+            Err(_) => Err(PjParseErrorKind::MissingPjParam.into()),
+        }
+    }
     pub fn address(&self) -> String {
         self.0.clone().address.to_string()
     }
@@ -85,16 +249,332 @@ impl PjUri {
     pub fn amount_sats(&self) -> Option<u64> {
         self.0.clone().amount.map(|e| e.to_sat())
     }
+    /// Amount requested as payment, to convert to any unit
+    #[cfg(not(feature = "uniffi"))]
+    pub fn amount(&self) -> Option<Amount> {
+        self.0.amount.map(Into::into)
+    }
+    /// Amount requested as payment, to convert to any unit
+    #[cfg(feature = "uniffi")]
+    pub fn amount(&self) -> Option<Arc<Amount>> {
+        self.0.amount.map(|amount| Arc::new(amount.into()))
+    }
+    /// Label for the payee, percent-decoded. `None` if absent or not valid UTF-8.
+    pub fn label(&self) -> Option<String> {
+        self.0.label.clone().and_then(|x| String::try_from(x).ok())
+    }
+    /// Message describing the payment, percent-decoded. `None` if absent or not valid UTF-8.
+    pub fn message(&self) -> Option<String> {
+        self.0.message.clone().and_then(|x| String::try_from(x).ok())
+    }
 
     pub fn pj_endpoint(&self) -> String {
         self.0.extras.endpoint().to_string()
     }
 
+    /// Always `true`, for code that handles [`Uri`]s and `PjUri`s alike.
+    pub fn has_pj(&self) -> bool {
+        true
+    }
+
+    /// This URI if its address is for `network`, or a [`PjParseErrorKind::NetworkMismatch`].
+    ///
+    /// Testnet, signet and regtest share base58 prefixes, and all but regtest share
+    /// the `tb` bech32 prefix, so those addresses are accepted for each network they share.
+    pub fn require_network(&self, network: Network) -> Result<PjUri, PjParseError> {
+        if !self.0.address.as_unchecked().is_valid_for_network(network) {
+            let address = self.address();
+            return Err(PjParseErrorKind::NetworkMismatch { address }.into());
+        }
+        Ok(self.clone())
+    }
+
+    /// The network of the URI's address, if only one network uses its prefix: mainnet, or
+    /// regtest for `bcrt` bech32 addresses. `None` for test network addresses shared between
+    /// networks, see [`PjUri::require_network`].
+    pub fn network(&self) -> Option<Network> {
+        let address = self.0.address.as_unchecked();
+        if address.is_valid_for_network(Network::Bitcoin) {
+            Some(Network::Bitcoin)
+        } else if !address.is_valid_for_network(Network::Testnet) {
+            Some(Network::Regtest)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the receiver allows output substitution, i.e. the URI has no `pjos=0`.
+    pub fn output_substitution_allowed(&self) -> bool {
+        query_param(&self.as_string(), "pjos") != Some("0")
+    }
+
+    /// The unix timestamp in seconds at which the receiver's v2 session expires, from the `EX`
+    /// parameter of the endpoint. `None` for v1 endpoints or an expiry that can't be parsed.
+    pub fn session_expiry(&self) -> Option<u64> {
+        fragment::expiry(self.0.extras.endpoint())
+    }
+
+    /// The directory's OHTTP keys from the `OH` parameter of a v2 endpoint. `None` for v1
+    /// endpoints or keys that can't be parsed.
+    #[cfg(not(feature = "uniffi"))]
+    pub fn ohttp_keys(&self) -> Option<OhttpKeys> {
+        fragment::ohttp_keys(self.0.extras.endpoint()).map(Into::into)
+    }
+    /// The directory's OHTTP keys from the `OH` parameter of a v2 endpoint. `None` for v1
+    /// endpoints or keys that can't be parsed.
+    #[cfg(feature = "uniffi")]
+    pub fn ohttp_keys(&self) -> Option<Arc<OhttpKeys>> {
+        fragment::ohttp_keys(self.0.extras.endpoint()).map(|keys| Arc::new(keys.into()))
+    }
+
+    /// The receiver's HPKE public key from the `RK` parameter of a v2 endpoint, base64url
+    /// encoded without padding. `None` for v1 endpoints or a key that can't be parsed.
+    pub fn receiver_pubkey(&self) -> Option<String> {
+        fragment::receiver_pubkey(self.0.extras.endpoint())
+    }
+
+    /// The URI rendered for a QR code, where uppercase fits the compact alphanumeric mode.
+    ///
+    /// Only the case-insensitive parts are uppercased: the scheme, a bech32 address, the
+    /// parameter names, the scheme and host of the pj endpoint, and the hex digits of percent
+    /// escapes. The endpoint's path and the other parameter values are kept as they are.
+    pub fn to_qr_string(&self) -> String {
+        let uri = self.as_string();
+        let rest = uri.split_once(':').map_or(uri.as_str(), |(_, rest)| rest);
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let address = match self.0.address.witness_program() {
+            Some(_) => address.to_ascii_uppercase(),
+            None => address.to_string(),
+        };
+        let params: Vec<String> = query
+            .split('&')
+            .map(|param| {
+                match param.split_once('=') {
+                    Some((key, value)) if key.eq_ignore_ascii_case("pj") => {
+                        format!("PJ={}", uppercase_origin(value))
+                    }
+                    Some((key, value)) => format!("{}={value}", key.to_ascii_uppercase()),
+                    None => param.to_ascii_uppercase(),
+                }
+            })
+            .collect();
+        let mut qr = format!("BITCOIN:{address}");
+        if !query.is_empty() {
+            qr.push('?');
+            qr.push_str(&params.join("&"));
+        }
+        uppercase_escapes(&qr)
+    }
+
+    /// The query parameters payjoin doesn't know, percent-decoded, e.g. a `lightning`
+    /// fallback. They are percent-encoded again when the URI is rendered with `as_string`.
+    pub fn extra_params(&self) -> HashMap<String, String> {
+        self.1.iter().cloned().collect()
+    }
+
+    /// The URI with its percent escapes normalized, the same as its `Display` form.
     pub fn as_string(&self) -> String {
-        self.0.clone().to_string()
+        self.to_string()
+    }
+}
+
+/// Escapes are written with uppercase hex digits, so URIs that differ only in the case of their
+/// escapes, e.g. a parsed `%c3%a9` and a built `%C3%A9`, render alike.
+impl std::fmt::Display for PjUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&uppercase_escapes(&extras::to_string(&self.0, &self.1)))
+    }
+}
+
+/// URIs are equal when their normalized `Display` forms are.
+impl PartialEq for PjUri {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for PjUri {}
+
+impl std::hash::Hash for PjUri {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_string().hash(state)
+    }
+}
+
+/// Builds the payjoin URI of a receiver session, from `Receiver::pj_uri_builder`.
+///
+/// The setters chain, each returning a new builder. Labels and messages are percent-encoded when
+/// the URI is rendered.
+#[derive(Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct PjUriBuilder(BuilderCore<PayjoinExtras>);
+
+impl From<payjoin::PjUri<'static>> for PjUriBuilder {
+    fn from(value: payjoin::PjUri<'static>) -> Self {
+        Self(BuilderCore(value))
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl PjUriBuilder {
+    /// Request `sats`, rendered exactly as decimal BTC. Amounts above 21,000,000 BTC are
+    /// rejected.
+    pub fn amount(&self, sats: u64) -> Result<Arc<Self>, InvalidAmount> {
+        Ok(Arc::new(Self(self.0.with_amount(sats)?)))
+    }
+    /// Request a decimal BTC amount, e.g. `"0.00123456"`, parsed exactly as by
+    /// `parse_btc_amount`. Prefer this to converting a float to satoshis.
+    pub fn amount_btc(&self, btc: String) -> Result<Arc<Self>, InvalidAmount> {
+        self.amount(parse_btc_amount(&btc)?)
+    }
+    /// Label the payee, e.g. with the receiver's name.
+    pub fn label(&self, label: String) -> Arc<Self> {
+        Arc::new(Self(self.0.with_label(label)))
+    }
+    /// Describe the payment to the sender.
+    pub fn message(&self, message: String) -> Arc<Self> {
+        Arc::new(Self(self.0.with_message(message)))
+    }
+    /// Replace the OHTTP keys in the `pj` endpoint, e.g. after the directory rotated its keys
+    /// mid-session. Fails for endpoints without an `OH` parameter.
+    pub fn override_ohttp_keys(
+        &self,
+        ohttp_keys: &OhttpKeys,
+    ) -> Result<Arc<Self>, OhttpKeysOverrideError> {
+        let mut endpoint = self.0 .0.extras.endpoint().clone();
+        let keys = ohttp_keys.0.to_string().to_uppercase();
+        if !fragment::set_param(&mut endpoint, "oh", &keys) {
+            return Err("the endpoint has no OH parameter".to_string().into());
+        }
+        Ok(Arc::new(self.with_endpoint(&endpoint)?))
+    }
+    /// The OHTTP keys the built URI will advertise, from the `OH` parameter of the endpoint.
+    #[cfg(not(feature = "uniffi"))]
+    pub fn ohttp_keys(&self) -> Option<OhttpKeys> {
+        fragment::ohttp_keys(self.0 .0.extras.endpoint()).map(Into::into)
+    }
+    /// The OHTTP keys the built URI will advertise, from the `OH` parameter of the endpoint.
+    #[cfg(feature = "uniffi")]
+    pub fn ohttp_keys(&self) -> Option<Arc<OhttpKeys>> {
+        fragment::ohttp_keys(self.0 .0.extras.endpoint()).map(|keys| Arc::new(keys.into()))
+    }
+    /// The unix timestamp in seconds at which the session expires, from the `EX` parameter of
+    /// the endpoint.
+    pub fn expiry(&self) -> Option<u64> {
+        fragment::expiry(self.0 .0.extras.endpoint())
+    }
+    pub fn build(&self) -> PjUri {
+        self.0 .0.clone().into()
+    }
+}
+
+impl PjUriBuilder {
+    /// This URI with the `pj` parameter set to `endpoint`, keeping the other parameters.
+    fn with_endpoint(&self, endpoint: &payjoin::Url) -> Result<Self, OhttpKeysOverrideError> {
+        let uri = &self.0 .0;
+        let pj: String =
+            url::form_urlencoded::byte_serialize(endpoint.as_str().as_bytes()).collect();
+        let mut rebuilt = format!("bitcoin:{}?pj={pj}", uri.address);
+        if let Some(pjos) = query_param(&uri.to_string(), "pjos") {
+            rebuilt.push_str(&format!("&pjos={pjos}"));
+        }
+        let mut pj_uri = payjoin::Uri::from_str(&rebuilt)
+            .map_err(|e| e.to_string())?
+            .assume_checked()
+            .check_pj_supported()
+            .map_err(|_| "the URI lost its pj parameter".to_string())?;
+        pj_uri.amount = uri.amount;
+        pj_uri.label = uri.label.clone();
+        pj_uri.message = uri.message.clone();
+        Ok(Self(BuilderCore(pj_uri)))
+    }
+}
+
+/// Builds a plain BIP21 URI without payjoin, e.g. to show a payment request when no receive
+/// session could be created.
+///
+/// Its setters are those of [`PjUriBuilder`], validating and encoding alike, so the URI reads
+/// the same as a payjoin one without its `pj` parameters.
+#[derive(Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct Bip21Builder(BuilderCore<MaybePayjoinExtras>);
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl Bip21Builder {
+    /// A builder for a URI paying `address`, with no amount, label or message yet.
+    #[cfg(not(feature = "uniffi"))]
+    pub fn new(address: crate::bitcoin_ffi::Address) -> Self {
+        Self::for_address(address.into())
+    }
+    /// A builder for a URI paying `address`, with no amount, label or message yet.
+    #[cfg(feature = "uniffi")]
+    #[uniffi::constructor]
+    pub fn new(address: Arc<crate::bitcoin_ffi::Address>) -> Self {
+        Self::for_address((*address).clone().into())
+    }
+    /// Request `sats`, rendered exactly as decimal BTC. Amounts above 21,000,000 BTC are
+    /// rejected.
+    pub fn amount(&self, sats: u64) -> Result<Arc<Self>, InvalidAmount> {
+        Ok(Arc::new(Self(self.0.with_amount(sats)?)))
+    }
+    /// Request a decimal BTC amount, e.g. `"0.00123456"`, parsed exactly as by
+    /// `parse_btc_amount`.
+    pub fn amount_btc(&self, btc: String) -> Result<Arc<Self>, InvalidAmount> {
+        self.amount(parse_btc_amount(&btc)?)
+    }
+    /// Label the payee, e.g. with the receiver's name.
+    pub fn label(&self, label: String) -> Arc<Self> {
+        Arc::new(Self(self.0.with_label(label)))
+    }
+    /// Describe the payment to the sender.
+    pub fn message(&self, message: String) -> Arc<Self> {
+        Arc::new(Self(self.0.with_message(message)))
+    }
+    pub fn build(&self) -> Uri {
+        self.0 .0.clone().into()
+    }
+}
+
+impl Bip21Builder {
+    fn for_address(address: Address<NetworkChecked>) -> Self {
+        Self(BuilderCore(bitcoin_uri::Uri::with_extras(address, MaybePayjoinExtras::Unsupported)))
+    }
+}
+
+/// The URI under construction by [`PjUriBuilder`] or [`Bip21Builder`], with the amount, label
+/// and message setters they share.
+#[derive(Clone)]
+struct BuilderCore<E>(bitcoin_uri::Uri<'static, NetworkChecked, E>);
+
+impl<E: Clone> BuilderCore<E> {
+    fn with_amount(&self, sats: u64) -> Result<Self, InvalidAmount> {
+        let mut uri = self.0.clone();
+        uri.amount = Some(requested_amount(sats)?);
+        Ok(Self(uri))
+    }
+    fn with_label(&self, label: String) -> Self {
+        let mut uri = self.0.clone();
+        uri.label = Some(label.into());
+        Self(uri)
+    }
+    fn with_message(&self, message: String) -> Self {
+        let mut uri = self.0.clone();
+        uri.message = Some(message.into());
+        Self(uri)
     }
 }
 
+/// `sats` as the amount of a payment request, if a URI may request it.
+fn requested_amount(sats: u64) -> Result<payjoin::bitcoin::Amount, InvalidAmount> {
+    if sats > MAX_AMOUNT_SATS {
+        return Err(InvalidAmount {
+            input: sats.to_string(),
+            reason: "more than 21,000,000 BTC".to_string(),
+        });
+    }
+    Ok(payjoin::bitcoin::Amount::from_sat(sats))
+}
+
 impl From<payjoin::Url> for Url {
     fn from(value: payjoin::Url) -> Self {
         Self(value)
@@ -109,6 +589,7 @@ impl From<Url> for payjoin::Url {
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+#[cfg_attr(feature = "uniffi", uniffi::export(Display, Eq, Hash))]
 pub struct Url(payjoin::Url);
 
 #[cfg_attr(feature = "uniffi", uniffi::export)]
@@ -120,7 +601,81 @@ impl Url {
     pub fn query(&self) -> Option<String> {
         self.0.query().map(|x| x.to_string())
     }
+    /// The query parameters, percent-decoded, in order. Keys may repeat.
+    pub fn query_pairs(&self) -> Vec<QueryParam> {
+        self.0
+            .query_pairs()
+            .map(|(key, value)| QueryParam { key: key.into_owned(), value: value.into_owned() })
+            .collect()
+    }
+    /// A copy of the URL with the query parameter `key` set to `value`, replacing any values it
+    /// had and keeping the other parameters in order.
+    pub fn set_query_param(&self, key: String, value: String) -> Url {
+        let mut url = self.0.clone();
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(k, _)| *k != key)
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs).append_pair(&key, &value);
+        Url(url)
+    }
+    /// Resolve `path` against the URL, e.g. `health` against `https://directory.example/` gives
+    /// `https://directory.example/health`.
+    ///
+    /// As with relative links, a base without a trailing slash has its last segment replaced.
+    pub fn join(&self, path: String) -> Result<Url, UrlParseError> {
+        self.0.join(&path).map(Url).map_err(Into::into)
+    }
+    /// The host, e.g. to log which relay a request went through without its session path.
+    pub fn host(&self) -> Option<String> {
+        self.0.host_str().map(str::to_string)
+    }
+    pub fn scheme(&self) -> String {
+        self.0.scheme().to_string()
+    }
+    /// The URL in normalized form, the same as its `Display` form.
     pub fn as_string(&self) -> String {
-        self.0.to_string()
+        self.to_string()
+    }
+}
+
+/// Parsing already lowercases the scheme and host, drops default ports and gives an empty path
+/// a `/`. Escapes are also written with uppercase hex digits, so that `Url::from_str` of the
+/// output gives back an equal `Url`.
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&uppercase_escapes(self.0.as_str()))
+    }
+}
+
+impl FromStr for Url {
+    type Err = UrlParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Url::parse(s.to_string())
     }
 }
+
+/// URLs are equal when their normalized `Display` forms are.
+impl PartialEq for Url {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for Url {}
+
+impl std::hash::Hash for Url {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_string().hash(state)
+    }
+}
+
+/// A query parameter of a [`Url`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct QueryParam {
+    pub key: String,
+    pub value: String,
+}
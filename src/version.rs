@@ -0,0 +1,39 @@
+/// The version of the payjoin-ffi library that was loaded.
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+pub fn library_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Fail fast if foreign bindings were generated against an incompatible version of this library.
+///
+/// `bindings_version` is the payjoin-ffi version baked into the bindings when they were
+/// generated. Versions are compatible when they share a major version, or a minor version while
+/// the major version is 0, since calling into a library with a different layout corrupts memory.
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+pub fn check_bindings_version(bindings_version: String) -> Result<(), BindingsMismatch> {
+    let library = library_version();
+    if compatibility_series(&bindings_version) == compatibility_series(&library) {
+        Ok(())
+    } else {
+        Err(BindingsMismatch { bindings: bindings_version, library })
+    }
+}
+
+/// The leading version components that must match for two versions to be ABI compatible.
+fn compatibility_series(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version.split('.').map(str::parse::<u64>);
+    match (parts.next()?.ok()?, parts.next()?.ok()?) {
+        (0, minor) => Some((0, minor)),
+        (major, _) => Some((major, 0)),
+    }
+}
+
+/// Error returned when foreign bindings and the loaded library versions are incompatible.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Bindings generated for payjoin-ffi {bindings} cannot call into payjoin-ffi {library}")]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+#[cfg_attr(feature = "uniffi", uniffi::export(Display))]
+pub struct BindingsMismatch {
+    bindings: String,
+    library: String,
+}
@@ -7,6 +7,8 @@ The tests simulate a full cycle of PayJoin transactions, including wallet initia
 */
 #![cfg(all(feature = "_danger-local-https", not(feature = "uniffi")))]
 
+extern crate payjoin_ffi_v0_22 as payjoin_ffi;
+
 use std::str::FromStr;
 use std::sync::{Mutex, MutexGuard};
 
@@ -223,10 +225,15 @@ mod v2 {
 
     use bdk::wallet::AddressIndex;
     use bitcoin_ffi::{Address, Network};
-    use payjoin_ffi::receive::{PayjoinProposal, Receiver, UncheckedProposal};
-    use payjoin_ffi::send::SenderBuilder;
+    use payjoin_ffi::receive::session::ReceiveSession;
+    use payjoin_ffi::receive::{
+        BatchCheckError, FinalizeError, InputSeenError, KeysSource, PayjoinProposal, Receiver,
+        ReceiverResponse, ReviewError, ScheduledBroadcast, UncheckedProposal,
+        INVALID_CALLBACK_PSBT,
+    };
+    use payjoin_ffi::send::{ExtractedRequest, SenderBuilder, SenderPoller, V2GetContext};
     use payjoin_ffi::uri::Uri;
-    use payjoin_ffi::{OhttpKeys, Request};
+    use payjoin_ffi::{DirectoryCapabilities, OhttpKeys, Request};
     use payjoin_test_utils::TestServices;
 
     use super::*;
@@ -251,17 +258,32 @@ mod v2 {
             let directory = services.directory_url();
             services.wait_for_services_ready().await?;
             let ohttp_keys = services.fetch_ohttp_keys().await?;
+            let body = ohttp_keys.encode()?;
+            assert_eq!(OhttpKeys::decode(body.clone())?.to_bytes()?, body);
 
             let address = receiver.get_address(AddressIndex::New);
             let session = Receiver::new(
                 Address::new(address.to_string(), Network::Regtest).unwrap(),
                 directory.to_string(),
-                OhttpKeys(ohttp_keys),
+                OhttpKeys(ohttp_keys.clone()),
                 None,
             )?;
+            assert!(!session.is_expired());
+            assert!(session.expiration().is_some());
+            assert_eq!(session.keys_source(), KeysSource::Provided);
+            let session = session.with_keys_fetched_at(1_700_000_000);
+            assert_eq!(session.keys_source(), KeysSource::Fetched { at: 1_700_000_000 });
+            assert_eq!(session.directory().as_string(), directory.to_string());
+            assert_eq!(session.ohttp_keys().unwrap().0.encode()?, ohttp_keys.encode()?);
+            let session = session.refresh_ohttp_keys(OhttpKeys(ohttp_keys), 1_700_000_060)?;
+            assert_eq!(session.keys_source(), KeysSource::Refreshed { at: 1_700_000_060 });
             let ohttp_relay = services.ohttp_relay_url();
+            let long_poll =
+                DirectoryCapabilities { long_poll: true, ..DirectoryCapabilities::legacy() };
             // Poll receive request
-            let (request, client_response) = session.extract_req(ohttp_relay.to_string())?;
+            let (request, client_response) =
+                session.extract_req_with_wait(ohttp_relay.to_string(), 30, &long_poll)?;
+            assert_eq!(request.timeout_secs, Some(30));
             let response = agent
                 .post(request.url.as_string())
                 .header("Content-Type", request.content_type)
@@ -272,19 +294,50 @@ mod v2 {
             let response_body =
                 session.process_res(&response.bytes().await?, &client_response).unwrap();
             // No proposal yet since sender has not responded
-            assert!(response_body.is_none());
+            assert!(matches!(response_body, ReceiverResponse::NoProposalYet));
+
+            // Persist the session mid-poll and resume from the restored copy, as an app that was
+            // killed while waiting for the sender would
+            let session = Receiver::from_json(&session.to_json()?)?;
+            assert_eq!(session.keys_source(), KeysSource::Refreshed { at: 1_700_000_060 });
 
             // **********************
             // Inside the Sender:
             // Create a funded PSBT (not broadcasted) to address with amount given in the pj_uri
-            let pj_uri =
-                Uri::parse(session.pj_uri().as_string()).unwrap().check_pj_supported().unwrap();
+            let pj_uri = session
+                .pj_uri(
+                    Some(100_000_000),
+                    Some("Coffee shop".to_string()),
+                    Some("Order 42".to_string()),
+                )
+                .unwrap();
+            let uri = Uri::parse(pj_uri.as_string()).unwrap();
+            assert_eq!(uri.amount_sats(), Some(100_000_000));
+            assert_eq!(uri.label().as_deref(), Some("Coffee shop"));
+            assert_eq!(uri.message().as_deref(), Some("Order 42"));
+            assert!(session.pj_uri(Some(u64::MAX), None, None).is_err());
+            let updated = session.update_pj_uri(50_000).unwrap();
+            assert_eq!(updated.amount_sats(), Some(50_000));
+            assert_eq!(updated.pj_endpoint(), pj_uri.pj_endpoint());
+            assert!(session.update_pj_uri(0).is_err());
+            assert!(session.update_pj_uri(u64::MAX).is_err());
+            let pj_uri = uri.check_pj_supported().unwrap();
+            let pj_uri_endpoint = pj_uri.pj_endpoint();
             let psbt = build_original_psbt(&sender, &pj_uri)?;
             println!("\nOriginal sender psbt: {:#?}", psbt.to_string());
 
             let req_ctx = SenderBuilder::new(psbt.to_string(), pj_uri)?
-                .build_recommended(payjoin::bitcoin::FeeRate::BROADCAST_MIN.to_sat_per_kwu())?;
-            let (request, context) = req_ctx.extract_v2(ohttp_relay.to_owned().into())?;
+                .build_recommended_with_fee_rate(payjoin_ffi::FeeRate::from_sat_per_kwu(
+                    payjoin::bitcoin::FeeRate::BROADCAST_MIN.to_sat_per_kwu(),
+                ))?;
+            assert!(req_ctx.can_use_v2());
+            assert!(req_ctx.ohttp_keys().is_some());
+            assert_eq!(req_ctx.endpoint().as_string(), pj_uri_endpoint);
+            let ExtractedRequest::V2 { request, context } =
+                req_ctx.extract_highest_version(ohttp_relay.to_owned().into())?
+            else {
+                panic!("a v2 endpoint should yield a v2 request");
+            };
             let response = agent
                 .post(request.url.as_string())
                 .header("Content-Type", request.content_type)
@@ -298,6 +351,23 @@ mod v2 {
             // **********************
             // Inside the Receiver:
 
+            // The receiver process fetches the proposal but is killed before decapsulating it.
+            // Its OHTTP context dies with it, so only the persisted session survives.
+            let persisted = session.to_json()?;
+            let (request, client_response) = session.extract_req(ohttp_relay.to_string())?;
+            let response = agent
+                .post(request.url.as_string())
+                .header("Content-Type", request.content_type)
+                .body(request.body)
+                .send()
+                .await?;
+            assert!(response.status().is_success());
+            drop((session, client_response, response));
+
+            // A new process restores the session and polls again with a fresh request, which
+            // the directory answers with the same proposal
+            let session = Receiver::from_json(&persisted)?;
+
             // GET fallback psbt
             let (request, client_response) = session.extract_req(ohttp_relay.to_string())?;
             let response = agent
@@ -306,9 +376,11 @@ mod v2 {
                 .body(request.body)
                 .send()
                 .await?;
-            let proposal = session
-                .process_res(&response.bytes().await?, &client_response)?
-                .expect("proposal should exist");
+            let ReceiverResponse::Proposal(proposal) =
+                session.process_res(&response.bytes().await?, &client_response)?
+            else {
+                panic!("proposal should exist");
+            };
             let payjoin_proposal = handle_directory_proposal(receiver, proposal);
             let (request, client_response) =
                 payjoin_proposal.extract_v2_req(ohttp_relay.to_string())?;
@@ -322,18 +394,46 @@ mod v2 {
 
             // **********************
             // Inside the Sender:
+            // The sender process restarts while polling. It restores the persisted context and
+            // keeps polling without posting the Original PSBT again.
+            let persisted = send_ctx.to_json()?;
+            drop(send_ctx);
+            let send_ctx = V2GetContext::from_json(&persisted)?;
+
             // Sender checks, signs, finalizes, extracts, and broadcasts
             // Replay post fallback to get the response
-            let (Request { url, body, content_type, .. }, ohttp_ctx) =
-                send_ctx.extract_req(ohttp_relay.to_string())?;
+            let send_ctx = Arc::new(send_ctx);
+            let poller = SenderPoller::new(send_ctx.clone(), ohttp_relay.to_string());
+            let (_, stale_ctx) = poller.next_request()?;
+            let (Request { url, body, content_type, .. }, ohttp_ctx) = poller.next_request()?;
             let response = agent
                 .post(url.as_string())
                 .header("Content-Type", content_type)
                 .body(body)
                 .send()
                 .await?;
+            let response = response.bytes().await?;
+            assert!(matches!(
+                poller.handle_response(&response, &stale_ctx),
+                Err(payjoin_ffi::send::ResponseError::StaleOhttpContext)
+            ));
             let checked_payjoin_proposal_psbt =
-                send_ctx.process_response(&response.bytes().await?, &ohttp_ctx)?.unwrap();
+                poller.handle_response(&response, &ohttp_ctx)?.unwrap();
+            // The directory keeps serving the proposal, so poll it again for the checked view
+            let (Request { url, body, content_type, .. }, ohttp_ctx) = poller.next_request()?;
+            let response = agent
+                .post(url.as_string())
+                .header("Content-Type", content_type)
+                .body(body)
+                .send()
+                .await?;
+            let response = response.bytes().await?;
+            let checked_proposal =
+                send_ctx.process_response_checked(&response, &ohttp_ctx)?.unwrap();
+            assert_eq!(checked_proposal.psbt(), checked_payjoin_proposal_psbt);
+            assert_eq!(checked_proposal.receiver_contributed_inputs(), Some(1));
+            assert!(checked_proposal.additional_fee_paid_by_sender().is_some());
+            assert_eq!(checked_proposal.outputs_substituted(), Some(true));
             let payjoin_tx = extract_pj_tx(&sender, checked_payjoin_proposal_psbt.as_str())?;
             blockchain_client.broadcast(payjoin_tx).unwrap();
             Ok(())
@@ -342,25 +442,144 @@ mod v2 {
 
     fn handle_directory_proposal(receiver: Wallet, proposal: UncheckedProposal) -> PayjoinProposal {
         // in a payment processor where the sender could go offline, this is where you schedule to broadcast the original_tx
-        let _to_broadcast_in_failure_case = proposal.extract_tx_to_schedule_broadcast();
+        let to_broadcast_in_failure_case = proposal.extract_tx_to_schedule_broadcast();
+        let fallback = proposal.fallback_transaction();
+        assert_eq!(fallback.bytes, to_broadcast_in_failure_case);
+        let fallback_tx: Transaction =
+            bdk::bitcoin::consensus::deserialize(&to_broadcast_in_failure_case).unwrap();
+        assert_eq!(fallback.txid, fallback_tx.txid().to_string());
+        assert_eq!(fallback.weight, fallback_tx.weight().to_wu());
+        let scheduled = proposal.schedule_broadcast(None);
+        assert_eq!(scheduled.fallback_transaction(), fallback);
+        assert!(!scheduled.should_broadcast_now(scheduled.broadcast_at() - 1));
+        assert!(scheduled.should_broadcast_now(scheduled.broadcast_at()));
+        let restored = ScheduledBroadcast::from_json(&scheduled.to_json().unwrap()).unwrap();
+        assert_eq!(restored, scheduled);
+        assert!(!proposal.is_output_substitution_disabled());
+        let session = ReceiveSession::from(proposal.clone());
+        assert!(session.is_v2() && session.v1().is_none());
+        assert_eq!(session.extract_tx_to_schedule_broadcast(), to_broadcast_in_failure_case);
 
         // Receive Check 1: Can Broadcast
+        proposal
+            .check_broadcast_suitability_with_view(None, |tx| {
+                assert_eq!(tx.bytes(), to_broadcast_in_failure_case);
+                assert_eq!(tx.hex(), bdk::bitcoin::consensus::encode::serialize_hex(&fallback_tx));
+                assert_eq!(tx.txid(), fallback.txid);
+                Ok(true)
+            })
+            .expect("the Original PSBT should be broadcastable");
         let proposal = proposal.assume_interactive_receiver();
         let receiver = Arc::new(receiver);
+        let original_psbt = proposal.psbt();
+        assert_eq!(
+            PartiallySignedTransaction::from_str(&original_psbt).unwrap().extract_tx().txid(),
+            bdk::bitcoin::consensus::deserialize::<Transaction>(&to_broadcast_in_failure_case)
+                .unwrap()
+                .txid()
+        );
         // Receive Check 2: receiver can't sign for proposal inputs
+        let timeout = ImplementationError::new(
+            "wallet RPC timed out".to_string(),
+            Some("rpc-timeout".to_string()),
+        );
+        let err = match proposal.check_inputs_not_owned(|_| Err(timeout.clone())) {
+            Err(err) => err,
+            Ok(_) => panic!("the callback's error should fail the check"),
+        };
+        let returned = err.implementation_error().expect("the callback's error should be kept");
+        assert_eq!(returned.code().as_deref(), Some("rpc-timeout"));
+        assert_eq!(returned.message(), "wallet RPC timed out");
+        assert!(matches!(
+            proposal.check_inputs_not_owned_batch(|_| Ok(vec![])),
+            Err(BatchCheckError::AnswerCountMismatch { actual: 0, .. })
+        ));
+        assert!(proposal
+            .check_inputs_not_owned_batch(|scripts| {
+                scripts.into_iter().map(|script| is_script_owned(&receiver, script)).collect()
+            })
+            .is_ok());
         let proposal = proposal
             .check_inputs_not_owned(|script| is_script_owned(&receiver, script.clone()))
             .expect("Receiver should not own any of the inputs");
 
         // Receive Check 3: have we seen this input before? More of a check for non-interactive i.e. payment processor receivers.
-        let wants_outputs = proposal
+        let sender_input: bitcoin_ffi::OutPoint = payjoin::bitcoin::consensus::deserialize::<
+            payjoin::bitcoin::Transaction,
+        >(&to_broadcast_in_failure_case)
+        .unwrap()
+        .input[0]
+            .previous_output
+            .into();
+        match proposal.check_no_inputs_seen_before_in(vec![sender_input.clone()]) {
+            Err(InputSeenError::Seen { outpoint, .. }) => assert_eq!(outpoint, sender_input),
+            _ => panic!("proposal spending a seen input should be rejected"),
+        }
+        assert!(proposal.check_no_inputs_seen_before_in(vec![]).is_ok());
+        let outputs_unknown = proposal
             .check_no_inputs_seen_before(|outpoint| mock_is_output_known(outpoint.clone()))
-            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            outputs_unknown.identify_receiver_outputs_batch(|_| Ok(vec![true])),
+            Err(BatchCheckError::AnswerCountMismatch { actual: 1, .. })
+        ));
+        assert!(outputs_unknown
+            .identify_receiver_outputs_batch(|scripts| {
+                scripts.into_iter().map(|script| is_script_owned(&receiver, script)).collect()
+            })
+            .is_ok());
+        let wants_outputs = outputs_unknown
             .identify_receiver_outputs(|script| is_script_owned(&receiver, script.clone()))
             .expect("Receiver should have at least one output");
-        _ = wants_outputs.substitute_receiver_script(&bitcoin_ffi::Script::new(
+        let wants_outputs = wants_outputs
+            .substitute_receiver_script(&bitcoin_ffi::Script::new(
+                receiver.get_address(AddressIndex::New).script_pubkey().into_bytes(),
+            ))
+            .expect("Sender should allow output substitution");
+
+        // Split the payment between a fresh address and a drain address
+        let original_tx: payjoin::bitcoin::Transaction =
+            payjoin::bitcoin::consensus::deserialize(&to_broadcast_in_failure_case).unwrap();
+        let payment: u64 = original_tx
+            .output
+            .iter()
+            .filter(|txout| is_script_owned(&receiver, txout.script_pubkey.to_bytes()).unwrap())
+            .map(|txout| txout.value.to_sat())
+            .sum();
+        let fresh_script = payjoin::bitcoin::ScriptBuf::from_bytes(
             receiver.get_address(AddressIndex::New).script_pubkey().into_bytes(),
+        );
+        let drain_script = payjoin::bitcoin::ScriptBuf::from_bytes(
+            receiver.get_address(AddressIndex::New).script_pubkey().into_bytes(),
+        );
+        let replacement_outputs = vec![
+            payjoin::bitcoin::TxOut {
+                value: payjoin::bitcoin::Amount::from_sat(payment / 2),
+                script_pubkey: fresh_script,
+            }
+            .into(),
+            payjoin::bitcoin::TxOut {
+                value: payjoin::bitcoin::Amount::from_sat(payment - payment / 2),
+                script_pubkey: drain_script.clone(),
+            }
+            .into(),
+        ];
+        let dust = vec![payjoin::bitcoin::TxOut {
+            value: payjoin::bitcoin::Amount::from_sat(1),
+            script_pubkey: drain_script.clone(),
+        }
+        .into()];
+        let drain_script = bitcoin_ffi::Script::new(drain_script.into_bytes());
+        assert!(matches!(
+            wants_outputs.try_substitute_receiver_outputs(|| Ok(dust.clone()), &drain_script),
+            Err(payjoin_ffi::receive::OutputSubstitutionError::DustOutput { index: 0, value: 1 })
         ));
+        assert!(wants_outputs
+            .try_substitute_receiver_outputs(|| Ok(replacement_outputs.clone()), &drain_script)
+            .is_ok());
+        let wants_outputs = wants_outputs
+            .replace_receiver_outputs(replacement_outputs, &drain_script)
+            .expect("Sender should allow output replacement");
         let wants_inputs = wants_outputs.commit_outputs();
 
         // Select receiver payjoin inputs. TODO Lock them.
@@ -370,16 +589,107 @@ mod v2 {
             .map(input_pair_from_local_utxo)
             .collect::<Result<Vec<_>, _>>()
             .unwrap();
+        let ranked = wants_inputs.try_preserving_privacy_ranked(available_inputs.clone()).unwrap();
+        assert!(!ranked.is_empty() && ranked.len() <= available_inputs.len());
         let selected_outpoint = wants_inputs
             .try_preserving_privacy(available_inputs)
             .expect("receiver input that avoids surveillance not found");
+        // The single selection is the best ranked candidate.
+        assert_eq!(format!("{:?}", ranked[0]), format!("{:?}", selected_outpoint));
 
         let provisional_proposal =
             wants_inputs.contribute_inputs(vec![selected_outpoint]).unwrap().commit_inputs();
 
+        let bundle = provisional_proposal.export_review_bundle(Some(10), Some(100)).unwrap();
+        let document: serde_json::Value = serde_json::from_str(&bundle.document).unwrap();
+        assert_eq!(document["version"], 1);
+        assert_eq!(document["contributions"]["inputs"].as_array().unwrap().len(), 1);
+        assert!(matches!(
+            provisional_proposal.apply_review_decision(
+                false,
+                bundle.hash.clone(),
+                Some(10),
+                Some(100)
+            ),
+            Err(ReviewError::Rejected)
+        ));
+        assert!(matches!(
+            provisional_proposal.apply_review_decision(
+                true,
+                bundle.hash.clone(),
+                Some(2),
+                Some(100)
+            ),
+            Err(ReviewError::BundleMismatch { .. })
+        ));
+        let reviewed_proposal = provisional_proposal
+            .apply_review_decision(true, bundle.hash, Some(10), Some(100))
+            .unwrap();
+        let preview = provisional_proposal.preview_fees(Some(10), Some(100)).unwrap();
+        // The sender built its request with build_recommended at the minimum relay fee rate.
+        let sender_params = provisional_proposal.sender_params();
+        assert_eq!(provisional_proposal.min_fee_rate_sat_per_vb(), Some(1));
+        assert!(provisional_proposal.max_additional_fee_contribution().is_some());
+        assert!(sender_params.additional_fee_output_index.is_some());
+        let error = provisional_proposal
+            .finalize_proposal_bytes(|_| Ok(vec![0x70, 0x73, 0x62, 0x74]), Some(10), Some(100))
+            .unwrap_err();
+        let callback_error = error.implementation_error().unwrap();
+        assert!(callback_error.is_invalid_callback_psbt());
+        assert_eq!(callback_error.code().as_deref(), Some(INVALID_CALLBACK_PSBT));
         let payjoin_proposal = provisional_proposal
             .finalize_proposal(|psbt| process_psbt(&receiver, psbt), Some(10), Some(100))
             .unwrap();
+        let txid = |psbt: String| {
+            payjoin::bitcoin::psbt::Psbt::from_str(&psbt).unwrap().unsigned_tx.compute_txid()
+        };
+        // Finalizing after an approved review yields the same payjoin
+        let reviewed_payjoin_proposal =
+            reviewed_proposal.finalize_proposal(|psbt| process_psbt(&receiver, psbt)).unwrap();
+        assert_eq!(txid(reviewed_payjoin_proposal.psbt()), txid(payjoin_proposal.psbt()));
+        assert_eq!(payjoin_proposal.original_psbt(), original_psbt);
+        assert_eq!(
+            payjoin::bitcoin::psbt::Psbt::deserialize(&payjoin_proposal.psbt_bytes())
+                .unwrap()
+                .to_string(),
+            payjoin_proposal.psbt()
+        );
+        let fees = payjoin_proposal.fee_split();
+        let total_fee = payjoin_proposal.total_fee().unwrap();
+        assert_eq!(fees.sender_fee.unwrap() + fees.receiver_fee.unwrap(), total_fee);
+        assert_eq!(
+            payjoin_proposal.additional_fee_contribution(),
+            Some(total_fee - fees.original_fee.unwrap())
+        );
+        assert!(payjoin_proposal.receiver_amount_received().is_some());
+        assert_eq!(preview.receiver_fee, fees.receiver_fee);
+        assert!(preview.effective_fee_rate.is_some());
+        let locked = payjoin_proposal.locked_utxos();
+        assert_eq!(locked.len(), payjoin_proposal.utxos_to_be_locked().len());
+        assert_eq!(locked.iter().filter(|utxo| utxo.receiver_contributed).count(), 1);
+        assert!(locked.iter().all(|utxo| utxo.txout.is_some()));
+
+        // A signer that panics leaves the proposal usable since no lock is held across callbacks
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            provisional_proposal
+                .begin_finalize(|_| panic!("signer crashed"), Some(10), Some(100))
+                .map(|_| ())
+        }));
+        assert!(panicked.is_err());
+
+        // A co-signer that is unavailable at first leaves the receiver input unsigned until the
+        // signatures are resumed after a restart
+        let now =
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let pending = provisional_proposal.begin_finalize(Ok, Some(10), Some(100)).unwrap();
+        assert_eq!(pending.unsatisfied_inputs().len(), 1);
+        assert!(matches!(pending.complete(now), Err(FinalizeError::Incomplete { unsatisfied: 1 })));
+        let pending = provisional_proposal.resume_finalize(&pending.to_json().unwrap()).unwrap();
+        let pending = pending.add_signatures(|psbt| process_psbt(&receiver, psbt)).unwrap();
+        assert!(pending.unsatisfied_inputs().is_empty());
+        assert!(matches!(pending.complete(u64::MAX), Err(FinalizeError::SessionExpired { .. })));
+        let completed = pending.complete(now).unwrap();
+        assert_eq!(txid(completed.psbt()), txid(payjoin_proposal.psbt()));
         payjoin_proposal
     }
 }
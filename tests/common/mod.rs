@@ -0,0 +1,233 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::str::FromStr;
+
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+use payjoin_ffi::receive::{
+    InputPair, PayjoinProposal, ProvisionalProposal, Receiver, ReceiverResponse, UncheckedProposal,
+};
+use payjoin_ffi::send::{Sender, SenderBuilder, V2GetContext};
+use payjoin_ffi::uri::{Uri, Url};
+use payjoin_ffi::{Address, FeeRate, Network, OhttpKeys};
+
+// OriginalPSBT test vector from BIP78, paying 0.02 BTC to 3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM
+pub const ORIGINAL_PSBT: &str = "cHNidP8BAHMCAAAAAY8nutGgJdyYGXWiBEb45Hoe9lWGbkxh/6bNiOJdCDuDAAAAAAD+////AtyVuAUAAAAAF6kUHehJ8GnSdBUOOv6ujXLrWmsJRDCHgIQeAAAAAAAXqRR3QJbbz0hnQ8IvQ0fptGn+votneofTAAAAAAEBIKgb1wUAAAAAF6kU3k4ekGHKWRNbA1rV5tR5kEVDVNCHAQcXFgAUx4pFclNVgo1WWAdN1SYNX8tphTABCGsCRzBEAiB8Q+A6dep+Rz92vhy26lT0AjZn4PRLi8Bf9qoB/CMk0wIgP/Rj2PWZ3gEjUkTlhDRNAQ0gXwTO7t9n+V14pZ6oljUBIQMVmsAaoNWHVMS02LfTSe0e388LNitPa1UQZyOihY+FFgABABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAA=";
+
+/// The script of the address the Original PSBT pays.
+pub fn payee_script() -> Vec<u8> {
+    payjoin::bitcoin::Address::from_str("3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey()
+        .to_bytes()
+}
+
+/// Size of the binary HTTP response inside an encapsulated directory response.
+pub const BHTTP_RESPONSE_SIZE: usize = 8192 - 32 - 16;
+
+pub fn key_config() -> KeyConfig {
+    KeyConfig::derive(
+        1,
+        Kem::K256Sha256,
+        vec![SymmetricSuite::new(Kdf::HkdfSha256, Aead::ChaCha20Poly1305)],
+        &[7; 32],
+    )
+    .unwrap()
+}
+
+pub fn ohttp_keys() -> OhttpKeys {
+    OhttpKeys(payjoin::OhttpKeys(key_config()))
+}
+
+pub fn receiver(expire_after: Option<u64>) -> Receiver {
+    Receiver::new(
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap(),
+        "https://directory.example".to_string(),
+        ohttp_keys(),
+        expire_after,
+    )
+    .unwrap()
+}
+
+/// Read a QUIC variable-length integer from the front of `bytes`.
+pub fn read_varint(bytes: &mut &[u8]) -> usize {
+    let len = 1 << (bytes[0] >> 6);
+    let mut value = (bytes[0] & 0x3f) as usize;
+    for byte in &bytes[1..len] {
+        value = (value << 8) | *byte as usize;
+    }
+    *bytes = &bytes[len..];
+    value
+}
+
+/// Read a length-prefixed field from the front of `bytes`.
+pub fn read_field<'a>(bytes: &mut &'a [u8]) -> &'a [u8] {
+    let len = read_varint(bytes);
+    let (field, rest) = bytes.split_at(len);
+    *bytes = rest;
+    field
+}
+
+/// The content of a known-length binary HTTP request.
+pub fn bhttp_request_content(mut request: &[u8]) -> Vec<u8> {
+    assert_eq!(read_varint(&mut request), 0, "known-length request");
+    // Method, scheme, authority, path and the header section
+    for _ in 0..5 {
+        read_field(&mut request);
+    }
+    read_field(&mut request).to_vec()
+}
+
+/// A known-length binary HTTP 200 response with `content`, padded to a directory's response size.
+pub fn bhttp_ok(content: &[u8]) -> Vec<u8> {
+    assert!(content.len() < 1 << 14);
+    let mut response = vec![0x01, 0x40, 200, 0];
+    response.extend([0x40 | (content.len() >> 8) as u8, content.len() as u8]);
+    response.extend(content);
+    response.push(0);
+    response.resize(BHTTP_RESPONSE_SIZE, 0);
+    response
+}
+
+/// A v2 sender paying the Original PSBT's payee with the payjoin parameters of `receiver`.
+pub fn sender(receiver: &Receiver) -> Sender {
+    let pj_uri = receiver.pj_uri(None, None, None).unwrap().as_string();
+    let pj_params = &pj_uri[pj_uri.find('?').unwrap() + 1..];
+    let uri =
+        Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&{pj_params}"))
+            .unwrap()
+            .check_pj_supported()
+            .unwrap();
+    SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri)
+        .unwrap()
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap()
+}
+
+/// The context `sender` polls with, once a directory using [`key_config`] accepted its
+/// Original PSBT.
+pub fn v2_get_context(sender: &Sender) -> V2GetContext {
+    let relay = Url::parse("https://relay.example".to_string()).unwrap();
+    let (request, context) = sender.extract_v2(relay).unwrap();
+    let server = ohttp::Server::new(key_config()).unwrap();
+    let (_, response) = server.decapsulate(&request.body).unwrap();
+    context.process_response(&response.encapsulate(&bhttp_response(200)).unwrap()).unwrap()
+}
+
+/// A known-length binary HTTP response with `status` and no content, padded to a directory's
+/// response size.
+pub fn bhttp_response(status: u16) -> Vec<u8> {
+    let mut response = vec![0x01, 0x40 | (status >> 8) as u8, status as u8, 0, 0, 0];
+    response.resize(BHTTP_RESPONSE_SIZE, 0);
+    response
+}
+
+/// The proposal a v2 sender paying `receiver` posts, as the receiver receives it from the
+/// directory.
+pub fn unchecked_proposal(receiver: &Receiver) -> UncheckedProposal {
+    receive_payload(receiver, &sender_payload(receiver))
+}
+
+/// The payload a v2 sender paying `receiver` posts to the directory for the receiver.
+pub fn sender_payload(receiver: &Receiver) -> Vec<u8> {
+    let mut directory = ohttp::Server::new(key_config()).unwrap();
+    let (post, _) = sender(receiver)
+        .extract_v2(Url::parse("https://relay.example".to_string()).unwrap())
+        .unwrap();
+    let (bhttp_post, _) = directory.decapsulate(&post.body).unwrap();
+    bhttp_request_content(&bhttp_post)
+}
+
+/// The sender's request `payload`, as `receiver` receives it from the directory.
+pub fn receive_payload(receiver: &Receiver, payload: &[u8]) -> UncheckedProposal {
+    let mut directory = ohttp::Server::new(key_config()).unwrap();
+    let (get, ctx) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+    let (_, response) = directory.decapsulate(&get.body).unwrap();
+    let body = response.encapsulate(&bhttp_ok(payload)).unwrap();
+    match receiver.process_res(&body, &ctx).unwrap() {
+        ReceiverResponse::Proposal(proposal) => proposal,
+        _ => panic!("the directory returned the sender's proposal"),
+    }
+}
+
+/// The payload `proposal` posts to the directory for the sender.
+pub fn proposal_payload(proposal: &PayjoinProposal) -> Vec<u8> {
+    let mut directory = ohttp::Server::new(key_config()).unwrap();
+    let (post, _) = proposal.extract_v2_req("https://relay.example".to_string()).unwrap();
+    let (bhttp_post, _) = directory.decapsulate(&post.body).unwrap();
+    bhttp_request_content(&bhttp_post)
+}
+
+/// The payload of the Payjoin proposal `receiver` posts for the sender's request `payload`,
+/// contributing no inputs.
+pub fn payjoin_payload(receiver: &Receiver, payload: &[u8]) -> Vec<u8> {
+    let proposal = receive_payload(receiver, payload)
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap()
+        .commit_outputs()
+        .commit_inputs()
+        .finalize_proposal(|psbt| Ok(psbt), None, None)
+        .unwrap();
+    proposal_payload(&proposal)
+}
+
+/// A P2WPKH input of `value` sats the receiver can contribute, spending output `vout` of a
+/// made up transaction.
+pub fn receiver_input(vout: u32, value: u64) -> InputPair {
+    let script =
+        payjoin::bitcoin::Address::from_str("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4")
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+    let txin = payjoin::bitcoin::TxIn {
+        previous_output: payjoin::bitcoin::OutPoint {
+            txid: payjoin::bitcoin::Txid::from_str(&"11".repeat(32)).unwrap(),
+            vout,
+        },
+        ..Default::default()
+    };
+    let psbtin = payjoin::bitcoin::psbt::Input {
+        witness_utxo: Some(payjoin::bitcoin::TxOut {
+            value: payjoin::bitcoin::Amount::from_sat(value),
+            script_pubkey: script,
+        }),
+        ..Default::default()
+    };
+    InputPair::new(txin.into(), psbtin.into()).unwrap()
+}
+
+/// The proposal of [`unchecked_proposal`] through every check, with `inputs` contributed by the
+/// receiver.
+pub fn provisional_proposal(receiver: &Receiver, inputs: Vec<InputPair>) -> ProvisionalProposal {
+    unchecked_proposal(receiver)
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap()
+        .commit_outputs()
+        .contribute_inputs(inputs)
+        .unwrap()
+        .commit_inputs()
+}
+
+/// The Original PSBT as a BIP78 sender posts it to a v1 receiver.
+pub fn v1_unchecked_proposal() -> payjoin_ffi::receive::v1::UncheckedProposal {
+    let body = ORIGINAL_PSBT.as_bytes().to_vec();
+    let headers = std::collections::HashMap::from([
+        ("Content-Type".to_string(), "text/plain".to_string()),
+        ("Content-Length".to_string(), body.len().to_string()),
+    ]);
+    payjoin_ffi::receive::v1::UncheckedProposal::from_request(body, "v=1".to_string(), headers)
+        .unwrap()
+}
@@ -0,0 +1,204 @@
+#![cfg(feature = "async")]
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use payjoin_ffi::receive::asynchronous::{
+    CanBroadcastAsync, IsOutputKnownAsync, IsScriptOwnedAsync,
+};
+use payjoin_ffi::receive::{Error, ImplementationError, Receiver};
+use payjoin_ffi::send::{HttpTransport, PollingPolicy, RunError};
+use payjoin_ffi::uri::Url;
+use payjoin_ffi::OutPoint;
+
+use crate::common::{
+    bhttp_ok, bhttp_response, key_config, payee_script, receiver, sender_payload,
+    unchecked_proposal,
+};
+
+/// An async callback that yields before answering and counts how often it is called.
+struct Counting<F> {
+    calls: AtomicUsize,
+    answer: F,
+}
+
+impl<F> Counting<F> {
+    fn new(answer: F) -> Self {
+        Self { calls: AtomicUsize::new(0), answer }
+    }
+
+    async fn call<Q>(&self, question: Q) -> Result<bool, ImplementationError>
+    where
+        F: Fn(Q) -> bool,
+    {
+        tokio::task::yield_now().await;
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok((self.answer)(question))
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait::async_trait]
+impl<F: Fn(Vec<u8>) -> bool + Send + Sync> CanBroadcastAsync for Counting<F> {
+    async fn callback(&self, tx: Vec<u8>) -> Result<bool, ImplementationError> {
+        self.call(tx).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<F: Fn(Vec<u8>) -> bool + Send + Sync> IsScriptOwnedAsync for Counting<F> {
+    async fn callback(&self, script: Vec<u8>) -> Result<bool, ImplementationError> {
+        self.call(script).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<F: Fn(OutPoint) -> bool + Send + Sync> IsOutputKnownAsync for Counting<F> {
+    async fn callback(&self, outpoint: OutPoint) -> Result<bool, ImplementationError> {
+        self.call(outpoint).await
+    }
+}
+
+#[tokio::test]
+async fn async_extract_req_matches_sync() {
+    let receiver = receiver(None);
+    let (sync_request, _) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+    let (async_request, _) =
+        receiver.extract_req_async("https://relay.example".to_string()).await.unwrap();
+    assert_eq!(async_request.url.as_string(), sync_request.url.as_string());
+    assert_eq!(async_request.content_type, sync_request.content_type);
+}
+
+#[tokio::test]
+async fn async_poll_stops_on_expired_session() {
+    let receiver = receiver(Some(0));
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    assert!(matches!(
+        receiver.extract_req_async("https://relay.example".to_string()).await,
+        Err(Error::SessionExpired { .. })
+    ));
+}
+
+#[tokio::test]
+async fn async_checks_await_each_callback_once_per_question() {
+    let receiver = receiver(None);
+    let proposal = unchecked_proposal(&receiver);
+    let payee = payee_script();
+
+    let can_broadcast = Counting::new(|_: Vec<u8>| true);
+    let proposal = proposal.check_broadcast_suitability_async(None, &can_broadcast).await.unwrap();
+    assert_eq!(can_broadcast.calls(), 1);
+
+    // The Original PSBT spends one input and pays two outputs
+    let is_owned = Counting::new(|_: Vec<u8>| false);
+    let proposal = proposal.check_inputs_not_owned_async(&is_owned).await.unwrap();
+    assert_eq!(is_owned.calls(), 1);
+
+    let is_known = Counting::new(|_: OutPoint| false);
+    let proposal = proposal.check_no_inputs_seen_before_async(&is_known).await.unwrap();
+    assert_eq!(is_known.calls(), 1);
+
+    let is_receiver_output = Counting::new(move |script: Vec<u8>| script == payee);
+    proposal.identify_receiver_outputs_async(&is_receiver_output).await.unwrap();
+    assert_eq!(is_receiver_output.calls(), 2);
+}
+
+#[tokio::test]
+async fn async_broadcast_check_rejects_on_callback_answer() {
+    let receiver = receiver(None);
+    let proposal = unchecked_proposal(&receiver);
+    let can_broadcast = Counting::new(|_: Vec<u8>| false);
+    assert!(proposal.check_broadcast_suitability_async(None, &can_broadcast).await.is_err());
+    assert_eq!(can_broadcast.calls(), 1);
+}
+
+/// How the directory answers one of the receiver's requests.
+enum Reply {
+    Fail,
+    Status(u16),
+    Proposal,
+}
+
+/// A directory holding the payload of a sender paying the receiver, which answers the
+/// receiver's requests as scripted and then has no proposal, recording how long the receiver
+/// waits instead of waiting.
+struct Mailbox {
+    server: Mutex<ohttp::Server>,
+    payload: Vec<u8>,
+    replies: Mutex<VecDeque<Reply>>,
+    sleeps: Mutex<Vec<u64>>,
+}
+
+impl Mailbox {
+    fn new(receiver: &Receiver, replies: impl IntoIterator<Item = Reply>) -> Self {
+        Self {
+            server: Mutex::new(ohttp::Server::new(key_config()).unwrap()),
+            payload: sender_payload(receiver),
+            replies: Mutex::new(replies.into_iter().collect()),
+            sleeps: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for Mailbox {
+    async fn post(
+        &self,
+        _url: String,
+        _headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, ImplementationError> {
+        let bhttp = match self.replies.lock().unwrap().pop_front().unwrap_or(Reply::Status(202)) {
+            Reply::Fail => {
+                return Err(ImplementationError::new("connection reset".to_string(), None))
+            }
+            Reply::Status(status) => bhttp_response(status),
+            Reply::Proposal => bhttp_ok(&self.payload),
+        };
+        let (_, response) = self.server.lock().unwrap().decapsulate(&body).unwrap();
+        Ok(response.encapsulate(&bhttp).unwrap())
+    }
+
+    async fn sleep(&self, secs: u64) {
+        self.sleeps.lock().unwrap().push(secs);
+    }
+}
+
+fn ohttp_relay() -> Url {
+    Url::parse("https://relay.example".to_string()).unwrap()
+}
+
+#[tokio::test]
+async fn poll_with_policy_retries_until_the_proposal_arrives() {
+    let receiver = receiver(None);
+    let mailbox = Mailbox::new(&receiver, [Reply::Fail, Reply::Status(202), Reply::Proposal]);
+    let policy = PollingPolicy::new(1, 2.0, 4, 60);
+    let proposal = receiver.poll_with_policy(&mailbox, ohttp_relay(), &policy).await.unwrap();
+    assert!(proposal.is_some());
+    // A retry after the failed request, then a poll without a proposal
+    assert_eq!(*mailbox.sleeps.lock().unwrap(), vec![1, 2]);
+}
+
+#[tokio::test]
+async fn poll_with_policy_ends_with_the_schedule() {
+    let receiver = receiver(None);
+    let mailbox = Mailbox::new(&receiver, []);
+    let policy = PollingPolicy::new(1, 1.0, 1, 3);
+    let proposal = receiver.poll_with_policy(&mailbox, ohttp_relay(), &policy).await.unwrap();
+    assert!(proposal.is_none());
+    assert_eq!(*mailbox.sleeps.lock().unwrap(), vec![1, 1, 1]);
+}
+
+#[tokio::test]
+async fn poll_with_policy_returns_directory_errors_at_once() {
+    let receiver = receiver(None);
+    let mailbox = Mailbox::new(&receiver, [Reply::Status(500)]);
+    let result =
+        receiver.poll_with_policy(&mailbox, ohttp_relay(), &PollingPolicy::recommended()).await;
+    assert!(matches!(result, Err(RunError::Receive(_))));
+    assert!(mailbox.sleeps.lock().unwrap().is_empty());
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use payjoin_ffi::receive::Error;
+use payjoin_ffi::ClientResponse;
+
+use crate::common::receiver;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn client_response_is_send_and_sync() {
+    assert_send_sync::<ClientResponse>();
+}
+
+#[test]
+fn client_response_is_usable_from_another_thread() {
+    let receiver = receiver(None);
+    let (_, ctx) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+    let ctx = Arc::new(ctx);
+    let body = vec![0; 8192];
+    assert!(ctx.matches(&body));
+
+    let result = std::thread::spawn({
+        let ctx = ctx.clone();
+        move || receiver.process_res(&body, &ctx)
+    })
+    .join()
+    .unwrap();
+    // The body isn't a valid response, but the context was consumed on the other thread
+    assert!(!matches!(result, Err(Error::OhttpContextAlreadyUsed)));
+    assert!(!ctx.matches(&[0; 8192]));
+}
+
+#[test]
+fn reusing_a_client_response_is_a_typed_error() {
+    let receiver = receiver(None);
+    let (_, ctx) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+    let body = vec![0; 8192];
+    let _ = receiver.process_res(&body, &ctx);
+    assert!(matches!(receiver.process_res(&body, &ctx), Err(Error::OhttpContextAlreadyUsed)));
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "test-utils")]
+
+use payjoin_ffi::receive::{Receiver, ReceiverKeysError};
+use payjoin_ffi::{Address, Network};
+
+use crate::common::ohttp_keys;
+
+fn receiver(secret_key: [u8; 32]) -> Receiver {
+    Receiver::new_with_keys(
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap(),
+        "https://directory.example".to_string(),
+        ohttp_keys(),
+        secret_key,
+        4_000_000_000,
+    )
+    .unwrap()
+}
+
+#[test]
+fn same_keys_give_the_same_session() {
+    let (a, b) = (receiver([7; 32]), receiver([7; 32]));
+    assert_eq!(a.id(), b.id());
+    assert_eq!(
+        a.pj_uri(None, None, None).unwrap().as_string(),
+        b.pj_uri(None, None, None).unwrap().as_string()
+    );
+    assert_eq!(a.expiration(), Some(4_000_000_000));
+}
+
+#[test]
+fn invalid_secret_key_is_rejected() {
+    let error = Receiver::new_with_keys(
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap(),
+        "https://directory.example".to_string(),
+        ohttp_keys(),
+        [0; 32],
+        4_000_000_000,
+    )
+    .unwrap_err();
+    assert!(matches!(error, ReceiverKeysError::InvalidSecretKey));
+}
+
+#[test]
+fn different_keys_give_different_sessions() {
+    assert_ne!(receiver([7; 32]).id(), receiver([8; 32]).id());
+}
+
+#[test]
+fn id_forms_identify_the_session_key_and_pj_url() {
+    use payjoin::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    let receiver = receiver([7; 32]);
+    let secret = SecretKey::from_slice(&[7; 32]).unwrap();
+    assert_eq!(
+        receiver.id_bytes(),
+        Some(PublicKey::from_secret_key(&Secp256k1::new(), &secret).serialize().to_vec())
+    );
+    assert!(receiver.id().starts_with(&receiver.short_id()));
+    assert_eq!(receiver.short_id().len(), payjoin_ffi::receive::SHORT_ID_CHARS);
+    assert!(receiver.pj_uri(None, None, None).unwrap().as_string().contains(&receiver.id()));
+}
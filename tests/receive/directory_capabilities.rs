@@ -0,0 +1,82 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use payjoin_ffi::directory::{DirectoryCapabilities, LEGACY_MAX_TTL_SECS};
+use payjoin_ffi::receive::Receiver;
+use payjoin_ffi::{Address, Network};
+
+use crate::common::{ohttp_keys, receiver};
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn receiver_for(capabilities: &DirectoryCapabilities, expire_after: Option<u64>) -> Receiver {
+    Receiver::new_for_directory(
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap(),
+        "https://directory.example".to_string(),
+        ohttp_keys(),
+        expire_after,
+        capabilities,
+    )
+    .unwrap()
+}
+
+/// The expiry of a session created with `expire_after` for a directory with `capabilities`, and
+/// the unix times just before and after it was created.
+fn expiry_for(capabilities: &DirectoryCapabilities, expire_after: Option<u64>) -> (u64, u64, u64) {
+    let before = now();
+    let expiry = receiver_for(capabilities, expire_after).expiration().unwrap();
+    (before, expiry, now())
+}
+
+#[test]
+fn full_featured_directory_is_respected() {
+    let capabilities = DirectoryCapabilities {
+        versions: vec!["2".to_string(), "3".to_string()],
+        long_poll: true,
+        max_ttl_secs: 604800,
+    };
+    assert_eq!(capabilities.effective_expire_after(Some(3 * 86400)), 3 * 86400);
+    assert_eq!(capabilities.effective_expire_after(None), 604800);
+}
+
+#[test]
+fn legacy_directory_gets_conservative_profile() {
+    let legacy = DirectoryCapabilities::legacy();
+    assert_eq!(legacy.versions, vec!["2".to_string()]);
+    assert!(!legacy.long_poll);
+    assert_eq!(legacy.effective_expire_after(Some(7 * 86400)), LEGACY_MAX_TTL_SECS);
+    assert_eq!(legacy.effective_expire_after(None), LEGACY_MAX_TTL_SECS);
+}
+
+#[test]
+fn sessions_are_capped_at_the_directory_ttl() {
+    let capabilities =
+        DirectoryCapabilities { max_ttl_secs: 600, ..DirectoryCapabilities::legacy() };
+    let (before, expiry, after) = expiry_for(&capabilities, Some(86400));
+    assert!((before + 600..=after + 600).contains(&expiry));
+}
+
+#[test]
+fn sessions_below_the_directory_ttl_keep_their_expiry() {
+    let capabilities =
+        DirectoryCapabilities { max_ttl_secs: 600, ..DirectoryCapabilities::legacy() };
+    let (before, expiry, after) = expiry_for(&capabilities, Some(60));
+    assert!((before + 60..=after + 60).contains(&expiry));
+}
+
+#[test]
+fn polls_wait_only_at_long_polling_directories() {
+    let receiver = receiver(Some(600));
+    let relay = "https://relay.example".to_string();
+    let legacy = DirectoryCapabilities::legacy();
+    let (request, _) = receiver.extract_req_with_wait(relay.clone(), 30, &legacy).unwrap();
+    assert_eq!(request.timeout_secs, None);
+
+    let long_poll = DirectoryCapabilities { long_poll: true, ..legacy };
+    let (request, _) = receiver.extract_req_with_wait(relay.clone(), 30, &long_poll).unwrap();
+    assert_eq!(request.timeout_secs, Some(30));
+    let (request, _) = receiver.extract_req_with_wait(relay, 3600, &long_poll).unwrap();
+    assert!(request.timeout_secs.is_some_and(|secs| secs <= 600));
+}
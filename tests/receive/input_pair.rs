@@ -0,0 +1,76 @@
+use payjoin::bitcoin::absolute::LockTime;
+use payjoin::bitcoin::hashes::Hash;
+use payjoin::bitcoin::transaction::Version;
+use payjoin::bitcoin::{
+    consensus, psbt, Amount, OutPoint, PubkeyHash, ScriptBuf, Transaction, TxIn, TxOut, Txid,
+};
+use payjoin_ffi::receive::{InputPair, NonWitnessInputError, PsbtInputError};
+
+fn previous_tx() -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([1; 20])),
+        }],
+    }
+}
+
+/// A witness input spending `script_pubkey`, its satisfaction weight left to be derived.
+fn witness_input(script_pubkey: ScriptBuf) -> Result<InputPair, PsbtInputError> {
+    let txin = TxIn {
+        previous_output: OutPoint { txid: Txid::all_zeros(), vout: 0 },
+        ..Default::default()
+    };
+    let psbtin = psbt::Input {
+        witness_utxo: Some(TxOut { value: Amount::from_sat(100_000), script_pubkey }),
+        ..Default::default()
+    };
+    InputPair::new(txin.into(), psbtin.into())
+}
+
+#[test]
+fn taproot_key_spend_input_is_accepted() {
+    let mut script = vec![0x51, 0x20];
+    script.extend([2; 32]);
+    assert!(witness_input(ScriptBuf::from_bytes(script)).is_ok());
+}
+
+#[test]
+fn input_of_unknown_satisfaction_weight_is_rejected() {
+    // A P2WSH spend's weight depends on its witness script, which the PSBT input doesn't carry
+    let mut script = vec![0x00, 0x20];
+    script.extend([3; 32]);
+    assert!(witness_input(ScriptBuf::from_bytes(script)).is_err());
+}
+
+#[test]
+fn legacy_input_is_paired_with_its_previous_transaction() {
+    let tx = previous_tx();
+    let outpoint = OutPoint { txid: tx.compute_txid(), vout: 0 };
+    assert!(InputPair::new_non_witness(consensus::serialize(&tx), outpoint.into()).is_ok());
+}
+
+#[test]
+fn mismatched_previous_transaction_is_rejected() {
+    let tx = previous_tx();
+    let wrong_txid = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+    let wrong_vout = OutPoint { txid: tx.compute_txid(), vout: 1 };
+    for outpoint in [wrong_txid, wrong_vout] {
+        assert!(matches!(
+            InputPair::new_non_witness(consensus::serialize(&tx), outpoint.into()),
+            Err(NonWitnessInputError::OutpointMismatch { .. })
+        ));
+    }
+}
+
+#[test]
+fn undecodable_previous_transaction_is_rejected() {
+    let outpoint = OutPoint { txid: previous_tx().compute_txid(), vout: 0 };
+    assert!(matches!(
+        InputPair::new_non_witness(vec![0xde, 0xad], outpoint.into()),
+        Err(NonWitnessInputError::InvalidTransaction { .. })
+    ));
+}
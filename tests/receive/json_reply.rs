@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use payjoin_ffi::receive::{
+    JsonReply, ReplyableError, V1RequestError, WellKnownError, MAX_REPLY_MESSAGE_CHARS,
+};
+
+fn implementation_error(msg: &str) -> Arc<ReplyableError> {
+    let error = payjoin::receive::ReplyableError::Implementation(msg.to_string().into());
+    Arc::new(error.into())
+}
+
+#[test]
+fn implementation_errors_do_not_leak_to_the_sender() {
+    let db = JsonReply::from_replyable_error(implementation_error("database at /var/lib/db"));
+    let wallet = JsonReply::from_replyable_error(implementation_error("wallet is locked"));
+    assert_eq!(db, wallet);
+}
+
+#[test]
+fn custom_message_replaces_message_and_keeps_error_code() {
+    let reply = JsonReply::from_replyable_error(implementation_error("wallet is locked"));
+    let custom = reply.with_message("This invoice expired,\nplease refresh\tthe page");
+    let json: serde_json::Value = serde_json::from_str(&custom.to_json()).unwrap();
+    let original: serde_json::Value = serde_json::from_str(&reply.to_json()).unwrap();
+    assert_eq!(json["errorCode"], original["errorCode"]);
+    assert_eq!(json["message"], "This invoice expired, please refresh the page");
+}
+
+#[test]
+fn overlong_custom_message_is_truncated() {
+    let reply = JsonReply::from_replyable_error(implementation_error("wallet is locked"));
+    let custom = reply.with_message(&"é".repeat(MAX_REPLY_MESSAGE_CHARS * 2));
+    let json: serde_json::Value = serde_json::from_str(&custom.to_json()).unwrap();
+    assert_eq!(json["message"].as_str().unwrap().chars().count(), MAX_REPLY_MESSAGE_CHARS);
+}
+
+fn response_json(reply: &JsonReply) -> (u16, serde_json::Value) {
+    let response = reply.to_bip78_response();
+    (response.status_code, serde_json::from_slice(&response.body).unwrap())
+}
+
+#[test]
+fn well_known_errors_map_to_bip78_responses() {
+    for (code, name, status) in [
+        (WellKnownError::Unavailable, "unavailable", 500),
+        (WellKnownError::NotEnoughMoney, "not-enough-money", 400),
+        (WellKnownError::VersionUnsupported, "version-unsupported", 400),
+        (WellKnownError::OriginalPsbtRejected, "original-psbt-rejected", 400),
+    ] {
+        let reply = JsonReply::well_known(code, "reason".to_string());
+        assert_eq!(reply.error_code(), code);
+        let (status_code, json) = response_json(&reply);
+        assert_eq!(status_code, status);
+        assert_eq!(json["errorCode"], name);
+        assert_eq!(json["message"], "reason");
+    }
+}
+
+#[test]
+fn implementation_errors_respond_unavailable() {
+    let reply = JsonReply::from_replyable_error(implementation_error("database at /var/lib/db"));
+    let (status_code, json) = response_json(&reply);
+    assert_eq!(status_code, 500);
+    assert_eq!(json["errorCode"], "unavailable");
+    assert!(!json.to_string().contains("/var/lib/db"));
+}
+
+#[test]
+fn v1_request_errors_map_to_well_known_errors() {
+    let version = V1RequestError::UnsupportedVersion { version: "3".to_string() };
+    assert_eq!(
+        JsonReply::from_v1_request_error(&version).error_code(),
+        WellKnownError::VersionUnsupported
+    );
+    let header = V1RequestError::MissingHeader { name: "content-type".to_string() };
+    assert_eq!(
+        JsonReply::from_v1_request_error(&header).error_code(),
+        WellKnownError::OriginalPsbtRejected
+    );
+    let proposal = V1RequestError::Proposal(implementation_error("wallet is locked"));
+    assert_eq!(
+        JsonReply::from_v1_request_error(&proposal).error_code(),
+        WellKnownError::Unavailable
+    );
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "_danger-local-testing")]
+
+use payjoin_ffi::receive::{Receiver, ReceiverResponse};
+use payjoin_ffi::uri::Url;
+use payjoin_ffi::{local_testing_ohttp_keys, unwrap_request, Address, LocalTestingError, Network};
+
+use crate::common::{receiver, sender};
+
+fn local_receiver() -> Receiver {
+    Receiver::new(
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap(),
+        "https://localhost:8080".to_string(),
+        local_testing_ohttp_keys(),
+        None,
+    )
+    .unwrap()
+}
+
+#[test]
+fn sender_and_receiver_talk_to_the_directory_in_plain_http() {
+    let receiver = local_receiver();
+    let sender = sender(&receiver);
+
+    let (post, post_ctx) =
+        sender.extract_v2(Url::parse("http://unused.relay".to_string()).unwrap()).unwrap();
+    let post = unwrap_request(post).unwrap();
+    assert_eq!(post.request.method, "POST");
+    assert!(post.request.url.as_string().starts_with("https://localhost:8080/"));
+    assert!(!post.request.body.is_empty());
+    let posted = post.request.body.clone();
+    post_ctx.process_response(&post.context.wrap_response(200, vec![]).unwrap()).unwrap();
+
+    let (get, get_ctx) = receiver.extract_req("http://unused.relay".to_string()).unwrap();
+    let get = unwrap_request(get).unwrap();
+    assert_eq!(get.request.method, "GET");
+    assert!(get.request.url.as_string().starts_with("https://localhost:8080/"));
+    let response = get.context.wrap_response(200, posted).unwrap();
+    assert!(matches!(
+        receiver.process_res(&response, &get_ctx).unwrap(),
+        ReceiverResponse::Proposal(_)
+    ));
+    assert_eq!(get.context.wrap_response(200, vec![]), Err(LocalTestingError::ContextAlreadyUsed));
+}
+
+#[test]
+fn requests_for_other_ohttp_keys_are_not_unwrapped() {
+    let (request, _) = receiver(None).extract_req("https://relay.example".to_string()).unwrap();
+    assert!(matches!(unwrap_request(request), Err(LocalTestingError::ForeignOhttpKeys)));
+}
+
+#[test]
+fn oversized_responses_are_rejected() {
+    let (request, _) = local_receiver().extract_req("http://unused.relay".to_string()).unwrap();
+    let plain = unwrap_request(request).unwrap();
+    assert!(matches!(
+        plain.context.wrap_response(200, vec![0; 8192]),
+        Err(LocalTestingError::ResponseTooLarge { len: 8192, .. })
+    ));
+}
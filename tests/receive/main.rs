@@ -0,0 +1,20 @@
+//! Integration tests of the receiver.
+#![cfg(not(feature = "uniffi"))]
+
+extern crate payjoin_ffi_v0_22 as payjoin_ffi;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+mod async_poll;
+mod client_response;
+mod deterministic;
+mod directory_capabilities;
+mod input_pair;
+mod json_reply;
+mod local_testing;
+mod proposal;
+mod scheduled_broadcast;
+mod seen_inputs;
+mod session_layout;
+mod v1_request;
@@ -0,0 +1,436 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::Witness;
+use payjoin_ffi::receive::session::ReceiveSession;
+use payjoin_ffi::receive::{
+    v1, ImplementationError, OutputSubstitutionError, Receiver, INVALID_FEE_RATE,
+};
+use payjoin_ffi::{FeeRate, Script};
+
+use crate::common::{
+    key_config, payee_script, provisional_proposal, receiver, receiver_input, unchecked_proposal,
+    v1_unchecked_proposal, ORIGINAL_PSBT,
+};
+
+#[test]
+fn fee_preview_matches_the_finalized_proposal() {
+    let receiver = receiver(None);
+    let proposal = provisional_proposal(&receiver, vec![receiver_input(0, 1_000_000)]);
+    for (min, max) in [(None, None), (Some(2), Some(50)), (Some(10), None)] {
+        let preview = proposal.preview_fees(min, max).unwrap();
+        let split = proposal.finalize_proposal(|psbt| Ok(psbt), min, max).unwrap().fee_split();
+        assert!(preview.receiver_fee.is_some());
+        assert_eq!(preview.receiver_fee, split.receiver_fee);
+        assert_eq!(
+            preview.sender_fee_contribution,
+            split.sender_fee.zip(split.original_fee).map(|(sender, original)| sender - original)
+        );
+    }
+}
+
+#[test]
+fn fee_preview_follows_the_fee_rates() {
+    let receiver = receiver(None);
+    let proposal = provisional_proposal(&receiver, vec![receiver_input(0, 1_000_000)]);
+    let low = proposal.preview_fees(Some(2), None).unwrap();
+    let high = proposal.preview_fees(Some(10), None).unwrap();
+    assert!(high.receiver_fee > low.receiver_fee);
+    let with_fee_rates = proposal
+        .preview_fees_with_fee_rates(Some(FeeRate::from_sat_per_vb(10).unwrap()), None)
+        .unwrap();
+    assert_eq!(with_fee_rates, high);
+}
+
+/// A signer that can only finalize the receiver input spending output `vout`.
+fn co_signer(vout: u32) -> impl Fn(String) -> Result<String, ImplementationError> {
+    move |psbt| {
+        let mut psbt = Psbt::from_str(&psbt).unwrap();
+        let index = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .position(|txin| {
+                txin.previous_output.vout == vout
+                    && txin.previous_output.txid.to_string() == "11".repeat(32)
+            })
+            .unwrap();
+        psbt.inputs[index].final_script_witness =
+            Some(Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]]));
+        Ok(psbt.to_string())
+    }
+}
+
+#[test]
+fn session_parameters_are_read_from_the_pj_uri() {
+    let receiver = receiver(Some(600));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(receiver.directory().as_string(), "https://directory.example/");
+    assert_eq!(receiver.ohttp_keys().unwrap().0.encode().unwrap(), key_config().encode().unwrap());
+    assert!(receiver.expiration().is_some_and(|at| at > now && at <= now + 600));
+    assert_eq!(receiver.id_bytes().map(|key| key.len()), Some(33));
+    assert!(!receiver.is_expired());
+}
+
+#[test]
+fn sender_params_are_read_when_the_proposal_arrives() {
+    let proposal = unchecked_proposal(&receiver(None));
+    assert!(!proposal.is_output_substitution_disabled());
+    let params = proposal
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap()
+        .commit_outputs()
+        .commit_inputs()
+        .sender_params();
+    assert_eq!(params.min_fee_rate_sat_per_vb, None);
+    assert_eq!(params.max_additional_fee_contribution, None);
+}
+
+#[test]
+fn overflowing_sat_per_vb_fails_to_finalize() {
+    let receiver = receiver(None);
+    let proposal = provisional_proposal(&receiver, vec![receiver_input(0, 1_000_000)]);
+    let error = proposal.finalize_proposal(|psbt| Ok(psbt), Some(u64::MAX), None).unwrap_err();
+    assert_eq!(error.implementation_error().unwrap().code().as_deref(), Some(INVALID_FEE_RATE));
+    assert!(proposal.finalize_proposal_bytes(|psbt| Ok(psbt), None, Some(u64::MAX)).is_err());
+    assert!(proposal.preview_fees(Some(u64::MAX), None).is_err());
+
+    let proposal = v1_wants_outputs()
+        .commit_outputs()
+        .contribute_inputs(vec![receiver_input(0, 1_000_000)])
+        .unwrap()
+        .commit_inputs();
+    assert!(proposal.finalize_proposal(|psbt| Ok(psbt), Some(u64::MAX), None).is_err());
+    let typed = proposal
+        .finalize_proposal_with_fee_rates(
+            |psbt| Ok(psbt),
+            Some(FeeRate::from_sat_per_vb(2).unwrap()),
+            None,
+        )
+        .unwrap();
+    let untyped = proposal.finalize_proposal(|psbt| Ok(psbt), Some(2), None).unwrap();
+    assert_eq!(typed.psbt(), untyped.psbt());
+}
+
+#[test]
+fn pending_signatures_survive_a_session_restore() {
+    let inputs = || vec![receiver_input(0, 1_000_000), receiver_input(1, 2_000_000)];
+    let receiver = receiver(None);
+    let pending =
+        provisional_proposal(&receiver, inputs()).begin_finalize(co_signer(0), None, None).unwrap();
+    assert_eq!(pending.unsatisfied_inputs().len(), 1);
+    let persisted = receiver.with_pending_signatures(&pending).to_json().unwrap();
+
+    let restored = Receiver::from_json(&persisted).unwrap();
+    let proposal = provisional_proposal(&restored, inputs());
+    let pending = restored.resume_finalize(&proposal).unwrap().expect("pending signatures");
+    assert_eq!(pending.unsatisfied_inputs().len(), 1);
+    let pending = pending.add_signatures(co_signer(1)).unwrap();
+    assert!(pending.unsatisfied_inputs().is_empty());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert!(pending.complete(now).is_ok());
+}
+
+#[test]
+fn sessions_without_pending_signatures_resume_nothing() {
+    let receiver = Receiver::from_json(&receiver(None).to_json().unwrap()).unwrap();
+    let proposal = provisional_proposal(&receiver, vec![receiver_input(0, 1_000_000)]);
+    assert!(receiver.resume_finalize(&proposal).unwrap().is_none());
+}
+
+fn v1_wants_outputs() -> v1::WantsOutputs {
+    v1_unchecked_proposal()
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap()
+}
+
+#[test]
+fn v1_proposal_takes_the_receiver_input() {
+    let wants_outputs = v1_wants_outputs();
+    assert!(wants_outputs.output_substitution());
+    let input = receiver_input(0, 1_000_000);
+    let proposal = wants_outputs
+        .commit_outputs()
+        .contribute_inputs(vec![input])
+        .unwrap()
+        .commit_inputs()
+        .finalize_proposal(|psbt| Ok(psbt), Some(2), None)
+        .unwrap();
+    let psbt = Psbt::from_str(&proposal.psbt()).unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+    let owned = proposal.owned_vouts();
+    assert_eq!(owned.len(), 1);
+    // The payee output grew by the receiver's input, less the receiver's share of the fee
+    let payee = &psbt.unsigned_tx.output[owned[0] as usize];
+    assert_eq!(payee.script_pubkey.to_bytes(), payee_script());
+    assert!(payee.value.to_sat() > 2_000_000 && payee.value.to_sat() < 3_000_000);
+    let locked = proposal.utxos_to_be_locked();
+    assert_eq!(locked.len(), 1);
+    assert_eq!(locked[0].vout, 0);
+}
+
+#[test]
+fn v1_outputs_are_substituted_before_inputs() {
+    let new_script = Script::new(vec![0x00, 0x14].into_iter().chain([0x33; 20]).collect());
+    let proposal = v1_wants_outputs()
+        .substitute_receiver_script(&new_script)
+        .unwrap()
+        .commit_outputs()
+        .commit_inputs()
+        .finalize_proposal(|psbt| Ok(psbt), None, None)
+        .unwrap();
+    let psbt = Psbt::from_str(&proposal.psbt()).unwrap();
+    let payee = &psbt.unsigned_tx.output[proposal.owned_vouts()[0] as usize];
+    assert_eq!(payee.script_pubkey.to_bytes(), new_script.to_bytes());
+
+    let dust = vec![payjoin::bitcoin::TxOut {
+        value: payjoin::bitcoin::Amount::from_sat(1),
+        script_pubkey: payjoin::bitcoin::ScriptBuf::from_bytes(new_script.to_bytes()),
+    }
+    .into()];
+    assert!(matches!(
+        v1_wants_outputs().replace_receiver_outputs(dust, &new_script),
+        Err(OutputSubstitutionError::DustOutput { .. })
+    ));
+    assert!(matches!(
+        v1_wants_outputs().replace_receiver_outputs(vec![], &new_script),
+        Err(OutputSubstitutionError::NoReplacementOutputs)
+    ));
+    let elsewhere = vec![payjoin::bitcoin::TxOut {
+        value: payjoin::bitcoin::Amount::from_sat(2_000_000),
+        script_pubkey: payjoin::bitcoin::ScriptBuf::from_bytes(payee_script()),
+    }
+    .into()];
+    assert!(matches!(
+        v1_wants_outputs().replace_receiver_outputs(elsewhere, &new_script),
+        Err(OutputSubstitutionError::InvalidDrainScript)
+    ));
+}
+
+#[test]
+fn payjoin_decides_whether_a_disabled_substitution_changes_anything() {
+    let body = ORIGINAL_PSBT.as_bytes().to_vec();
+    let headers = std::collections::HashMap::from([
+        ("Content-Type".to_string(), "text/plain".to_string()),
+        ("Content-Length".to_string(), body.len().to_string()),
+    ]);
+    let query = "v=1&disableoutputsubstitution=true".to_string();
+    let wants_outputs = v1::UncheckedProposal::from_request(body, query, headers)
+        .unwrap()
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap();
+    assert!(!wants_outputs.output_substitution());
+    assert!(wants_outputs.substitute_receiver_script(&Script::new(payee_script())).is_ok());
+    let new_script = Script::new(vec![0x00, 0x14].into_iter().chain([0x33; 20]).collect());
+    assert!(matches!(
+        wants_outputs.substitute_receiver_script(&new_script),
+        Err(OutputSubstitutionError::Disabled)
+    ));
+}
+
+#[test]
+fn a_failing_output_generator_fails_the_substitution() {
+    let receiver = receiver(None);
+    let wants_outputs = unchecked_proposal(&receiver)
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap();
+    let result = wants_outputs.try_substitute_receiver_outputs(
+        || Err(ImplementationError::new("wallet locked".to_string(), Some("locked".to_string()))),
+        &Script::new(payee_script()),
+    );
+    match result.err() {
+        Some(OutputSubstitutionError::Implementation(e)) => {
+            assert_eq!(e.code().as_deref(), Some("locked"))
+        }
+        other => panic!("expected Implementation, got {other:?}"),
+    }
+}
+
+#[test]
+fn v1_response_body_is_the_bare_base64_psbt() {
+    let proposal = v1_wants_outputs()
+        .commit_outputs()
+        .commit_inputs()
+        .finalize_proposal(|psbt| Ok(psbt), None, None)
+        .unwrap();
+    let body = proposal.body();
+    assert_eq!(body, proposal.psbt().into_bytes());
+    assert!(!body.ends_with(b"\n"));
+    assert_eq!(
+        Psbt::from_str(std::str::from_utf8(&body).unwrap()).unwrap().serialize(),
+        proposal.psbt_bytes()
+    );
+}
+
+#[test]
+fn receive_session_drives_either_version() {
+    let receiver = receiver(None);
+    for session in [
+        ReceiveSession::from(v1_unchecked_proposal()),
+        ReceiveSession::from(unchecked_proposal(&receiver)),
+    ] {
+        let v2 = session.is_v2();
+        assert_eq!(session.v1().is_some(), !v2);
+        assert_eq!(session.v2().is_some(), v2);
+        let proposal = session
+            .check_broadcast_suitability_with_fee_rate(Some(FeeRate::from_sat_per_kwu(0)), |_| {
+                Ok(true)
+            })
+            .unwrap()
+            .check_inputs_not_owned(|_| Ok(false))
+            .unwrap()
+            .check_no_inputs_seen_before(|_| Ok(false))
+            .unwrap()
+            .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+            .unwrap()
+            .commit_outputs()
+            .contribute_inputs(vec![receiver_input(0, 1_000_000)])
+            .unwrap()
+            .commit_inputs()
+            .finalize_proposal(|psbt| Ok(psbt), None, None)
+            .unwrap();
+        assert_eq!(proposal.is_v2(), v2);
+        assert_eq!(Psbt::from_str(&proposal.psbt()).unwrap().unsigned_tx.input.len(), 2);
+        assert_eq!(proposal.utxos_to_be_locked().len(), 1);
+    }
+}
+
+#[test]
+fn receive_session_refuses_unbroadcastable_proposals() {
+    let session = ReceiveSession::from(v1_unchecked_proposal());
+    assert!(session.check_broadcast_suitability(None, |_| Ok(false)).is_err());
+}
+
+#[test]
+fn candidates_are_ranked_by_how_far_they_avoid_uih() {
+    // The Original PSBT spends 97983400 sats to outputs of 95983068 and 2000000 sats, so a
+    // candidate avoids UIH2 when it is worth more than the 2000000 sat payment output.
+    let candidates: Vec<_> = [1_000_000, 3_000_000, 200_000_000, 5_000_000, 150_000_000]
+        .into_iter()
+        .zip(0..)
+        .map(|(value, vout)| receiver_input(vout, value))
+        .collect();
+    // Candidates worth more than the sender's input score the same and keep their order.
+    let expected = format!("{:?}", [2, 4, 3, 1].map(|i| candidates[i].clone()));
+    let receiver = receiver(None);
+    for session in [
+        ReceiveSession::from(v1_unchecked_proposal()),
+        ReceiveSession::from(unchecked_proposal(&receiver)),
+    ] {
+        let wants_inputs = session
+            .check_broadcast_suitability_with_fee_rate(Some(FeeRate::from_sat_per_kwu(0)), |_| {
+                Ok(true)
+            })
+            .unwrap()
+            .check_inputs_not_owned(|_| Ok(false))
+            .unwrap()
+            .check_no_inputs_seen_before(|_| Ok(false))
+            .unwrap()
+            .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+            .unwrap()
+            .commit_outputs();
+        let ranked = wants_inputs.try_preserving_privacy_ranked(candidates.clone()).unwrap();
+        assert_eq!(format!("{:?}", ranked), expected);
+        let selected = wants_inputs.try_preserving_privacy(candidates.clone()).unwrap();
+        assert_eq!(format!("{:?}", selected), format!("{:?}", candidates[2]));
+
+        assert!(wants_inputs.try_preserving_privacy_ranked(vec![]).is_err());
+        assert!(wants_inputs.try_preserving_privacy_ranked(vec![candidates[0].clone()]).is_err());
+    }
+}
+
+#[test]
+fn proposal_fees_and_amounts_are_computed_from_the_psbt() {
+    let original = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+    let sender_in = original.inputs[0].witness_utxo.as_ref().unwrap().value.to_sat();
+    let original_fee =
+        sender_in - original.unsigned_tx.output.iter().map(|o| o.value.to_sat()).sum::<u64>();
+
+    let split_script = Script::new(vec![0x00, 0x14].into_iter().chain([0x33; 20]).collect());
+    let drain_script = Script::new(vec![0x00, 0x14].into_iter().chain([0x44; 20]).collect());
+    let receiver = receiver(None);
+    let wants_outputs = unchecked_proposal(&receiver)
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap();
+    let substituted = wants_outputs
+        .replace_receiver_outputs(
+            vec![payjoin::bitcoin::TxOut {
+                value: payjoin::bitcoin::Amount::from_sat(1_500_000),
+                script_pubkey: payjoin::bitcoin::ScriptBuf::from_bytes(split_script.to_bytes()),
+            }
+            .into()],
+            &drain_script,
+        )
+        .unwrap();
+    let receiver_scripts = [split_script.to_bytes(), drain_script.to_bytes()];
+
+    // One receiver input with the outputs as the sender made them, then two receiver inputs
+    // with the payment split across substituted outputs.
+    for (wants_outputs, inputs, scripts) in [
+        (&wants_outputs, vec![(0, 1_000_000)], vec![payee_script()]),
+        (&substituted, vec![(0, 1_000_000), (1, 250_000)], receiver_scripts.to_vec()),
+    ] {
+        let receiver_in: u64 = inputs.iter().map(|(_, value)| value).sum();
+        let proposal = wants_outputs
+            .commit_outputs()
+            .contribute_inputs(
+                inputs.into_iter().map(|(vout, value)| receiver_input(vout, value)).collect(),
+            )
+            .unwrap()
+            .commit_inputs()
+            .finalize_proposal(|psbt| Ok(psbt), Some(2), None)
+            .unwrap();
+        let psbt = Psbt::from_str(&proposal.psbt()).unwrap();
+        let outputs = &psbt.unsigned_tx.output;
+        let total_out: u64 = outputs.iter().map(|o| o.value.to_sat()).sum();
+        let receiver_out: u64 = outputs
+            .iter()
+            .filter(|o| scripts.contains(&o.script_pubkey.to_bytes()))
+            .map(|o| o.value.to_sat())
+            .sum();
+
+        let total_fee = sender_in + receiver_in - total_out;
+        assert!(total_fee > original_fee);
+        assert_eq!(proposal.total_fee(), Some(total_fee));
+        assert_eq!(proposal.additional_fee_contribution(), Some(total_fee - original_fee));
+        assert_eq!(proposal.receiver_amount_received(), Some(receiver_out - receiver_in));
+        let split = proposal.fee_split();
+        assert_eq!(split.original_fee, Some(original_fee));
+        assert_eq!(split.total_fee, Some(total_fee));
+        assert_eq!(split.sender_fee.zip(split.receiver_fee).map(|(s, r)| s + r), Some(total_fee));
+
+        let record = proposal.to_tx_record(None, 42);
+        assert_eq!(
+            (record.sender_fee, record.receiver_fee),
+            (split.sender_fee, split.receiver_fee)
+        );
+        assert_eq!(record.net_amount_to_receiver, (receiver_out - receiver_in) as i64);
+        assert_eq!(record.session_id, None);
+    }
+}
@@ -0,0 +1,42 @@
+use payjoin_ffi::receive::ScheduledBroadcast;
+
+fn scheduled(broadcast_at: u64) -> ScheduledBroadcast {
+    ScheduledBroadcast::from_json(
+        &serde_json::json!({
+            "tx": [1, 2, 3],
+            "txid": "00".repeat(32),
+            "weight": 400,
+            "broadcast_at": broadcast_at,
+        })
+        .to_string(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn broadcast_is_due_from_the_expiry() {
+    let scheduled = scheduled(1_700_000_000);
+    assert!(!scheduled.should_broadcast_now(0));
+    assert!(!scheduled.should_broadcast_now(1_699_999_999));
+    assert!(scheduled.should_broadcast_now(1_700_000_000));
+    assert!(scheduled.should_broadcast_now(1_700_000_001));
+}
+
+#[test]
+fn schedule_without_expiry_is_due_immediately() {
+    assert!(scheduled(0).should_broadcast_now(0));
+}
+
+#[test]
+fn schedule_roundtrips_through_json() {
+    let scheduled = scheduled(1_700_000_000);
+    let restored = ScheduledBroadcast::from_json(&scheduled.to_json().unwrap()).unwrap();
+    assert_eq!(restored, scheduled);
+    assert_eq!(restored.fallback_transaction().bytes, vec![1, 2, 3]);
+    assert_eq!(restored.broadcast_at(), 1_700_000_000);
+}
+
+#[test]
+fn incomplete_schedule_is_rejected() {
+    assert!(ScheduledBroadcast::from_json(r#"{"tx": [1], "txid": "00"}"#).is_err());
+}
@@ -0,0 +1,57 @@
+use payjoin::bitcoin::hashes::Hash;
+use payjoin::bitcoin::{OutPoint, Txid};
+use payjoin_ffi::receive::{InMemorySeenInputs, SeenInputsStore};
+
+use crate::common::{payee_script, receiver, unchecked_proposal};
+
+fn outpoint(vout: u32) -> payjoin_ffi::OutPoint {
+    OutPoint { txid: Txid::all_zeros(), vout }.into()
+}
+
+#[test]
+fn outpoints_are_seen_once_marked() {
+    let store = InMemorySeenInputs::new();
+    assert!(!store.is_seen(outpoint(0)).unwrap());
+    assert!(!store.is_seen(outpoint(0)).unwrap());
+    store.mark_seen(vec![outpoint(0)]).unwrap();
+    store.mark_seen(vec![outpoint(0)]).unwrap();
+    assert!(store.is_seen(outpoint(0)).unwrap());
+    assert!(!store.is_seen(outpoint(1)).unwrap());
+}
+
+#[test]
+fn checking_inputs_does_not_mark_them_seen() {
+    let store = InMemorySeenInputs::new();
+    let receiver = receiver(None);
+    let check = || {
+        unchecked_proposal(&receiver)
+            .assume_interactive_receiver()
+            .check_inputs_not_owned(|_| Ok(false))
+            .unwrap()
+            .check_no_inputs_seen_before_with_store(&store)
+    };
+    // A proposal that fails after the check can be retried
+    assert!(check().is_ok());
+    assert!(check().is_ok());
+}
+
+#[test]
+fn committed_proposal_inputs_are_refused() {
+    let store = InMemorySeenInputs::new();
+    let receiver = receiver(None);
+    let proposal = unchecked_proposal(&receiver)
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap();
+    let inputs = proposal
+        .check_no_inputs_seen_before_with_store(&store)
+        .unwrap()
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .unwrap()
+        .commit_outputs()
+        .commit_inputs()
+        .finalize_proposal(|psbt| Ok(psbt), None, None)
+        .unwrap();
+    inputs.mark_inputs_seen(&store).unwrap();
+    assert!(proposal.check_no_inputs_seen_before_with_store(&store).is_err());
+}
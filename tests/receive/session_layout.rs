@@ -0,0 +1,40 @@
+//! The FFI reads and replaces fields of payjoin's serialized sessions that payjoin has no API
+//! for. These tests pin where those fields are, so a payjoin update that moves them fails here.
+
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+use payjoin_ffi::OhttpKeys;
+
+use crate::common::{ohttp_keys, receiver, unchecked_proposal};
+
+#[test]
+fn receiver_keeps_its_keys_and_expiry_in_the_context() {
+    let session: serde_json::Value =
+        serde_json::from_str(&receiver(None).to_json().unwrap()).unwrap();
+    assert_eq!(
+        session.pointer("/context/ohttp_keys"),
+        Some(&serde_json::to_value(&ohttp_keys().0).unwrap())
+    );
+    assert!(session.pointer("/context/s").and_then(|s| s.as_array()).is_some_and(|s| s.len() == 2));
+    assert!(session.pointer("/context/expiry").is_some());
+}
+
+#[test]
+fn refreshed_keys_replace_the_session_keys() {
+    let suite = SymmetricSuite::new(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let config = KeyConfig::derive(1, Kem::K256Sha256, vec![suite], &[8; 32]).unwrap();
+    let rotated = OhttpKeys(payjoin::OhttpKeys(config));
+    let receiver = receiver(None).refresh_ohttp_keys(rotated.clone(), 1_700_000_000).unwrap();
+    assert_eq!(receiver.ohttp_keys().unwrap().0.encode().unwrap(), rotated.0.encode().unwrap());
+}
+
+#[test]
+fn proposal_keeps_the_sender_params_under_v1() {
+    let proposal: payjoin::receive::v2::UncheckedProposal =
+        unchecked_proposal(&receiver(None)).into();
+    let proposal = serde_json::to_value(&proposal).unwrap();
+    let params = proposal.pointer("/v1/params").unwrap();
+    assert!(params.get("additional_fee_contribution").is_some());
+    assert!(params["min_fee_rate"].is_u64());
+    assert!(params["output_substitution"].is_string());
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use payjoin::bitcoin::absolute::LockTime;
+use payjoin::bitcoin::hashes::Hash;
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::transaction::Version;
+use payjoin::bitcoin::{
+    Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, WPubkeyHash, Witness,
+};
+use payjoin_ffi::receive::v1::{Headers, MapHeaders, UncheckedProposal, MAX_CONTENT_LENGTH};
+use payjoin_ffi::receive::V1RequestError;
+
+use crate::common::{payee_script, ORIGINAL_PSBT};
+
+fn headers(content_type: &str, content_length: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("Content-Type".to_string(), content_type.to_string()),
+        ("Content-Length".to_string(), content_length.to_string()),
+    ])
+}
+
+fn from_request(body: &[u8], query: &str, headers: HashMap<String, String>) -> V1RequestError {
+    match UncheckedProposal::from_request(body.to_vec(), query.to_string(), headers) {
+        Ok(_) => panic!("request should be rejected"),
+        Err(e) => e,
+    }
+}
+
+#[test]
+fn rejects_missing_and_invalid_headers() {
+    let body = b"cHNidP8=";
+    assert!(matches!(
+        from_request(body, "v=1", HashMap::new()),
+        V1RequestError::MissingHeader { name } if name == "content-type"
+    ));
+    assert!(matches!(
+        from_request(body, "v=1", headers("application/json", "8")),
+        V1RequestError::InvalidContentType { .. }
+    ));
+    assert!(matches!(
+        from_request(body, "v=1", headers("text/plain", "eight")),
+        V1RequestError::InvalidContentLength { .. }
+    ));
+    assert!(matches!(
+        from_request(body, "v=1", headers("text/plain", "9")),
+        V1RequestError::ContentLengthMismatch { declared: 9, actual: 8 }
+    ));
+    assert!(matches!(
+        from_request(body, "v=1", headers("text/plain", &(MAX_CONTENT_LENGTH + 1).to_string())),
+        V1RequestError::ContentTooLarge { .. }
+    ));
+}
+
+#[test]
+fn rejects_invalid_query_parameters() {
+    let body = b"cHNidP8=";
+    let ok_headers = || headers("text/plain", "8");
+    assert!(matches!(
+        from_request(body, "v=2", ok_headers()),
+        V1RequestError::UnsupportedVersion { version } if version == "2"
+    ));
+    for query in [
+        "v=1&additionalfeeoutputindex=-1",
+        "v=1&maxadditionalfeecontribution=lots",
+        "v=1&minfeerate=NaN",
+        "v=1&disableoutputsubstitution=1",
+    ] {
+        assert!(
+            matches!(
+                from_request(body, query, ok_headers()),
+                V1RequestError::InvalidParameter { .. }
+            ),
+            "{query} should be rejected"
+        );
+    }
+}
+
+#[test]
+fn rejects_malformed_psbt() {
+    let body = b"not a psbt";
+    assert!(matches!(
+        from_request(body, "v=1", headers("text/plain", "10")),
+        V1RequestError::Proposal(_)
+    ));
+}
+
+/// Headers stored under the spelling the HTTP framework used, as a case-sensitive map would.
+struct CanonicalHeaders(HashMap<String, String>);
+
+impl Headers for CanonicalHeaders {
+    fn get_header(&self, key: String) -> Option<String> {
+        self.0.get(&key).cloned()
+    }
+}
+
+#[test]
+fn header_names_are_case_insensitive() {
+    let headers = MapHeaders::new(headers("text/plain", "8"));
+    assert_eq!(headers.get_header("CONTENT-TYPE".to_string()).as_deref(), Some("text/plain"));
+
+    let canonical = CanonicalHeaders(headers_with_names("Content-Type", "Content-Length"));
+    let uppercase = CanonicalHeaders(headers_with_names("CONTENT-TYPE", "CONTENT-LENGTH"));
+    for headers in [canonical, uppercase] {
+        match UncheckedProposal::from_request_with_headers(
+            b"cHNidP8=".to_vec(),
+            "v=1".to_string(),
+            &headers,
+        ) {
+            Err(V1RequestError::Proposal(_)) => {}
+            Err(e) => panic!("headers should be found, got {e}"),
+            Ok(_) => panic!("an empty PSBT should be rejected"),
+        }
+    }
+}
+
+fn headers_with_names(content_type: &str, content_length: &str) -> HashMap<String, String> {
+    HashMap::from([
+        (content_type.to_string(), "text/plain".to_string()),
+        (content_length.to_string(), "8".to_string()),
+    ])
+}
+
+fn original_tx() -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new() }],
+    }
+}
+
+#[test]
+fn fallback_details_are_computed_from_the_original_psbt() {
+    let tx = original_tx();
+    let body = Psbt::from_unsigned_tx(tx.clone()).unwrap().to_string().into_bytes();
+    let proposal = UncheckedProposal::from_request(
+        body.clone(),
+        "v=1".to_string(),
+        headers("text/plain", &body.len().to_string()),
+    )
+    .unwrap_or_else(|e| panic!("the PSBT should be accepted, got {e}"));
+    assert_eq!(proposal.fallback_txid(), tx.compute_txid().to_string());
+    assert_eq!(proposal.fallback_weight(), tx.weight().to_wu());
+    let fallback = proposal.fallback_transaction();
+    assert_eq!(fallback.bytes, proposal.extract_tx_to_schedule_broadcast());
+    assert_eq!(fallback.txid, proposal.fallback_txid());
+}
+
+/// The Original PSBT with a P2WPKH input next to its P2SH-P2WPKH one.
+fn mixed_input_scripts_psbt() -> Psbt {
+    let mut psbt = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+    let txin = TxIn {
+        previous_output: OutPoint { txid: Txid::from_byte_array([2; 32]), vout: 0 },
+        sequence: psbt.unsigned_tx.input[0].sequence,
+        ..Default::default()
+    };
+    let mut input = psbt.inputs[0].clone();
+    input.witness_utxo = Some(TxOut {
+        value: Amount::from_sat(100_000),
+        script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([3; 20])),
+    });
+    input.final_script_sig = None;
+    psbt.unsigned_tx.input.push(txin);
+    psbt.inputs.push(input);
+    psbt
+}
+
+#[test]
+fn mixed_input_scripts_are_accepted() {
+    let body = mixed_input_scripts_psbt().to_string();
+    let proposal = UncheckedProposal::from_request(
+        body.clone().into_bytes(),
+        "v=1".to_string(),
+        headers("text/plain", &body.len().to_string()),
+    )
+    .unwrap();
+    let outputs_unknown = proposal
+        .assume_interactive_receiver()
+        .check_inputs_not_owned(|_| Ok(false))
+        .unwrap()
+        .check_no_inputs_seen_before(|_| Ok(false))
+        .unwrap();
+    assert!(outputs_unknown
+        .identify_receiver_outputs(|script| Ok(*script == payee_script()))
+        .is_ok());
+}
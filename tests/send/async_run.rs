@@ -0,0 +1,212 @@
+#![cfg(feature = "async")]
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin_ffi::receive::{ImplementationError, Receiver};
+use payjoin_ffi::send::{HttpTransport, PollingPolicy, RunError, Sender};
+use payjoin_ffi::uri::Url;
+
+use crate::common::{
+    bhttp_ok, bhttp_request_content, bhttp_response, key_config, payjoin_payload, receiver,
+};
+
+/// A sender paying a v2 endpoint whose directory uses [`key_config`].
+fn sender() -> Sender {
+    crate::common::sender(&receiver(None))
+}
+
+fn ohttp_relay() -> Url {
+    Url::parse("https://relay.example".to_string()).unwrap()
+}
+
+/// A directory that accepts the Original PSBT and never has a proposal for the sender.
+struct MockDirectory {
+    server: Mutex<ohttp::Server>,
+    requests: Mutex<Vec<Vec<u8>>>,
+}
+
+impl MockDirectory {
+    fn new() -> Self {
+        Self {
+            server: Mutex::new(ohttp::Server::new(key_config()).unwrap()),
+            requests: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for MockDirectory {
+    async fn post(
+        &self,
+        _url: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, ImplementationError> {
+        assert_eq!(headers["Content-Type"], "message/ohttp-req");
+        let (_, response) = self.server.lock().unwrap().decapsulate(&body).unwrap();
+        let mut requests = self.requests.lock().unwrap();
+        // 200 for the Original PSBT, 202 for every poll
+        let status = if requests.is_empty() { 200 } else { 202 };
+        requests.push(body);
+        Ok(response.encapsulate(&bhttp_response(status)).unwrap())
+    }
+
+    async fn sleep(&self, secs: u64) {
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+    }
+}
+
+struct FailingTransport;
+
+#[async_trait::async_trait]
+impl HttpTransport for FailingTransport {
+    async fn post(
+        &self,
+        _url: String,
+        _headers: HashMap<String, String>,
+        _body: Vec<u8>,
+    ) -> Result<Vec<u8>, ImplementationError> {
+        Err(ImplementationError::new("connection refused".to_string(), None))
+    }
+
+    async fn sleep(&self, _secs: u64) {}
+}
+
+/// A directory relaying the Original PSBT to `receiver`, which proposes a payjoin without
+/// contributing inputs, and returning the proposal to the sender's first poll.
+struct ProposingDirectory {
+    server: Mutex<ohttp::Server>,
+    receiver: Receiver,
+    proposal: Mutex<Option<Vec<u8>>>,
+    sleeps: Mutex<Vec<u64>>,
+}
+
+impl ProposingDirectory {
+    fn new() -> Self {
+        Self {
+            server: Mutex::new(ohttp::Server::new(key_config()).unwrap()),
+            receiver: receiver(None),
+            proposal: Mutex::default(),
+            sleeps: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for ProposingDirectory {
+    async fn post(
+        &self,
+        _url: String,
+        _headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, ImplementationError> {
+        let (request, response) = self.server.lock().unwrap().decapsulate(&body).unwrap();
+        let mut proposal = self.proposal.lock().unwrap();
+        let content = match proposal.as_ref() {
+            Some(payload) => payload.clone(),
+            None => {
+                *proposal = Some(payjoin_payload(&self.receiver, &bhttp_request_content(&request)));
+                vec![]
+            }
+        };
+        Ok(response.encapsulate(&bhttp_ok(&content)).unwrap())
+    }
+
+    async fn sleep(&self, secs: u64) {
+        self.sleeps.lock().unwrap().push(secs);
+    }
+}
+
+/// A directory whose first request fails, recording how long the sender waits instead of
+/// waiting.
+struct FlakyDirectory {
+    directory: MockDirectory,
+    failed: Mutex<bool>,
+    sleeps: Mutex<Vec<u64>>,
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for FlakyDirectory {
+    async fn post(
+        &self,
+        url: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, ImplementationError> {
+        if !std::mem::replace(&mut *self.failed.lock().unwrap(), true) {
+            return Err(ImplementationError::new("connection reset".to_string(), None));
+        }
+        self.directory.post(url, headers, body).await
+    }
+
+    async fn sleep(&self, secs: u64) {
+        self.sleeps.lock().unwrap().push(secs);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn run_returns_the_receivers_proposal() {
+    let directory = ProposingDirectory::new();
+    let sender = crate::common::sender(&directory.receiver);
+    let psbt = sender.run(&directory, ohttp_relay(), 1, 60).await.unwrap();
+    let psbt = Psbt::from_str(&psbt).unwrap();
+    let original = Psbt::from_str(crate::common::ORIGINAL_PSBT).unwrap();
+    assert_eq!(psbt.unsigned_tx.input, original.unsigned_tx.input);
+    assert_eq!(psbt.unsigned_tx.output.len(), original.unsigned_tx.output.len());
+    // The proposal was there at the first poll
+    assert!(directory.sleeps.lock().unwrap().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn run_polls_with_fresh_requests_until_the_deadline() {
+    let directory = MockDirectory::new();
+    let result = sender().run(&directory, ohttp_relay(), 1, 3).await;
+    assert!(matches!(
+        result,
+        Err(RunError::FallbackDue { original_tx: Some(original_tx) }) if !original_tx.is_empty()
+    ));
+
+    let requests = directory.requests.lock().unwrap();
+    // The Original PSBT and at least one poll
+    assert!(requests.len() >= 2);
+    for (i, request) in requests.iter().enumerate() {
+        assert!(requests[i + 1..].iter().all(|other| other != request));
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn run_is_cancelled_by_dropping_it() {
+    let directory = MockDirectory::new();
+    let sender = sender();
+    let run = sender.run(&directory, ohttp_relay(), 60, 600);
+    // The Original PSBT and the first poll, then the run waits a minute for the next
+    assert!(tokio::time::timeout(Duration::from_secs(30), run).await.is_err());
+    assert_eq!(directory.requests.lock().unwrap().len(), 2);
+    tokio::time::advance(Duration::from_secs(120)).await;
+    assert_eq!(directory.requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn run_returns_transport_errors() {
+    let result = sender().run(&FailingTransport, ohttp_relay(), 1, 60).await;
+    assert!(matches!(result, Err(RunError::Transport(_))));
+}
+
+#[tokio::test]
+async fn run_with_policy_backs_off_and_retries_transient_errors() {
+    let directory = FlakyDirectory {
+        directory: MockDirectory::new(),
+        failed: Mutex::new(false),
+        sleeps: Mutex::default(),
+    };
+    let policy = PollingPolicy::new(1, 2.0, 4, 10);
+    let result = sender().run_with_policy(&directory, ohttp_relay(), &policy).await;
+    assert!(matches!(result, Err(RunError::FallbackDue { .. })));
+    // A retry after the failed post, then the Original PSBT and four polls until the schedule ends
+    assert_eq!(*directory.sleeps.lock().unwrap(), vec![1, 2, 4, 3]);
+    assert_eq!(directory.directory.requests.lock().unwrap().len(), 5);
+}
@@ -0,0 +1,124 @@
+use std::str::FromStr;
+
+use payjoin::bitcoin::hashes::Hash;
+use payjoin::bitcoin::psbt::{Output, Psbt};
+use payjoin::bitcoin::{Amount, ScriptBuf, TxOut, WPubkeyHash};
+use payjoin_ffi::send::{BuildSenderErrorKind, Sender, SenderBuilder};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::FeeRate;
+
+use crate::common::ORIGINAL_PSBT;
+
+fn builder(psbt: &Psbt) -> SenderBuilder {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    SenderBuilder::new(psbt.to_string(), uri).unwrap()
+}
+
+/// Add an output paying `value` to `script_pubkey`, funded from the change at output 0.
+fn add_output(psbt: &mut Psbt, script_pubkey: ScriptBuf, value: Amount) {
+    psbt.unsigned_tx.output[0].value -= value;
+    psbt.unsigned_tx.output.push(TxOut { value, script_pubkey });
+    psbt.outputs.push(Output::default());
+}
+
+/// The Original PSBT batched with other payees, `output_count` outputs in total.
+fn batched(output_count: usize) -> Psbt {
+    let mut psbt = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+    for i in 2..output_count {
+        let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([i as u8; 20]));
+        add_output(&mut psbt, script, Amount::from_sat(10_000));
+    }
+    psbt
+}
+
+fn build_batched(psbt: &Psbt) -> Result<Sender, Option<BuildSenderErrorKind>> {
+    builder(psbt)
+        .build_batched_with_fee_rate(0, 1_000, FeeRate::from_sat_per_kwu(0), false)
+        .map_err(|e| e.kind())
+}
+
+#[test]
+fn auto_detection_needs_at_most_two_outputs() {
+    let psbt = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+    let sender = builder(&psbt).build_recommended_with_fee_rate(FeeRate::from_sat_per_kwu(0));
+    let sender = sender.unwrap();
+    assert_eq!(sender.payee_output_index(), Some(1));
+    assert_eq!(sender.change_output_index(), Some(0));
+
+    let sender = builder(&psbt)
+        .build_with_additional_fee_with_fee_rate(1_000, None, FeeRate::from_sat_per_kwu(0), false)
+        .unwrap();
+    assert_eq!(sender.change_output_index(), Some(0));
+
+    let batched = builder(&batched(3)).build_with_additional_fee_with_fee_rate(
+        1_000,
+        None,
+        FeeRate::from_sat_per_kwu(0),
+        false,
+    );
+    assert!(batched.is_err());
+}
+
+#[test]
+fn batched_payments_name_their_change_output() {
+    for output_count in [3, 50] {
+        let sender = build_batched(&batched(output_count)).unwrap();
+        assert_eq!(sender.payee_output_index(), Some(1));
+        assert_eq!(sender.change_output_index(), Some(0));
+
+        let (request, _) = sender.extract_v1();
+        let wire = Psbt::from_str(std::str::from_utf8(&request.body).unwrap()).unwrap();
+        assert_eq!(wire.unsigned_tx.output.len(), output_count);
+
+        let restored = Sender::from_json(&sender.to_json().unwrap()).unwrap();
+        assert_eq!(restored.payee_output_index(), Some(1));
+        assert_eq!(restored.change_output_index(), Some(0));
+    }
+}
+
+#[test]
+fn batched_payee_output_must_match_the_uri() {
+    let mut psbt = batched(3);
+    psbt.unsigned_tx.output[1].value = Amount::from_sat(1_999_000);
+    assert_eq!(
+        build_batched(&psbt).err(),
+        Some(Some(BuildSenderErrorKind::PayeeAmountMismatch {
+            expected: 2_000_000,
+            actual: 1_999_000
+        }))
+    );
+
+    // The amount is checked against everything paid to the payee
+    let mut split = batched(3);
+    split.unsigned_tx.output[1].value = Amount::from_sat(1_500_000);
+    let payee = split.unsigned_tx.output[1].script_pubkey.clone();
+    add_output(&mut split, payee, Amount::from_sat(400_000));
+    assert_eq!(
+        build_batched(&split).err(),
+        Some(Some(BuildSenderErrorKind::PayeeAmountMismatch {
+            expected: 2_000_000,
+            actual: 1_900_000
+        }))
+    );
+
+    let mut psbt = batched(3);
+    let payee = psbt.unsigned_tx.output[1].script_pubkey.clone();
+    add_output(&mut psbt, payee, Amount::from_sat(2_000_000));
+    assert_eq!(
+        build_batched(&psbt).err(),
+        Some(Some(BuildSenderErrorKind::AmbiguousPayeeOutput { matching_outputs: 2 }))
+    );
+
+    let psbt = batched(3);
+    let error = builder(&psbt)
+        .build_batched_with_fee_rate(1, 1_000, FeeRate::from_sat_per_kwu(0), false)
+        .err()
+        .and_then(|e| e.kind());
+    assert_eq!(error, Some(BuildSenderErrorKind::ChangeIndexPointsAtPayee { index: 1 }));
+}
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin_ffi::receive::Receiver;
+use payjoin_ffi::send::{BuildSenderErrorKind, SenderBuilder};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::{Address, FeeRate, Network, OhttpKeys};
+
+use crate::common::{key_config, ORIGINAL_PSBT};
+
+fn builder(psbt: &Psbt) -> SenderBuilder {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    SenderBuilder::new(psbt.to_string(), uri).unwrap()
+}
+
+fn original() -> Psbt {
+    Psbt::from_str(ORIGINAL_PSBT).unwrap()
+}
+
+fn non_incentivizing_error(psbt: &Psbt) -> Option<BuildSenderErrorKind> {
+    builder(psbt)
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .err()
+        .and_then(|e| e.kind())
+}
+
+#[test]
+fn cleartext_endpoints_are_rejected() {
+    let address =
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap();
+    let keys = OhttpKeys(payjoin::OhttpKeys(key_config()));
+    let receiver = Receiver::new(address, "http://directory.example".to_string(), keys, None);
+    let uri = receiver.unwrap().pj_uri(None, None, None).unwrap();
+    let endpoint = uri.pj_endpoint();
+    assert!(endpoint.starts_with("http://directory.example/"));
+
+    let error = SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri).err().unwrap();
+    assert_eq!(error.kind(), Some(BuildSenderErrorKind::UnsecuredEndpoint { endpoint }));
+}
+
+#[test]
+fn input_without_utxo_is_identified() {
+    let mut psbt = original();
+    psbt.inputs[0].witness_utxo = None;
+    assert_eq!(
+        non_incentivizing_error(&psbt),
+        Some(BuildSenderErrorKind::MissingUtxoInfo { input_index: 0 })
+    );
+}
+
+#[test]
+fn unfinalized_input_is_identified() {
+    let mut psbt = original();
+    psbt.inputs[0].final_script_sig = None;
+    psbt.inputs[0].final_script_witness = None;
+    assert_eq!(
+        non_incentivizing_error(&psbt),
+        Some(BuildSenderErrorKind::InputNotFinalized { input_index: 0 })
+    );
+}
+
+#[test]
+fn fee_output_checks() {
+    let builder = builder(&original());
+    let kind = |change_index, contribution, clamp| {
+        builder
+            .build_with_additional_fee_with_fee_rate(
+                contribution,
+                Some(change_index),
+                FeeRate::from_sat_per_kwu(0),
+                clamp,
+            )
+            .err()?
+            .kind()
+    };
+    assert_eq!(
+        kind(2, 1000, false),
+        Some(BuildSenderErrorKind::ChangeIndexOutOfBounds { index: 2, output_count: 2 })
+    );
+    assert_eq!(
+        kind(1, 1000, false),
+        Some(BuildSenderErrorKind::ChangeIndexPointsAtPayee { index: 1 })
+    );
+    let available = original().unsigned_tx.output[0].value.to_sat();
+    assert_eq!(
+        kind(0, available + 1, false),
+        Some(BuildSenderErrorKind::FeeOutputTooSmall {
+            output_index: 0,
+            required: available + 1,
+            available,
+        })
+    );
+    assert_eq!(kind(0, available + 1, true), None);
+    assert!(builder
+        .build_with_additional_fee_with_fee_rate(1000, Some(0), FeeRate::from_sat_per_kwu(0), false)
+        .is_ok());
+}
+
+#[test]
+fn change_index_beyond_u8_is_checked() {
+    let builder = builder(&original());
+    let error = builder
+        .build_with_additional_fee_with_fee_rate(
+            1000,
+            Some(300),
+            FeeRate::from_sat_per_kwu(0),
+            false,
+        )
+        .unwrap_err();
+    assert_eq!(
+        error.kind(),
+        Some(BuildSenderErrorKind::ChangeIndexOutOfBounds { index: 300, output_count: 2 })
+    );
+}
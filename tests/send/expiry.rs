@@ -0,0 +1,66 @@
+use payjoin_ffi::send::{ResponseError, Sender, SenderBuilder};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::{DirectoryCapabilities, FeeRate};
+
+use crate::common::{receiver, v2_get_context, ORIGINAL_PSBT};
+
+/// A sender paying a v2 endpoint whose directory uses [`key_config`], giving up at unix time
+/// `deadline`.
+fn sender(deadline: u64) -> Sender {
+    let pj_uri = receiver(None).pj_uri(None, None, None).unwrap().as_string();
+    let pj_params = &pj_uri[pj_uri.find('?').unwrap() + 1..];
+    let uri =
+        Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&{pj_params}"))
+            .unwrap()
+            .check_pj_supported()
+            .unwrap();
+    SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri)
+        .unwrap()
+        .fallback_at(deadline)
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap()
+}
+
+#[test]
+fn process_response_fails_once_the_fallback_is_due() {
+    let sender = sender(0);
+    let context = v2_get_context(&sender);
+    let (_, ohttp_ctx) = context.extract_req("https://relay.example".to_string()).unwrap();
+    let result = context.process_response(&[0; 8192], &ohttp_ctx);
+    assert!(matches!(
+        result,
+        Err(ResponseError::FallbackDue { original_tx }) if Some(&original_tx) == sender.fallback_tx().as_ref()
+    ));
+}
+
+#[test]
+fn fallback_survives_persistence() {
+    let sender = sender(u32::MAX as u64);
+    assert!(sender.fallback_tx().is_some_and(|tx| !tx.is_empty()));
+    let restored = Sender::from_json(&sender.to_json().unwrap()).unwrap();
+    assert_eq!(restored.fallback_tx(), sender.fallback_tx());
+    assert_eq!(restored.fallback_deadline(), sender.fallback_deadline());
+    let context = v2_get_context(&restored);
+    let restored = Sender::from_json(&context.to_json().unwrap()).unwrap();
+    assert_eq!(restored.fallback_deadline(), sender.fallback_deadline());
+}
+
+#[test]
+fn polls_wait_only_at_long_polling_directories() {
+    let sender = sender(u32::MAX as u64);
+    let deadline = sender.fallback_deadline().unwrap();
+    let context = v2_get_context(&sender);
+    let relay = "https://relay.example".to_string();
+    let legacy = DirectoryCapabilities::legacy();
+    let (request, _) =
+        context.extract_poll_req_with_wait(relay.clone(), deadline - 60, 30, &legacy).unwrap();
+    assert_eq!(request.timeout_secs, None);
+
+    let long_poll = DirectoryCapabilities { long_poll: true, ..legacy };
+    let (request, _) =
+        context.extract_poll_req_with_wait(relay.clone(), deadline - 60, 30, &long_poll).unwrap();
+    assert_eq!(request.timeout_secs, Some(30));
+    let (request, _) =
+        context.extract_poll_req_with_wait(relay, deadline - 10, 30, &long_poll).unwrap();
+    assert_eq!(request.timeout_secs, Some(10));
+}
@@ -0,0 +1,84 @@
+use payjoin::bitcoin::base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use payjoin::bitcoin::base64::Engine;
+use payjoin::bitcoin::bech32::{self, Hrp, NoChecksum};
+use payjoin::bitcoin::hex::FromHex;
+use payjoin_ffi::send::{ExtractedRequest, Sender, SenderBuilder};
+use payjoin_ffi::uri::{Uri, Url};
+use payjoin_ffi::FeeRate;
+
+use crate::common::ORIGINAL_PSBT;
+
+// The secp256k1 generator point, standing in for a receiver's public key
+const RECEIVER_KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+fn sender(endpoint: &str) -> Sender {
+    let uri =
+        Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj={endpoint}"))
+            .unwrap()
+            .check_pj_supported()
+            .unwrap();
+    SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri)
+        .unwrap()
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap()
+}
+
+fn ohttp_relay() -> Url {
+    Url::parse("https://relay.example.com".to_string()).unwrap()
+}
+
+#[test]
+fn v1_endpoint_extracts_v1_request() {
+    let sender = sender("https://example.com/pj");
+    assert!(!sender.can_use_v2());
+    assert_eq!(sender.endpoint().as_string(), "https://example.com/pj");
+    assert!(sender.ohttp_keys().is_none());
+    let ExtractedRequest::V1 { request, .. } =
+        sender.extract_highest_version(ohttp_relay()).unwrap()
+    else {
+        panic!("a v1 endpoint should yield a v1 request");
+    };
+    assert_eq!(request.url.as_string(), sender.extract_v1().0.url.as_string());
+}
+
+#[test]
+fn endpoint_with_receiver_key_can_use_v2() {
+    let rk = bech32::encode_upper::<NoChecksum>(
+        Hrp::parse("RK").unwrap(),
+        &Vec::<u8>::from_hex(RECEIVER_KEY).unwrap(),
+    )
+    .unwrap();
+    let sender = sender(&format!("https://example.com/SESSION%23{rk}"));
+    assert!(sender.can_use_v2());
+    assert_eq!(sender.endpoint().as_string(), format!("https://example.com/SESSION#{rk}"));
+    assert!(sender.ohttp_keys().is_none());
+    // The support is derived from the endpoint again when the sender is restored.
+    assert!(Sender::from_json(&sender.to_json().unwrap()).unwrap().can_use_v2());
+    // A v1 request can still be forced.
+    let (request, _) = sender.extract_v1();
+    assert!(request.url.as_string().starts_with("https://example.com/SESSION"));
+}
+
+#[test]
+fn receiver_pubkey_is_reported_for_logs() {
+    let key = Vec::<u8>::from_hex(RECEIVER_KEY).unwrap();
+    let rk =
+        |key: &[u8]| bech32::encode_upper::<NoChecksum>(Hrp::parse("RK").unwrap(), key).unwrap();
+    let endpoint = format!("https://example.com/SESSION%23{}", rk(&key));
+    let expected = Some(URL_SAFE_NO_PAD.encode(&key));
+    assert_eq!(sender(&endpoint).receiver_pubkey(), expected);
+    let uri = Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj={endpoint}"))
+        .unwrap()
+        .check_pj_supported()
+        .unwrap();
+    assert_eq!(uri.receiver_pubkey(), expected);
+
+    assert_eq!(sender("https://example.com/pj").receiver_pubkey(), None);
+    // A truncated key
+    let endpoint = format!("https://example.com/SESSION%23{}", rk(&key[..32]));
+    let uri = Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj={endpoint}"))
+        .unwrap()
+        .check_pj_supported()
+        .unwrap();
+    assert_eq!(uri.receiver_pubkey(), None);
+}
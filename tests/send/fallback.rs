@@ -0,0 +1,83 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use payjoin_ffi::send::{Sender, SenderBuilder};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::FeeRate;
+
+use crate::common::{receiver, ORIGINAL_PSBT};
+
+/// A builder paying the Original PSBT's payee at a v1 endpoint, which declares no expiry.
+fn v1_builder() -> SenderBuilder {
+    builder("pj=https://example.com/SESSION")
+}
+
+/// A builder paying the Original PSBT's payee at the v2 endpoint of a receiver whose session
+/// expires `expire_after` seconds from now, and the expiry the endpoint declares.
+fn v2_builder(expire_after: u64) -> (SenderBuilder, u64) {
+    let pj_uri = receiver(Some(expire_after)).pj_uri(None, None, None).unwrap();
+    let expiry = pj_uri.session_expiry().unwrap();
+    let pj_uri = pj_uri.as_string();
+    (builder(&pj_uri[pj_uri.find('?').unwrap() + 1..]), expiry)
+}
+
+/// A builder paying the Original PSBT's payee with the payjoin parameters `pj_params`.
+fn builder(pj_params: &str) -> SenderBuilder {
+    let uri =
+        Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&{pj_params}"))
+            .unwrap()
+            .check_pj_supported()
+            .unwrap();
+    SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri).unwrap()
+}
+
+fn build(builder: SenderBuilder) -> Sender {
+    builder.build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0)).unwrap()
+}
+
+#[test]
+fn no_deadline_without_fallback_or_expiry() {
+    let sender = build(v1_builder());
+    assert_eq!(sender.fallback_deadline(), None);
+    assert!(!sender.fallback_due(u64::MAX));
+}
+
+#[test]
+fn fallback_due_at_deadline() {
+    let sender = build(v1_builder().fallback_at(1_000));
+    assert_eq!(sender.fallback_deadline(), Some(1_000));
+    assert!(!sender.fallback_due(999));
+    assert!(sender.fallback_due(1_000));
+}
+
+#[test]
+fn fallback_after_secs_counts_from_build() {
+    let now = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let before = now();
+    let deadline = build(v1_builder().fallback_after_secs(60)).fallback_deadline().unwrap();
+    assert!((before + 60..=now() + 60).contains(&deadline));
+}
+
+#[test]
+fn receiver_expiry_before_fallback_wins() {
+    let (builder, expiry) = v2_builder(10);
+    let sender = build(builder.fallback_at(expiry + 3600));
+    assert_eq!(sender.fallback_deadline(), Some(expiry));
+    assert!(!sender.fallback_due(expiry - 1));
+    assert!(sender.fallback_due(expiry));
+}
+
+#[test]
+fn fallback_before_receiver_expiry_wins() {
+    let (builder, expiry) = v2_builder(3600);
+    let sender = build(builder.fallback_at(expiry - 3590));
+    assert_eq!(sender.fallback_deadline(), Some(expiry - 3590));
+    assert!(!sender.fallback_due(expiry - 3591));
+    assert!(sender.fallback_due(expiry - 3590));
+}
+
+#[test]
+fn deadline_survives_persistence() {
+    let sender = build(v1_builder().fallback_at(1_000));
+    let restored = Sender::from_json(&sender.to_json().unwrap()).unwrap();
+    assert_eq!(restored.fallback_deadline(), Some(1_000));
+}
@@ -0,0 +1,41 @@
+use payjoin_ffi::send::{BuildSenderErrorKind, SenderBuilder};
+use payjoin_ffi::FeeRate;
+
+use crate::common::ORIGINAL_PSBT;
+
+const BIP21: &str =
+    "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj";
+
+fn kind(psbt: &str, bip21: &str) -> Option<BuildSenderErrorKind> {
+    SenderBuilder::from_psbt_and_bip21(psbt.to_string(), bip21.to_string()).err()?.kind()
+}
+
+#[test]
+fn builds_from_a_payjoin_uri() {
+    let builder = SenderBuilder::from_psbt_and_bip21(ORIGINAL_PSBT.to_string(), BIP21.to_string());
+    let sender =
+        builder.unwrap().build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0));
+    assert_eq!(sender.unwrap().payee_output_index(), Some(1));
+}
+
+#[test]
+fn rejects_uris_without_a_payjoin_endpoint() {
+    let bip21 = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02";
+    assert_eq!(kind(ORIGINAL_PSBT, bip21), Some(BuildSenderErrorKind::MissingPayjoinEndpoint));
+}
+
+#[test]
+fn rejects_amounts_the_psbt_doesnt_pay() {
+    let bip21 = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.03&pj=https://example.com/pj";
+    assert_eq!(
+        kind(ORIGINAL_PSBT, bip21),
+        Some(BuildSenderErrorKind::PayeeAmountMismatch { expected: 3_000_000, actual: 2_000_000 })
+    );
+}
+
+#[test]
+fn rejects_cleartext_endpoints() {
+    let bip21 = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=http://example.com/pj";
+    let result = SenderBuilder::from_psbt_and_bip21(ORIGINAL_PSBT.to_string(), bip21.to_string());
+    assert!(result.is_err());
+}
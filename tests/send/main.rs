@@ -0,0 +1,24 @@
+//! Integration tests of the sender.
+#![cfg(not(feature = "uniffi"))]
+
+extern crate payjoin_ffi_v0_22 as payjoin_ffi;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+mod async_run;
+mod batched;
+mod build_error;
+mod expiry;
+mod extract;
+mod fallback;
+mod from_bip21;
+mod params;
+mod persistence;
+mod polling_policy;
+mod post_context;
+mod psbt_metadata;
+mod psbt_v2;
+mod request_headers;
+mod response_body;
+mod tx_record;
@@ -0,0 +1,62 @@
+use payjoin_ffi::send::{Sender, SenderBuilder};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::FeeRate;
+
+use crate::common::ORIGINAL_PSBT;
+
+fn builder() -> SenderBuilder {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri).unwrap()
+}
+
+#[test]
+fn accessors_match_the_request() {
+    let sender = builder()
+        .build_with_additional_fee_with_fee_rate(
+            1_000,
+            Some(0),
+            FeeRate::from_sat_per_vb(2).unwrap(),
+            false,
+        )
+        .unwrap();
+    assert_eq!(sender.fee_contribution(), Some((1_000, 0)));
+    assert_eq!(sender.min_fee_rate_sat_per_vb(), Some(2));
+    assert_eq!(sender.min_fee_rate().map(|rate| rate.to_sat_per_kwu()), Some(500));
+
+    let (request, _) = sender.extract_v1();
+    let url = request.url.as_string();
+    assert!(url.contains("maxadditionalfeecontribution=1000"));
+    assert!(url.contains("additionalfeeoutputindex=0"));
+    assert_eq!(sender.original_psbt().into_bytes(), request.body);
+}
+
+#[test]
+fn non_incentivizing_sender_offers_no_contribution() {
+    let sender =
+        builder().build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0)).unwrap();
+    assert_eq!(sender.fee_contribution(), None);
+    assert_eq!(sender.min_fee_rate(), None);
+    assert_eq!(sender.min_fee_rate_sat_per_vb(), None);
+}
+
+#[test]
+fn restored_senders_report_the_same_request() {
+    let sender = builder()
+        .build_with_additional_fee_with_fee_rate(
+            1_000,
+            Some(0),
+            FeeRate::from_sat_per_vb(2).unwrap(),
+            false,
+        )
+        .unwrap();
+    let restored = Sender::from_json(&sender.to_json().unwrap()).unwrap();
+    assert_eq!(restored.fee_contribution(), sender.fee_contribution());
+    assert_eq!(restored.min_fee_rate(), sender.min_fee_rate());
+    assert_eq!(restored.original_psbt(), sender.original_psbt());
+}
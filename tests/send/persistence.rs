@@ -0,0 +1,91 @@
+use payjoin_ffi::send::{Sender, SenderBuilder, V2GetContext};
+use payjoin_ffi::uri::{Uri, Url};
+use payjoin_ffi::FeeRate;
+
+use crate::common::{
+    bhttp_ok, bhttp_request_content, key_config, payjoin_payload, read_field, read_varint,
+    receiver, ORIGINAL_PSBT,
+};
+
+fn sender(builder: impl Fn(SenderBuilder) -> SenderBuilder) -> Sender {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    builder(SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri).unwrap())
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap()
+}
+
+fn assert_round_trips(sender: Sender) {
+    let json = sender.to_json().unwrap();
+    let restored = Sender::from_json(&json).unwrap();
+    assert_eq!(restored.to_json().unwrap(), json);
+    assert_eq!(restored.fallback_deadline(), sender.fallback_deadline());
+
+    let (request, _) = sender.extract_v1();
+    let (restored_request, _) = restored.extract_v1();
+    assert_eq!(restored_request.url.as_string(), request.url.as_string());
+    assert_eq!(restored_request.body, request.body);
+}
+
+#[test]
+fn sender_round_trips() {
+    assert_round_trips(sender(|builder| builder));
+}
+
+#[test]
+fn sender_with_local_state_round_trips() {
+    assert_round_trips(sender(|builder| builder.fallback_after_secs(60)));
+}
+
+#[test]
+fn senders_persisted_by_payjoin_alone_are_restored() {
+    let sender = sender(|builder| builder);
+    let json: serde_json::Value = serde_json::from_str(&sender.to_json().unwrap()).unwrap();
+    let restored = Sender::from_json(&json["sender"].to_string()).unwrap();
+    assert_eq!(restored.endpoint(), sender.endpoint());
+    assert_eq!(restored.endpoint().as_string(), "https://example.com/pj");
+    assert!(!restored.can_use_v2());
+}
+
+#[test]
+fn invalid_json_is_rejected() {
+    assert!(Sender::from_json("{").is_err());
+    assert!(Sender::from_json(r#"{"sender": null}"#).is_err());
+}
+
+#[test]
+fn v2_get_context_resumes_polling_without_posting_again() {
+    let receiver = receiver(None);
+    let relay = Url::parse("https://relay.example".to_string()).unwrap();
+    let mut directory = ohttp::Server::new(key_config()).unwrap();
+
+    let (post, post_ctx) = crate::common::sender(&receiver).extract_v2(relay).unwrap();
+    let (bhttp_post, response) = directory.decapsulate(&post.body).unwrap();
+    let payload = bhttp_request_content(&bhttp_post);
+    let context =
+        post_ctx.process_response(&response.encapsulate(&bhttp_ok(&[])).unwrap()).unwrap();
+
+    let persisted = context.to_json().unwrap();
+    drop(context);
+    let restored = V2GetContext::from_json(&persisted).unwrap();
+    assert_eq!(restored.to_json().unwrap(), persisted);
+
+    let (get, ohttp_ctx) = restored.extract_req("https://relay.example".to_string()).unwrap();
+    let (bhttp_get, response) = directory.decapsulate(&get.body).unwrap();
+    let mut request = bhttp_get.as_slice();
+    assert_eq!(read_varint(&mut request), 0);
+    assert_eq!(read_field(&mut request), b"GET", "the restored context only polls");
+    let body = response.encapsulate(&bhttp_ok(&payjoin_payload(&receiver, &payload))).unwrap();
+    assert!(restored.process_response(&body, &ohttp_ctx).unwrap().is_some());
+}
+
+#[test]
+fn invalid_v2_get_context_json_is_rejected() {
+    assert!(V2GetContext::from_json("{").is_err());
+    assert!(V2GetContext::from_json(&sender(|builder| builder).to_json().unwrap()).is_err());
+}
@@ -0,0 +1,46 @@
+#![cfg(feature = "async")]
+
+use payjoin_ffi::receive::ImplementationError;
+use payjoin_ffi::send::{PollingPolicy, RejectionCode, ResponseError, RunError};
+
+fn delays(policy: &PollingPolicy) -> Vec<u64> {
+    (0..).map_while(|attempt| policy.next_delay(attempt, None)).collect()
+}
+
+#[test]
+fn delays_grow_to_the_max_interval_until_the_deadline() {
+    let policy = PollingPolicy::new(5, 2.0, 60, 300);
+    assert_eq!(delays(&policy), vec![5, 10, 20, 40, 60, 60, 60, 45]);
+    assert_eq!(delays(&policy).iter().sum::<u64>(), 300);
+
+    assert_eq!(delays(&PollingPolicy::new(2, 1.0, 2, 5)), vec![2, 2, 1]);
+    // A shrinking multiplier polls at a constant interval
+    assert_eq!(delays(&PollingPolicy::new(4, 0.5, 10, 12)), vec![4, 4, 4]);
+    assert_eq!(delays(&PollingPolicy::new(5, 2.0, 60, 0)), Vec::<u64>::new());
+}
+
+#[test]
+fn late_attempts_use_the_max_interval() {
+    let policy = PollingPolicy::new(1, 2.0, 60, u64::MAX);
+    assert_eq!(policy.next_delay(u32::MAX, None), Some(60));
+    assert_eq!(PollingPolicy::recommended().next_delay(u32::MAX, None), None);
+}
+
+#[test]
+fn only_transient_errors_are_retried() {
+    let policy = PollingPolicy::recommended();
+    let transport = RunError::from(ImplementationError::new("timed out".to_string(), None));
+    assert!(transport.is_transient());
+    assert_eq!(policy.next_delay(0, Some(transport)), Some(5));
+
+    let rejected = RunError::Response(ResponseError::ReceiverRejected {
+        code: RejectionCode::NotEnoughMoney,
+        message: String::new(),
+    });
+    let expired = RunError::FallbackDue { original_tx: None };
+    let invalid = RunError::Response(ResponseError::InvalidEncoding { msg: String::new() });
+    for error in [rejected, expired, invalid] {
+        assert!(!error.is_transient());
+        assert_eq!(policy.next_delay(0, Some(error)), None);
+    }
+}
@@ -0,0 +1,28 @@
+use payjoin_ffi::send::ResponseError;
+use payjoin_ffi::uri::Url;
+
+use crate::common::{bhttp_response, key_config, receiver, sender};
+
+#[test]
+fn reusing_a_post_context_is_a_typed_error() {
+    let relay = Url::parse("https://relay.example".to_string()).unwrap();
+    let (request, context) = sender(&receiver(None)).extract_v2(relay).unwrap();
+    let server = ohttp::Server::new(key_config()).unwrap();
+    let (_, response) = server.decapsulate(&request.body).unwrap();
+    let response = response.encapsulate(&bhttp_response(200)).unwrap();
+    assert!(context.process_response(&response).is_ok());
+    assert!(matches!(
+        context.process_response(&response),
+        Err(ResponseError::OhttpContextAlreadyUsed)
+    ));
+}
+
+#[test]
+fn post_responses_that_dont_decapsulate_are_typed() {
+    let relay = Url::parse("https://relay.example".to_string()).unwrap();
+    let (_, context) = sender(&receiver(None)).extract_v2(relay).unwrap();
+    assert!(matches!(
+        context.process_response(&[0; 8192]),
+        Err(ResponseError::Decapsulation { .. })
+    ));
+}
@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use payjoin::bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin_ffi::send::SenderBuilder;
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::FeeRate;
+
+use crate::common::ORIGINAL_PSBT;
+
+// BIP32 test vector 1 master xpub
+const XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+/// The Original PSBT as a multisig coordinator would produce it, with wallet metadata.
+fn psbt_with_metadata() -> Psbt {
+    let mut psbt = Psbt::from_str(ORIGINAL_PSBT).unwrap();
+    let xpub = Xpub::from_str(XPUB).unwrap();
+    let origin = (Fingerprint::from([0xde, 0xad, 0xbe, 0xef]), DerivationPath::master());
+    psbt.xpub.insert(xpub, origin.clone());
+    psbt.inputs[0].bip32_derivation.insert(xpub.public_key, origin.clone());
+    psbt.outputs[0].bip32_derivation.insert(xpub.public_key, origin);
+    psbt
+}
+
+fn wire_psbt(builder: SenderBuilder) -> Psbt {
+    let (request, _) = builder
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap()
+        .extract_v1();
+    Psbt::from_str(std::str::from_utf8(&request.body).unwrap()).unwrap()
+}
+
+fn builder() -> SenderBuilder {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    SenderBuilder::new(psbt_with_metadata().to_string(), uri).unwrap()
+}
+
+#[test]
+fn metadata_is_stripped_from_the_wire_psbt() {
+    let psbt = wire_psbt(builder());
+    assert!(psbt.xpub.is_empty());
+    assert!(psbt.inputs.iter().all(|input| input.bip32_derivation.is_empty()));
+    assert!(psbt.outputs.iter().all(|output| output.bip32_derivation.is_empty()));
+    assert!(psbt.inputs[0].witness_utxo.is_some());
+    assert!(psbt.inputs[0].final_script_witness.is_some());
+}
+
+#[test]
+fn metadata_is_kept_on_request() {
+    let psbt = wire_psbt(builder().keep_psbt_metadata());
+    assert_eq!(psbt.xpub.len(), 1);
+    assert_eq!(psbt.inputs[0].bip32_derivation.len(), 1);
+    assert_eq!(psbt.outputs[0].bip32_derivation.len(), 1);
+}
@@ -0,0 +1,56 @@
+use payjoin_ffi::send::{Sender, SenderBuilder};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::FeeRate;
+
+// OriginalPSBT test vector from BIP78, paying 0.02 BTC to 3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM
+const ORIGINAL_PSBT: &str = "cHNidP8BAHMCAAAAAY8nutGgJdyYGXWiBEb45Hoe9lWGbkxh/6bNiOJdCDuDAAAAAAD+////AtyVuAUAAAAAF6kUHehJ8GnSdBUOOv6ujXLrWmsJRDCHgIQeAAAAAAAXqRR3QJbbz0hnQ8IvQ0fptGn+votneofTAAAAAAEBIKgb1wUAAAAAF6kU3k4ekGHKWRNbA1rV5tR5kEVDVNCHAQcXFgAUx4pFclNVgo1WWAdN1SYNX8tphTABCGsCRzBEAiB8Q+A6dep+Rz92vhy26lT0AjZn4PRLi8Bf9qoB/CMk0wIgP/Rj2PWZ3gEjUkTlhDRNAQ0gXwTO7t9n+V14pZ6oljUBIQMVmsAaoNWHVMS02LfTSe0e388LNitPa1UQZyOihY+FFgABABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAA=";
+
+// The same Original PSBT as a BIP370 v2 PSBT, with locktime 211 as its fallback locktime
+const ORIGINAL_PSBT_V2: &str = "cHNidP8BAgQCAAAAAQME0wAAAAEEAQEBBQECAfsEAgAAAAABDiCPJ7rRoCXcmBl1ogRG+OR6HvZVhm5MYf+mzYjiXQg7gwEPBAAAAAABEAT+////AQEgqBvXBQAAAAAXqRTeTh6QYcpZE1sDWtXm1HmQRUNU0IcBBxcWABTHikVyU1WCjVZYB03VJg1fy2mFMAEIawJHMEQCIHxD4Dp16n5HP3a+HLbqVPQCNmfg9EuLwF/2qgH8IyTTAiA/9GPY9ZneASNSROWENE0BDSBfBM7u32f5XXilnqiWNQEhAxWawBqg1YdUxLTYt9NJ7R7fzws2K09rVRBnI6KFj4UWAAEDCNyVuAUAAAAAAQQXqRQd6EnwadJ0FQ46/q6NcutaawlEMIcBABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAEDCICEHgAAAAAAAQQXqRR3QJbbz0hnQ8IvQ0fptGn+votneocA";
+
+fn builder(psbt: &str) -> SenderBuilder {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    SenderBuilder::new(psbt.to_string(), uri).unwrap()
+}
+
+fn sender(psbt: &str) -> Sender {
+    builder(psbt).build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0)).unwrap()
+}
+
+#[test]
+fn v2_original_psbt_is_sent_as_v0() {
+    let (v0_request, _) = sender(ORIGINAL_PSBT).extract_v1();
+    let (v2_request, _) = sender(ORIGINAL_PSBT_V2).extract_v1();
+    assert_eq!(v2_request.body, v0_request.body);
+    assert_eq!(v2_request.url.as_string(), v0_request.url.as_string());
+}
+
+#[test]
+fn psbt_version_is_carried_through_persistence() {
+    assert_eq!(sender(ORIGINAL_PSBT).psbt_version(), 0);
+    let sender = sender(ORIGINAL_PSBT_V2);
+    assert_eq!(sender.psbt_version(), 2);
+    let restored = Sender::from_json(&sender.to_json().unwrap()).unwrap();
+    assert_eq!(restored.psbt_version(), 2);
+    assert_eq!(restored.to_json().unwrap(), sender.to_json().unwrap());
+}
+
+#[test]
+fn invalid_v2_psbt_is_rejected() {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    // A v2 global map without input and output counts
+    let error = SenderBuilder::new("cHNidP8B+wQCAAAAAA==".to_string(), uri).err().unwrap();
+    assert!(error.message().contains("version 2"));
+}
@@ -0,0 +1,46 @@
+use payjoin_ffi::send::SenderBuilder;
+use payjoin_ffi::uri::{Uri, Url};
+use payjoin_ffi::{FeeRate, Request};
+
+use crate::common::{receiver, ORIGINAL_PSBT};
+
+fn sender_builder(pj_params: &str) -> SenderBuilder {
+    let uri =
+        Uri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&{pj_params}"))
+            .unwrap()
+            .check_pj_supported()
+            .unwrap();
+    SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri).unwrap()
+}
+
+fn assert_headers(request: &Request, content_type: &str) {
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.headers["Content-Type"], content_type);
+    assert_eq!(request.headers["Content-Length"], request.body.len().to_string());
+    assert_eq!(request.content_type, content_type);
+}
+
+#[test]
+fn v1_requests_are_plain_text() {
+    let sender = sender_builder("pj=https://example.com/pj")
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap();
+    let (request, _) = sender.extract_v1();
+    assert_headers(&request, "text/plain");
+}
+
+#[test]
+fn v2_requests_are_ohttp() {
+    let receiver = receiver(None);
+    let (request, _) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+    assert_headers(&request, "message/ohttp-req");
+
+    // Pay the BIP78 test vector's payee with the receiver's payjoin parameters
+    let pj_uri = receiver.pj_uri(None, None, None).unwrap().as_string();
+    let sender = sender_builder(&pj_uri[pj_uri.find('?').unwrap() + 1..])
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap();
+    let relay = Url::parse("https://relay.example".to_string()).unwrap();
+    let (request, _) = sender.extract_v2(relay).unwrap();
+    assert_headers(&request, "message/ohttp-req");
+}
@@ -0,0 +1,197 @@
+use payjoin::bitcoin::base64::engine::general_purpose::STANDARD;
+use payjoin::bitcoin::base64::Engine;
+use payjoin_ffi::send::{RejectionCode, ResponseError, SenderBuilder, V1Context};
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::FeeRate;
+
+use crate::common::{receiver, sender, v2_get_context, ORIGINAL_PSBT};
+
+fn v1_context() -> V1Context {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com/pj"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    let builder = SenderBuilder::new(ORIGINAL_PSBT.to_string(), uri).unwrap();
+    builder
+        .build_non_incentivizing_with_fee_rate(FeeRate::from_sat_per_kwu(0))
+        .unwrap()
+        .extract_v1()
+        .1
+}
+
+/// Deterministic pseudo-random bytes, so failures reproduce.
+fn pseudo_random_bodies(count: usize) -> Vec<Vec<u8>> {
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    (0..count)
+        .map(|i| {
+            (0..i % 97)
+                .map(|_| {
+                    state =
+                        state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    (state >> 56) as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn v1_distinguishes_bad_encoding_from_bad_payload() {
+    let context = v1_context();
+    assert!(matches!(
+        context.process_response_str("not base64!".to_string()),
+        Err(ResponseError::InvalidEncoding { .. })
+    ));
+    assert!(matches!(
+        context.process_response_str(" cHNidP8=\n".to_string()),
+        Err(ResponseError::InvalidEncoding { .. })
+    ));
+    assert!(matches!(
+        context.process_response_str(
+            "{\"errorCode\": \"unavailable\", \"message\": \"down\"}\n".to_string()
+        ),
+        Err(ResponseError::ReceiverRejected { code: RejectionCode::Unavailable, .. })
+    ));
+}
+
+#[test]
+fn v1_accepts_base64_psbt_with_whitespace() {
+    let context = v1_context();
+    let bodies = [
+        format!("{ORIGINAL_PSBT}\n").into_bytes(),
+        format!("{ORIGINAL_PSBT}\r\n").into_bytes(),
+        format!("{}\r\n{}", &ORIGINAL_PSBT[..64], &ORIGINAL_PSBT[64..]).into_bytes(),
+    ];
+    for body in bodies {
+        // Echoing the Original PSBT back fails the sender checks, but only once it was decoded.
+        assert!(!matches!(
+            context.process_response(body),
+            Err(ResponseError::InvalidEncoding { .. })
+        ));
+    }
+    assert!(matches!(
+        context.process_response(b"\xff\xfe".to_vec()),
+        Err(ResponseError::InvalidEncoding { .. })
+    ));
+}
+
+#[test]
+fn v1_binary_psbt_is_processed_by_the_bytes_method() {
+    let context = v1_context();
+    let psbt = STANDARD.decode(ORIGINAL_PSBT).unwrap();
+    assert!(matches!(
+        context.process_response(psbt.clone()),
+        Err(ResponseError::InvalidEncoding { .. })
+    ));
+    assert!(!matches!(
+        context.process_response_bytes(psbt.clone()),
+        Err(ResponseError::InvalidEncoding { .. })
+    ));
+    let mut truncated = psbt;
+    truncated.truncate(20);
+    assert!(matches!(
+        context.process_response_bytes(truncated),
+        Err(ResponseError::InvalidEncoding { .. })
+    ));
+}
+
+#[test]
+fn v1_malformed_bodies_do_not_panic() {
+    let context = v1_context();
+    for body in pseudo_random_bodies(500) {
+        let _ = context.process_response(body.clone());
+        let _ = context.process_response_str(String::from_utf8_lossy(&body).into_owned());
+        let _ = context.process_response_str(STANDARD.encode(&body));
+        let _ = context.process_response_bytes(body);
+    }
+}
+
+#[test]
+fn v2_responses_that_dont_decapsulate_are_typed() {
+    let context = v2_get_context(&sender(&receiver(None)));
+    let (_, ohttp_ctx) = context.extract_req("https://relay.example".to_string()).unwrap();
+    assert!(matches!(
+        context.process_response(&[0; 100], &ohttp_ctx),
+        Err(ResponseError::Decapsulation { .. })
+    ));
+    // A response of the wrong length doesn't use up the OHTTP context
+    assert!(ohttp_ctx.matches(&[0; 8192]));
+    assert!(matches!(
+        context.process_response(&[0; 8192], &ohttp_ctx),
+        Err(ResponseError::Decapsulation { .. })
+    ));
+    assert!(matches!(
+        context.process_response(&[0; 8192], &ohttp_ctx),
+        Err(ResponseError::OhttpContextAlreadyUsed)
+    ));
+}
+
+#[test]
+fn receiver_malformed_bodies_do_not_panic() {
+    let receiver = receiver(None);
+    for body in pseudo_random_bodies(200) {
+        let (_, context) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+        let _ = receiver.process_res(&body, &context);
+    }
+}
+
+#[test]
+fn version_unsupported_is_typed() {
+    let body = br#"{"errorCode": "version-unsupported", "supported": [1], "message": "This version of payjoin is not supported."}"#;
+    match v1_context().process_response(body.to_vec()) {
+        Err(ResponseError::VersionUnsupported { supported, msg }) => {
+            assert_eq!(supported, vec![1]);
+            assert_eq!(msg, "This version of payjoin is not supported.");
+        }
+        other => panic!("expected VersionUnsupported, got {other:?}"),
+    }
+    let other_error = br#"{"errorCode": "unavailable", "message": "The payjoin endpoint is not available for now."}"#;
+    assert!(matches!(
+        v1_context().process_response(other_error.to_vec()),
+        Err(ResponseError::ReceiverRejected { code: RejectionCode::Unavailable, .. })
+    ));
+}
+
+#[test]
+fn receiver_rejections_are_typed() {
+    let cases = [
+        ("unavailable", RejectionCode::Unavailable),
+        ("not-enough-money", RejectionCode::NotEnoughMoney),
+        ("original-psbt-rejected", RejectionCode::OriginalPsbtRejected),
+        ("out-of-cheese", RejectionCode::Unrecognized("out-of-cheese".to_string())),
+    ];
+    for (error_code, expected) in cases {
+        let body = format!(r#"{{"errorCode": "{error_code}", "message": "from the receiver"}}"#);
+        match v1_context().process_response(body.into_bytes()) {
+            Err(ResponseError::ReceiverRejected { code, message }) => {
+                assert_eq!(code, expected);
+                assert_eq!(message, "from the receiver");
+            }
+            other => panic!("expected ReceiverRejected, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn malformed_error_bodies_are_not_rejections() {
+    for body in [
+        r#"{"errorCode": "unavailable""#,
+        r#"{"errorCode": 5, "message": "down"}"#,
+        r#"{"message": "down"}"#,
+        r#"["unavailable"]"#,
+    ] {
+        let result = v1_context().process_response(body.as_bytes().to_vec());
+        assert!(result.is_err());
+        assert!(!matches!(result, Err(ResponseError::ReceiverRejected { .. })));
+    }
+    match v1_context().process_response(br#"{"errorCode": "unavailable"}"#.to_vec()) {
+        Err(ResponseError::ReceiverRejected { code, message }) => {
+            assert_eq!(code, RejectionCode::Unavailable);
+            assert!(message.is_empty());
+        }
+        other => panic!("expected ReceiverRejected, got {other:?}"),
+    }
+}
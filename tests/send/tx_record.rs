@@ -0,0 +1,109 @@
+use std::str::FromStr;
+
+use payjoin::bitcoin::absolute::LockTime;
+use payjoin::bitcoin::psbt::Psbt;
+use payjoin::bitcoin::transaction::Version;
+use payjoin::bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+use payjoin_ffi::send::sender_tx_record;
+use payjoin_ffi::uri::Uri;
+use payjoin_ffi::PayjoinDirection;
+
+use crate::common::receiver;
+
+const PAYEE: &str = "12c6DSiU4Rq3P4ZxziKxzrL5LmMBrzjrJX";
+
+fn outpoint(vout: u32) -> OutPoint {
+    OutPoint::from_str("4d3a8a3e1b3e1d8b2f2c8e0f7b8d6f5c0a1e2d3c4b5a69788796a5b4c3d2e1f0:0")
+        .map(|o| OutPoint { vout, ..o })
+        .unwrap()
+}
+
+fn psbt(inputs: &[(OutPoint, u64)], outputs: Vec<TxOut>) -> Psbt {
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|(previous_output, _)| {
+                TxIn {
+                    previous_output: *previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                }
+            })
+            .collect(),
+        output: outputs,
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+    for (input, (_, value)) in psbt.inputs.iter_mut().zip(inputs) {
+        input.witness_utxo =
+            Some(TxOut { value: Amount::from_sat(*value), script_pubkey: ScriptBuf::new() });
+    }
+    psbt
+}
+
+#[test]
+fn sender_record_splits_fees_between_parties() {
+    let uri = Uri::parse(format!("bitcoin:{PAYEE}?amount=0.0005&pj=https://example.com/SESSION"))
+        .unwrap()
+        .check_pj_supported()
+        .unwrap();
+    let payee =
+        payjoin::bitcoin::Address::from_str(PAYEE).unwrap().assume_checked().script_pubkey();
+    let change = ScriptBuf::from_bytes(vec![0x00, 0x14, 0x01]);
+
+    // Sender spends 100_000 to pay 50_000 with 49_000 change and a 1_000 sat fee.
+    let original = psbt(
+        &[(outpoint(0), 100_000)],
+        vec![
+            TxOut { value: Amount::from_sat(50_000), script_pubkey: payee.clone() },
+            TxOut { value: Amount::from_sat(49_000), script_pubkey: change.clone() },
+        ],
+    );
+    // Receiver adds 30_000 and pays 300 sats for its input, the sender adds 200 sats of fee.
+    let payjoin = psbt(
+        &[(outpoint(0), 100_000), (outpoint(1), 30_000)],
+        vec![
+            TxOut { value: Amount::from_sat(79_700), script_pubkey: payee },
+            TxOut { value: Amount::from_sat(48_800), script_pubkey: change },
+        ],
+    );
+
+    let record = sender_tx_record(original.to_string(), payjoin.to_string(), &uri, 42).unwrap();
+    assert_eq!(record.txid, payjoin.unsigned_tx.compute_txid().to_string());
+    assert_eq!(record.direction, PayjoinDirection::Sent);
+    assert_eq!(record.net_amount_to_receiver, 49_700);
+    assert_eq!(record.sender_fee, Some(1_200));
+    assert_eq!(record.receiver_fee, Some(300));
+    assert_eq!(
+        record.sender_fee.unwrap() + record.receiver_fee.unwrap(),
+        payjoin.fee().unwrap().to_sat()
+    );
+    assert_eq!(record.counterparty_output_count, 1);
+    assert_eq!(record.timestamp, 42);
+    // A v1 endpoint names no session on a directory
+    assert_eq!(record.session_id, None);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(serde_json::from_str::<payjoin_ffi::PayjoinTxRecord>(&json).unwrap(), record);
+    }
+}
+
+#[test]
+fn sender_record_names_the_v2_session() {
+    let receiver = receiver(None);
+    let uri = receiver.pj_uri(None, None, None).unwrap();
+    let payee = payjoin::bitcoin::Address::from_str(&uri.address())
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+    let original = psbt(
+        &[(outpoint(0), 100_000)],
+        vec![TxOut { value: Amount::from_sat(99_000), script_pubkey: payee }],
+    );
+    let record = sender_tx_record(original.to_string(), original.to_string(), &uri, 42).unwrap();
+    assert_eq!(record.session_id, Some(receiver.id()));
+}
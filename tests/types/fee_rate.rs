@@ -0,0 +1,36 @@
+use payjoin_ffi::{FeeRate, FeeRateError};
+
+#[test]
+fn units_convert() {
+    let rate = FeeRate::from_sat_per_vb(1).unwrap();
+    assert_eq!(rate.to_sat_per_kwu(), 250);
+    assert_eq!(rate, FeeRate::from_sat_per_kwu(250));
+    assert_eq!(FeeRate::from_sat_per_kwu(251).to_sat_per_vb_ceil(), 2);
+}
+
+#[test]
+fn sat_per_vb_overflowing_kwu_is_rejected() {
+    assert_eq!(
+        FeeRate::from_sat_per_vb(u64::MAX),
+        Err(FeeRateError::Overflow { sat_per_vb: u64::MAX })
+    );
+    assert!(FeeRate::from_sat_per_vb(u64::MAX / 250).is_ok());
+    assert!(FeeRate::from_sat_per_vb(u64::MAX / 250 + 1).is_err());
+}
+
+#[test]
+fn fractional_sat_per_vb_rounds_up_to_kwu() {
+    assert_eq!(FeeRate::from_sat_per_vb_f64(12.4).unwrap().to_sat_per_kwu(), 3100);
+    assert_eq!(FeeRate::from_sat_per_vb_f64(1.001).unwrap().to_sat_per_kwu(), 251);
+    assert_eq!(FeeRate::from_sat_per_vb_f64(0.0).unwrap().to_sat_per_kwu(), 0);
+}
+
+#[test]
+fn invalid_fractional_sat_per_vb_is_rejected() {
+    assert_eq!(FeeRate::from_sat_per_vb_f64(f64::NAN), Err(FeeRateError::NotANumber));
+    assert_eq!(FeeRate::from_sat_per_vb_f64(-1.0), Err(FeeRateError::Negative));
+    assert_eq!(
+        FeeRate::from_sat_per_vb_f64(f64::INFINITY),
+        Err(FeeRateError::TooHigh { max_sat_per_vb: payjoin_ffi::types::MAX_SAT_PER_VB })
+    );
+}
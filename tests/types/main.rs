@@ -0,0 +1,11 @@
+//! Integration tests of the types shared by senders and receivers.
+#![cfg(not(feature = "uniffi"))]
+
+extern crate payjoin_ffi_v0_22 as payjoin_ffi;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+mod fee_rate;
+mod ohttp_keys;
+mod version;
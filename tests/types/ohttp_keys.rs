@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+use payjoin_ffi::receive::Receiver;
+use payjoin_ffi::{Address, Network, OhttpKeys, OhttpKeysParseError};
+
+use crate::common::ohttp_keys;
+
+#[test]
+fn bytes_round_trip() {
+    let bytes = ohttp_keys().to_bytes().unwrap();
+    let restored = OhttpKeys::decode(bytes.clone()).unwrap();
+    assert_eq!(restored.to_bytes().unwrap(), bytes);
+    assert_eq!(restored.to_string(), ohttp_keys().to_string());
+}
+
+#[test]
+fn strings_round_trip() {
+    let encoded = ohttp_keys().to_string();
+    assert!(encoded.to_uppercase().starts_with("OH1"), "{encoded}");
+    let restored = OhttpKeys::from_str(&encoded).unwrap();
+    assert_eq!(restored.to_string(), encoded);
+    assert_eq!(restored.to_bytes().unwrap(), ohttp_keys().to_bytes().unwrap());
+    assert_eq!(
+        OhttpKeys::parse(encoded.to_lowercase()).unwrap().to_bytes().unwrap(),
+        restored.to_bytes().unwrap()
+    );
+}
+
+#[test]
+fn truncated_encodings_are_reported() {
+    let bytes = ohttp_keys().to_bytes().unwrap();
+    for len in [0, 1, bytes.len() / 2, bytes.len() - 1] {
+        assert_eq!(
+            OhttpKeys::decode(bytes[..len].to_vec()).err(),
+            Some(OhttpKeysParseError::Truncated),
+            "{len} bytes"
+        );
+    }
+    let encoded = ohttp_keys().to_string();
+    assert_eq!(
+        OhttpKeys::parse(encoded[..encoded.len() - 8].to_string()).err(),
+        Some(OhttpKeysParseError::Truncated)
+    );
+}
+
+#[test]
+fn corrupt_encodings_are_reported() {
+    let mut bytes = ohttp_keys().to_bytes().unwrap();
+    // The public key follows the key and KEM identifiers.
+    bytes[3] = 0x07;
+    assert!(matches!(OhttpKeys::decode(bytes), Err(OhttpKeysParseError::Corrupt { .. })));
+    let mut bytes = ohttp_keys().to_bytes().unwrap();
+    bytes.push(0);
+    assert!(matches!(OhttpKeys::decode(bytes), Err(OhttpKeysParseError::Corrupt { .. })));
+    for encoded in ["not ohttp keys", "RK1QYPSJ", ""] {
+        assert!(
+            matches!(
+                OhttpKeys::parse(encoded.to_string()),
+                Err(OhttpKeysParseError::Corrupt { .. })
+            ),
+            "{encoded}"
+        );
+    }
+}
+
+#[test]
+fn restored_keys_encapsulate_requests() {
+    let restored = OhttpKeys::decode(ohttp_keys().to_bytes().unwrap()).unwrap();
+    let restored = OhttpKeys::from_str(&restored.to_string()).unwrap();
+    let receiver = Receiver::new(
+        Address::new("bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4".to_string(), Network::Regtest)
+            .unwrap(),
+        "https://directory.example".to_string(),
+        restored,
+        None,
+    )
+    .unwrap();
+    let (request, _) = receiver.extract_req("https://relay.example".to_string()).unwrap();
+    assert!(!request.body.is_empty());
+}
+
+/// The body of a directory's `/ohttp-keys` response: a single secp256k1 key configuration
+/// with the HKDF-SHA256/ChaCha20Poly1305 suite, as payjoin directories serve it.
+const DIRECTORY_RESPONSE: &[u8] = include_bytes!("../data/ohttp-keys.bin");
+
+/// A key configuration for `kem` with `suites`.
+fn key_config(kem: Kem, suites: &[(Kdf, Aead)]) -> Vec<u8> {
+    let suites = suites.iter().map(|(kdf, aead)| SymmetricSuite::new(*kdf, *aead)).collect();
+    KeyConfig::derive(1, kem, suites, &[7; 32]).unwrap().encode().unwrap()
+}
+
+/// `configs` as an RFC 9458 `application/ohttp-keys` body.
+fn key_config_list(configs: &[Vec<u8>]) -> Vec<u8> {
+    configs
+        .iter()
+        .flat_map(|config| [(config.len() as u16).to_be_bytes().to_vec(), config.clone()])
+        .flatten()
+        .collect()
+}
+
+#[test]
+fn decodes_a_directory_response() {
+    let keys = OhttpKeys::decode(DIRECTORY_RESPONSE.to_vec()).unwrap();
+    assert_eq!(keys.to_bytes().unwrap(), DIRECTORY_RESPONSE);
+    assert_eq!(OhttpKeys::parse(keys.to_string()).unwrap().to_bytes().unwrap(), DIRECTORY_RESPONSE);
+    let listed = key_config_list(&[DIRECTORY_RESPONSE.to_vec()]);
+    assert_eq!(OhttpKeys::decode(listed).unwrap().to_bytes().unwrap(), DIRECTORY_RESPONSE);
+}
+
+#[test]
+fn keeps_the_served_key_config() {
+    let k256 = key_config(
+        Kem::K256Sha256,
+        &[(Kdf::HkdfSha256, Aead::Aes128Gcm), (Kdf::HkdfSha256, Aead::ChaCha20Poly1305)],
+    );
+    let body = key_config_list(&[
+        key_config(Kem::X25519Sha256, &[(Kdf::HkdfSha256, Aead::ChaCha20Poly1305)]),
+        k256.clone(),
+    ]);
+    assert_eq!(OhttpKeys::decode(body).unwrap().to_bytes().unwrap(), k256);
+}
+
+#[test]
+fn unsupported_key_configs_are_reported() {
+    const X25519: u16 = 0x0020;
+    let x25519 = key_config(Kem::X25519Sha256, &[(Kdf::HkdfSha256, Aead::ChaCha20Poly1305)]);
+    assert_eq!(
+        OhttpKeys::decode(x25519.clone()).err(),
+        Some(OhttpKeysParseError::UnsupportedKem { kem: X25519 })
+    );
+    assert_eq!(
+        OhttpKeys::decode(key_config_list(&[x25519])).err(),
+        Some(OhttpKeysParseError::UnsupportedKem { kem: X25519 })
+    );
+    // P-256, which the OHTTP implementation doesn't support
+    let mut p256 = DIRECTORY_RESPONSE.to_vec();
+    p256[1..3].copy_from_slice(&0x0010u16.to_be_bytes());
+    assert_eq!(OhttpKeys::decode(p256).err(), Some(OhttpKeysParseError::Unsupported));
+}
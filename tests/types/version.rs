@@ -0,0 +1,20 @@
+use payjoin_ffi::{check_bindings_version, library_version};
+
+#[test]
+fn bindings_for_the_same_release_series_are_accepted() {
+    let library = library_version();
+    assert!(check_bindings_version(library.clone()).is_ok());
+
+    let (series, _patch) = library.rsplit_once('.').unwrap();
+    assert!(check_bindings_version(format!("{series}.999")).is_ok());
+}
+
+#[test]
+fn mismatched_bindings_fail_fast() {
+    for bindings in ["0.0.1", "999.0.0", "", "not-a-version"] {
+        assert!(
+            check_bindings_version(bindings.to_string()).is_err(),
+            "bindings version {bindings:?} should be rejected"
+        );
+    }
+}
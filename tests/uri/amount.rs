@@ -0,0 +1,58 @@
+use payjoin_ffi::uri::{format_btc_amount, parse_btc_amount, Uri, MAX_AMOUNT_SATS};
+
+#[test]
+fn parses_boundaries() {
+    assert_eq!(parse_btc_amount("0"), Ok(0));
+    assert_eq!(parse_btc_amount("0.00000001"), Ok(1));
+    assert_eq!(parse_btc_amount(".5"), Ok(50_000_000));
+    assert_eq!(parse_btc_amount("1."), Ok(100_000_000));
+    assert_eq!(parse_btc_amount("21000000"), Ok(MAX_AMOUNT_SATS));
+    assert_eq!(parse_btc_amount("21000000.00000000"), Ok(MAX_AMOUNT_SATS));
+}
+
+#[test]
+fn rejects_invalid_amounts() {
+    for input in [
+        "",
+        ".",
+        "21000000.00000001",
+        "184467440737.09551616",
+        "99999999999999999999999",
+        "0.000000001",
+        "-1",
+        "+1",
+        "1e3",
+        "1,5",
+        " 1",
+        "1.2.3",
+    ] {
+        assert!(parse_btc_amount(input).is_err(), "{input:?} should be rejected");
+    }
+}
+
+#[test]
+fn formats_exactly() {
+    assert_eq!(format_btc_amount(0), "0");
+    assert_eq!(format_btc_amount(1), "0.00000001");
+    assert_eq!(format_btc_amount(150_000_000), "1.5");
+    assert_eq!(format_btc_amount(MAX_AMOUNT_SATS), "21000000");
+    assert_eq!(format_btc_amount(MAX_AMOUNT_SATS + 1), "21000000.00000001");
+    assert_eq!(format_btc_amount(u64::MAX), "184467440737.09551615");
+}
+
+#[test]
+fn formatting_round_trips() {
+    for sats in [0, 1, 10, 99_999_999, 100_000_000, 123_456_789, MAX_AMOUNT_SATS] {
+        assert_eq!(parse_btc_amount(&format_btc_amount(sats)), Ok(sats));
+    }
+}
+
+#[test]
+fn uri_rejects_amount_over_supply() {
+    let uri = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=21000000.00000001&pj=https://example.com";
+    assert!(Uri::parse(uri.to_string()).is_err());
+    let uri = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https://example.com";
+    let amount = Uri::parse(uri.to_string()).unwrap().amount().unwrap();
+    assert_eq!(amount.to_sat(), 2_000_000);
+    assert_eq!(format_btc_amount(amount.to_sat()), "0.02");
+}
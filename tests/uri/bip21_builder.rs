@@ -0,0 +1,70 @@
+use payjoin_ffi::uri::{Bip21Builder, PjUri, Uri};
+use payjoin_ffi::{Address, Network};
+
+use crate::common::receiver;
+
+const ADDRESS: &str = "bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4";
+
+fn address() -> Address {
+    Address::new(ADDRESS.to_string(), Network::Regtest).unwrap()
+}
+
+#[test]
+fn builds_a_uri_without_payjoin() {
+    let uri = Bip21Builder::new(address())
+        .amount(123_456)
+        .unwrap()
+        .label("Café & Co.".to_string())
+        .message("Order #42".to_string())
+        .build();
+    assert!(!uri.has_pj());
+    assert!(uri.check_pj_supported().is_err());
+
+    let parsed = Uri::parse(uri.as_string()).unwrap();
+    assert_eq!(parsed.address(), ADDRESS);
+    assert_eq!(parsed.amount_sats(), Some(123_456));
+    assert_eq!(parsed.amount().map(|amount| amount.to_sat()), Some(123_456));
+    assert_eq!(parsed.label().as_deref(), Some("Café & Co."));
+    assert_eq!(parsed.message().as_deref(), Some("Order #42"));
+    assert!(!parsed.has_pj());
+}
+
+#[test]
+fn bare_address() {
+    assert_eq!(Bip21Builder::new(address()).build().as_string(), format!("bitcoin:{ADDRESS}"));
+}
+
+#[test]
+fn validates_amounts_like_the_pj_uri_builder() {
+    assert!(Bip21Builder::new(address()).amount(2_100_000_000_000_001).is_err());
+    assert!(Bip21Builder::new(address()).amount_btc("0.000000001".to_string()).is_err());
+    assert!(Bip21Builder::new(address()).amount_btc("-1".to_string()).is_err());
+    let uri = Bip21Builder::new(address()).amount_btc("0.02".to_string()).unwrap().build();
+    assert_eq!(uri.amount_sats(), Some(2_000_000));
+}
+
+#[test]
+fn formats_like_a_pj_uri_without_the_pj_parameters() {
+    let label = "Café & Co.".to_string();
+    let plain = Bip21Builder::new(address()).amount(2_000_000).unwrap().label(label.clone());
+    let pj = receiver(None).pj_uri_builder().amount(2_000_000).unwrap().label(label).build();
+    assert!(pj.has_pj());
+    assert!(Uri::parse(pj.as_string()).unwrap().has_pj());
+    assert!(pj.as_string().starts_with(&format!("{}&pj=", plain.build().as_string())));
+    assert!(PjUri::parse(plain.build().as_string()).is_err());
+}
+
+#[test]
+fn setters_leave_the_builder_they_are_called_on_unchanged() {
+    let builder = Bip21Builder::new(address());
+    let labeled = builder.label("Shop".to_string());
+    let paid = labeled.amount(2_000_000).unwrap();
+    assert_eq!(builder.build().as_string(), format!("bitcoin:{ADDRESS}"));
+    assert_eq!(labeled.build().amount_sats(), None);
+    assert_eq!(paid.build().label().as_deref(), Some("Shop"));
+
+    let pj_builder = receiver(None).pj_uri_builder();
+    let pj_labeled = pj_builder.label("Shop".to_string());
+    assert_eq!(pj_builder.build().label(), None);
+    assert_eq!(pj_labeled.build().label().as_deref(), Some("Shop"));
+}
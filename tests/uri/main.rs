@@ -0,0 +1,15 @@
+//! Integration tests of BIP21 URIs and URLs.
+#![cfg(not(feature = "uniffi"))]
+
+extern crate payjoin_ffi_v0_22 as payjoin_ffi;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+mod amount;
+mod bip21_builder;
+mod network;
+mod normalization;
+mod parse_error;
+mod pj_uri;
+mod url;
@@ -0,0 +1,78 @@
+use payjoin::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use payjoin::bitcoin::{Address, CompressedPublicKey, Network};
+use payjoin_ffi::uri::{PjParseErrorKind, PjUri};
+
+const NETWORKS: [Network; 4] =
+    [Network::Bitcoin, Network::Testnet, Network::Signet, Network::Regtest];
+
+fn key() -> CompressedPublicKey {
+    let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+    CompressedPublicKey(PublicKey::from_secret_key(&Secp256k1::new(), &secret))
+}
+
+fn pj_uri(address: &str) -> PjUri {
+    PjUri::parse(format!("bitcoin:{address}?pj=https://example.com/pj")).unwrap()
+}
+
+/// The networks `address` is accepted for.
+fn accepted(address: &str) -> Vec<Network> {
+    let uri = pj_uri(address);
+    NETWORKS.into_iter().filter(|network| uri.require_network(*network).is_ok()).collect()
+}
+
+#[test]
+fn mainnet_addresses_are_inferred() {
+    for address in [
+        Address::p2wpkh(&key(), Network::Bitcoin).to_string(),
+        Address::p2pkh(key(), Network::Bitcoin).to_string(),
+        "3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM".to_string(),
+    ] {
+        assert_eq!(pj_uri(&address).network(), Some(Network::Bitcoin));
+        assert_eq!(accepted(&address), vec![Network::Bitcoin]);
+    }
+}
+
+#[test]
+fn regtest_bech32_is_told_apart_from_testnet() {
+    let regtest = Address::p2wpkh(&key(), Network::Regtest).to_string();
+    assert!(regtest.starts_with("bcrt1"));
+    assert_eq!(pj_uri(&regtest).network(), Some(Network::Regtest));
+    assert_eq!(pj_uri(&regtest.to_uppercase()).network(), Some(Network::Regtest));
+    assert_eq!(accepted(&regtest), vec![Network::Regtest]);
+
+    let testnet = Address::p2wpkh(&key(), Network::Testnet).to_string();
+    assert!(testnet.starts_with("tb1"));
+    assert_eq!(pj_uri(&testnet).network(), None);
+    assert_eq!(accepted(&testnet), vec![Network::Testnet, Network::Signet]);
+}
+
+#[test]
+fn test_network_base58_addresses_are_shared() {
+    let address = Address::p2pkh(key(), Network::Regtest).to_string();
+    assert_eq!(address, Address::p2pkh(key(), Network::Testnet).to_string());
+    assert_eq!(pj_uri(&address).network(), None);
+    assert_eq!(accepted(&address), NETWORKS[1..].to_vec());
+}
+
+#[test]
+fn mismatches_name_the_address() {
+    let uri = pj_uri("3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM");
+    assert_eq!(
+        uri.require_network(Network::Regtest).err().unwrap().kind(),
+        Some(PjParseErrorKind::NetworkMismatch {
+            address: "3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM".to_string()
+        })
+    );
+    assert_eq!(uri.require_network(Network::Bitcoin).unwrap().as_string(), uri.as_string());
+}
+
+#[test]
+fn receiver_addresses_are_checked_against_their_network() {
+    let testnet = Address::p2wpkh(&key(), Network::Testnet).to_string();
+    assert!(payjoin_ffi::Address::new(testnet.clone(), Network::Bitcoin).is_err());
+    assert!(payjoin_ffi::Address::new(testnet, Network::Testnet).is_ok());
+
+    let uri = crate::common::receiver(None).pj_uri(None, None, None).unwrap();
+    assert_eq!(uri.network(), Some(Network::Regtest));
+    assert!(uri.require_network(Network::Regtest).is_ok());
+}
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use payjoin_ffi::uri::{PjUri, Url};
+
+const URLS: [&str; 8] = [
+    "https://example.com",
+    "HTTPS://Example.COM:443/pj",
+    "https://example.com:8443/pj/",
+    "https://example.com/pj?v=2&label=caf%c3%a9",
+    "https://example.com/a%2fb#frag%2a",
+    "http://2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion/pj",
+    "https://[::1]:3000/pj",
+    "https://example.com/%F0%9f%8D%95?%e2=%E2",
+];
+
+const PJ_URIS: [&str; 5] = [
+    "bitcoin:bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4?pj=https://example.com/pj",
+    "BITCOIN:BCRT1Q6D3A2W975YNY0ASUVD9A67NER4NKS58FF0Q8G4?pj=https://example.com/pj",
+    "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&label=Caf%c3%a9&pj=https://example.com/pj",
+    "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02&pj=https%3a%2f%2fexample.com%2fpj&pjos=0",
+    "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://example.com/pj&note=caf%c3%a9",
+];
+
+fn url(input: &str) -> Url {
+    Url::parse(input.to_string()).unwrap()
+}
+
+fn pj_uri(input: &str) -> PjUri {
+    PjUri::parse(input.to_string()).unwrap()
+}
+
+#[test]
+fn urls_round_trip_through_their_string_form() {
+    for input in URLS {
+        let url = url(input);
+        let normalized = url.to_string();
+        assert_eq!(Url::from_str(&normalized).unwrap(), url, "{input}");
+        assert_eq!(Url::from_str(&normalized).unwrap().to_string(), normalized, "{input}");
+        assert_eq!(url.as_string(), normalized);
+    }
+}
+
+#[test]
+fn equivalent_urls_are_equal() {
+    for (a, b) in [
+        ("https://example.com", "HTTPS://EXAMPLE.com:443/"),
+        ("https://example.com/caf%c3%a9", "https://example.com/caf%C3%A9"),
+        ("https://example.com/pj?x=%2f#%2a", "https://Example.com/pj?x=%2F#%2A"),
+    ] {
+        assert_eq!(url(a), url(b), "{a} {b}");
+        assert_eq!(url(a).to_string(), url(b).to_string());
+        assert_eq!(HashSet::from([url(a), url(b)]).len(), 1);
+    }
+    assert_ne!(url("https://example.com/pj"), url("https://example.com/pj/"));
+    assert_ne!(url("https://example.com/pj"), url("https://example.com:8443/pj"));
+}
+
+#[test]
+fn only_valid_escapes_are_uppercased() {
+    assert_eq!(
+        url("https://example.com/100%-off/%ab%zz?x=%g1#%a").to_string(),
+        "https://example.com/100%-off/%AB%zz?x=%g1#%a"
+    );
+    // Every pair of characters after a `%`, instead of a property test, which would need a new
+    // dev-dependency for this one check.
+    let chars = ['0', '9', 'a', 'f', 'g', 'A', 'F', 'G', 'z', '-', '%'];
+    for first in chars {
+        for second in chars {
+            let input = format!("https://example.com/x%{first}{second}y");
+            let escape = match first.is_ascii_hexdigit() && second.is_ascii_hexdigit() {
+                true => format!("{first}{second}").to_ascii_uppercase(),
+                false => format!("{first}{second}"),
+            };
+            let rendered = url(&input).to_string();
+            assert_eq!(rendered, format!("https://example.com/x%{escape}y"), "{input}");
+            assert_eq!(url(&rendered).to_string(), rendered, "{input}");
+        }
+    }
+}
+
+#[test]
+fn pj_uris_round_trip_through_their_string_form() {
+    for input in PJ_URIS {
+        let uri = pj_uri(input);
+        let normalized = uri.to_string();
+        assert_eq!(pj_uri(&normalized), uri, "{input}");
+        assert_eq!(pj_uri(&normalized).to_string(), normalized, "{input}");
+        assert_eq!(uri.as_string(), normalized);
+        assert!(!normalized.contains("%c3") && !normalized.contains("%2f"), "{normalized}");
+    }
+}
+
+#[test]
+fn equivalent_pj_uris_are_equal() {
+    assert_eq!(pj_uri(PJ_URIS[0]), pj_uri(PJ_URIS[1]));
+    let escaped = PJ_URIS[2].replace("%c3%a9", "%C3%A9");
+    assert_eq!(pj_uri(PJ_URIS[2]), pj_uri(&escaped));
+
+    let uris: HashSet<PjUri> = PJ_URIS.iter().map(|uri| pj_uri(uri)).collect();
+    assert_eq!(uris.len(), PJ_URIS.len() - 1);
+    assert_ne!(pj_uri(PJ_URIS[2]), pj_uri(PJ_URIS[4]));
+}
@@ -0,0 +1,75 @@
+use payjoin::bitcoin::Network;
+use payjoin_ffi::uri::{PjParseErrorKind, PjUri, Uri};
+
+fn kind(uri: &str) -> Option<PjParseErrorKind> {
+    Uri::parse(uri.to_string()).err().and_then(|e| e.kind())
+}
+
+#[test]
+fn non_bitcoin_uris_have_an_invalid_scheme() {
+    for (uri, scheme) in [
+        ("https://example.com", "https"),
+        ("litecoin:MQMcJhpWHYVeQArcZR3sBgyPZxxRtnH441", "litecoin"),
+        ("3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM", ""),
+    ] {
+        assert_eq!(kind(uri), Some(PjParseErrorKind::InvalidScheme { scheme: scheme.to_string() }));
+    }
+}
+
+#[test]
+fn invalid_addresses_are_reported() {
+    for address in ["", "3CZZi7aWFugaCdUCS15dgrUUViupmB8bVX", "bc1qnotanaddress"] {
+        assert_eq!(
+            kind(&format!("bitcoin:{address}?amount=1")),
+            Some(PjParseErrorKind::InvalidAddress { address: address.to_string() })
+        );
+    }
+}
+
+#[test]
+fn addresses_for_another_network_are_reported() {
+    let uri = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://example.com/pj";
+    assert!(Uri::parse_for_network(uri.to_string(), Network::Bitcoin).is_ok());
+    let error = Uri::parse_for_network(uri.to_string(), Network::Regtest).err().unwrap();
+    assert_eq!(
+        error.kind(),
+        Some(PjParseErrorKind::NetworkMismatch {
+            address: "3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM".to_string()
+        })
+    );
+}
+
+#[test]
+fn uris_without_pj_are_reported() {
+    let uri = "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount=0.02";
+    assert!(Uri::parse(uri.to_string()).is_ok());
+    let error = PjUri::parse(uri.to_string()).err().unwrap();
+    assert_eq!(error.kind(), Some(PjParseErrorKind::MissingPjParam));
+    assert!(PjUri::parse(format!("{uri}&pj=https://example.com/pj")).is_ok());
+}
+
+#[test]
+fn malformed_amounts_are_reported() {
+    for amount in ["x", "-1", "21000000.00000001", "0.000000001"] {
+        let kind = kind(&format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?amount={amount}"));
+        assert!(
+            matches!(kind, Some(PjParseErrorKind::InvalidAmount { amount: ref a, .. }) if a == amount),
+            "{amount:?}: {kind:?}"
+        );
+    }
+}
+
+#[test]
+fn unknown_required_parameters_are_reported() {
+    assert_eq!(
+        kind("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?REQ-fancy=1"),
+        Some(PjParseErrorKind::UnsupportedRequiredParameter { name: "REQ-fancy".to_string() })
+    );
+}
+
+#[test]
+fn onion_endpoints_are_accepted() {
+    let onion = "http://pay3gxqnspbnv5jc7qz5zzvw6dgtr4dqz6x4zrfhq36pydyncztjyd.onion/pj";
+    let uri = PjUri::parse(format!("bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj={onion}"));
+    assert_eq!(uri.unwrap().pj_endpoint(), onion);
+}
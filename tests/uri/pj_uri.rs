@@ -0,0 +1,304 @@
+use std::str::FromStr;
+
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+use payjoin_ffi::uri::{PjParseErrorKind, PjUri, PjUriBuilder, Uri};
+use payjoin_ffi::OhttpKeys;
+
+use crate::common::{ohttp_keys, receiver};
+
+fn pj_uri(params: &str) -> PjUri {
+    Uri::parse(format!(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?{params}&pj=https://example.com/pj"
+    ))
+    .unwrap()
+    .check_pj_supported()
+    .unwrap()
+}
+
+/// Directory keys other than the [`ohttp_keys`] the receiver was built with.
+fn rotated_ohttp_keys() -> OhttpKeys {
+    let suite = SymmetricSuite::new(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let config = KeyConfig::derive(1, Kem::K256Sha256, vec![suite], &[8; 32]).unwrap();
+    OhttpKeys(payjoin::OhttpKeys(config))
+}
+
+#[test]
+fn reads_back_the_payment_request() {
+    let uri = pj_uri("amount=0.02&label=Coffee%20shop&message=Order%20%2342");
+    assert_eq!(uri.address(), "3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM");
+    assert_eq!(uri.amount_sats(), Some(2_000_000));
+    assert_eq!(uri.label().as_deref(), Some("Coffee shop"));
+    assert_eq!(uri.message().as_deref(), Some("Order #42"));
+    assert_eq!(uri.pj_endpoint(), "https://example.com/pj");
+
+    let uri = pj_uri("amount=0.02");
+    assert_eq!(uri.label(), None);
+    assert_eq!(uri.message(), None);
+}
+
+#[test]
+fn decodes_unicode_labels() {
+    let uri = pj_uri("label=%E2%82%BF%20caf%C3%A9&message=%F0%9F%8D%95%20%E3%83%94%E3%82%B6");
+    assert_eq!(uri.label().as_deref(), Some("₿ café"));
+    assert_eq!(uri.message().as_deref(), Some("🍕 ピザ"));
+}
+
+#[test]
+fn labels_that_arent_utf8_are_none() {
+    assert_eq!(pj_uri("label=%FF%FE").label(), None);
+}
+
+#[test]
+fn builder_encodes_special_characters() {
+    let receiver = receiver(None);
+    let label = "Café & Co. #1 ?=+%";
+    let message = "50% off: ₿ 🍕\nthanks!";
+    let uri = receiver
+        .pj_uri_builder()
+        .amount(2_000_000)
+        .unwrap()
+        .label(label.to_string())
+        .message(message.to_string())
+        .build();
+    assert_eq!(uri.label().as_deref(), Some(label));
+    assert_eq!(uri.message().as_deref(), Some(message));
+    assert_eq!(uri.amount_sats(), Some(2_000_000));
+
+    let rendered = uri.as_string();
+    assert!(!rendered.contains(' ') && !rendered.contains("& Co") && !rendered.contains('\n'));
+    let parsed = Uri::parse(rendered).unwrap().check_pj_supported().unwrap();
+    assert_eq!(parsed.label().as_deref(), Some(label));
+    assert_eq!(parsed.message().as_deref(), Some(message));
+    assert_eq!(parsed.pj_endpoint(), uri.pj_endpoint());
+}
+
+#[test]
+fn builder_amounts_round_trip_exactly() {
+    let receiver = receiver(None);
+    for (sats, btc) in [
+        (123_456, "0.00123456"),
+        (1, "0.00000001"),
+        (12_345_678, "0.12345678"),
+        (2_099_999_999_999_999, "20999999.99999999"),
+    ] {
+        let uri = receiver.pj_uri_builder().amount(sats).unwrap().build();
+        assert!(uri.as_string().contains(&format!("amount={btc}&")), "{}", uri.as_string());
+        assert_eq!(PjUri::parse(uri.as_string()).unwrap().amount_sats(), Some(sats));
+
+        let from_btc = receiver.pj_uri_builder().amount_btc(btc.to_string()).unwrap().build();
+        assert_eq!(from_btc.amount_sats(), Some(sats));
+        assert_eq!(from_btc.as_string(), uri.as_string());
+    }
+}
+
+#[test]
+fn builder_rejects_invalid_btc_amounts() {
+    let receiver = receiver(None);
+    for btc in ["-0.1", "0.000000001", "1e-8", "0,5", "", "21000000.00000001"] {
+        assert!(receiver.pj_uri_builder().amount_btc(btc.to_string()).is_err(), "{btc}");
+    }
+}
+
+#[test]
+fn builder_matches_pj_uri() {
+    let receiver = receiver(None);
+    let built = receiver.pj_uri_builder().label("Shop".to_string()).build();
+    let uri = receiver.pj_uri(None, Some("Shop".to_string()), None).unwrap();
+    assert_eq!(built.as_string(), uri.as_string());
+    assert!(receiver.pj_uri_builder().amount(u64::MAX).is_err());
+}
+
+#[test]
+fn output_substitution_follows_pjos() {
+    assert!(pj_uri("amount=0.02").output_substitution_allowed());
+    assert!(pj_uri("amount=0.02&pjos=1").output_substitution_allowed());
+    assert!(!pj_uri("amount=0.02&pjos=0").output_substitution_allowed());
+    assert!(Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://example.com/pj&pjos=2".to_string()
+    )
+    .is_err());
+}
+
+#[test]
+fn session_expiry_is_read_from_the_endpoint() {
+    let receiver = receiver(None);
+    let uri = receiver.pj_uri(None, None, None).unwrap();
+    assert!(uri.session_expiry().is_some());
+    assert_eq!(uri.session_expiry(), receiver.expiration());
+
+    // v1 endpoints have no expiry
+    assert_eq!(pj_uri("amount=0.02").session_expiry(), None);
+    // Not bech32, and too short for a timestamp
+    for fragment in ["EX1INVALID", "EX1QQQQQ"] {
+        let uri = Uri::parse(format!(
+            "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://example.com/pj%23{fragment}"
+        ))
+        .unwrap()
+        .check_pj_supported()
+        .unwrap();
+        assert_eq!(uri.session_expiry(), None);
+    }
+}
+
+const BOLT11: &str = "LNBC10U1P3PJ257PP5YZTKWJCZ5FTL5LAXKAV23ZMZEKAW37ZK6KMV80PK4XAEV5QHTZ7QDPDWD3XGER9WD5KWM36YPRX7U3QD36KUCMGYP282ETNV3SHJCQZPGXQYZ5VQSP5USYC4LK9CHSFP53KVCNVQ456GANH60D89REYKDNGSMTJ6YW3NHVQ9QYYSSQJCEWM5CJWZ4A6RFJX77C490YCED6PEMK0UPKXHY89CMM7SCT66K8GNEANWYKZGDRWRFJE69H9U5U0W57RRCSYSAS7GADWMZXC8C6T0SPJAZUP6";
+
+#[test]
+fn unified_uris_keep_their_lightning_fallback() {
+    let uri = pj_uri(&format!("amount=0.02&lightning={BOLT11}&note=caf%C3%A9"));
+    let extras = uri.extra_params();
+    assert_eq!(extras.len(), 2);
+    assert_eq!(extras["lightning"], BOLT11);
+    assert_eq!(extras["note"], "café");
+
+    let rendered = uri.as_string();
+    assert!(rendered.contains(&format!("lightning={BOLT11}")));
+    assert!(rendered.contains("note=caf%C3%A9"));
+    let reparsed = Uri::parse(rendered).unwrap();
+    assert_eq!(reparsed.extra_params(), extras);
+    assert_eq!(reparsed.check_pj_supported().unwrap().extra_params(), extras);
+
+    assert!(pj_uri("amount=0.02").extra_params().is_empty());
+}
+
+#[test]
+fn unknown_parameters_are_encoded_again_when_rendered() {
+    let uri = pj_uri("note=fish%20%26%20chips%3F");
+    assert_eq!(uri.extra_params()["note"], "fish & chips?");
+
+    let rendered = uri.as_string();
+    assert!(!rendered.contains("fish & chips"));
+    let reparsed = Uri::parse(rendered).unwrap();
+    assert_eq!(reparsed.extra_params(), uri.extra_params());
+    assert_eq!(reparsed.check_pj_supported().unwrap(), uri);
+}
+
+#[test]
+fn unknown_required_parameters_are_rejected() {
+    let error = Uri::parse(format!(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?lightning={BOLT11}&req-fancy=1&pj=https://example.com/pj"
+    ))
+    .err()
+    .unwrap();
+    assert_eq!(
+        error.kind(),
+        Some(PjParseErrorKind::UnsupportedRequiredParameter { name: "req-fancy".to_string() })
+    );
+    let error = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://example.com/pj&pjos=2".to_string(),
+    );
+    assert_eq!(error.err().unwrap().kind(), None);
+}
+
+#[test]
+fn qr_string_parses_back_to_the_same_uri() {
+    let receiver = receiver(None);
+    let uri =
+        receiver.pj_uri_builder().amount(2_000_000).unwrap().label("Café".to_string()).build();
+    let qr = uri.to_qr_string();
+    assert!(qr.starts_with("BITCOIN:BCRT1Q6D3A2W975YNY0ASUVD9A67NER4NKS58FF0Q8G4?"));
+    assert!(qr.contains("HTTPS"));
+    assert!(!qr.contains("%c3") && !qr.contains("%a9"));
+
+    let parsed = Uri::parse(qr).unwrap().check_pj_supported().unwrap();
+    assert_eq!(parsed.address(), uri.address());
+    assert_eq!(parsed.amount_sats(), uri.amount_sats());
+    assert_eq!(parsed.label(), uri.label());
+    assert_eq!(parsed.pj_endpoint(), uri.pj_endpoint());
+    assert_eq!(parsed.receiver_pubkey(), uri.receiver_pubkey());
+    assert_eq!(parsed.session_expiry(), uri.session_expiry());
+}
+
+#[test]
+fn qr_string_fits_the_alphanumeric_mode() {
+    let receiver = receiver(None);
+    let uri = receiver.pj_uri_builder().amount(2_000_000).unwrap().build();
+    let qr = uri.to_qr_string();
+    assert!(qr.contains("?AMOUNT=0.02&") && qr.contains("&PJ=HTTPS://"), "{qr}");
+    // The `?`, `=` and `&` separating BIP21 parameters are the only characters outside the set
+    let alphanumeric =
+        |c: char| c.is_ascii_digit() || c.is_ascii_uppercase() || " $%*+-./:".contains(c);
+    let outside: Vec<char> = qr.chars().filter(|c| !alphanumeric(*c)).collect();
+    assert!(outside.iter().all(|c| ['?', '=', '&'].contains(c)), "{qr}");
+    assert_eq!(PjUri::parse(qr).unwrap(), uri);
+}
+
+#[test]
+fn qr_string_keeps_case_sensitive_parts() {
+    let uri = Uri::parse(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://Pay.Example.com/Session/aBc-9_Z"
+            .to_string(),
+    )
+    .unwrap()
+    .check_pj_supported()
+    .unwrap();
+    let qr = uri.to_qr_string();
+    // Base58 addresses are case-sensitive
+    assert!(qr.starts_with("BITCOIN:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?"));
+    assert!(qr.contains("PAY.EXAMPLE.COM"));
+    let parsed = Uri::parse(qr).unwrap().check_pj_supported().unwrap();
+    assert_eq!(parsed.pj_endpoint(), "https://pay.example.com/Session/aBc-9_Z");
+    assert_eq!(parsed.pj_endpoint(), uri.pj_endpoint());
+}
+
+#[test]
+fn builder_exposes_the_fragment_parameters() {
+    let receiver = receiver(None);
+    let builder = receiver.pj_uri_builder();
+    let uri = builder.build();
+    assert_eq!(builder.ohttp_keys().unwrap().0.to_string(), ohttp_keys().0.to_string());
+    assert_eq!(uri.ohttp_keys().unwrap().0.to_string(), ohttp_keys().0.to_string());
+    assert!(builder.expiry().is_some());
+    assert_eq!(builder.expiry(), uri.session_expiry());
+
+    let parsed = PjUri::parse(uri.as_string()).unwrap();
+    assert_eq!(parsed.ohttp_keys().unwrap().0.to_string(), ohttp_keys().0.to_string());
+    assert_eq!(parsed.session_expiry(), builder.expiry());
+    assert!(pj_uri("amount=0.02").ohttp_keys().is_none());
+}
+
+#[test]
+fn builder_overrides_the_ohttp_keys() {
+    let receiver = receiver(None);
+    let original = receiver.pj_uri_builder().amount(2_000_000).unwrap().label("Shop".to_string());
+    let expiry = original.expiry();
+    let rotated = original.override_ohttp_keys(&rotated_ohttp_keys()).unwrap();
+    assert_eq!(rotated.ohttp_keys().unwrap().0.to_string(), rotated_ohttp_keys().0.to_string());
+    assert_eq!(rotated.expiry(), expiry);
+
+    let uri = PjUri::parse(rotated.build().as_string()).unwrap();
+    assert_eq!(uri.ohttp_keys().unwrap().0.to_string(), rotated_ohttp_keys().0.to_string());
+    assert_eq!(uri.session_expiry(), expiry);
+    assert_eq!(uri.amount_sats(), Some(2_000_000));
+    assert_eq!(uri.label().as_deref(), Some("Shop"));
+    assert_eq!(uri.receiver_pubkey(), receiver.pj_uri_builder().build().receiver_pubkey());
+    assert_eq!(
+        uri.pj_endpoint().split('#').next(),
+        original.build().pj_endpoint().split('#').next()
+    );
+}
+
+#[test]
+fn overriding_ohttp_keys_of_a_v1_endpoint_fails() {
+    let v1 = payjoin::Uri::from_str(
+        "bitcoin:3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM?pj=https://example.com/pj",
+    )
+    .unwrap()
+    .assume_checked()
+    .check_pj_supported()
+    .unwrap();
+    assert!(PjUriBuilder::from(v1).override_ohttp_keys(&rotated_ohttp_keys()).is_err());
+}
+
+#[test]
+fn session_pj_uri_matches_the_bare_builder() {
+    let receiver = receiver(None);
+    let uri = receiver.session_pj_uri();
+    assert_eq!(uri.as_string(), receiver.pj_uri_builder().build().as_string());
+    assert_eq!(uri.as_string(), receiver.pj_uri(None, None, None).unwrap().as_string());
+    assert_eq!(uri.address(), "bcrt1q6d3a2w975yny0asuvd9a67ner4nks58ff0q8g4");
+    assert_eq!((uri.amount_sats(), uri.label(), uri.message()), (None, None, None));
+    assert_eq!(uri.ohttp_keys().unwrap().0.to_string(), ohttp_keys().0.to_string());
+    assert!(uri.session_expiry().is_some());
+    assert!(uri.receiver_pubkey().is_some());
+}
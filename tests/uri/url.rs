@@ -0,0 +1,52 @@
+use payjoin_ffi::uri::{QueryParam, Url};
+
+fn url(input: &str) -> Url {
+    Url::parse(input.to_string()).unwrap()
+}
+
+fn param(key: &str, value: &str) -> QueryParam {
+    QueryParam { key: key.to_string(), value: value.to_string() }
+}
+
+#[test]
+fn reads_the_parts_of_a_url() {
+    let relay = url("https://relay.example:8443/session/ABC?v=2&note=caf%C3%A9+bar#frag");
+    assert_eq!(relay.scheme(), "https");
+    assert_eq!(relay.host().as_deref(), Some("relay.example"));
+    assert_eq!(relay.query().as_deref(), Some("v=2&note=caf%C3%A9+bar"));
+    assert_eq!(relay.query_pairs(), vec![param("v", "2"), param("note", "café bar")]);
+
+    assert_eq!(url("https://relay.example").query_pairs(), vec![]);
+    assert_eq!(url("unix:/run/payjoin.sock").host(), None);
+}
+
+#[test]
+fn sets_query_params() {
+    let endpoint = url("https://example.com/pj?v=1&maxadditionalfeecontribution=100&v=2#frag");
+    let updated = endpoint.set_query_param("v".to_string(), "a b&c".to_string());
+    assert_eq!(
+        updated.query_pairs(),
+        vec![param("maxadditionalfeecontribution", "100"), param("v", "a b&c")]
+    );
+    assert!(updated.as_string().ends_with("#frag"));
+    // The original is unchanged
+    assert_eq!(endpoint.query_pairs().len(), 3);
+
+    let added = url("https://example.com/pj").set_query_param("v".into(), "2".into());
+    assert_eq!(added.as_string(), "https://example.com/pj?v=2");
+}
+
+#[test]
+fn joins_paths() {
+    let directory = url("https://directory.example/");
+    assert_eq!(
+        directory.join("health".to_string()).unwrap().as_string(),
+        "https://directory.example/health"
+    );
+    let session = url("https://directory.example/SESSION");
+    assert_eq!(
+        session.join("health".to_string()).unwrap().as_string(),
+        "https://directory.example/health"
+    );
+    assert!(directory.join("http://[::1".to_string()).is_err());
+}